@@ -0,0 +1,53 @@
+//! Times showing a small file through the `--cache`, the output stored for
+//! it read back, against highlighting it again.
+//!
+//! ```text
+//! cargo run --release --example cache -- [LINES]
+//! ```
+
+use anyhow::{Context, Result};
+use ccat::{CachedOutput, HighlighterConfig, OutputCache, SyntaxHighlighter};
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 200;
+
+fn main() -> Result<()> {
+    let line_count = match std::env::args().nth(1) {
+        Some(count) => count.parse().with_context(|| format!("Invalid line count '{}'", count))?,
+        None => 200,
+    };
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("bench.rs");
+    let content: String = (0..line_count)
+        .map(|line| format!("fn f{0}(x: u32) -> u32 {{ x + {0} }} // line {0}\n", line))
+        .collect();
+    std::fs::write(&file, &content)?;
+
+    let highlighter = SyntaxHighlighter::new();
+    let config = HighlighterConfig::default();
+    let cache = OutputCache::new(dir.path().join("cache"), "bench", u64::MAX);
+    let highlight = || -> Result<String> {
+        highlighter.highlight_reader(std::io::BufReader::new(std::fs::File::open(&file)?), &config, "Rust")?.collect()
+    };
+    let entry = cache.entry(&file, "").context("The file has no cache entry")?;
+    cache.put(&entry, &CachedOutput { syntax: "Rust".to_string(), via: None, output: highlight()? })?;
+
+    let mut highlighted = Duration::MAX;
+    let mut cached = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        let fresh = highlight()?;
+        highlighted = highlighted.min(started.elapsed());
+
+        // a hit keys the file again before reading its entry
+        let started = Instant::now();
+        let hit = cache.entry(&file, "").and_then(|entry| cache.get(&entry)).context("The cache missed")?;
+        cached = cached.min(started.elapsed());
+        anyhow::ensure!(hit.output == fresh, "the cached output differs from the highlighted one");
+    }
+
+    println!("{} lines, best of {}:", line_count, ROUNDS);
+    println!("  highlighted  {:>10.2?}", highlighted);
+    println!("  cache hit    {:>10.2?}  ({:.1}x faster)", cached, highlighted.as_secs_f64() / cached.as_secs_f64());
+    Ok(())
+}
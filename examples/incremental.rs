@@ -0,0 +1,48 @@
+//! Times re-rendering a large file after a one-line change, from scratch and
+//! through an [`IncrementalSession`] that rendered the version before.
+//!
+//! ```text
+//! cargo run --release --example incremental -- [LINES]
+//! ```
+
+use anyhow::{Context, Result};
+use ccat::{HighlighterConfig, IncrementalSession, SyntaxHighlighter};
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 5;
+
+fn main() -> Result<()> {
+    let line_count = match std::env::args().nth(1) {
+        Some(count) => count.parse().with_context(|| format!("Invalid line count '{}'", count))?,
+        None => 20_000,
+    };
+    let highlighter = SyntaxHighlighter::new();
+    let config = HighlighterConfig::default();
+    let mut lines: Vec<String> = (0..line_count)
+        .map(|line| format!("fn f{0}(x: u32) -> u32 {{ x + {0} }} // line {0}\n", line))
+        .collect();
+
+    let mut full = Duration::MAX;
+    let mut incremental = Duration::MAX;
+    for round in 0..ROUNDS {
+        let mut session = IncrementalSession::new(&highlighter, &config)?;
+        session.render(&lines.concat(), "bench.rs")?;
+        // each round edits a line near the middle
+        lines[line_count / 2 + round] = format!("let edited = {};\n", round);
+        let content = lines.concat();
+
+        let started = Instant::now();
+        let from_scratch = IncrementalSession::new(&highlighter, &config)?.render(&content, "bench.rs")?;
+        full = full.min(started.elapsed());
+
+        let started = Instant::now();
+        let reused = session.render(&content, "bench.rs")?;
+        incremental = incremental.min(started.elapsed());
+        anyhow::ensure!(reused == from_scratch, "the incremental rendering differs from the full one");
+    }
+
+    println!("{} lines, one changed, best of {}:", line_count, ROUNDS);
+    println!("  full re-render  {:>10.2?}", full);
+    println!("  incremental     {:>10.2?}  ({:.1}x faster)", incremental, full.as_secs_f64() / incremental.as_secs_f64());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! A minimal full-screen viewer built on [`SyntaxHighlighter::highlight_lines`]
+//! and [`GutterRenderer`]: the gutter stays put on the left while the content
+//! scrolls in both directions.
+//!
+//! ```text
+//! cargo run --example tui --features pager -- src/lib.rs
+//! ```
+//!
+//! Arrow keys or h/j/k/l scroll, space and b page, q quits.
+
+use anyhow::{Context, Result};
+use ccat::{GutterRenderer, HighlightedLine, HighlighterConfig, Rgb, Span, Style, SyntaxHighlighter};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use unicode_width::UnicodeWidthChar;
+
+const GUTTER_STYLE: Style = Style {
+    fg: Rgb(0x65, 0x73, 0x7e),
+    bg: None,
+    bold: false,
+    italic: false,
+    underline: false,
+    strikethrough: false,
+};
+
+fn main() -> Result<()> {
+    let path = std::env::args().nth(1).context("usage: tui FILE")?;
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read file '{}'", path))?;
+
+    let config = HighlighterConfig { show_line_numbers: true, ..HighlighterConfig::default() };
+    let lines = SyntaxHighlighter::new().highlight_lines(&content, &path, &config)?;
+    let gutter = GutterRenderer::new(content.lines().count(), &config)?;
+
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+    let result = run(&lines, &gutter);
+    terminal::disable_raw_mode()?;
+    execute!(io::stdout(), SetAttribute(Attribute::Reset), Show, LeaveAlternateScreen)?;
+
+    result
+}
+
+fn run(lines: &[HighlightedLine], gutter: &GutterRenderer) -> Result<()> {
+    let (mut top, mut left) = (0usize, 0usize);
+
+    loop {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        draw(lines, gutter, top, left, width, height)?;
+
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => top += 1,
+            KeyCode::Up | KeyCode::Char('k') => top = top.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => left += 4,
+            KeyCode::Left | KeyCode::Char('h') => left = left.saturating_sub(4),
+            KeyCode::Char(' ') | KeyCode::PageDown => top += height,
+            KeyCode::Char('b') | KeyCode::PageUp => top = top.saturating_sub(height),
+            _ => {}
+        }
+        top = top.min(lines.len().saturating_sub(height));
+    }
+}
+
+fn draw(lines: &[HighlightedLine], gutter: &GutterRenderer, top: usize, left: usize, width: usize, height: usize) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    queue!(stdout, Clear(ClearType::All))?;
+
+    for (row, line) in lines.iter().skip(top).take(height).enumerate() {
+        queue!(stdout, MoveTo(0, row as u16))?;
+
+        // the gutter pane never scrolls sideways
+        for cell in gutter.cells(Some(line.number), GUTTER_STYLE) {
+            print_span(&mut stdout, &cell)?;
+        }
+
+        let mut column = 0;
+        let room = width.saturating_sub(gutter.width());
+        for span in &line.spans {
+            let mut visible = String::new();
+            for c in span.text.chars() {
+                let c = if c == '\t' { ' ' } else { c };
+                let char_width = c.width().unwrap_or(0);
+                if column >= left && column + char_width <= left + room {
+                    visible.push(c);
+                }
+                column += char_width;
+            }
+            print_span(&mut stdout, &Span { style: span.style, text: visible })?;
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+fn print_span(out: &mut impl Write, span: &Span) -> Result<()> {
+    let Rgb(r, g, b) = span.style.fg;
+    queue!(out, SetForegroundColor(Color::Rgb { r, g, b }), Print(&span.text))?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Feeds arbitrary bytes through ccat's whole input pipeline: decoding
+//! (gzip, BOM sniffing, transcoding, the binary check), detection, and
+//! highlighting as a syntax picked from the input, through each of the
+//! renderers. Errors are fine, panics and hangs are not; the memory budget
+//! and line timeout are those of `--paranoid`.
+//!
+//! Run with `cargo fuzz run pipeline -- -timeout=10` from the repository.
+
+#![no_main]
+
+use ccat::{
+    hexdump, ExtractOptions, HexdumpConfig, HighlighterConfig, MemoryBudget, OutputProfile, SyntaxHighlighter, WrapMode,
+};
+use libfuzzer_sys::fuzz_target;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+const ENCODINGS: &[Option<&str>] = &[None, Some("utf-16le"), Some("shift_jis"), Some("windows-1252")];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&choice, bytes)) = data.split_first() else {
+        return;
+    };
+    let highlighter = &*HIGHLIGHTER;
+
+    // extract_text reads a file, as the command line does
+    let path = std::env::temp_dir().join(format!("ccat-fuzz-{}", std::process::id()));
+    if std::fs::write(&path, bytes).is_err() {
+        return;
+    }
+    let options = ExtractOptions {
+        encoding: ENCODINGS[usize::from(choice) % ENCODINGS.len()].map(str::to_string),
+        ..ExtractOptions::default()
+    };
+    let content = match highlighter.extract_text(&path.to_string_lossy(), &options) {
+        Ok(extracted) => extracted.content,
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+
+    let syntaxes = highlighter.available_syntaxes();
+    let syntax = syntaxes[usize::from(choice) * 7 % syntaxes.len()].to_string();
+    let config = HighlighterConfig {
+        force_syntax: (choice & 1 == 0).then_some(syntax),
+        show_line_numbers: choice & 2 != 0,
+        wrap: (choice & 4 != 0).then_some(WrapMode::Word),
+        terminal_width: Some(usize::from(choice % 40) + 1),
+        tab_width: (choice & 8 != 0).then_some(4),
+        rainbow_brackets: choice & 16 != 0,
+        reverse: choice & 32 != 0,
+        profile: OutputProfile { zero_width_escapes: choice & 64 != 0, ..OutputProfile::FULL },
+        max_line_length: Some(1024),
+        line_timeout: Some(Duration::from_millis(100)),
+        memory_budget: MemoryBudget::new(256 << 20),
+        ..HighlighterConfig::default()
+    };
+
+    let _ = highlighter.detect(&content, "fuzz", &config);
+    let _ = highlighter.highlight_content(&content, "fuzz", &config);
+    let _ = highlighter.highlight_lines(&content, "fuzz", &config);
+    let _ = highlighter.theme_diff(&content, "fuzz", &config, "InspiredGitHub", 80);
+    let _ = highlighter.outline(&content, "fuzz", &config, 2, None);
+    let _ = highlighter.highlight_patch(&content, &config);
+    if ccat::is_mail("fuzz", &content) || choice & 128 != 0 {
+        let _ = highlighter.highlight_mail(&content, &config);
+    }
+    let _ = hexdump(bytes, &HexdumpConfig::default());
+});
@@ -0,0 +1,231 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use syntect::highlighting::{Color, Style};
+
+/// Color adjustments for readers with low vision or color vision deficiencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Accessibility {
+    /// Raise every foreground to WCAG AAA contrast (7:1)
+    HighContrast,
+    /// Compensate for weak green perception, with AA contrast (4.5:1)
+    Deuteranopia,
+    /// Compensate for weak red perception, with AA contrast (4.5:1)
+    Protanopia,
+    /// Compensate for weak blue perception, with AA contrast (4.5:1)
+    Tritanopia,
+}
+
+impl Accessibility {
+    /// The contrast ratio every foreground is raised to.
+    pub fn min_contrast(self) -> f32 {
+        match self {
+            Self::HighContrast => 7.0,
+            _ => 4.5,
+        }
+    }
+}
+
+type Matrix = [[f32; 3]; 3];
+
+const RGB_TO_LMS: Matrix = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+
+const LMS_TO_RGB: Matrix = [
+    [0.080_944_45, -0.130_504_4, 0.116_721_07],
+    [-0.010_248_53, 0.054_019_33, -0.113_614_7],
+    [-0.000_365_297, -0.004_121_615, 0.693_511_4],
+];
+
+// dichromat simulations in LMS space (Viénot, Brettel and Mollon 1999)
+const PROTANOPIA: Matrix = [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+const DEUTERANOPIA: Matrix = [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]];
+const TRITANOPIA: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-0.395913, 0.801109, 0.0]];
+
+// how the information lost to each deficiency is shifted into the channels that remain
+const RED_GREEN_SHIFT: Matrix = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+const BLUE_SHIFT: Matrix = [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]];
+
+fn multiply(matrix: &Matrix, v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn channel(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Daltonizes `color`: simulates how it looks with the deficiency and adds the
+/// difference back into channels the reader can tell apart, so colors that
+/// would collapse into one stay distinguishable.
+pub(crate) fn daltonize(color: Color, mode: Accessibility) -> Color {
+    let (simulation, shift) = match mode {
+        Accessibility::HighContrast => return color,
+        Accessibility::Protanopia => (&PROTANOPIA, &RED_GREEN_SHIFT),
+        Accessibility::Deuteranopia => (&DEUTERANOPIA, &RED_GREEN_SHIFT),
+        Accessibility::Tritanopia => (&TRITANOPIA, &BLUE_SHIFT),
+    };
+
+    let rgb = [color.r as f32, color.g as f32, color.b as f32];
+    let simulated = multiply(&LMS_TO_RGB, multiply(simulation, multiply(&RGB_TO_LMS, rgb)));
+    let error = [rgb[0] - simulated[0], rgb[1] - simulated[1], rgb[2] - simulated[2]];
+    let correction = multiply(shift, error);
+
+    Color {
+        r: channel(rgb[0] + correction[0]),
+        g: channel(rgb[1] + correction[1]),
+        b: channel(rgb[2] + correction[2]),
+        a: color.a,
+    }
+}
+
+/// WCAG relative luminance of an sRGB color.
+pub(crate) fn relative_luminance(color: Color) -> f32 {
+    let linear = |value: u8| {
+        let value = value as f32 / 255.0;
+        if value <= 0.03928 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+/// WCAG contrast ratio between two colors, from 1 to 21.
+pub(crate) fn contrast_ratio(a: Color, b: Color) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    let (light, dark) = if a > b { (a, b) } else { (b, a) };
+
+    (light + 0.05) / (dark + 0.05)
+}
+
+/// Moves `foreground` towards white or black, whichever `background` contrasts
+/// with more, by the least amount that reaches `min_ratio`.
+pub(crate) fn enforce_contrast(foreground: Color, background: Color, min_ratio: f32) -> Color {
+    if contrast_ratio(foreground, background) >= min_ratio {
+        return foreground;
+    }
+
+    let white = Color { r: 255, g: 255, b: 255, a: foreground.a };
+    let black = Color { r: 0, g: 0, b: 0, a: foreground.a };
+    let target = if contrast_ratio(white, background) >= contrast_ratio(black, background) { white } else { black };
+
+    // contrast grows monotonically with the blend weight, so bisect for the smallest one
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if contrast_ratio(crate::color::blend(foreground, target, middle), background) >= min_ratio {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+
+    crate::color::blend(foreground, target, high)
+}
+
+/// Applies an [`Accessibility`] mode to the foregrounds of highlighted ranges,
+/// remembering each color it has already adjusted.
+#[derive(Clone)]
+pub(crate) struct ColorTransform {
+    mode: Accessibility,
+    background: Color,
+    adjusted: HashMap<(u8, u8, u8, u8), Color>,
+}
+
+impl ColorTransform {
+    pub(crate) fn new(mode: Accessibility, background: Color) -> Self {
+        Self {
+            mode,
+            background,
+            adjusted: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn color(&mut self, color: Color) -> Color {
+        let (mode, background) = (self.mode, self.background);
+
+        *self.adjusted.entry((color.r, color.g, color.b, color.a)).or_insert_with(|| {
+            enforce_contrast(daltonize(color, mode), background, mode.min_contrast())
+        })
+    }
+
+    pub(crate) fn apply<'a>(&mut self, ranges: Vec<(Style, &'a str)>) -> Vec<(Style, &'a str)> {
+        ranges.into_iter()
+            .map(|(style, text)| (Style { foreground: self.color(style.foreground), ..style }, text))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(hex: u32) -> Color {
+        Color { r: (hex >> 16) as u8, g: (hex >> 8) as u8, b: hex as u8, a: 0xff }
+    }
+
+    /// How `color` looks with the deficiency of `simulation`.
+    fn simulate(color: Color, simulation: &Matrix) -> [f32; 3] {
+        multiply(&LMS_TO_RGB, multiply(simulation, multiply(&RGB_TO_LMS, [color.r as f32, color.g as f32, color.b as f32])))
+    }
+
+    fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn contrast_ratios_match_wcag() {
+        assert!((contrast_ratio(rgb(0xffffff), rgb(0x000000)) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(rgb(0x777777), rgb(0xffffff)) - 4.48).abs() < 0.01);
+        assert_eq!(contrast_ratio(rgb(0x336699), rgb(0x336699)), 1.0);
+    }
+
+    #[test]
+    fn low_contrast_foregrounds_are_raised_to_the_minimum_and_no_further() {
+        let background = rgb(0x2b303b);
+        for foreground in [0x65737e, 0x4f5b66, 0x8fa1b3, 0x343d46] {
+            let raised = enforce_contrast(rgb(foreground), background, 7.0);
+            let ratio = contrast_ratio(raised, background);
+            assert!((7.0..7.3).contains(&ratio), "#{:06x} became {:?} at {}", foreground, raised, ratio);
+        }
+        // on a light background the foreground darkens
+        let darkened = enforce_contrast(rgb(0xaaaaaa), rgb(0xffffff), 4.5);
+        assert!(darkened.r < 0xaa && contrast_ratio(darkened, rgb(0xffffff)) >= 4.5);
+        // enough contrast already is left alone
+        assert_eq!(enforce_contrast(rgb(0xffffff), background, 7.0), rgb(0xffffff));
+    }
+
+    #[test]
+    fn daltonizing_pulls_apart_colors_that_collide() {
+        // the red and green of a typical diff theme
+        let (red, green) = (rgb(0xd05a5a), rgb(0x6ab05d));
+        for (mode, simulation) in [(Accessibility::Deuteranopia, &DEUTERANOPIA), (Accessibility::Protanopia, &PROTANOPIA)] {
+            let before = distance(simulate(red, simulation), simulate(green, simulation));
+            let after = distance(simulate(daltonize(red, mode), simulation), simulate(daltonize(green, mode), simulation));
+            assert!(after > before * 1.2, "{:?}: {} to {}", mode, before, after);
+        }
+
+        let (blue, green) = (rgb(0x5a8fd0), rgb(0x5dd0a0));
+        let before = distance(simulate(blue, &TRITANOPIA), simulate(green, &TRITANOPIA));
+        let after = distance(simulate(daltonize(blue, Accessibility::Tritanopia), &TRITANOPIA), simulate(daltonize(green, Accessibility::Tritanopia), &TRITANOPIA));
+        assert!(after > before, "{} to {}", before, after);
+    }
+
+    #[test]
+    fn grays_are_left_as_they_are() {
+        for mode in [Accessibility::Deuteranopia, Accessibility::Protanopia, Accessibility::Tritanopia] {
+            let gray = daltonize(rgb(0x808080), mode);
+            assert!(gray.r.abs_diff(0x80) <= 2 && gray.g.abs_diff(0x80) <= 2 && gray.b.abs_diff(0x80) <= 2, "{:?}: {:?}", mode, gray);
+        }
+    }
+
+    #[test]
+    fn the_transform_applies_to_every_range() {
+        let background = rgb(0x000000);
+        let mut transform = ColorTransform::new(Accessibility::HighContrast, background);
+        let dim = Style { foreground: rgb(0x333333), ..Style::default() };
+        let ranges = transform.apply(vec![(dim, "a"), (dim, "b")]);
+        assert!(ranges.iter().all(|(style, _)| contrast_ratio(style.foreground, background) >= 7.0));
+    }
+}
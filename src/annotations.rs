@@ -0,0 +1,263 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use syntect::highlighting::{Color, Style};
+
+use crate::gutter::TERMINAL_TAB_WIDTH;
+use crate::profile::{OutputProfile, Underline};
+use crate::width::{display_width, measure};
+
+/// A note attached to a line or an inclusive range of lines (1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub lines: RangeInclusive<usize>,
+    pub note: String,
+}
+
+impl Annotation {
+    fn parse(lines: &str, note: String) -> Result<Self> {
+        let parse_line = |value: &str| -> Result<usize> {
+            match value.trim().parse::<usize>() {
+                Ok(0) | Err(_) => bail!("Invalid annotation line '{}'", lines),
+                Ok(line) => Ok(line),
+            }
+        };
+
+        let (start, end) = match lines.split_once(':') {
+            Some((start, end)) => (parse_line(start)?, parse_line(end)?),
+            None => {
+                let line = parse_line(lines)?;
+                (line, line)
+            }
+        };
+
+        if start > end {
+            bail!("Invalid annotation range '{}', start is after end", lines);
+        }
+
+        Ok(Self { lines: start..=end, note })
+    }
+}
+
+/// Line annotations for a set of files, as loaded from a TOML or JSON file
+/// shaped like `{ "src/lib.rs": { "42": "entry point", "87:93": "detection logic" } }`.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    files: BTreeMap<String, Vec<Annotation>>,
+}
+
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct RawAnnotations(BTreeMap<String, BTreeMap<String, String>>);
+
+impl Annotations {
+    /// Loads annotations from `path`, parsed as JSON for `.json` files and TOML otherwise.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read annotations file '{}'", path))?;
+
+        let is_json = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        if is_json {
+            Self::from_json(&content)
+        } else {
+            Self::from_toml(&content)
+        }
+        .with_context(|| format!("Failed to parse annotations file '{}'", path))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Self::from_raw(toml::from_str(content)?)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        Self::from_raw(serde_json::from_str(content)?)
+    }
+
+    fn from_raw(raw: RawAnnotations) -> Result<Self> {
+        let mut files = BTreeMap::new();
+
+        for (file, notes) in raw.0 {
+            let mut annotations = notes.into_iter()
+                .map(|(lines, note)| Annotation::parse(&lines, note))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Invalid annotations for '{}'", file))?;
+            annotations.sort_by_key(|annotation| *annotation.lines.start());
+
+            files.insert(file, annotations);
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Returns the annotations for `file_path`. A key matches when it is equal to
+    /// the path or names its trailing components, so `lib.rs` matches `src/lib.rs`.
+    pub fn for_file(&self, file_path: &str) -> Vec<Annotation> {
+        let path = Path::new(file_path);
+
+        self.files.iter()
+            .filter(|(key, _)| path.ends_with(Path::new(key.as_str())))
+            .flat_map(|(_, annotations)| annotations.iter().cloned())
+            .collect()
+    }
+}
+
+/// Returns the annotations that reference lines past the end of a file with `line_count` lines.
+pub fn out_of_range(annotations: &[Annotation], line_count: usize) -> impl Iterator<Item = &Annotation> {
+    annotations.iter()
+        .filter(move |annotation| *annotation.lines.end() > line_count)
+}
+
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders an annotated line: the whole line gets the `highlight` background and
+/// `note`, if any, is right-aligned after the code when it fits within
+/// `terminal_width`, or put on a marker line of its own below it otherwise.
+/// Underlined ranges get the `underline`.
+pub(crate) fn render_line(ranges: &[(Style, &str)], highlight: Color, note: Option<&str>, gutter_width: usize, terminal_width: Option<usize>, underline: Option<Underline>, profile: &OutputProfile) -> String {
+    let (dim, reset) = (profile.sgr(DIM), profile.sgr(RESET));
+
+    let (ranges, ending) = split_line_ending(ranges);
+    let ranges: Vec<(Style, &str)> = ranges.iter()
+        .map(|(style, text)| (Style { background: highlight, ..*style }, *text))
+        .collect();
+
+    let mut result = profile.escape_underlined(&ranges, true, underline);
+    let Some(note) = note else {
+        result.push_str(reset);
+        result.push_str(ending);
+        return result;
+    };
+
+    let code: String = ranges.iter().map(|(_, text)| *text).collect();
+    let code_width = measure(&code, TERMINAL_TAB_WIDTH, profile);
+    let note_width = display_width(note, TERMINAL_TAB_WIDTH, profile.ambiguous_width);
+
+    match terminal_width {
+        Some(width) if gutter_width + code_width + note_width + 2 > width => {
+            result.push_str(reset);
+            result.push_str(if ending.is_empty() { "\n" } else { ending });
+            result.push_str(&format!("{}{}{} {}{}{}", " ".repeat(gutter_width), dim, profile.symbol("└", "`-"), note, reset, ending));
+        }
+        Some(width) => {
+            let padding = width - gutter_width - code_width - note_width;
+            result.push_str(&format!("{}{}{}{}{}", reset, " ".repeat(padding), dim, note, reset));
+            result.push_str(ending);
+        }
+        None => {
+            result.push_str(&format!("{}  {}{}{}{}", reset, dim, note, reset, ending));
+        }
+    }
+
+    result
+}
+
+/// Separates the trailing `\n` or `\r\n` from the last range of a line.
+fn split_line_ending<'a>(ranges: &[(Style, &'a str)]) -> (Vec<(Style, &'a str)>, &'a str) {
+    let mut ranges = ranges.to_vec();
+    let mut ending = "";
+
+    if let Some((_, text)) = ranges.last_mut() {
+        let trimmed = text.trim_end_matches(['\n', '\r']);
+        ending = &text[trimmed.len()..];
+        *text = trimmed;
+    }
+
+    (ranges, ending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(annotations: &[Annotation]) -> Vec<(RangeInclusive<usize>, &str)> {
+        annotations.iter().map(|annotation| (annotation.lines.clone(), annotation.note.as_str())).collect()
+    }
+
+    #[test]
+    fn notes_are_keyed_by_line_or_range_and_sorted() {
+        let annotations = Annotations::from_toml("[\"src/lib.rs\"]\n\"87:93\" = \"detection\"\n\"42\" = \"entry point\"\n\" 9 : 9 \" = \"padded\"\n").unwrap();
+        assert_eq!(lines(&annotations.for_file("src/lib.rs")), [(9..=9, "padded"), (42..=42, "entry point"), (87..=93, "detection")]);
+
+        let json = Annotations::from_json(r#"{ "lib.rs": { "3": "three" } }"#).unwrap();
+        assert_eq!(lines(&json.for_file("src/lib.rs")), [(3..=3, "three")]);
+    }
+
+    #[test]
+    fn keys_match_the_trailing_components_of_a_path() {
+        let annotations = Annotations::from_json(r#"{ "lib.rs": { "1": "any lib.rs" }, "src/lib.rs": { "2": "src's" }, "main.rs": { "3": "main" } }"#).unwrap();
+
+        assert_eq!(lines(&annotations.for_file("crate/src/lib.rs")), [(1..=1, "any lib.rs"), (2..=2, "src's")]);
+        assert_eq!(lines(&annotations.for_file("lib.rs")), [(1..=1, "any lib.rs")]);
+        assert!(annotations.for_file("src/mylib.rs").is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        for (lines, error) in [
+            ("0", "Invalid annotation line '0'"),
+            ("x", "Invalid annotation line 'x'"),
+            ("3:", "Invalid annotation line '3:'"),
+            ("5:2", "Invalid annotation range '5:2', start is after end"),
+        ] {
+            let json = format!(r#"{{ "a.rs": {{ "{}": "note" }} }}"#, lines);
+            let error_chain = format!("{:#}", Annotations::from_json(&json).unwrap_err());
+            assert_eq!(error_chain, format!("Invalid annotations for 'a.rs': {}", error), "{}", lines);
+        }
+        assert!(Annotations::from_json(r#"{ "a.rs": { "1": 2 } }"#).is_err());
+    }
+
+    #[test]
+    fn files_parse_by_their_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("notes.JSON");
+        std::fs::write(&json, r#"{ "a.rs": { "1": "json" } }"#).unwrap();
+        let toml = dir.path().join("notes.txt");
+        std::fs::write(&toml, "[\"a.rs\"]\n1 = \"toml\"\n").unwrap();
+
+        assert_eq!(lines(&Annotations::from_file(json.to_str().unwrap()).unwrap().for_file("a.rs")), [(1..=1, "json")]);
+        assert_eq!(lines(&Annotations::from_file(toml.to_str().unwrap()).unwrap().for_file("a.rs")), [(1..=1, "toml")]);
+
+        let missing = dir.path().join("missing.toml");
+        let error = Annotations::from_file(missing.to_str().unwrap()).unwrap_err().to_string();
+        assert_eq!(error, format!("Failed to read annotations file '{}'", missing.display()));
+        std::fs::write(&toml, "[\"a.rs\"]\n1 = ").unwrap();
+        let error = Annotations::from_file(toml.to_str().unwrap()).unwrap_err().to_string();
+        assert_eq!(error, format!("Failed to parse annotations file '{}'", toml.display()));
+    }
+
+    #[test]
+    fn annotations_past_the_end_are_found() {
+        let annotations = Annotations::from_json(r#"{ "a.rs": { "2": "in", "2:4": "across", "5": "past" } }"#).unwrap().for_file("a.rs");
+        let past: Vec<&str> = out_of_range(&annotations, 3).map(|annotation| annotation.note.as_str()).collect();
+        assert_eq!(past, ["across", "past"]);
+        assert_eq!(out_of_range(&annotations, 5).count(), 0);
+    }
+
+    #[test]
+    fn notes_are_right_aligned_or_put_below_the_line() {
+        let ranges = [(Style::default(), "let x = 1;\n")];
+        let render = |width| render_line(&ranges, Color::BLACK, Some("note"), 2, width, None, &OutputProfile::DUMB);
+
+        assert_eq!(render(Some(20)), format!("let x = 1;{}note\n", " ".repeat(4)));
+        assert_eq!(render(Some(17)), "let x = 1;\n  `- note\n");
+        assert_eq!(render(None), "let x = 1;  note\n");
+        assert_eq!(render_line(&ranges, Color::BLACK, None, 2, Some(20), None, &OutputProfile::DUMB), "let x = 1;\n");
+        // a last line without an ending stays without one
+        assert_eq!(render_line(&[(Style::default(), "x")], Color::BLACK, Some("n"), 0, None, None, &OutputProfile::DUMB), "x  n");
+    }
+
+    #[test]
+    fn annotated_lines_get_the_highlight_background() {
+        let highlight = Color { r: 1, g: 2, b: 3, a: 0xff };
+        let profile = OutputProfile { colors: true, ..OutputProfile::DUMB };
+        let line = render_line(&[(Style::default(), "x\r\n")], highlight, Some("n"), 0, Some(8), None, &profile);
+        assert!(line.contains("\x1b[48;2;1;2;3m"), "{:?}", line);
+        assert!(line.ends_with("\x1b[2mn\x1b[0m\r\n"), "{:?}", line);
+    }
+}
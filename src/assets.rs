@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxDefinition, SyntaxSet, SyntaxSetBuilder};
+
+use crate::SyntaxHighlighter;
+
+/// Where a theme or syntax was loaded from, from lowest to highest precedence.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssetOrigin {
+    Builtin,
+    UserDir(PathBuf),
+    CliFile(PathBuf),
+}
+
+impl fmt::Display for AssetOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Builtin => write!(f, "builtin"),
+            Self::UserDir(path) => write!(f, "user: {}", path.display()),
+            Self::CliFile(path) => write!(f, "cli: {}", path.display()),
+        }
+    }
+}
+
+/// A theme or syntax that lost to another one with the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed {
+    pub name: String,
+    pub origin: AssetOrigin,
+    pub shadowed_by: AssetOrigin,
+}
+
+/// Extra places to load themes and syntaxes from, on top of the builtin ones.
+///
+/// When names collide the winner is, from highest precedence: theme files
+/// given on the command line, then the user directories, then the builtins.
+/// Directory entries are loaded in sorted order so the result never depends
+/// on iteration order.
+#[derive(Debug, Clone, Default)]
+pub struct AssetSources {
+    /// `.tmTheme` files and VS Code `.json` themes, named after their file stem
+    pub theme_files: Vec<PathBuf>,
+    /// Directory of `.tmTheme` and VS Code `.json` files
+    pub theme_dir: Option<PathBuf>,
+    /// Directory of `.sublime-syntax` files
+    pub syntax_dir: Option<PathBuf>,
+    /// Directory to cache parsed theme files in, keyed by their content
+    pub theme_cache_dir: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub(crate) struct Provenance {
+    pub(crate) themes: BTreeMap<String, AssetOrigin>,
+    pub(crate) syntaxes: BTreeMap<String, AssetOrigin>,
+    pub(crate) shadowed_themes: Vec<Shadowed>,
+    pub(crate) shadowed_syntaxes: Vec<Shadowed>,
+    pub(crate) cached_themes: Vec<PathBuf>,
+    /// What couldn't be used of each VS Code theme, by name
+    pub(crate) theme_warnings: BTreeMap<String, Vec<String>>,
+}
+
+impl Provenance {
+    fn builtin(theme_set: &ThemeSet, syntax_set: &SyntaxSet) -> Self {
+        Self {
+            themes: theme_set.themes.keys()
+                .map(|name| (name.clone(), AssetOrigin::Builtin))
+                .collect(),
+            syntaxes: syntax_set.syntaxes().iter()
+                .map(|syntax| (syntax.name.clone(), AssetOrigin::Builtin))
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
+
+fn record(origins: &mut BTreeMap<String, AssetOrigin>, shadowed: &mut Vec<Shadowed>, name: String, origin: AssetOrigin) {
+    if let Some(previous) = origins.insert(name.clone(), origin.clone()) {
+        shadowed.push(Shadowed { name, origin: previous, shadowed_by: origin });
+    }
+}
+
+fn sorted_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory '{}'", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+fn theme_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Loads a theme file, going through `cache_dir` when given. Returns whether
+/// the theme came from the cache.
+fn load_theme(path: &Path, cache_dir: Option<&Path>) -> Result<(Theme, bool)> {
+    let Some(cache_dir) = cache_dir else {
+        let theme = ThemeSet::get_theme(path)
+            .with_context(|| format!("Failed to load theme '{}'", path.display()))?;
+        return Ok((theme, false));
+    };
+
+    let source = fs::read(path)
+        .with_context(|| format!("Failed to load theme '{}'", path.display()))?;
+
+    // the version is part of the key since dumps are only readable by the same syntect
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    let cache_file = cache_dir.join(format!("{}-{:016x}.themedump", theme_name(path), hasher.finish()));
+
+    // an unreadable cache entry is treated as a miss and overwritten
+    if let Ok(theme) = syntect::dumps::from_dump_file(&cache_file) {
+        return Ok((theme, true));
+    }
+
+    let theme = ThemeSet::load_from_reader(&mut io::Cursor::new(source))
+        .with_context(|| format!("Failed to load theme '{}'", path.display()))?;
+
+    fs::create_dir_all(cache_dir)
+        .with_context(|| format!("Failed to create theme cache '{}'", cache_dir.display()))?;
+    syntect::dumps::dump_to_file(&theme, &cache_file)
+        .with_context(|| format!("Failed to write theme cache '{}'", cache_file.display()))?;
+
+    Ok((theme, false))
+}
+
+impl SyntaxHighlighter {
+    /// Loads the builtin assets plus everything from `sources`.
+    pub fn with_sources(sources: &AssetSources) -> Result<Self> {
+        Self::load(sources, SyntaxSet::load_defaults_newlines())
+    }
+
+    /// Loads the themes of `sources` but no syntaxes besides plain text,
+    /// for output that is never highlighted, so every file is detected as
+    /// plain text.
+    pub fn without_syntaxes(sources: &AssetSources) -> Result<Self> {
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add_plain_text_syntax();
+        Self::load(&AssetSources { syntax_dir: None, ..sources.clone() }, builder.build())
+    }
+
+    fn load(sources: &AssetSources, mut syntax_set: SyntaxSet) -> Result<Self> {
+        let mut theme_set = ThemeSet::load_defaults();
+        let mut provenance = Provenance::builtin(&theme_set, &syntax_set);
+
+        let user_themes = match &sources.theme_dir {
+            Some(dir) => {
+                let mut files = sorted_files(dir, "tmTheme")?;
+                files.extend(sorted_files(dir, "json")?);
+                files
+            }
+            None => Vec::new(),
+        };
+        let themes = user_themes.into_iter()
+            .map(|path| (AssetOrigin::UserDir(path.clone()), path))
+            .chain(sources.theme_files.iter().map(|path| (AssetOrigin::CliFile(path.clone()), path.clone())));
+
+        for (origin, path) in themes {
+            // JSON parses quickly enough that only .tmTheme files are cached
+            let name = theme_name(&path);
+            let (theme, cached) = match path.extension().is_some_and(|extension| extension == "json") {
+                true => {
+                    let (theme, warnings) = crate::theme_from_vscode_json(&path)?;
+                    provenance.theme_warnings.insert(name.clone(), warnings);
+                    (theme, false)
+                }
+                false => load_theme(&path, sources.theme_cache_dir.as_deref())?,
+            };
+
+            if cached {
+                provenance.cached_themes.push(path.clone());
+            }
+
+            theme_set.themes.insert(name.clone(), theme);
+            record(&mut provenance.themes, &mut provenance.shadowed_themes, name, origin);
+        }
+
+        if let Some(dir) = &sources.syntax_dir {
+            let files = sorted_files(dir, "sublime-syntax")?;
+
+            if !files.is_empty() {
+                let mut builder = syntax_set.into_builder();
+
+                for path in files {
+                    let source = fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read syntax '{}'", path.display()))?;
+                    let syntax = SyntaxDefinition::load_from_str(&source, true, None)
+                        .with_context(|| format!("Failed to load syntax '{}'", path.display()))?;
+
+                    record(&mut provenance.syntaxes, &mut provenance.shadowed_syntaxes, syntax.name.clone(), AssetOrigin::UserDir(path));
+                    builder.add(syntax);
+                }
+
+                // syntect's lookups prefer the syntax added last, matching the precedence above
+                syntax_set = builder.build();
+            }
+        }
+
+        Ok(Self {
+            syntax_set,
+            theme_set,
+            provenance,
+        })
+    }
+
+    /// Returns where the theme called `name` was loaded from.
+    pub fn theme_origin(&self, name: &str) -> Option<&AssetOrigin> {
+        self.provenance.themes.get(name)
+    }
+
+    /// Returns where the syntax called `name` was loaded from.
+    pub fn syntax_origin(&self, name: &str) -> Option<&AssetOrigin> {
+        self.provenance.syntaxes.get(name)
+    }
+
+    /// Themes that were replaced by a higher-precedence theme of the same name.
+    pub fn shadowed_themes(&self) -> &[Shadowed] {
+        &self.provenance.shadowed_themes
+    }
+
+    /// Theme files that were loaded from the theme cache instead of parsed.
+    pub fn cached_themes(&self) -> &[PathBuf] {
+        &self.provenance.cached_themes
+    }
+
+    /// What couldn't be used of the theme called `name`, when it was
+    /// imported from a VS Code theme.
+    pub fn theme_warnings(&self, name: &str) -> &[String] {
+        self.provenance.theme_warnings.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Syntaxes that were replaced by a higher-precedence syntax of the same name.
+    pub fn shadowed_syntaxes(&self) -> &[Shadowed] {
+        &self.provenance.shadowed_syntaxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_theme(dir: &Path, name: &str, background: &str) -> PathBuf {
+        let path = dir.join(format!("{}.tmTheme", name));
+        fs::write(&path, format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\"><dict>\
+             <key>name</key><string>{}</string><key>settings</key><array><dict><key>settings</key><dict>\
+             <key>background</key><string>{}</string><key>foreground</key><string>#ffffff</string>\
+             </dict></dict></array></dict></plist>\n",
+            name, background,
+        )).unwrap();
+        path
+    }
+
+    fn background(highlighter: &SyntaxHighlighter, theme: &str) -> Option<syntect::highlighting::Color> {
+        highlighter.theme_set.themes[theme].settings.background
+    }
+
+    #[test]
+    fn a_cli_theme_beats_the_user_dir_which_beats_the_builtin() {
+        let user = tempfile::tempdir().unwrap();
+        let cli = tempfile::tempdir().unwrap();
+        let user_theme = write_theme(user.path(), "InspiredGitHub", "#111111");
+        write_theme(user.path(), "Mine", "#222222");
+        let cli_theme = write_theme(cli.path(), "Mine", "#333333");
+
+        let highlighter = SyntaxHighlighter::with_sources(&AssetSources {
+            theme_files: vec![cli_theme.clone()],
+            theme_dir: Some(user.path().to_path_buf()),
+            ..AssetSources::default()
+        }).unwrap();
+
+        assert_eq!(highlighter.theme_origin("Mine"), Some(&AssetOrigin::CliFile(cli_theme.clone())));
+        assert_eq!(background(&highlighter, "Mine").map(|color| color.r), Some(0x33));
+        assert_eq!(highlighter.theme_origin("InspiredGitHub"), Some(&AssetOrigin::UserDir(user_theme.clone())));
+        assert_eq!(background(&highlighter, "InspiredGitHub").map(|color| color.r), Some(0x11));
+
+        assert_eq!(highlighter.shadowed_themes(), [
+            Shadowed { name: "InspiredGitHub".to_string(), origin: AssetOrigin::Builtin, shadowed_by: AssetOrigin::UserDir(user_theme) },
+            Shadowed { name: "Mine".to_string(), origin: AssetOrigin::UserDir(user.path().join("Mine.tmTheme")), shadowed_by: AssetOrigin::CliFile(cli_theme) },
+        ]);
+    }
+
+    #[test]
+    fn a_user_syntax_shadows_the_builtin_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rust.sublime-syntax");
+        fs::write(&path, "%YAML 1.2\n---\nname: Rust\nfile_extensions: [rs]\nscope: source.rust.mine\ncontexts:\n  main:\n    - match: fn\n      scope: keyword.mine\n").unwrap();
+
+        let highlighter = SyntaxHighlighter::with_sources(&AssetSources {
+            syntax_dir: Some(dir.path().to_path_buf()),
+            ..AssetSources::default()
+        }).unwrap();
+
+        assert_eq!(highlighter.syntax_origin("Rust"), Some(&AssetOrigin::UserDir(path.clone())));
+        assert_eq!(highlighter.shadowed_syntaxes(), [
+            Shadowed { name: "Rust".to_string(), origin: AssetOrigin::Builtin, shadowed_by: AssetOrigin::UserDir(path) },
+        ]);
+        let syntax = highlighter.syntax_set.find_syntax_by_extension("rs").unwrap();
+        assert_eq!(syntax.scope.build_string(), "source.rust.mine");
+    }
+
+    #[test]
+    fn directory_entries_load_in_sorted_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b", "c", "a"] {
+            fs::write(dir.path().join(format!("{}.tmTheme", name)), "").unwrap();
+        }
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let names: Vec<String> = sorted_files(dir.path(), "tmTheme").unwrap().iter().map(|path| theme_name(path)).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+}
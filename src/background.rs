@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use crate::multiplexer::Escape;
+use crate::{OutputProfile, Rgb};
+
+/// OSC 111, which puts the terminal's own background back.
+const RESET: &str = "\x1b]111\x07";
+
+/// Exit status after an interrupt, as a shell reports a SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// The terminal's background set to a theme's with OSC 11 for as long as
+/// this lives. Dropping it, or an interrupt while it lives, resets the
+/// background with OSC 111.
+pub struct TerminalBackground {
+    reset: String,
+}
+
+impl TerminalBackground {
+    /// Sets the background to `color`, returning None when `profile` may
+    /// not send the escape.
+    pub fn set(color: Rgb, profile: &OutputProfile) -> Result<Option<Self>> {
+        let Some((set, reset)) = sequences(color, profile) else {
+            return Ok(None);
+        };
+
+        let on_interrupt = reset.clone();
+        // the main thread may hold the stdout lock, so the terminal is written directly
+        ctrlc::set_handler(move || {
+            if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+                let _ = tty.write_all(on_interrupt.as_bytes());
+            }
+            std::process::exit(EXIT_INTERRUPTED);
+        })
+        .context("Failed to install the interrupt handler for --set-bg")?;
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(set.as_bytes())?;
+        stdout.flush()?;
+
+        Ok(Some(Self { reset }))
+    }
+}
+
+/// The escapes setting the background to `color` and resetting it, through
+/// `profile`'s passthrough, or None when `profile` may not send them.
+fn sequences(color: Rgb, profile: &OutputProfile) -> Option<(String, String)> {
+    if !profile.sends(Escape::Background) {
+        return None;
+    }
+
+    let set = format!("\x1b]11;rgb:{:02x}/{:02x}/{:02x}\x07", color.0, color.1, color.2);
+    Some((profile.passthrough(&set), profile.passthrough(RESET)))
+}
+
+impl Drop for TerminalBackground {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(self.reset.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Multiplexer;
+
+    #[test]
+    fn the_background_is_set_with_osc_11_and_reset_with_osc_111() {
+        let (set, reset) = sequences(Rgb(0x2b, 0x30, 0x3b), &OutputProfile::FULL).unwrap();
+        assert_eq!(set, "\x1b]11;rgb:2b/30/3b\x07");
+        assert_eq!(reset, "\x1b]111\x07");
+    }
+
+    #[test]
+    fn the_sequences_go_through_the_multiplexer() {
+        let profile = OutputProfile { multiplexer: Some(Multiplexer::Tmux), ..OutputProfile::FULL };
+        let (set, reset) = sequences(Rgb(0xff, 0xff, 0xff), &profile).unwrap();
+        assert_eq!(set, "\x1bPtmux;\x1b\x1b]11;rgb:ff/ff/ff\x07\x1b\\");
+        assert_eq!(reset, "\x1bPtmux;\x1b\x1b]111\x07\x1b\\");
+    }
+
+    #[test]
+    fn profiles_without_escapes_leave_the_background_alone() {
+        assert_eq!(sequences(Rgb(0, 0, 0), &OutputProfile::DUMB), None);
+        assert!(TerminalBackground::set(Rgb(0, 0, 0), &OutputProfile::DUMB).unwrap().is_none());
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::Result;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{read_input, HighlighterConfig, MemoryBudget, SyntaxHighlighter, Timings};
+
+/// How long a job whose input doesn't fit the memory budget waits before
+/// trying again.
+const BUDGET_RETRY: Duration = Duration::from_millis(2);
+
+/// What a [`BatchJob`] highlights.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchInput {
+    /// A file, read when its job runs
+    Path(PathBuf),
+    /// Text already in memory, with the name its syntax is detected from
+    Content { name: String, content: String },
+}
+
+/// One file of a [`SyntaxHighlighter::highlight_batch`].
+pub struct BatchJob {
+    pub input: BatchInput,
+    /// Highlights this job with its own config instead of the batch's
+    pub config: Option<HighlighterConfig>,
+}
+
+impl BatchJob {
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Self { input: BatchInput::Path(path.into()), config: None }
+    }
+
+    pub fn content(name: impl Into<String>, content: impl Into<String>) -> Self {
+        Self { input: BatchInput::Content { name: name.into(), content: content.into() }, config: None }
+    }
+
+    pub fn with_config(self, config: HighlighterConfig) -> Self {
+        Self { config: Some(config), ..self }
+    }
+
+    /// The path or name the job is known by.
+    pub fn name(&self) -> String {
+        match &self.input {
+            BatchInput::Path(path) => path.display().to_string(),
+            BatchInput::Content { name, .. } => name.clone(),
+        }
+    }
+}
+
+/// How a batch is run.
+#[derive(Default)]
+pub struct BatchOptions {
+    /// The config of every job without its own
+    pub config: HighlighterConfig,
+    /// Jobs run at once, the available parallelism when None
+    pub parallelism: Option<NonZeroUsize>,
+    /// Budget the inputs of the jobs running at once are held against. A
+    /// job whose input doesn't fit waits for others to give theirs back,
+    /// and fails when nothing holds any of the budget. Outputs are held
+    /// against the budget of their job's config, as for a single file.
+    pub memory_budget: MemoryBudget,
+}
+
+/// One job's highlighted text.
+#[derive(Debug, Clone)]
+pub struct HighlightOutput {
+    pub name: String,
+    pub text: String,
+    /// Name of the syntax it was highlighted as
+    pub syntax: String,
+    pub timings: Timings,
+}
+
+/// What a batch gave: each job's output or error, in the order of the jobs.
+#[derive(Debug)]
+pub struct BatchReport {
+    pub results: Vec<Result<HighlightOutput>>,
+    /// Reading, detection and highlighting times summed over the jobs that
+    /// succeeded; the slowest lines are left out, they are per file
+    pub timings: Timings,
+    /// Wall-clock time of the whole batch
+    pub elapsed: Duration,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|result| result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.succeeded()
+    }
+}
+
+impl SyntaxHighlighter {
+    /// Highlights `jobs` on a pool of threads, like
+    /// [`highlight_content_timed`](Self::highlight_content_timed) would one
+    /// after another. One job failing doesn't stop the others.
+    pub fn highlight_batch(&self, jobs: &[BatchJob], options: &BatchOptions) -> BatchReport {
+        self.highlight_batch_with_progress(jobs, options, |_, _| {})
+    }
+
+    /// Like [`highlight_batch`](Self::highlight_batch), calling `progress`
+    /// with the number of jobs done and of all jobs each time one finishes.
+    /// It is called on the calling thread, in the order jobs finish.
+    pub fn highlight_batch_with_progress(&self, jobs: &[BatchJob], options: &BatchOptions, mut progress: impl FnMut(usize, usize)) -> BatchReport {
+        let started = Instant::now();
+        let workers = options.parallelism
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+            .min(jobs.len())
+            .max(1);
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<Option<Result<HighlightOutput>>> = jobs.iter().map(|_| None).collect();
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let next = &next;
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    let result = self.run_job(job, options);
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            for (done, (index, result)) in receiver.into_iter().enumerate() {
+                results[index] = Some(result);
+                progress(done + 1, jobs.len());
+            }
+        });
+
+        let results: Vec<Result<HighlightOutput>> = results.into_iter()
+            .map(|result| result.expect("every job sends its result"))
+            .collect();
+        let mut timings = Timings::default();
+        for output in results.iter().flatten() {
+            timings.read += output.timings.read;
+            timings.detect += output.timings.detect;
+            timings.highlight += output.timings.highlight;
+        }
+
+        BatchReport { results, timings, elapsed: started.elapsed() }
+    }
+
+    fn run_job(&self, job: &BatchJob, options: &BatchOptions) -> Result<HighlightOutput> {
+        let config = job.config.as_ref().unwrap_or(&options.config);
+        let name = job.name();
+
+        let size = match &job.input {
+            BatchInput::Path(path) => std::fs::metadata(path).map_or(0, |metadata| metadata.len() as usize),
+            BatchInput::Content { content, .. } => content.len(),
+        };
+        let _reservation = loop {
+            if let Some(reservation) = options.memory_budget.try_reserve(size) {
+                break reservation;
+            }
+            // with nothing else holding any, waiting wouldn't make room
+            if options.memory_budget.used() == 0 {
+                break options.memory_budget.reserve(size, &format!("'{}'", name))?;
+            }
+            thread::sleep(BUDGET_RETRY);
+        };
+
+        self.highlight_job(job, &name, config)
+    }
+
+    fn highlight_job(&self, job: &BatchJob, name: &str, config: &HighlighterConfig) -> Result<HighlightOutput> {
+        let read_started = Instant::now();
+        let read;
+        let content = match &job.input {
+            BatchInput::Path(path) => {
+                read = read_input(&path.to_string_lossy())?;
+                read.content.as_str()
+            }
+            BatchInput::Content { content, .. } => content.as_str(),
+        };
+        let mut timings = Timings { read: read_started.elapsed(), ..Timings::default() };
+
+        let (text, syntax) = self.highlight_detected(content, name, config, Some(&mut timings))?;
+
+        Ok(HighlightOutput { name: name.to_string(), text, syntax: syntax.name.clone(), timings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    #[test]
+    fn a_tight_budget_runs_fewer_jobs_at_once_but_all_of_them() {
+        let jobs: Vec<BatchJob> = (0..8).map(|index| BatchJob::content(format!("{}.rs", index), "fn main() {}\n".repeat(10))).collect();
+        // room for one job's input at a time
+        let options = BatchOptions { parallelism: NonZeroUsize::new(4), memory_budget: MemoryBudget::new(200), ..BatchOptions::default() };
+
+        let report = HIGHLIGHTER.highlight_batch(&jobs, &options);
+        assert_eq!(report.succeeded(), 8);
+        assert_eq!(options.memory_budget.used(), 0);
+    }
+
+    #[test]
+    fn a_job_bigger_than_the_budget_fails_alone() {
+        let jobs = [BatchJob::content("big.rs", "x".repeat(100)), BatchJob::content("small.rs", "x")];
+        let options = BatchOptions { parallelism: NonZeroUsize::new(1), memory_budget: MemoryBudget::new(50), ..BatchOptions::default() };
+
+        let report = HIGHLIGHTER.highlight_batch(&jobs, &options);
+        assert!(report.results[0].is_err());
+        assert!(report.results[1].is_ok());
+    }
+
+    #[test]
+    fn results_come_in_job_order_with_their_own_config_and_errors() {
+        let numbered = HighlighterConfig { show_line_numbers: true, profile: crate::OutputProfile::DUMB, ..HighlighterConfig::default() };
+        let jobs: Vec<BatchJob> = (0..20)
+            .map(|index| BatchJob::content(format!("{}.py", index), format!("x = {}\n", index)))
+            .chain([
+                BatchJob::path("no/such/file.rs"),
+                BatchJob::content("numbered.json", "{}\n").with_config(numbered),
+            ])
+            .collect();
+        let mut progress = Vec::new();
+
+        let report = HIGHLIGHTER.highlight_batch_with_progress(&jobs, &BatchOptions::default(), |done, total| progress.push((done, total)));
+        assert_eq!((report.succeeded(), report.failed()), (21, 1));
+        for (index, result) in report.results[..20].iter().enumerate() {
+            let output = result.as_ref().unwrap();
+            assert_eq!((output.name.as_str(), output.syntax.as_str()), (format!("{}.py", index).as_str(), "Python"));
+            assert_eq!(output.text, HIGHLIGHTER.highlight_content(&format!("x = {}\n", index), &output.name, &HighlighterConfig::default()).unwrap());
+        }
+        assert!(report.results[20].as_ref().unwrap_err().to_string().contains("no/such/file.rs"));
+        let numbered = report.results[21].as_ref().unwrap();
+        assert_eq!((numbered.syntax.as_str(), numbered.text.as_str()), ("JSON", "   1 | {}\n"));
+        assert_eq!(progress, (1..=22).map(|done| (done, 22)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn files_are_read_when_their_job_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+
+        let report = HIGHLIGHTER.highlight_batch(&[BatchJob::path(&path)], &BatchOptions::default());
+        let output = report.results[0].as_ref().unwrap();
+        assert_eq!(output.syntax, "Rust");
+        assert_eq!(output.text, HIGHLIGHTER.highlight_content("fn main() {}\n", "main.rs", &HighlighterConfig::default()).unwrap());
+        assert_eq!(report.timings.read, output.timings.read);
+    }
+}
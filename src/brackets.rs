@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use std::ops::Range;
+use syntect::highlighting::{Color, Style, Theme};
+use syntect::parsing::{Scope, ScopeStack, ScopeStackOp};
+
+use crate::color::{distance, luminance};
+use crate::Rgb;
+
+const DARK_PALETTE: [&str; 6] = ["#ffd700", "#da70d6", "#179fff", "#3dd68c", "#ff8c42", "#ff6fa8"];
+const LIGHT_PALETTE: [&str; 6] = ["#b58900", "#8e44ad", "#0062cc", "#1e8449", "#c0392b", "#d65d0e"];
+const ERROR_COLOR: Color = Color { r: 0xff, g: 0x33, b: 0x33, a: 0xff };
+
+// squared rgb distance below which a palette entry is considered too close to the background
+const MIN_BACKGROUND_DISTANCE: u32 = 90 * 90;
+
+/// The `rainbow-palette` of the parsed config file, a list of colors such
+/// as `["#ff0000", "#00ff00"]`, None when it sets none.
+pub fn configured_palette(config: Option<&toml::Table>) -> Result<Option<Vec<Rgb>>> {
+    let Some(value) = config.and_then(|config| config.get("rainbow-palette")) else {
+        return Ok(None);
+    };
+    let Some(colors) = value.as_array() else {
+        bail!("'rainbow-palette' in the config file must be a list of colors such as [\"#ff0000\", \"#00ff00\"]");
+    };
+
+    colors.iter()
+        .map(|color| match color.as_str() {
+            Some(color) => color.trim().parse().map_err(anyhow::Error::msg).context("Invalid 'rainbow-palette' in the config file"),
+            None => bail!("'rainbow-palette' in the config file must be a list of colors such as [\"#ff0000\", \"#00ff00\"]"),
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+/// Recolors bracket pairs by nesting depth.
+///
+/// Depth is carried across lines, so one instance must be used per file.
+#[derive(Clone)]
+pub(crate) struct RainbowBrackets {
+    palette: Vec<Color>,
+    open: Vec<char>,
+}
+
+impl RainbowBrackets {
+    pub(crate) fn new(theme: &Theme, palette: Option<&[Rgb]>) -> Self {
+        let palette = match palette {
+            Some(colors) if !colors.is_empty() => colors.iter().copied().map(Color::from).collect(),
+            _ => Self::palette_for(theme),
+        };
+
+        Self {
+            palette,
+            open: Vec::new(),
+        }
+    }
+
+    fn palette_for(theme: &Theme) -> Vec<Color> {
+        let background = theme.settings.background.unwrap_or(Color::BLACK);
+        let candidates = if luminance(background) < 0.5 { DARK_PALETTE } else { LIGHT_PALETTE };
+
+        let all: Vec<Color> = candidates.iter()
+            .map(|hex| hex.parse().expect("builtin palette colors are valid"))
+            .collect();
+
+        let distinct: Vec<Color> = all.iter()
+            .copied()
+            .filter(|color| distance(*color, background) >= MIN_BACKGROUND_DISTANCE)
+            .collect();
+
+        if distinct.is_empty() { all } else { distinct }
+    }
+
+    /// Splits out every bracket outside of `literals` into its own range
+    /// with the foreground replaced by its depth color.
+    pub(crate) fn apply<'a>(&mut self, ranges: Vec<(Style, &'a str)>, literals: &[Range<usize>]) -> Vec<(Style, &'a str)> {
+        let mut result = Vec::with_capacity(ranges.len());
+        let mut offset = 0;
+
+        for (style, text) in ranges {
+            let mut start = 0;
+
+            for (i, c) in text.char_indices() {
+                let position = offset + i;
+                if literals.iter().any(|range| range.contains(&position)) {
+                    continue;
+                }
+
+                let Some(color) = self.color_for(c) else {
+                    continue;
+                };
+
+                if start < i {
+                    result.push((style, &text[start..i]));
+                }
+                let end = i + c.len_utf8();
+                result.push((Style { foreground: color, ..style }, &text[i..end]));
+                start = end;
+            }
+
+            if start < text.len() {
+                result.push((style, &text[start..]));
+            }
+            offset += text.len();
+        }
+
+        result
+    }
+
+    fn color_for(&mut self, c: char) -> Option<Color> {
+        match c {
+            '(' | '[' | '{' => {
+                let color = self.depth_color(self.open.len());
+                self.open.push(c);
+                Some(color)
+            }
+            ')' | ']' | '}' => {
+                let opener = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+
+                if self.open.last() == Some(&opener) {
+                    self.open.pop();
+                    Some(self.depth_color(self.open.len()))
+                } else {
+                    Some(ERROR_COLOR)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn depth_color(&self, depth: usize) -> Color {
+        self.palette[depth % self.palette.len()]
+    }
+}
+
+/// Returns the byte ranges of `line` that sit inside string or comment scopes.
+///
+/// `stack` is the scope stack at the start of the line and `ops` are the parser
+/// operations for it.
+pub(crate) fn literal_ranges(stack: &ScopeStack, ops: &[(usize, ScopeStackOp)], line_len: usize) -> Vec<Range<usize>> {
+    let string = Scope::new("string").expect("valid scope");
+    let comment = Scope::new("comment").expect("valid scope");
+
+    scope_ranges(stack, ops, line_len, &[string, comment])
+}
+
+/// Returns the byte ranges of `line` that sit inside any of `scopes`, which
+/// match the scopes they are a prefix of, as for [`literal_ranges`].
+pub(crate) fn scope_ranges(stack: &ScopeStack, ops: &[(usize, ScopeStackOp)], line_len: usize, scopes: &[Scope]) -> Vec<Range<usize>> {
+    let is_inside = |stack: &ScopeStack| {
+        stack.as_slice()
+            .iter()
+            .any(|item| scopes.iter().any(|scope| scope.is_prefix_of(*item)))
+    };
+
+    let mut stack = stack.clone();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    for (index, op) in ops {
+        if *index > start && is_inside(&stack) {
+            ranges.push(start..*index);
+        }
+        start = start.max(*index);
+        // a malformed op only means we might misplace a range
+        let _ = stack.apply(op);
+    }
+
+    if line_len > start && is_inside(&stack) {
+        ranges.push(start..line_len);
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::ThemeSettings;
+    use syntect::parsing::{ParseState, SyntaxSet};
+
+    const PALETTE: [Rgb; 2] = [Rgb(1, 1, 1), Rgb(2, 2, 2)];
+
+    fn theme(background: Color) -> Theme {
+        Theme { settings: ThemeSettings { background: Some(background), ..ThemeSettings::default() }, ..Theme::default() }
+    }
+
+    /// Each bracket of `line` with the red channel of its color, the rest
+    /// of the line left out.
+    fn colors(rainbow: &mut RainbowBrackets, line: &str, literals: &[Range<usize>]) -> Vec<(String, u8)> {
+        rainbow.apply(vec![(Style::default(), line)], literals).into_iter()
+            .filter(|(style, _)| *style != Style::default())
+            .map(|(style, text)| (text.to_string(), style.foreground.r))
+            .collect()
+    }
+
+    fn pairs(brackets: &[(&str, u8)]) -> Vec<(String, u8)> {
+        brackets.iter().map(|(text, red)| (text.to_string(), *red)).collect()
+    }
+
+    #[test]
+    fn brackets_take_the_color_of_their_depth() {
+        let mut rainbow = RainbowBrackets::new(&Theme::default(), Some(&PALETTE));
+        assert_eq!(
+            colors(&mut rainbow, "f(a[b{c}], (d))", &[]),
+            pairs(&[("(", 1), ("[", 2), ("{", 1), ("}", 1), ("]", 2), ("(", 2), (")", 2), (")", 1)]),
+        );
+    }
+
+    #[test]
+    fn depth_carries_across_lines() {
+        let mut rainbow = RainbowBrackets::new(&Theme::default(), Some(&PALETTE));
+        colors(&mut rainbow, "fn main() {", &[]);
+        assert_eq!(colors(&mut rainbow, "    f(x)", &[]), pairs(&[("(", 2), (")", 2)]));
+        assert_eq!(colors(&mut rainbow, "}", &[]), pairs(&[("}", 1)]));
+    }
+
+    #[test]
+    fn unmatched_closers_are_errors() {
+        let mut rainbow = RainbowBrackets::new(&Theme::default(), Some(&PALETTE));
+        assert_eq!(colors(&mut rainbow, "(]) }", &[]), pairs(&[("(", 1), ("]", 0xff), (")", 1), ("}", 0xff)]));
+    }
+
+    #[test]
+    fn brackets_in_literals_are_left_alone() {
+        let mut rainbow = RainbowBrackets::new(&Theme::default(), Some(&PALETTE));
+        let line = "f(\"(\") // )";
+        let literals = [2..5, 7..11];
+        assert_eq!(colors(&mut rainbow, line, &literals), pairs(&[("(", 1), (")", 1)]));
+    }
+
+    #[test]
+    fn the_default_palette_keeps_away_from_the_background() {
+        let dark = RainbowBrackets::new(&theme(Color::BLACK), None).palette;
+        assert_eq!(dark.len(), DARK_PALETTE.len());
+
+        let light = RainbowBrackets::new(&theme(Color { r: 0xfd, g: 0xf6, b: 0xe3, a: 0xff }), None).palette;
+        assert_eq!(light.len(), LIGHT_PALETTE.len());
+
+        // a dark yellow background takes the dark palette without its gold
+        let yellow = Color { r: 0xb5, g: 0x89, b: 0x10, a: 0xff };
+        let on_yellow = RainbowBrackets::palette_for(&theme(yellow));
+        assert!(on_yellow.iter().all(|color| distance(*color, yellow) >= MIN_BACKGROUND_DISTANCE));
+        assert!(!on_yellow.contains(&DARK_PALETTE[0].parse().unwrap()));
+        assert!(on_yellow.len() < DARK_PALETTE.len());
+
+        // an empty override falls back to the theme's palette
+        assert_eq!(RainbowBrackets::new(&theme(Color::BLACK), Some(&[])).palette, dark);
+    }
+
+    #[test]
+    fn the_palette_comes_from_the_config_file() {
+        let palette = |text: &str| configured_palette(Some(&text.parse().unwrap()));
+        assert_eq!(configured_palette(None).unwrap(), None);
+        assert_eq!(palette("cache = true").unwrap(), None);
+        assert_eq!(palette("rainbow-palette = [\"#ff0000\", \" #0f0\"]").unwrap(), Some(vec![Rgb(0xff, 0, 0), Rgb(0, 0xff, 0)]));
+
+        let error = |text: &str| format!("{:#}", palette(text).unwrap_err());
+        assert!(error("rainbow-palette = \"#ff0000\"").starts_with("'rainbow-palette' in the config file must be a list"));
+        assert!(error("rainbow-palette = [1]").starts_with("'rainbow-palette' in the config file must be a list"));
+        assert_eq!(error("rainbow-palette = [\"red\"]"), "Invalid 'rainbow-palette' in the config file: Invalid color 'red', expected #RRGGBB");
+    }
+
+    #[test]
+    fn literal_ranges_cover_strings_and_comments() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let syntax = syntax_set.find_syntax_by_extension("rs").unwrap();
+        let line = "let s = \"(\"; // )\n";
+        let ops = ParseState::new(syntax).parse_line(line, &syntax_set).unwrap();
+
+        let ranges = literal_ranges(&ScopeStack::new(), &ops, line.len());
+        let literals: Vec<&str> = ranges.iter().map(|range| &line[range.clone()]).collect();
+        assert_eq!(literals.concat().trim_end(), "\"(\"// )");
+        assert!(!ranges.iter().any(|range| range.contains(&line.find('=').unwrap())));
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::guard::parse_size;
+
+/// A shared ceiling on the bytes ccat buffers, such as file contents and
+/// highlighted output.
+///
+/// Clones share the same counter, so one budget can be handed to every
+/// component that buffers. The default budget is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBudget {
+    inner: Option<Arc<Shared>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Some(Arc::new(Shared { limit, used: AtomicUsize::new(0) })),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// The `max-memory` of the parsed config file, a size such as `"512M"`
+    /// or a number of bytes, None when it sets none.
+    pub fn configured_limit(config: Option<&toml::Table>) -> Result<Option<u64>> {
+        match config.and_then(|config| config.get("max-memory")) {
+            Some(toml::Value::String(size)) => parse_size(size)
+                .context("Invalid 'max-memory' in the config file")
+                .map(Some),
+            Some(toml::Value::Integer(size)) if *size >= 0 => Ok(Some(*size as u64)),
+            Some(_) => bail!("'max-memory' in the config file must be a size such as \"512M\""),
+            None => Ok(None),
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.inner.as_ref().map(|shared| shared.limit)
+    }
+
+    /// Bytes currently reserved across all clones.
+    pub fn used(&self) -> usize {
+        self.inner.as_ref().map_or(0, |shared| shared.used.load(Ordering::Relaxed))
+    }
+
+    /// Reserves `bytes`, or returns None when that would exceed the limit.
+    pub fn try_reserve(&self, bytes: usize) -> Option<Reservation> {
+        self.take(bytes).then(|| Reservation { budget: self.clone(), bytes })
+    }
+
+    /// Like [`try_reserve`](Self::try_reserve), failing with an error naming `what`.
+    pub fn reserve(&self, bytes: usize, what: &str) -> Result<Reservation> {
+        match self.try_reserve(bytes) {
+            Some(reservation) => Ok(reservation),
+            None => bail!(
+                "Buffering {} needs {} bytes, over the memory budget of {} bytes ({} in use)",
+                what, bytes, self.limit().unwrap_or(usize::MAX), self.used()
+            ),
+        }
+    }
+
+    fn take(&self, bytes: usize) -> bool {
+        let Some(shared) = &self.inner else {
+            return true;
+        };
+
+        shared.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|total| *total <= shared.limit)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        if let Some(shared) = &self.inner {
+            shared.used.fetch_sub(bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Bytes held against a [`MemoryBudget`], given back when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Grows the reservation to at least `bytes`, failing with an error naming
+    /// `what` when the budget can't cover it.
+    pub fn grow_to(&mut self, bytes: usize, what: &str) -> Result<()> {
+        if bytes <= self.bytes {
+            return Ok(());
+        }
+
+        let extra = bytes - self.bytes;
+        if !self.budget.take(extra) {
+            bail!(
+                "Buffering {} needs {} bytes, over the memory budget of {} bytes ({} in use)",
+                what, bytes, self.budget.limit().unwrap_or(usize::MAX), self.budget.used()
+            );
+        }
+        self.bytes = bytes;
+
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reservations_are_given_back_when_dropped() {
+        let budget = MemoryBudget::new(100);
+        let first = budget.try_reserve(60).unwrap();
+        assert!(budget.try_reserve(50).is_none());
+        assert_eq!(budget.used(), 60);
+
+        drop(first);
+        assert_eq!(budget.used(), 0);
+        assert!(budget.try_reserve(100).is_some());
+    }
+
+    #[test]
+    fn clones_share_one_counter() {
+        let budget = MemoryBudget::new(10);
+        let clone = budget.clone();
+        let _held = clone.try_reserve(8).unwrap();
+        assert_eq!(budget.used(), 8);
+        assert!(budget.reserve(3, "the rest").is_err());
+    }
+
+    #[test]
+    fn growing_past_the_limit_keeps_the_old_size() {
+        let budget = MemoryBudget::new(10);
+        let mut reservation = budget.reserve(4, "a").unwrap();
+        reservation.grow_to(8, "a").unwrap();
+        assert!(reservation.grow_to(11, "a").is_err());
+        assert_eq!(reservation.bytes(), 8);
+        // shrinking is a no-op
+        reservation.grow_to(2, "a").unwrap();
+        assert_eq!(budget.used(), 8);
+    }
+
+    #[test]
+    fn an_exceeded_budget_names_what_needed_it() {
+        let error = MemoryBudget::new(5).reserve(6, "'big.log'").unwrap_err();
+        assert_eq!(error.to_string(), "Buffering 'big.log' needs 6 bytes, over the memory budget of 5 bytes (0 in use)");
+    }
+
+    #[test]
+    fn the_unlimited_budget_counts_nothing() {
+        let budget = MemoryBudget::unlimited();
+        let _held = budget.reserve(usize::MAX, "everything").unwrap();
+        assert_eq!(budget.used(), 0);
+        assert_eq!(budget.limit(), None);
+    }
+
+    #[test]
+    fn threads_never_hold_more_than_the_limit_together() {
+        let budget = MemoryBudget::new(10);
+        let peak = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        if let Some(_reservation) = budget.try_reserve(3) {
+                            peak.fetch_max(budget.used(), Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(peak.load(Ordering::Relaxed) <= 10);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn the_config_file_sets_the_limit_as_a_size_or_bytes() {
+        let limit = |config: &str| MemoryBudget::configured_limit(Some(&toml::from_str(config).unwrap()));
+        assert_eq!(limit("max-memory = \"64K\"").unwrap(), Some(64 * 1024));
+        assert_eq!(limit("max-memory = 1000").unwrap(), Some(1000));
+        assert_eq!(limit("cache = true").unwrap(), None);
+        assert!(limit("max-memory = true").is_err());
+        assert!(limit("max-memory = -1").is_err());
+        assert_eq!(MemoryBudget::configured_limit(None).unwrap(), None);
+    }
+}
@@ -0,0 +1,18 @@
+use syntect::highlighting::Color;
+
+/// Relative brightness of a color in `0.0..=1.0`.
+pub(crate) fn luminance(color: Color) -> f32 {
+    (0.2126 * color.r as f32 + 0.7152 * color.g as f32 + 0.0722 * color.b as f32) / 255.0
+}
+
+/// Squared euclidean distance between two colors in RGB space.
+pub(crate) fn distance(a: Color, b: Color) -> u32 {
+    let channel = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    channel(a.r, b.r) + channel(a.g, b.g) + channel(a.b, b.b)
+}
+
+/// Mixes `weight` of `b` into `a`, with `weight` in `0.0..=1.0`.
+pub(crate) fn blend(a: Color, b: Color, weight: f32) -> Color {
+    let channel = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * weight).round() as u8;
+    Color { r: channel(a.r, b.r), g: channel(a.g, b.g), b: channel(a.b, b.b), a: a.a }
+}
@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::dirs::config_dir;
+use crate::error_snippet::toml_context;
+
+/// The path of the config file, `config.toml` in [`config_dir`].
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Reads and parses the TOML config file at `config_path`, None when it
+/// doesn't exist. The settings each take their keys from the table this
+/// returns, so the file is read and its errors reported only once.
+pub fn load_config(config_path: Option<&Path>) -> Result<Option<toml::Table>> {
+    let Some(path) = config_path.filter(|path| path.is_file()) else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+    let config = toml::from_str(&content)
+        .map_err(|error| toml_context(error.into(), format!("Failed to parse config file '{}'", path.display()), path, &content))?;
+
+    Ok(Some(config))
+}
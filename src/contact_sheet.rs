@@ -0,0 +1,165 @@
+use anyhow::Result;
+use serde::Serialize;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Style, Theme};
+use syntect::html::{append_highlighted_html_for_styled_line, highlighted_html_for_string, start_highlighted_html_snippet, IncludeBackground};
+use syntect::parsing::SyntaxReference;
+use syntect::util::LinesWithEndings;
+
+use crate::width::AmbiguousWidth;
+use crate::wrap::{self, WrapMode};
+use crate::{scope_override, HighlighterConfig, SyntaxHighlighter};
+
+/// How HTML output fits the lines wider than its
+/// [`HighlighterConfig::export_width`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportWrap {
+    /// Break them into rows, as `--wrap` does in a terminal
+    Wrap,
+    /// Cut them short, ending in `…`
+    Chop,
+    /// Leave them whole, for the `<pre>` to scroll
+    Scroll,
+}
+
+/// The snippet shown on a contact sheet when none is given.
+pub const PREVIEW_SNIPPET: &str = r#"use std::collections::HashMap;
+
+/// Counts how often each word occurs.
+pub fn word_counts(text: &str) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split_whitespace() {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn main() {
+    let counts = word_counts("the quick brown fox jumps over the lazy dog");
+    println!("{} distinct words, 'the' {} times", counts.len(), counts["the"]);
+}
+"#;
+
+/// A file name [`PREVIEW_SNIPPET`] is detected by.
+pub const PREVIEW_SNIPPET_PATH: &str = "preview.rs";
+
+impl SyntaxHighlighter {
+    /// Renders `snippet` under every available theme into one HTML page: a
+    /// grid of `<pre>` blocks, each captioned with its theme's name. The
+    /// syntax is picked from `file_path` and `config` as usual.
+    pub fn contact_sheet_html(&self, snippet: &str, file_path: &str, config: &HighlighterConfig) -> Result<String> {
+        let (syntax, _) = self.resolve_syntax(snippet, file_path, config)?;
+        let mut themes: Vec<_> = self.theme_set.themes.iter().collect();
+        themes.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut html = String::from(concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ccat themes</title>\n<style>\n",
+            "body { font-family: sans-serif; margin: 1em; }\n",
+            ".sheet { display: grid; grid-template-columns: repeat(auto-fill, minmax(32em, 1fr)); gap: 1em; }\n",
+            "figure { margin: 0; }\n",
+            "figcaption { font-weight: bold; margin-bottom: 0.3em; }\n",
+            "pre { margin: 0; padding: 0.6em; overflow-x: auto; font-size: 0.8em; }\n",
+            "</style>\n</head>\n<body>\n<div class=\"sheet\">\n",
+        ));
+
+        for (name, theme) in themes {
+            let theme = scope_override::apply(theme, &config.scope_overrides);
+            let block = self.html_block(snippet, syntax, theme.as_ref(), config)?;
+
+            html.push_str(&format!(
+                "<figure data-theme=\"{0}\">\n<figcaption>{0}</figcaption>\n{1}</figure>\n",
+                escape_html(name),
+                block,
+            ));
+        }
+
+        html.push_str("</div>\n</body>\n</html>\n");
+        Ok(html)
+    }
+
+    /// `snippet` as a `<pre>` block, each line fitted to the config's
+    /// `export_width` the way a terminal's would be, so the rows of a line
+    /// too long for it follow one another.
+    fn html_block(&self, snippet: &str, syntax: &SyntaxReference, theme: &Theme, config: &HighlighterConfig) -> Result<String> {
+        let Some(width) = config.export_width.filter(|_| config.export_wrap != ExportWrap::Scroll) else {
+            return Ok(highlighted_html_for_string(snippet, &self.syntax_set, syntax, theme)?);
+        };
+
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let (mut html, background) = start_highlighted_html_snippet(theme);
+        let ambiguous = config.profile.ambiguous_width;
+        for line in LinesWithEndings::from(snippet) {
+            let ranges = highlighter.highlight_line(line, &self.syntax_set)?;
+            let rows = match config.export_wrap {
+                ExportWrap::Chop => vec![chop(&ranges, width, ambiguous)],
+                _ => wrap::wrap_ranges(&ranges, width, config.wrap.unwrap_or(WrapMode::Word), ambiguous, false),
+            };
+            for row in rows {
+                append_highlighted_html_for_styled_line(&row, IncludeBackground::IfDifferent(background), &mut html)?;
+                if !row.last().is_some_and(|(_, text)| text.ends_with('\n')) {
+                    html.push('\n');
+                }
+            }
+        }
+        html.push_str("</pre>\n");
+        Ok(html)
+    }
+}
+
+/// The first `width` columns of a highlighted line, the last of them a `…`
+/// when the rest is cut.
+fn chop<'a>(ranges: &[(Style, &'a str)], width: usize, ambiguous: AmbiguousWidth) -> Vec<(Style, &'a str)> {
+    let mut rows = wrap::wrap_ranges(ranges, width, WrapMode::Character, ambiguous, false);
+    if rows.len() == 1 {
+        return rows.remove(0);
+    }
+
+    let mut row = wrap::wrap_ranges(ranges, width.saturating_sub(1).max(1), WrapMode::Character, ambiguous, false).remove(0);
+    let style = row.last().map_or(ranges[0].0, |(style, _)| *style);
+    row.push((style, "…"));
+    row
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    #[test]
+    fn every_theme_gets_one_captioned_block_in_name_order() {
+        let html = HIGHLIGHTER.contact_sheet_html(PREVIEW_SNIPPET, PREVIEW_SNIPPET_PATH, &HighlighterConfig::default()).unwrap();
+
+        let captions: Vec<&str> = html.split("<figcaption>").skip(1)
+            .map(|rest| rest.split_once("</figcaption>").unwrap().0)
+            .collect();
+        let mut names: Vec<&str> = HIGHLIGHTER.theme_set.themes.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(captions, names);
+        assert_eq!(html.matches("<pre ").count(), names.len());
+        assert!(html.contains("&amp;str"), "the snippet is escaped");
+    }
+
+    #[test]
+    fn chopped_lines_end_in_an_ellipsis_at_the_export_width() {
+        let config = HighlighterConfig { export_width: Some(12), export_wrap: ExportWrap::Chop, ..HighlighterConfig::default() };
+        let html = HIGHLIGHTER.contact_sheet_html("let value = \"a long string\";\nok\n", "a.rs", &config).unwrap();
+        let block = html.split("<pre ").nth(1).unwrap().split_once("</pre>").unwrap().0;
+        let text: String = block.split('<').filter_map(|tag| tag.split_once('>').map(|(_, text)| text)).collect();
+        assert_eq!(text, "\nlet value =…\nok\n");
+    }
+
+    #[test]
+    fn captions_are_escaped() {
+        assert_eq!(escape_html("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{bail, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The conversions a `custom:` format may use.
+const CONVERSIONS: &str = "%Y %y %C %m %d %e %j %H %I %M %S %p %a %A %b %B %h %u %w %s %F %T %R %D %c %Z %z %n %t %%";
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+/// Days of a common year before each month.
+const DAYS_BEFORE_MONTH: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// How timestamps are shown, as `--date-format` takes it. Machine readable
+/// output always has RFC 3339 in UTC instead, whatever the format.
+///
+/// There is no time zone database here, so every format shows the time in
+/// UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// How long ago, as `3 days ago`
+    Relative,
+    /// RFC 3339, as `2024-05-01T12:34:56Z`
+    Iso,
+    /// The date order and clock of `$LC_ALL`, `$LC_TIME` or `$LANG`, in numbers
+    Locale,
+    /// A strftime format, checked when parsed
+    Custom(String),
+}
+
+impl DateFormat {
+    /// Parses `relative`, `iso`, `locale` or `custom:<strftime>`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec {
+            "relative" => Ok(Self::Relative),
+            "iso" => Ok(Self::Iso),
+            "locale" => Ok(Self::Locale),
+            _ => match spec.strip_prefix("custom:") {
+                Some(format) => {
+                    check_strftime(format, spec)?;
+                    Ok(Self::Custom(format.to_string()))
+                }
+                None => bail!("Invalid date format '{}', expected relative, iso, locale or custom:<strftime>", spec),
+            },
+        }
+    }
+
+    /// `time` in this format, relative to `now`.
+    pub fn format(&self, time: SystemTime, now: SystemTime) -> String {
+        match self {
+            Self::Relative => relative(time, now),
+            Self::Iso => rfc3339(time),
+            Self::Locale => strftime(locale_format(&locale()), time),
+            Self::Custom(format) => strftime(format, time),
+        }
+    }
+}
+
+/// `time` as `2024-05-01T12:34:56Z`.
+pub fn rfc3339(time: SystemTime) -> String {
+    strftime("%Y-%m-%dT%H:%M:%SZ", time)
+}
+
+/// How far `time` is from `now` in its largest whole unit, from seconds to
+/// years, as `1 minute ago`, `3 days ago` or `in 2 hours`. Months are 30
+/// days and years 365.
+pub fn relative(time: SystemTime, now: SystemTime) -> String {
+    let (seconds, future) = match now.duration_since(time) {
+        Ok(age) => (age.as_secs(), false),
+        Err(error) => (error.duration().as_secs(), true),
+    };
+    let (count, unit) = match seconds {
+        0 => return "just now".to_string(),
+        1..=59 => (seconds, "second"),
+        60..=3_599 => (seconds / 60, "minute"),
+        3_600..=86_399 => (seconds / 3_600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+
+    match future {
+        true => format!("in {} {}{}", count, unit, plural),
+        false => format!("{} {}{} ago", count, unit, plural),
+    }
+}
+
+/// A point in time split into its calendar fields, in UTC.
+struct Civil {
+    seconds: i64,
+    year: i64,
+    month: i64,
+    day: i64,
+    /// 1-based day of the year
+    day_of_year: i64,
+    /// 0 for Monday
+    weekday: usize,
+    second_of_day: i64,
+}
+
+impl Civil {
+    fn new(time: SystemTime) -> Self {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(error) => -(error.duration().as_secs() as i64),
+        };
+        let (days, second_of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+        // Howard Hinnant's civil_from_days
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_march_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_march_year + 2) / 153;
+        let day = day_of_march_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let day_of_year = DAYS_BEFORE_MONTH[month as usize - 1] + day + i64::from(leap && month > 2);
+
+        Self {
+            seconds,
+            year,
+            month,
+            day,
+            day_of_year,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 3).rem_euclid(7) as usize,
+            second_of_day,
+        }
+    }
+}
+
+/// `time` in the strftime `format`, which [`check_strftime`] has accepted.
+fn strftime(format: &str, time: SystemTime) -> String {
+    let civil = Civil::new(time);
+    let (hour, minute, second) = (civil.second_of_day / 3600, civil.second_of_day % 3600 / 60, civil.second_of_day % 60);
+    let month = MONTHS[civil.month as usize - 1];
+    let weekday = WEEKDAYS[civil.weekday];
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            result.push(char);
+            continue;
+        }
+        let Some(conversion) = chars.next() else {
+            break;
+        };
+        let text = match conversion {
+            'Y' => format!("{:04}", civil.year),
+            'y' => format!("{:02}", civil.year.rem_euclid(100)),
+            'C' => format!("{:02}", civil.year.div_euclid(100)),
+            'm' => format!("{:02}", civil.month),
+            'd' => format!("{:02}", civil.day),
+            'e' => format!("{:2}", civil.day),
+            'j' => format!("{:03}", civil.day_of_year),
+            'H' => format!("{:02}", hour),
+            'I' => format!("{:02}", (hour + 11) % 12 + 1),
+            'M' => format!("{:02}", minute),
+            'S' => format!("{:02}", second),
+            'p' => if hour < 12 { "AM" } else { "PM" }.to_string(),
+            'a' => weekday[..3].to_string(),
+            'A' => weekday.to_string(),
+            'b' | 'h' => month[..3].to_string(),
+            'B' => month.to_string(),
+            'u' => (civil.weekday + 1).to_string(),
+            'w' => ((civil.weekday + 1) % 7).to_string(),
+            's' => civil.seconds.to_string(),
+            'F' => strftime("%Y-%m-%d", time),
+            'T' => strftime("%H:%M:%S", time),
+            'R' => strftime("%H:%M", time),
+            'D' => strftime("%m/%d/%y", time),
+            'c' => strftime("%a %b %e %H:%M:%S %Y", time),
+            'Z' => "UTC".to_string(),
+            'z' => "+0000".to_string(),
+            'n' => "\n".to_string(),
+            't' => "\t".to_string(),
+            _ => "%".to_string(),
+        };
+        result.push_str(&text);
+    }
+    result
+}
+
+/// Fails on a conversion [`strftime`] doesn't know, naming the ones it does.
+fn check_strftime(format: &str, spec: &str) -> Result<()> {
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some(conversion) if CONVERSIONS.split(' ').any(|known| known.ends_with(conversion)) => {}
+            Some(conversion) => bail!("Unknown conversion '%{}' in date format '{}', expected one of {}", conversion, spec, CONVERSIONS),
+            None => bail!("Date format '{}' ends in a lone '%', write '%%' for a percent sign", spec),
+        }
+    }
+    Ok(())
+}
+
+/// The locale timestamps are shown for, from the environment.
+fn locale() -> String {
+    ["LC_ALL", "LC_TIME", "LANG"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// The strftime format for `locale`, such as `en_US.UTF-8`: month first
+/// with a 12-hour clock in the US, year first where dates are written so,
+/// with dots where they are, and day first elsewhere. Month names aren't
+/// translated, so dates are in numbers.
+fn locale_format(locale: &str) -> &'static str {
+    let name = locale.split(['.', '@']).next().unwrap_or_default();
+    let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+
+    match (language, territory) {
+        ("" | "C" | "POSIX", _) => "%a %b %e %H:%M:%S %Y",
+        (_, "US") => "%m/%d/%Y %I:%M %p",
+        ("zh" | "ja" | "ko" | "hu" | "lt" | "mn", _) => "%Y/%m/%d %H:%M",
+        ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "nn" | "da" | "tr" | "uk" | "ro" | "hr" | "sl", _) => "%d.%m.%Y %H:%M",
+        _ => "%d/%m/%Y %H:%M",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 2024-02-29T13:05:09Z, a Thursday.
+    fn leap_day() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_709_211_909)
+    }
+
+    fn ago(seconds: u64) -> String {
+        relative(leap_day() - Duration::from_secs(seconds), leap_day())
+    }
+
+    #[test]
+    fn relative_times_change_unit_at_each_boundary() {
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(1), "1 second ago");
+        assert_eq!(ago(59), "59 seconds ago");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3_599), "59 minutes ago");
+        assert_eq!(ago(3_600), "1 hour ago");
+        assert_eq!(ago(86_399), "23 hours ago");
+        assert_eq!(ago(86_400), "1 day ago");
+        assert_eq!(ago(2_591_999), "29 days ago");
+        assert_eq!(ago(2_592_000), "1 month ago");
+        assert_eq!(ago(31_535_999), "12 months ago");
+        assert_eq!(ago(31_536_000), "1 year ago");
+        assert_eq!(ago(10 * 31_536_000), "10 years ago");
+        assert_eq!(relative(leap_day() + Duration::from_secs(7_200), leap_day()), "in 2 hours");
+    }
+
+    #[test]
+    fn iso_times_are_rfc3339_in_utc() {
+        assert_eq!(rfc3339(leap_day()), "2024-02-29T13:05:09Z");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH - Duration::from_secs(1)), "1969-12-31T23:59:59Z");
+        assert_eq!(DateFormat::Iso.format(leap_day(), SystemTime::now()), "2024-02-29T13:05:09Z");
+    }
+
+    #[test]
+    fn strftime_conversions() {
+        assert_eq!(strftime("%A %a %B %b %e %j %u %w", leap_day()), "Thursday Thu February Feb 29 060 4 4");
+        assert_eq!(strftime("%I:%M %p %y %C %%", leap_day()), "01:05 PM 24 20 %");
+        assert_eq!(strftime("%F %T %R %D", leap_day()), "2024-02-29 13:05:09 13:05 02/29/24");
+        assert_eq!(strftime("%c %Z %z %s", leap_day()), "Thu Feb 29 13:05:09 2024 UTC +0000 1709211909");
+        assert_eq!(strftime("%j", UNIX_EPOCH + Duration::from_secs(365 * 86_400)), "001");
+    }
+
+    #[test]
+    fn custom_formats_are_checked_when_parsed() {
+        assert_eq!(DateFormat::parse("custom:%Y-%m-%d").unwrap(), DateFormat::Custom("%Y-%m-%d".to_string()));
+        assert_eq!(DateFormat::parse("custom:%d %B").unwrap().format(leap_day(), leap_day()), "29 February");
+
+        let error = DateFormat::parse("custom:%Y %Q").unwrap_err().to_string();
+        assert!(error.starts_with("Unknown conversion '%Q' in date format 'custom:%Y %Q', expected one of %Y %y"), "{}", error);
+        let error = DateFormat::parse("custom:100%").unwrap_err().to_string();
+        assert!(error.contains("ends in a lone '%'"), "{}", error);
+        let error = DateFormat::parse("yesterday").unwrap_err().to_string();
+        assert!(error.starts_with("Invalid date format 'yesterday'"), "{}", error);
+    }
+
+    #[test]
+    fn locales_pick_their_date_order() {
+        assert_eq!(locale_format("en_US.UTF-8"), "%m/%d/%Y %I:%M %p");
+        assert_eq!(locale_format("de_DE.UTF-8@euro"), "%d.%m.%Y %H:%M");
+        assert_eq!(locale_format("ja_JP"), "%Y/%m/%d %H:%M");
+        assert_eq!(locale_format("en_GB.UTF-8"), "%d/%m/%Y %H:%M");
+        assert_eq!(locale_format("C"), "%a %b %e %H:%M:%S %Y");
+        assert_eq!(locale_format(""), "%a %b %e %H:%M:%S %Y");
+    }
+}
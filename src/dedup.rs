@@ -0,0 +1,127 @@
+use regex::Regex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+/// A timestamp at the start of a log line: ISO 8601, syslog style or a bare
+/// time of day, optionally in brackets.
+static LEADING_TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| Regex::new(concat!(
+    r"^\s*\[?(?:",
+    r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?",
+    r"|[A-Z][a-z]{2} +\d{1,2} \d{2}:\d{2}:\d{2}",
+    r"|\d{2}:\d{2}:\d{2}(?:[.,]\d+)?",
+    r")\]?\s*",
+)).expect("timestamp pattern is valid"));
+
+/// Collapses runs of identical consecutive lines into their first line.
+///
+/// Clones share a count of the lines collapsed away, so one `Dedup` can be
+/// handed to the config of every file of a run and read back at the end.
+#[derive(Debug, Clone, Default)]
+pub struct Dedup {
+    /// Compare lines without a leading timestamp
+    pub ignore_timestamps: bool,
+    collapsed: Arc<AtomicUsize>,
+}
+
+impl Dedup {
+    pub fn new(ignore_timestamps: bool) -> Self {
+        Self { ignore_timestamps, collapsed: Arc::default() }
+    }
+
+    /// The same settings with a count of its own, for output that shouldn't
+    /// add to this one's.
+    pub fn untracked(&self) -> Self {
+        Self::new(self.ignore_timestamps)
+    }
+
+    /// Lines collapsed into the line before them, across all clones.
+    pub fn collapsed(&self) -> usize {
+        self.collapsed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record(&self, run: &Run) {
+        self.collapsed.fetch_add(run.count - 1, Ordering::Relaxed);
+    }
+
+    /// What two lines are compared by: the raw text without its line ending
+    /// and, if enabled, its leading timestamp.
+    pub(crate) fn key<'a>(&self, line: &'a str) -> &'a str {
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        match LEADING_TIMESTAMP.find(line).filter(|_| self.ignore_timestamps) {
+            Some(timestamp) => &line[timestamp.end()..],
+            None => line,
+        }
+    }
+}
+
+/// A run of lines shown as one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    /// Number of the run's last line
+    pub last: usize,
+    /// How many lines the run has, the shown one included
+    pub count: usize,
+    pub kind: RunKind,
+}
+
+/// What a [`Run`] collapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunKind {
+    /// Identical lines, shown as the first of them
+    Identical,
+    /// Blank lines past `collapse_blank`, shown as the last blank line kept
+    Blank,
+    /// The body of an outline entry, shown as the entry
+    Folded,
+    /// A region between fold markers, shown as its first line
+    Region,
+}
+
+/// Formats `count` with thousands separators, as in `4,812`.
+pub(crate) fn group_digits(count: usize) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_drop_the_line_ending_and_optionally_the_timestamp() {
+        let plain = Dedup::new(false);
+        assert_eq!(plain.key("2024-05-01T10:00:00Z retry\r\n"), "2024-05-01T10:00:00Z retry");
+
+        let ignoring = Dedup::new(true);
+        for line in ["2024-05-01T10:00:00.123+02:00 retry\n", "[2024-05-01 10:00:01,5] retry", "May  1 10:00:02 retry", "10:00:03 retry"] {
+            assert_eq!(ignoring.key(line), "retry", "{:?}", line);
+        }
+        // only a leading timestamp is dropped
+        assert_eq!(ignoring.key("retry at 10:00:03"), "retry at 10:00:03");
+    }
+
+    #[test]
+    fn counts_are_shared_by_clones_but_not_untracked_copies() {
+        let dedup = Dedup::new(false);
+        dedup.clone().record(&Run { last: 4, count: 3, kind: RunKind::Identical });
+        dedup.untracked().record(&Run { last: 9, count: 5, kind: RunKind::Identical });
+        assert_eq!(dedup.collapsed(), 2);
+    }
+
+    #[test]
+    fn digits_are_grouped_in_threes() {
+        assert_eq!(group_digits(7), "7");
+        assert_eq!(group_digits(4812), "4,812");
+        assert_eq!(group_digits(1_000_000), "1,000,000");
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// Which signal `detect_syntax` uses to pick a syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectStrategy {
+    /// Extension, then first line, then plain text
+    #[default]
+    Auto,
+    /// File extension only
+    Extension,
+    /// The `#!` interpreter only
+    Shebang,
+    /// syntect's first-line patterns only
+    FirstLine,
+    /// Shebang, then first-line patterns, ignoring the file name
+    Content,
+}
+
+impl DetectStrategy {
+    /// The steps this strategy tries, in order.
+    pub fn steps(self) -> &'static [DetectStep] {
+        match self {
+            Self::Auto => &[DetectStep::Extension, DetectStep::FirstLine],
+            Self::Extension => &[DetectStep::Extension],
+            Self::Shebang => &[DetectStep::Shebang],
+            Self::FirstLine => &[DetectStep::FirstLine],
+            Self::Content => &[DetectStep::Content],
+        }
+    }
+}
+
+/// One way of recognizing a file's syntax; `detect_syntax` tries them in
+/// order and takes the first that matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectStep {
+    /// ccat's extension mappings, then syntect's extension list
+    Extension,
+    /// The whole file name, for files like `Makefile` or `Gemfile`
+    Filename,
+    /// The `#!` interpreter
+    Shebang,
+    /// syntect's first-line patterns
+    FirstLine,
+    /// The shebang, then the first-line patterns
+    Content,
+}
+
+impl DetectStep {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "extension" => Ok(Self::Extension),
+            "filename" => Ok(Self::Filename),
+            "shebang" => Ok(Self::Shebang),
+            "firstline" | "first-line" => Ok(Self::FirstLine),
+            "content" => Ok(Self::Content),
+            _ => bail!("Invalid detection step '{}', expected extension, filename, shebang, firstline or content", name.trim()),
+        }
+    }
+
+    /// Parses a comma-separated order such as `shebang,extension`, rejecting
+    /// unknown and repeated steps.
+    pub fn parse_order(spec: &str) -> Result<Vec<Self>> {
+        let mut steps = Vec::new();
+
+        for name in spec.split(',') {
+            let step = Self::parse(name)?;
+            if steps.contains(&step) {
+                bail!("Detection step '{}' is given more than once in '{}'", name.trim(), spec);
+            }
+            steps.push(step);
+        }
+
+        Ok(steps)
+    }
+}
+
+/// How a file's syntax was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionMethod {
+    /// Set explicitly by the caller
+    Forced,
+    /// ccat's own extension mappings
+    Mapping,
+    /// syntect's file extension list
+    Extension,
+    /// syntect's file extension list, matched against the whole file name
+    Filename,
+    /// The `#!` interpreter
+    Shebang,
+    /// syntect's first-line patterns
+    FirstLine,
+    /// Nothing matched
+    PlainText,
+}
+
+impl DetectionMethod {
+    /// A short lowercase name, e.g. `first-line`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Forced => "forced",
+            Self::Mapping => "mapping",
+            Self::Extension => "extension",
+            Self::Filename => "filename",
+            Self::Shebang => "shebang",
+            Self::FirstLine => "first-line",
+            Self::PlainText => "plain-text",
+        }
+    }
+}
+
+/// The result of detecting a file's syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub syntax: String,
+    pub method: DetectionMethod,
+}
+
+/// Returns the interpreter named by a `#!` first line, without its directory
+/// or trailing version number (`#!/usr/bin/env python3` gives `python`).
+pub(crate) fn shebang_interpreter(content: &str) -> Option<&str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?;
+
+    if program.rsplit('/').next() == Some("env") {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+
+    let name = program.rsplit('/').next()?;
+    let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    (!name.is_empty()).then_some(name)
+}
+
+/// Maps interpreters whose name is not a syntect syntax name or extension.
+pub(crate) fn interpreter_syntax(interpreter: &str) -> &str {
+    match interpreter {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" => "Bourne Again Shell (bash)",
+        "node" | "nodejs" | "deno" => "JavaScript",
+        "python" | "pypy" => "Python",
+        "ruby" => "Ruby",
+        "perl" => "Perl",
+        "php" => "PHP",
+        "lua" | "luajit" => "Lua",
+        "Rscript" => "R",
+        "tclsh" | "wish" => "Tcl",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebang_interpreters_drop_the_directory_and_version() {
+        assert_eq!(shebang_interpreter("#!/bin/bash\n"), Some("bash"));
+        assert_eq!(shebang_interpreter("#!/usr/bin/env -S python3 -u\n"), Some("python"));
+        assert_eq!(shebang_interpreter("#!/usr/bin/ruby2.7\n"), Some("ruby"));
+        assert_eq!(shebang_interpreter("echo no shebang\n"), None);
+    }
+
+    #[test]
+    fn detection_orders_reject_unknown_and_repeated_steps() {
+        assert_eq!(DetectStep::parse_order("shebang, Extension").unwrap(), [DetectStep::Shebang, DetectStep::Extension]);
+        assert!(DetectStep::parse_order("shebang,magic").is_err());
+        assert!(DetectStep::parse_order("shebang,extension,shebang").is_err());
+    }
+}
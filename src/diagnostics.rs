@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use syntect::highlighting::{Color, FontStyle, Style};
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The letter shown in the gutter.
+    pub fn badge(self) -> char {
+        match self {
+            Self::Note => 'N',
+            Self::Warning => 'W',
+            Self::Error => 'E',
+        }
+    }
+
+    /// The word a message is prefixed with, as compilers print it.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+
+    pub(crate) fn color(self) -> Color {
+        match self {
+            Self::Note => Color { r: 0x61, g: 0xaf, b: 0xef, a: 0xff },
+            Self::Warning => Color { r: 0xe5, g: 0xc0, b: 0x7b, a: 0xff },
+            Self::Error => Color { r: 0xf0, g: 0x47, b: 0x47, a: 0xff },
+        }
+    }
+}
+
+/// Where in a file a diagnostic points. Lines and columns are 1-based,
+/// columns count characters and `column_end` is exclusive, as rustc reports them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticSpan {
+    pub file: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    /// Whether this is the location the message is about, rather than context
+    pub primary: bool,
+}
+
+/// A compiler or linter message about some source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub spans: Vec<DiagnosticSpan>,
+}
+
+impl Diagnostic {
+    /// The line the message is shown under: its primary span's first line.
+    pub fn line(&self) -> Option<usize> {
+        self.spans.iter()
+            .find(|span| span.primary)
+            .or_else(|| self.spans.first())
+            .map(|span| span.line_start)
+    }
+}
+
+/// A tool's machine-readable diagnostics output format.
+pub trait DiagnosticFormat {
+    /// Parses the tool's output into diagnostics.
+    fn parse(&self, output: &str) -> Result<Vec<Diagnostic>>;
+}
+
+/// rustc's `--error-format=json`, one message per line, as well as cargo's
+/// `--message-format=json`, which wraps those in `compiler-message` records.
+/// Other lines are skipped.
+pub struct RustcJson;
+
+#[derive(Deserialize)]
+struct CargoRecord {
+    reason: Option<String>,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    level: String,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    column_end: usize,
+    #[serde(default)]
+    is_primary: bool,
+}
+
+impl DiagnosticFormat for RustcJson {
+    fn parse(&self, output: &str) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+
+        for line in output.lines().filter(|line| line.trim_start().starts_with('{')) {
+            let message = match serde_json::from_str::<CargoRecord>(line) {
+                Ok(CargoRecord { reason: Some(reason), message }) => {
+                    match message.filter(|_| reason == "compiler-message") {
+                        Some(message) => message,
+                        None => continue,
+                    }
+                }
+                _ => match serde_json::from_str::<RustcMessage>(line) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                },
+            };
+
+            let severity = match message.level.as_str() {
+                level if level.starts_with("error") => Severity::Error,
+                "warning" => Severity::Warning,
+                _ => Severity::Note,
+            };
+            diagnostics.push(Diagnostic {
+                severity,
+                message: message.message,
+                spans: message.spans.into_iter()
+                    .map(|span| DiagnosticSpan {
+                        file: span.file_name,
+                        line_start: span.line_start,
+                        line_end: span.line_end,
+                        column_start: span.column_start,
+                        column_end: span.column_end,
+                        primary: span.is_primary,
+                    })
+                    .collect(),
+            });
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+/// Diagnostics for a set of files, as loaded from a tool's output.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Loads the diagnostics in `path`, written by a tool in `format`.
+    pub fn from_file(path: &str, format: &dyn DiagnosticFormat) -> Result<Self> {
+        let output = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read diagnostics file '{}'", path))?;
+
+        let diagnostics = format.parse(&output)
+            .with_context(|| format!("Failed to parse diagnostics file '{}'", path))?;
+        Ok(Self { diagnostics })
+    }
+
+    /// Returns the diagnostics pointing into `file_path`, keeping only their
+    /// spans in it. A span's file matches when it is equal to the path or
+    /// names its trailing components, so `src/lib.rs` matches `./src/lib.rs`.
+    pub fn for_file(&self, file_path: &str) -> Vec<Diagnostic> {
+        let path = Path::new(file_path);
+
+        self.diagnostics.iter()
+            .filter_map(|diagnostic| {
+                let spans: Vec<DiagnosticSpan> = diagnostic.spans.iter()
+                    .filter(|span| path.ends_with(Path::new(span.file.trim_start_matches("./"))))
+                    .cloned()
+                    .collect();
+                (!spans.is_empty()).then(|| Diagnostic { spans, ..diagnostic.clone() })
+            })
+            .collect()
+    }
+}
+
+/// The most serious diagnostic touching line `number`, if any.
+pub(crate) fn line_severity(diagnostics: &[Diagnostic], number: usize) -> Option<Severity> {
+    diagnostics.iter()
+        .filter(|diagnostic| diagnostic.spans.iter().any(|span| (span.line_start..=span.line_end).contains(&number)))
+        .map(|diagnostic| diagnostic.severity)
+        .max()
+}
+
+/// Marks the text of line `number` that diagnostics point at with the
+/// underline font style, which is cleared everywhere else first. `ranges`
+/// are consecutive pieces of `line`.
+pub(crate) fn mark_spans<'a>(ranges: Vec<(Style, &'a str)>, line: &str, number: usize, diagnostics: &[Diagnostic]) -> Vec<(Style, &'a str)> {
+    let text = line.trim_end_matches(['\n', '\r']);
+    let char_count = text.chars().count();
+
+    // the byte ranges of `line` covered by a span
+    let covered: Vec<(usize, usize)> = diagnostics.iter()
+        .flat_map(|diagnostic| &diagnostic.spans)
+        .filter(|span| (span.line_start..=span.line_end).contains(&number))
+        .filter_map(|span| {
+            let start = if span.line_start == number { span.column_start.saturating_sub(1) } else { 0 };
+            let end = if span.line_end == number { span.column_end.saturating_sub(1) } else { char_count };
+            let (start, end) = (char_byte(text, start.min(char_count)), char_byte(text, end.min(char_count)));
+            (start < end).then_some((start, end))
+        })
+        .collect();
+
+    underline_spans(ranges, &covered)
+}
+
+/// Gives the `covered` byte ranges of the line `ranges` are consecutive
+/// pieces of the underline font style, clearing it everywhere else.
+pub(crate) fn underline_spans<'a>(ranges: Vec<(Style, &'a str)>, covered: &[(usize, usize)]) -> Vec<(Style, &'a str)> {
+    let mut marked = Vec::with_capacity(ranges.len());
+    let mut offset = 0;
+
+    for (style, piece) in ranges {
+        let style = Style { font_style: style.font_style - FontStyle::UNDERLINE, ..style };
+        let piece_start = offset;
+        offset += piece.len();
+
+        // cut the piece at every span boundary inside it
+        let mut cuts: Vec<usize> = covered.iter()
+            .flat_map(|&(start, end)| [start, end])
+            .filter(|cut| (piece_start + 1..piece_start + piece.len()).contains(cut))
+            .map(|cut| cut - piece_start)
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        let mut last = 0;
+        for cut in cuts.into_iter().chain([piece.len()]) {
+            if cut == last || !piece.is_char_boundary(cut) {
+                continue;
+            }
+            let start = piece_start + last;
+            let underlined = covered.iter().any(|&(span_start, span_end)| (span_start..span_end).contains(&start));
+            let font_style = if underlined { style.font_style | FontStyle::UNDERLINE } else { style.font_style };
+            marked.push((Style { font_style, ..style }, &piece[last..cut]));
+            last = cut;
+        }
+    }
+
+    marked
+}
+
+/// Byte offset of the character at index `chars` of `text`.
+fn char_byte(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map_or(text.len(), |(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(file: &str, line_start: usize, column_start: usize, line_end: usize, column_end: usize) -> DiagnosticSpan {
+        DiagnosticSpan { file: file.to_string(), line_start, line_end, column_start, column_end, primary: true }
+    }
+
+    fn error(spans: Vec<DiagnosticSpan>) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, message: "mismatched types".to_string(), spans }
+    }
+
+    #[test]
+    fn rustc_json_reads_cargo_records_and_bare_messages() {
+        let output = concat!(
+            "{\"reason\":\"compiler-artifact\",\"package_id\":\"x\"}\n",
+            "{\"reason\":\"compiler-message\",\"message\":{\"message\":\"unused variable\",\"level\":\"warning\",\"spans\":[",
+            "{\"file_name\":\"src/lib.rs\",\"line_start\":3,\"line_end\":3,\"column_start\":9,\"column_end\":12,\"is_primary\":true}]}}\n",
+            "   Compiling ccat v0.1.0\n",
+            "{\"message\":\"aborting\",\"level\":\"error: internal compiler error\",\"spans\":[]}\n",
+            "{\"message\":\"see here\",\"level\":\"help\"}\n",
+            "{not json\n",
+        );
+        let diagnostics = RustcJson.parse(output).unwrap();
+
+        assert_eq!(diagnostics, vec![
+            Diagnostic { severity: Severity::Warning, message: "unused variable".to_string(), spans: vec![span("src/lib.rs", 3, 9, 3, 12)] },
+            Diagnostic { severity: Severity::Error, message: "aborting".to_string(), spans: vec![] },
+            Diagnostic { severity: Severity::Note, message: "see here".to_string(), spans: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn a_diagnostic_is_shown_under_its_primary_span() {
+        let mut context = span("src/lib.rs", 2, 1, 2, 4);
+        context.primary = false;
+        assert_eq!(error(vec![context.clone(), span("src/lib.rs", 7, 1, 9, 2)]).line(), Some(7));
+        assert_eq!(error(vec![context]).line(), Some(2));
+        assert_eq!(error(vec![]).line(), None);
+    }
+
+    #[test]
+    fn only_the_spans_in_the_file_are_kept() {
+        let diagnostics = Diagnostics { diagnostics: vec![
+            error(vec![span("src/lib.rs", 1, 1, 1, 2), span("src/main.rs", 4, 1, 4, 2)]),
+            error(vec![span("./src/main.rs", 5, 1, 5, 2)]),
+            error(vec![span("build.rs", 6, 1, 6, 2)]),
+        ] };
+
+        let for_main = diagnostics.for_file("crate/src/main.rs");
+        assert_eq!(for_main.len(), 2);
+        assert_eq!(for_main[0].spans, vec![span("src/main.rs", 4, 1, 4, 2)]);
+        assert_eq!(for_main[1].spans, vec![span("./src/main.rs", 5, 1, 5, 2)]);
+        assert!(diagnostics.for_file("src/other.rs").is_empty());
+    }
+
+    #[test]
+    fn the_most_serious_diagnostic_on_a_line_wins() {
+        let warning = Diagnostic { severity: Severity::Warning, ..error(vec![span("a.rs", 1, 1, 3, 1)]) };
+        let diagnostics = [warning, error(vec![span("a.rs", 2, 1, 2, 5)])];
+
+        assert_eq!(line_severity(&diagnostics, 1), Some(Severity::Warning));
+        assert_eq!(line_severity(&diagnostics, 2), Some(Severity::Error));
+        assert_eq!(line_severity(&diagnostics, 3), Some(Severity::Warning));
+        assert_eq!(line_severity(&diagnostics, 4), None);
+    }
+
+    fn underlined(line: &str, number: usize, diagnostics: &[Diagnostic]) -> Vec<String> {
+        let style = Style { font_style: FontStyle::UNDERLINE, ..Style::default() };
+        mark_spans(vec![(style, line)], line, number, diagnostics).into_iter()
+            .filter(|(style, _)| style.font_style.contains(FontStyle::UNDERLINE))
+            .map(|(_, text)| text.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn span_columns_count_characters() {
+        // 1-based columns 8..11 are "ünï", two bytes each
+        let line = "let x: ünïcode = 1;\n";
+        assert_eq!(underlined(line, 1, &[error(vec![span("a.rs", 1, 8, 1, 11)])]), ["ünï"]);
+    }
+
+    #[test]
+    fn multi_line_spans_cover_the_lines_between() {
+        let diagnostics = [error(vec![span("a.rs", 1, 5, 3, 2)])];
+
+        assert_eq!(underlined("let é = [\n", 1, &diagnostics), ["é = ["]);
+        assert_eq!(underlined("    1, 2,\n", 2, &diagnostics), ["    1, 2,"]);
+        assert_eq!(underlined("];\n", 3, &diagnostics), ["]"]);
+        assert!(underlined("x\n", 4, &diagnostics).is_empty());
+    }
+
+    #[test]
+    fn spans_past_the_end_of_the_line_stop_at_it() {
+        let diagnostics = [error(vec![span("a.rs", 1, 3, 1, 40)])];
+        assert_eq!(underlined("abcd\r\n", 1, &diagnostics), ["cd"]);
+    }
+
+    #[test]
+    fn underlines_cut_pieces_at_span_boundaries() {
+        let style = Style::default();
+        let marked = underline_spans(vec![(style, "let "), (style, "value"), (style, " = 1;")], &[(2, 6), (10, 11)]);
+        let pieces: Vec<(&str, bool)> = marked.iter()
+            .map(|(style, text)| (*text, style.font_style.contains(FontStyle::UNDERLINE)))
+            .collect();
+
+        assert_eq!(pieces, [("le", false), ("t ", true), ("va", true), ("lue", false), (" ", false), ("=", true), (" 1;", false)]);
+    }
+}
@@ -0,0 +1,295 @@
+use similar::{DiffTag, TextDiff};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use syntect::highlighting::{Color, Highlighter, Theme};
+use syntect::parsing::Scope;
+
+use crate::OutputProfile;
+
+#[cfg(feature = "git")]
+use anyhow::{Context, Result};
+
+/// How a line of the new text differs from the old one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    /// Inserted where nothing was removed
+    Added,
+    /// Replacing removed lines
+    Modified,
+}
+
+/// The line-by-line differences between an old and a new text, seen from
+/// the new one: what happened to each of its lines, and which old lines
+/// were removed before it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LineDiff {
+    /// Changes by 1-based line number of the new text
+    changes: BTreeMap<usize, LineChange>,
+    /// Removed lines, by the number of the new line they stood before; one
+    /// past the last line for the ones at the end
+    removed: BTreeMap<usize, Vec<String>>,
+}
+
+impl LineDiff {
+    /// Diffs `old` against `new` with Myers' algorithm, so the removed and
+    /// added lines are as few as possible. A last line that only one of them
+    /// ends with a newline is the same line in both.
+    pub fn new(old: &str, new: &str) -> Self {
+        let (old, new) = (terminated(old), terminated(new));
+        let old_lines: Vec<&str> = old.lines().collect();
+
+        let mut diff = Self::default();
+        for op in TextDiff::from_lines(old.as_ref(), new.as_ref()).ops() {
+            let (tag, removed, added) = op.as_tag_tuple();
+            if tag == DiffTag::Equal {
+                continue;
+            }
+            // removed lines are shown before the ones replacing them
+            if !removed.is_empty() {
+                diff.removed.insert(added.start + 1, old_lines[removed.clone()].iter().map(|line| line.to_string()).collect());
+            }
+            let change = if removed.is_empty() { LineChange::Added } else { LineChange::Modified };
+            diff.changes.extend((added.start + 1..=added.end).map(|line| (line, change)));
+        }
+
+        diff
+    }
+
+    /// Every line of `new` added, as for a file that didn't exist before.
+    pub fn added(new: &str) -> Self {
+        Self {
+            changes: (1..=new.lines().count()).map(|line| (line, LineChange::Added)).collect(),
+            removed: BTreeMap::new(),
+        }
+    }
+
+    /// The diff as a unified one shows it, with the lines replacing removed
+    /// ones marked added rather than modified.
+    pub fn unified(mut self) -> Self {
+        self.changes.values_mut().for_each(|change| *change = LineChange::Added);
+        self
+    }
+
+    /// Diffs the file at `path` as it is now against the revision `rev` of
+    /// the Git repository it is in, e.g. `HEAD~3`, a branch or a tag. A file
+    /// that didn't exist at `rev` is all added.
+    #[cfg(feature = "git")]
+    pub fn since(path: &str, rev: &str, new: &str) -> Result<Self> {
+        let absolute = std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        let repo = git2::Repository::discover(absolute.parent().unwrap_or(&absolute))
+            .with_context(|| format!("'{}' is not in a Git repository", path))?;
+        let workdir = repo.workdir()
+            .with_context(|| format!("The Git repository of '{}' has no working tree", path))?;
+        let relative = absolute.strip_prefix(std::fs::canonicalize(workdir)?)
+            .with_context(|| format!("'{}' is outside its Git repository", path))?;
+
+        let tree = repo.revparse_single(rev)
+            .and_then(|object| object.peel_to_tree())
+            .with_context(|| format!("Unknown Git revision '{}'", rev))?;
+        let entry = match tree.get_path(relative) {
+            Ok(entry) => entry,
+            Err(error) if error.code() == git2::ErrorCode::NotFound => return Ok(Self::added(new)),
+            Err(error) => return Err(error).with_context(|| format!("Failed to read '{}' at '{}'", path, rev)),
+        };
+        let blob = entry.to_object(&repo)
+            .and_then(|object| object.peel_to_blob())
+            .with_context(|| format!("Failed to read '{}' at '{}'", path, rev))?;
+
+        Ok(Self::new(&String::from_utf8_lossy(blob.content()), new))
+    }
+
+    /// The change to 1-based `line` of the new text, None when it is unchanged.
+    pub fn change(&self, line: usize) -> Option<LineChange> {
+        self.changes.get(&line).copied()
+    }
+
+    /// The old lines removed just before `line`, or at the end for one past
+    /// the last line.
+    pub fn removed_before(&self, line: usize) -> &[String] {
+        self.removed.get(&line).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the texts are the same line for line.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The sign column a [`LineDiff`] is shown in, colored like the theme's
+/// `markup.inserted`, `markup.changed` and `markup.deleted` scopes.
+pub(crate) struct DiffSigns {
+    pub(crate) added: Color,
+    pub(crate) modified: Color,
+    pub(crate) removed: Color,
+}
+
+impl DiffSigns {
+    /// Columns the signs take up.
+    pub(crate) const WIDTH: usize = 2;
+
+    pub(crate) fn new(theme: &Theme) -> Self {
+        let highlighter = Highlighter::new(theme);
+        let color = |scope: &str, fallback: Color| {
+            let scope = Scope::new(scope).expect("scope names are valid");
+            highlighter.style_mod_for_stack(&[scope]).foreground.unwrap_or(fallback)
+        };
+
+        Self {
+            added: color("markup.inserted", Color { r: 0x6a, g: 0xb0, b: 0x5d, a: 0xff }),
+            modified: color("markup.changed", Color { r: 0xd8, g: 0xa6, b: 0x57, a: 0xff }),
+            removed: color("markup.deleted", Color { r: 0xd0, g: 0x5a, b: 0x5a, a: 0xff }),
+        }
+    }
+
+    /// The sign for a line with `change`, blank for an unchanged one.
+    pub(crate) fn line(&self, change: Option<LineChange>, profile: &OutputProfile) -> String {
+        match change {
+            Some(LineChange::Added) => sign('+', self.added, profile),
+            Some(LineChange::Modified) => sign('~', self.modified, profile),
+            None => " ".repeat(Self::WIDTH),
+        }
+    }
+
+    /// The sign for a removed line.
+    pub(crate) fn removed(&self, profile: &OutputProfile) -> String {
+        sign('-', self.removed, profile)
+    }
+
+    /// A removed line `text`, dimmed and struck through.
+    pub(crate) fn removed_line(text: &str, profile: &OutputProfile) -> String {
+        format!("{}{}{}\n", profile.sgr("\x1b[2;9m"), text.trim_end_matches(['\n', '\r']), profile.sgr("\x1b[0m"))
+    }
+}
+
+fn sign(symbol: char, color: Color, profile: &OutputProfile) -> String {
+    if profile.colors {
+        format!("\x1b[38;2;{};{};{}m{}\x1b[0m ", color.r, color.g, color.b, symbol)
+    } else {
+        format!("{} ", symbol)
+    }
+}
+
+/// `text` ending with a newline unless it is empty.
+fn terminated(text: &str) -> Cow<'_, str> {
+    if text.is_empty() || text.ends_with('\n') {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(format!("{}\n", text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_seen_from_the_new_text() {
+        let diff = LineDiff::new("a\nb\nc\nd\n", "a\nB\nd\ne\n");
+
+        assert_eq!(diff.change(1), None);
+        assert_eq!(diff.change(2), Some(LineChange::Modified));
+        assert_eq!(diff.change(3), None);
+        assert_eq!(diff.change(4), Some(LineChange::Added));
+        assert_eq!(diff.removed_before(2), ["b", "c"]);
+        assert!(diff.removed_before(3).is_empty());
+        assert!(!diff.is_empty());
+
+        assert_eq!(diff.unified().change(2), Some(LineChange::Added));
+    }
+
+    #[test]
+    fn lines_removed_at_the_end_stand_past_the_last_line() {
+        let diff = LineDiff::new("a\nb\nc\n", "a\n");
+        assert_eq!(diff.removed_before(2), ["b", "c"]);
+        assert_eq!(diff.change(1), None);
+    }
+
+    #[test]
+    fn a_new_file_is_all_added() {
+        let diff = LineDiff::added("x\ny\n");
+        assert_eq!((diff.change(1), diff.change(2), diff.change(3)), (Some(LineChange::Added), Some(LineChange::Added), None));
+    }
+
+    #[test]
+    fn empty_and_identical_texts_have_no_changes() {
+        assert!(LineDiff::new("", "").is_empty());
+        assert!(LineDiff::new("a\nb\n", "a\nb\n").is_empty());
+        assert_eq!(LineDiff::new("", "x\ny\n"), LineDiff::added("x\ny\n"));
+
+        let emptied = LineDiff::new("x\ny\n", "");
+        assert_eq!(emptied.removed_before(1), ["x", "y"]);
+        assert_eq!(emptied.change(1), None);
+    }
+
+    #[test]
+    fn a_text_changed_throughout_is_one_replacement() {
+        let diff = LineDiff::new("a\nb\n", "c\nd\ne\n");
+        assert_eq!(diff.removed_before(1), ["a", "b"]);
+        assert!(diff.removed_before(2).is_empty());
+        assert!((1..=3).all(|line| diff.change(line) == Some(LineChange::Modified)));
+        assert_eq!(diff.change(4), None);
+    }
+
+    #[test]
+    fn a_missing_last_newline_is_no_change() {
+        assert!(LineDiff::new("a\nb", "a\nb\n").is_empty());
+        assert!(LineDiff::new("a\nb\n", "a\nb").is_empty());
+
+        let diff = LineDiff::new("a\nb", "a\nc");
+        assert_eq!(diff.change(2), Some(LineChange::Modified));
+        assert_eq!(diff.removed_before(2), ["b"]);
+    }
+
+    #[cfg(feature = "git")]
+    fn commit(repo: &git2::Repository, files: &[(&str, &str)], message: &str) {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (name, content) in files {
+            std::fs::write(workdir.join(name), content).unwrap();
+            index.add_path(std::path::Path::new(name)).unwrap();
+        }
+        index.write().unwrap();
+
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("ccat", "ccat@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, parent.as_ref().into_iter().collect::<Vec<_>>().as_slice()).unwrap();
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn since_diffs_against_any_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        commit(&repo, &[("f.txt", "a\nb\nc\nd\n")], "one");
+        commit(&repo, &[("f.txt", "a\nB\nc\nd\ne\n")], "two");
+        let path = dir.path().join("f.txt");
+        let path = path.to_str().unwrap();
+        let now = "a\nB\nd\ne\nf\n";
+
+        let since_first = LineDiff::since(path, "HEAD~1", now).unwrap();
+        assert_eq!(since_first, LineDiff::new("a\nb\nc\nd\n", now));
+        assert_eq!(since_first.removed_before(2), ["b", "c"]);
+
+        let since_head = LineDiff::since(path, "HEAD", now).unwrap();
+        assert_eq!(since_head.change(2), None);
+        assert_eq!(since_head.removed_before(3), ["c"]);
+        assert_eq!(since_head.change(5), Some(LineChange::Added));
+
+        assert!(LineDiff::since(path, "HEAD~1", "a\nb\nc\nd\n").unwrap().is_empty());
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn files_missing_at_the_revision_are_all_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        commit(&repo, &[("old.txt", "x\n")], "one");
+        commit(&repo, &[("new.txt", "y\nz\n")], "two");
+        let path = dir.path().join("new.txt");
+
+        assert_eq!(LineDiff::since(path.to_str().unwrap(), "HEAD~1", "y\nz\n").unwrap(), LineDiff::added("y\nz\n"));
+        assert_eq!(LineDiff::since(path.to_str().unwrap(), "nope", "y\n").unwrap_err().to_string(), "Unknown Git revision 'nope'");
+    }
+}
@@ -0,0 +1,20 @@
+use std::env;
+use std::path::PathBuf;
+
+/// ccat's directory under `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("ccat"))
+}
+
+/// ccat's directory under `$XDG_CACHE_HOME`, falling back to `~/.cache`.
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("ccat"))
+}
@@ -0,0 +1,206 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Editors that take the line as `+N` before the file.
+const PLUS_LINE: &[&str] = &[
+    "vi", "vim", "nvim", "gvim", "mvim", "view", "nano", "pico", "emacs", "emacsclient", "micro", "kak", "joe", "jed", "ne", "mg",
+];
+
+/// Editors that take `file:line` as a single argument.
+const FILE_COLON_LINE: &[&str] = &["subl", "sublime_text", "zed", "hx", "helix"];
+
+/// Editors that take `--goto file:line`.
+const GOTO: &[&str] = &["code", "code-insiders", "codium", "vscodium", "cursor"];
+
+/// Editors that take `--line N` before the file.
+const LINE_FLAG: &[&str] = &["mate", "idea", "pycharm", "webstorm", "clion", "goland", "rustrover"];
+
+/// How to open a file at a line: a program and its arguments, in which
+/// `{file}` and `{line}` are substituted.
+///
+/// The arguments are passed to the program as they are, never through a
+/// shell, so file names need no quoting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditorCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl EditorCommand {
+    /// Parses a template such as `code --goto {file}:{line}`. Words are split
+    /// on whitespace; without a `{file}` word the file is passed last.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut words = template.split_whitespace().map(str::to_string);
+        let Some(program) = words.next() else {
+            bail!("Editor command is empty");
+        };
+
+        let mut args: Vec<String> = words.collect();
+        if !args.iter().any(|arg| arg.contains("{file}")) {
+            args.push("{file}".to_string());
+        }
+
+        Ok(Self { program, args })
+    }
+
+    /// Builds the command for an editor such as the value of `$EDITOR`,
+    /// which may carry its own arguments (`emacsclient -t`). The way to
+    /// pass the line is picked from the program's name; editors not known
+    /// to take one are only given the file.
+    pub fn for_editor(editor: &str) -> Result<Self> {
+        let command = Self::parse(editor)?;
+        let name = Path::new(&command.program)
+            .file_stem()
+            .map_or_else(String::new, |name| name.to_string_lossy().to_ascii_lowercase());
+
+        let mut args: Vec<String> = command.args.into_iter()
+            .filter(|arg| arg != "{file}")
+            .collect();
+        let file_args: &[&str] = match name.as_str() {
+            name if PLUS_LINE.contains(&name) => &["+{line}", "{file}"],
+            name if FILE_COLON_LINE.contains(&name) => &["{file}:{line}"],
+            name if GOTO.contains(&name) => &["--goto", "{file}:{line}"],
+            name if LINE_FLAG.contains(&name) => &["--line", "{line}", "{file}"],
+            _ => &["{file}"],
+        };
+        args.extend(file_args.iter().map(|arg| arg.to_string()));
+
+        Ok(Self { program: command.program, args })
+    }
+
+    /// The `editor-command` of the parsed config file when it has one,
+    /// otherwise the command for `$VISUAL` or `$EDITOR`.
+    pub fn load(config: Option<&toml::Table>) -> Result<Self> {
+        match config.and_then(|config| config.get("editor-command")) {
+            Some(toml::Value::String(template)) => return Self::parse(template),
+            Some(_) => bail!("'editor-command' in the config file must be a string"),
+            None => {}
+        }
+
+        let editor = ["VISUAL", "EDITOR"].into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|editor| !editor.trim().is_empty())
+            .context("No editor to launch, set $VISUAL or $EDITOR or 'editor-command' in the config file")?;
+        Self::for_editor(&editor)
+    }
+
+    /// The command opening `file` at `line`.
+    pub fn command(&self, file: &str, line: usize) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(self.args(file, line));
+        command
+    }
+
+    /// Opens `file` at `line` and waits for the editor to exit.
+    pub fn launch(&self, file: &str, line: usize) -> Result<()> {
+        let shown = self.display(file, line);
+        let status = self.command(file, line).status()
+            .with_context(|| format!("Failed to launch editor: {}", shown))?;
+
+        if !status.success() {
+            bail!("Editor failed ({}): {}", status, shown);
+        }
+        Ok(())
+    }
+
+    /// The command line opening `file` at `line`, quoted for a POSIX shell.
+    pub fn display(&self, file: &str, line: usize) -> String {
+        std::iter::once(self.program.clone())
+            .chain(self.args(file, line))
+            .map(|word| shell_quote(&word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The command as a template, as `editor-command` takes it.
+    pub fn template(&self) -> String {
+        std::iter::once(&self.program).chain(&self.args).cloned().collect::<Vec<_>>().join(" ")
+    }
+
+    fn args(&self, file: &str, line: usize) -> Vec<String> {
+        // a file named like an option would be read as one
+        let file = if file.starts_with('-') { format!("./{}", file) } else { file.to_string() };
+        let line = line.to_string();
+
+        self.args.iter()
+            .map(|arg| arg.replace("{line}", &line).replace("{file}", &file))
+            .collect()
+    }
+}
+
+fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:+=@%,".contains(c));
+
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(editor: &EditorCommand, file: &str, line: usize) -> Vec<String> {
+        std::iter::once(editor.program.clone()).chain(editor.args(file, line)).collect()
+    }
+
+    #[test]
+    fn editors_get_the_line_their_way() {
+        let cases = [
+            ("vim", vec!["vim", "+7", "a.rs"]),
+            ("/usr/local/bin/nvim", vec!["/usr/local/bin/nvim", "+7", "a.rs"]),
+            ("emacsclient -t", vec!["emacsclient", "-t", "+7", "a.rs"]),
+            ("code --wait", vec!["code", "--wait", "--goto", "a.rs:7"]),
+            ("subl", vec!["subl", "a.rs:7"]),
+            ("mate", vec!["mate", "--line", "7", "a.rs"]),
+            ("Notepad.exe", vec!["Notepad.exe", "a.rs"]),
+        ];
+
+        for (editor, expected) in cases {
+            assert_eq!(argv(&EditorCommand::for_editor(editor).unwrap(), "a.rs", 7), expected, "{}", editor);
+        }
+        assert!(EditorCommand::for_editor("  ").is_err());
+    }
+
+    #[test]
+    fn templates_substitute_the_file_and_line() {
+        let editor = EditorCommand::parse("code --goto {file}:{line}").unwrap();
+        assert_eq!(argv(&editor, "src/main.rs", 12), ["code", "--goto", "src/main.rs:12"]);
+        assert_eq!(editor.template(), "code --goto {file}:{line}");
+
+        // without {file} the file goes last
+        let editor = EditorCommand::parse("kate -l {line}").unwrap();
+        assert_eq!(argv(&editor, "a.rs", 3), ["kate", "-l", "3", "a.rs"]);
+        assert!(EditorCommand::parse("").is_err());
+    }
+
+    #[test]
+    fn file_names_are_neither_options_nor_shell_words() {
+        let editor = EditorCommand::for_editor("vim").unwrap();
+        assert_eq!(argv(&editor, "-rf", 1), ["vim", "+1", "./-rf"]);
+
+        // the words reach the program as they are, only the display is quoted
+        assert_eq!(argv(&editor, "it's $HOME.rs", 2), ["vim", "+2", "it's $HOME.rs"]);
+        assert_eq!(editor.display("it's $HOME.rs", 2), r"vim +2 'it'\''s $HOME.rs'");
+        assert_eq!(editor.display("src/a b.rs", 2), "vim +2 'src/a b.rs'");
+    }
+
+    #[test]
+    fn the_configured_command_comes_first() {
+        let config: toml::Table = toml::from_str("editor-command = \"hx {file}:{line}\"").unwrap();
+        assert_eq!(EditorCommand::load(Some(&config)).unwrap(), EditorCommand::parse("hx {file}:{line}").unwrap());
+
+        let config: toml::Table = toml::from_str("editor-command = 1").unwrap();
+        assert_eq!(EditorCommand::load(Some(&config)).unwrap_err().to_string(), "'editor-command' in the config file must be a string");
+    }
+
+    #[test]
+    fn a_failed_launch_shows_the_command() {
+        let editor = EditorCommand::parse("/nonexistent/editor --at {line}").unwrap();
+        let error = editor.launch("a b.rs", 4).unwrap_err();
+        assert_eq!(error.to_string(), "Failed to launch editor: /nonexistent/editor --at 4 'a b.rs'");
+    }
+}
@@ -0,0 +1,404 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// The `.editorconfig` properties that apply to one file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorConfig {
+    /// Lowercased keys with their values, the last setting of each winning
+    properties: Vec<(String, String)>,
+}
+
+impl EditorConfig {
+    /// Collects the properties for `file_path` from the `.editorconfig` files
+    /// in its directory and each parent, stopping at one with `root = true`.
+    /// Closer files and later sections take precedence.
+    pub fn for_file(file_path: &str) -> Result<Self> {
+        let path = std::fs::canonicalize(file_path)
+            .with_context(|| format!("Failed to read file '{}'", file_path))?;
+
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let config = dir.join(".editorconfig");
+            if !config.is_file() {
+                continue;
+            }
+
+            let content = std::fs::read_to_string(&config)
+                .with_context(|| format!("Failed to read '{}'", config.display()))?;
+            let parsed = ConfigFile::parse(&content, dir.to_path_buf());
+            let root = parsed.root;
+            files.push(parsed);
+            if root {
+                break;
+            }
+        }
+
+        let mut properties = Self::default();
+        for file in files.iter().rev() {
+            file.apply(&path, &mut properties);
+        }
+        Ok(properties)
+    }
+
+    /// The value of a property, with known values lowercased.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = key.to_ascii_lowercase();
+        self.properties.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Columns between tab stops: `tab_width`, or a numeric `indent_size`.
+    pub fn tab_width(&self) -> Option<usize> {
+        let width = |key| self.get(key).and_then(|value| value.parse().ok()).filter(|width| *width > 0);
+        width("tab_width").or_else(|| width("indent_size"))
+    }
+
+    /// The declared line ending: `lf`, `crlf` or `cr`.
+    pub fn end_of_line(&self) -> Option<&str> {
+        self.get("end_of_line").filter(|value| matches!(*value, "lf" | "crlf" | "cr"))
+    }
+
+    fn set(&mut self, key: String, value: String) {
+        match self.properties.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.properties.push((key, value)),
+        }
+    }
+}
+
+/// Values of these properties are case-insensitive, so they are lowercased.
+const KNOWN_PROPERTIES: &[&str] = &[
+    "indent_style", "indent_size", "tab_width", "end_of_line", "charset",
+    "trim_trailing_whitespace", "insert_final_newline", "root",
+];
+
+struct ConfigFile {
+    root: bool,
+    sections: Vec<(Section, Vec<(String, String)>)>,
+}
+
+impl ConfigFile {
+    /// Parses an INI-style `.editorconfig`. Unparsable lines and sections
+    /// with invalid globs are ignored, like the reference implementation does.
+    fn parse(content: &str, dir: PathBuf) -> Self {
+        let mut root = false;
+        let mut sections: Vec<(Section, Vec<(String, String)>)> = Vec::new();
+        let mut current: Option<usize> = None;
+        let mut skipping = false;
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+
+            if let Some(glob) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                match Section::new(glob, &dir) {
+                    Some(section) => {
+                        sections.push((section, Vec::new()));
+                        current = Some(sections.len() - 1);
+                        skipping = false;
+                    }
+                    None => skipping = true,
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once(['=', ':']) else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let mut value = value.trim().to_string();
+            if KNOWN_PROPERTIES.contains(&key.as_str()) {
+                value.make_ascii_lowercase();
+            }
+
+            match current {
+                _ if skipping => {}
+                Some(index) => sections[index].1.push((key, value)),
+                None if key == "root" => root = value == "true",
+                None => {}
+            }
+        }
+
+        Self { root, sections }
+    }
+
+    fn apply(&self, path: &Path, properties: &mut EditorConfig) {
+        for (section, pairs) in &self.sections {
+            if section.matches(path) {
+                for (key, value) in pairs {
+                    properties.set(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A section header's glob, compiled to a regex over absolute paths.
+struct Section {
+    regex: Regex,
+    /// `{n1..n2}` ranges, in the order of their capture groups
+    ranges: Vec<(i64, i64)>,
+}
+
+impl Section {
+    /// Globs without a `/` match file names in any directory below `dir`;
+    /// others are relative to `dir`.
+    fn new(glob: &str, dir: &Path) -> Option<Self> {
+        let dir = dir.to_string_lossy();
+        let dir = dir.trim_end_matches('/');
+        let glob = match glob.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if glob.contains('/') => glob.to_string(),
+            None => format!("**/{}", glob),
+        };
+
+        let mut ranges = Vec::new();
+        let pattern = translate(&glob, &mut ranges);
+        let regex = Regex::new(&format!("^{}/{}$", regex::escape(dir), pattern)).ok()?;
+        Some(Self { regex, ranges })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        let Some(captures) = self.regex.captures(&path) else {
+            return false;
+        };
+
+        self.ranges.iter().enumerate().all(|(index, (low, high))| {
+            captures.get(index + 1)
+                .and_then(|number| number.as_str().parse::<i64>().ok())
+                .is_none_or(|number| (*low..=*high).contains(&number))
+        })
+    }
+}
+
+/// Translates an EditorConfig glob to a regex: `*` stops at `/`, `**` does
+/// not, `?` is one character, `[...]` and `[!...]` are classes, `{a,b}`
+/// alternatives and `{1..3}` a number in a range.
+fn translate(glob: &str, ranges: &mut Vec<(i64, i64)>) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        index += 1;
+
+        match c {
+            '\\' if index < chars.len() => {
+                regex.push_str(&regex::escape(&chars[index].to_string()));
+                index += 1;
+            }
+            '*' if chars.get(index) == Some(&'*') => {
+                index += 1;
+                // `**/` also matches no directory at all
+                if chars.get(index) == Some(&'/') {
+                    index += 1;
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[index..].iter().position(|c| *c == ']') {
+                Some(length) if !chars[index..index + length].contains(&'/') => {
+                    let class: String = chars[index..index + length].iter().collect();
+                    let (negated, class) = match class.strip_prefix(['!', '^']) {
+                        Some(rest) => (true, rest.to_string()),
+                        None => (false, class),
+                    };
+                    regex.push('[');
+                    if negated {
+                        regex.push('^');
+                    }
+                    regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                    index += length + 1;
+                }
+                _ => regex.push_str("\\["),
+            },
+            '{' => match closing_brace(&chars, index) {
+                Some(end) => {
+                    let inner: String = chars[index..end].iter().collect();
+                    index = end + 1;
+
+                    if let Some(range) = numeric_range(&inner) {
+                        ranges.push(range);
+                        regex.push_str("([+-]?[0-9]+)");
+                    } else if has_top_level_comma(&inner) {
+                        let alternatives: Vec<String> = split_top_level(&inner).iter()
+                            .map(|alternative| translate(alternative, ranges))
+                            .collect();
+                        regex.push_str(&format!("(?:{})", alternatives.join("|")));
+                    } else {
+                        // a brace pair without alternatives is literal
+                        regex.push_str(&format!("\\{{{}\\}}", translate(&inner, ranges)));
+                    }
+                }
+                None => regex.push_str("\\{"),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex
+}
+
+fn closing_brace(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut index = start;
+
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    None
+}
+
+fn numeric_range(inner: &str) -> Option<(i64, i64)> {
+    let (low, high) = inner.split_once("..")?;
+    let (low, high): (i64, i64) = (low.parse().ok()?, high.parse().ok()?);
+    Some((low.min(high), low.max(high)))
+}
+
+fn has_top_level_comma(inner: &str) -> bool {
+    split_top_level(inner).len() > 1
+}
+
+/// Splits on commas outside of nested braces.
+fn split_top_level(inner: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    let mut escaped = false;
+
+    for c in inner.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().expect("parts is never empty").push(c);
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the section `glob` of `/project/.editorconfig` applies to
+    /// `/project/<path>`.
+    fn matches(glob: &str, path: &str) -> bool {
+        Section::new(glob, Path::new("/project")).unwrap().matches(Path::new(&format!("/project/{}", path)))
+    }
+
+    #[test]
+    fn a_star_matches_any_file_name() {
+        assert!(matches("*", "a.txt"));
+        assert!(matches("*", "deep/down/b.rs"));
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(!matches("*.rs", "src/main.rsx"));
+    }
+
+    #[test]
+    fn braces_list_alternatives() {
+        assert!(matches("*.{js,py}", "a.js"));
+        assert!(matches("*.{js,py}", "scripts/b.py"));
+        assert!(!matches("*.{js,py}", "c.rs"));
+        assert!(matches("{Makefile,*.mk}", "build/rules.mk"));
+        // a single word in braces is literal
+        assert!(matches("{single}.txt", "{single}.txt"));
+    }
+
+    #[test]
+    fn a_double_star_crosses_directories_but_a_star_does_not() {
+        assert!(matches("lib/**.rs", "lib/a.rs"));
+        assert!(matches("lib/**.rs", "lib/x/y/a.rs"));
+        assert!(!matches("lib/**.rs", "src/lib/a.rs"));
+        assert!(matches("lib/*.rs", "lib/a.rs"));
+        assert!(!matches("lib/*.rs", "lib/x/a.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+    }
+
+    #[test]
+    fn globs_with_a_slash_are_anchored_to_the_file_directory() {
+        assert!(matches("/top.txt", "top.txt"));
+        assert!(!matches("/top.txt", "sub/top.txt"));
+        assert!(!matches("docs/*.md", "src/docs/a.md"));
+    }
+
+    #[test]
+    fn classes_question_marks_and_ranges() {
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file10.txt"));
+        assert!(matches("[ab].c", "a.c"));
+        assert!(!matches("[!ab].c", "a.c"));
+        assert!(matches("[!ab].c", "z.c"));
+        assert!(matches("v{1..3}.txt", "v2.txt"));
+        assert!(matches("v{3..1}.txt", "v1.txt"));
+        assert!(!matches("v{1..3}.txt", "v4.txt"));
+        assert!(matches("[a", "[a"));
+    }
+
+    #[test]
+    fn later_sections_win_and_known_values_are_lowercased() {
+        let file = ConfigFile::parse(
+            "root = TRUE\n; comment\n[*]\nindent_size = 4\nEnd_Of_Line = CRLF\n[*.py]\ntab_width = 8\n[[bad]\nindent_size = 1\nCustom = Value\n",
+            PathBuf::from("/project"),
+        );
+        assert!(file.root);
+
+        let mut python = EditorConfig::default();
+        file.apply(Path::new("/project/a.py"), &mut python);
+        assert_eq!(python.tab_width(), Some(8));
+        assert_eq!(python.end_of_line(), Some("crlf"));
+
+        let mut rust = EditorConfig::default();
+        file.apply(Path::new("/project/a.rs"), &mut rust);
+        assert_eq!(rust.tab_width(), Some(4));
+        assert_eq!(rust.get("custom"), None);
+    }
+
+    #[test]
+    fn closer_files_take_precedence_up_to_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(dir.path().join(".editorconfig"), "[*]\ntab_width = 2\ncharset = latin1\n").unwrap();
+        std::fs::write(project.join(".editorconfig"), "root = true\n[*]\ntab_width = 4\nend_of_line = lf\n").unwrap();
+        std::fs::write(project.join("src/.editorconfig"), "[*.rs]\ntab_width = 8\n").unwrap();
+        std::fs::write(project.join("src/main.rs"), "").unwrap();
+        std::fs::write(project.join("src/notes.txt"), "").unwrap();
+
+        let rust = EditorConfig::for_file(project.join("src/main.rs").to_str().unwrap()).unwrap();
+        assert_eq!((rust.tab_width(), rust.end_of_line()), (Some(8), Some("lf")));
+        let notes = EditorConfig::for_file(project.join("src/notes.txt").to_str().unwrap()).unwrap();
+        assert_eq!(notes.tab_width(), Some(4));
+        // the file above the root one isn't read
+        assert_eq!(notes.get("charset"), None);
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long sudo may take to read a file once it has let us in.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a running `sudo cat` is checked on.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Reads the files the user can't through `sudo cat`, for `--sudo`.
+///
+/// Only `cat` runs as root, never ccat itself, and what it reads comes back
+/// over a pipe and is only ever held in memory. sudo is asked for a password
+/// at most once a run: `sudo -v` lets us in up front, after which each file
+/// is read with `sudo -n`, which fails rather than asking again. Once sudo
+/// has refused, every other file fails without asking.
+///
+/// `$CCAT_SUDO` names a program to run in sudo's place with its arguments.
+#[derive(Debug)]
+pub struct Elevation {
+    program: String,
+    /// Whether sudo let us in, None until it has been asked
+    granted: Option<bool>,
+}
+
+impl Default for Elevation {
+    fn default() -> Self {
+        let program = std::env::var("CCAT_SUDO").ok()
+            .filter(|program| !program.trim().is_empty())
+            .unwrap_or_else(|| "sudo".to_string());
+
+        Self { program, granted: None }
+    }
+}
+
+impl Elevation {
+    /// Whether the config file opts into asking sudo for the files that
+    /// can't be read without --sudo, with `sudo-prompt = true`.
+    pub fn prompt_configured(config: Option<&toml::Table>) -> Result<bool> {
+        match config.and_then(|config| config.get("sudo-prompt")) {
+            Some(toml::Value::Boolean(prompt)) => Ok(*prompt),
+            Some(_) => bail!("'sudo-prompt' in the config file must be true or false"),
+            None => Ok(false),
+        }
+    }
+
+    /// The bytes of `path`, read as root. `asking` is called with the name
+    /// of the program just before it is asked to let us in, which may prompt
+    /// for a password, so the user can be told why.
+    pub fn read(&mut self, path: &str, asking: impl FnOnce(&str)) -> Result<Vec<u8>> {
+        match self.granted {
+            Some(true) => {}
+            Some(false) => bail!("Failed to read file '{}': permission denied, and '{}' already refused", path, self.program),
+            None => {
+                asking(&self.program);
+                let status = Command::new(&self.program).arg("-v").status();
+                self.granted = Some(status.as_ref().is_ok_and(|status| status.success()));
+                let status = status.with_context(|| format!("Failed to run '{}'", self.program))?;
+                if !status.success() {
+                    bail!("Failed to read file '{}': permission denied, and '{}' refused", path, self.program);
+                }
+            }
+        }
+
+        let mut child = Command::new(&self.program)
+            .args(["-n", "--", "cat", "--", path])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run '{}'", self.program))?;
+
+        // both pipes are drained as cat writes, so it never blocks on a full one
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stdout = thread::spawn(move || {
+            let mut bytes = Vec::new();
+            stdout.read_to_end(&mut bytes).map(|_| bytes)
+        });
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = thread::spawn(move || {
+            let mut text = String::new();
+            let _ = stderr.read_to_string(&mut text);
+            text
+        });
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait().with_context(|| format!("Failed to wait for '{}'", self.program))? {
+                Some(status) => break status,
+                None if started.elapsed() >= READ_TIMEOUT => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    bail!("Failed to read file '{}': '{}' timed out after {} s", path, self.program, READ_TIMEOUT.as_secs());
+                }
+                None => thread::sleep(POLL),
+            }
+        };
+
+        let bytes = stdout.join().expect("the stdout reader doesn't panic")
+            .with_context(|| format!("Failed to read file '{}' through '{}'", path, self.program))?;
+        if !status.success() {
+            let stderr = stderr.join().expect("the stderr reader doesn't panic");
+            let reason = stderr.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+            bail!("Failed to read file '{}' through '{}' ({}): {}", path, self.program, status, reason);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Whether opening `path` fails only for want of permission.
+pub fn is_permission_denied(path: &str) -> bool {
+    File::open(path).is_err_and(|error| error.kind() == ErrorKind::PermissionDenied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn the_prompt_is_opted_into_by_the_config_file() {
+        let config = |text: &str| toml::from_str::<toml::Table>(text).unwrap();
+        assert!(!Elevation::prompt_configured(None).unwrap());
+        assert!(!Elevation::prompt_configured(Some(&config("cache = true"))).unwrap());
+        assert!(Elevation::prompt_configured(Some(&config("sudo-prompt = true"))).unwrap());
+        let error = Elevation::prompt_configured(Some(&config("sudo-prompt = \"yes\""))).unwrap_err();
+        assert_eq!(error.to_string(), "'sudo-prompt' in the config file must be true or false");
+    }
+
+    /// A stand-in for sudo that logs each run to `log` and lets us in, or
+    /// refuses with `-v` when `refuse`.
+    #[cfg(unix)]
+    fn fake_sudo(dir: &Path, refuse: bool) -> Elevation {
+        use std::os::unix::fs::PermissionsExt;
+        let program = dir.join("sudo");
+        let script = format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$*\" >> '{}'\n[ \"$1\" = -v ] && exit {}\nshift 4\nexec cat -- \"$1\"\n",
+            dir.join("log").display(),
+            i32::from(refuse),
+        );
+        std::fs::write(&program, script).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Elevation { program: program.to_string_lossy().into_owned(), granted: None }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sudo_is_asked_once_and_then_runs_cat() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        std::fs::write(&a, "first\n").unwrap();
+        std::fs::write(&b, b"\xffsecond").unwrap();
+        let mut elevation = fake_sudo(dir.path(), false);
+
+        let mut asked = Vec::new();
+        assert_eq!(elevation.read(&a.to_string_lossy(), |program| asked.push(program.to_string())).unwrap(), b"first\n");
+        assert_eq!(elevation.read(&b.to_string_lossy(), |program| asked.push(program.to_string())).unwrap(), b"\xffsecond");
+        assert_eq!(asked, [dir.path().join("sudo").to_string_lossy()]);
+        let log = std::fs::read_to_string(dir.path().join("log")).unwrap();
+        assert_eq!(log, format!("-v\n-n -- cat -- {}\n-n -- cat -- {}\n", a.display(), b.display()));
+
+        let error = elevation.read(&dir.path().join("missing").to_string_lossy(), |_| {}).unwrap_err().to_string();
+        assert!(error.contains("No such file"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_refusal_is_remembered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut elevation = fake_sudo(dir.path(), true);
+
+        let error = elevation.read("/etc/shadow", |_| {}).unwrap_err().to_string();
+        assert!(error.ends_with("refused"), "{}", error);
+        let error = elevation.read("/etc/gshadow", |_| panic!("a refusal isn't asked again")).unwrap_err().to_string();
+        assert!(error.ends_with("already refused"), "{}", error);
+        assert_eq!(std::fs::read_to_string(dir.path().join("log")).unwrap(), "-v\n");
+    }
+
+    #[test]
+    fn a_missing_program_fails_to_run() {
+        let mut elevation = Elevation { program: "/nonexistent/sudo".to_string(), granted: None };
+        let error = elevation.read("/etc/shadow", |_| {}).unwrap_err().to_string();
+        assert_eq!(error, "Failed to run '/nonexistent/sudo'");
+        assert!(elevation.read("/etc/shadow", |_| {}).unwrap_err().to_string().ends_with("already refused"));
+    }
+}
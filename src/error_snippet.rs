@@ -0,0 +1,249 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::diagnostics::Severity;
+use crate::width::display_width;
+use crate::{HighlighterConfig, NewlineMode, OutputProfile, SyntaxHighlighter};
+
+/// Lines shown above and below the one an error is on.
+const CONTEXT_LINES: usize = 3;
+
+/// Tab width the excerpt is drawn with, so the caret lines up under tabs.
+const SNIPPET_TAB_WIDTH: usize = 4;
+
+/// Where in a file an error happened, carried as the context holding its
+/// message so [`render_error_snippet`] can show the place. It displays as
+/// the message alone, so an error reads the same with or without one.
+///
+/// The code that finds the fault may not know the file it is reading:
+/// the highlighting pipeline knows the line, and the caller that handed it
+/// the content fills in the path and the lines around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub message: String,
+    /// The file, None until a caller that knows it fills it in
+    pub path: Option<String>,
+    /// 1-based line number in the file
+    pub line: usize,
+    /// 1-based column of the offending character, None when the whole line is
+    pub column: Option<usize>,
+    /// Offset of the offending byte from the start of the file
+    pub byte_offset: Option<usize>,
+    /// Syntax to highlight the excerpt as, detected from the path when None
+    pub syntax: Option<String>,
+    /// The lines around `line`, with the number of the first of them
+    excerpt: Option<(usize, String)>,
+}
+
+impl ErrorLocation {
+    /// A fault on a whole line whose file isn't known yet.
+    pub(crate) fn line(message: String, line: usize) -> Self {
+        Self { message, path: None, line, column: None, byte_offset: None, syntax: None, excerpt: None }
+    }
+
+    /// A fault at byte `offset` of the file at `path`, whose content is
+    /// `bytes`, which need not be valid UTF-8.
+    pub(crate) fn at_offset(message: String, path: &str, bytes: &[u8], offset: usize) -> Self {
+        let offset = offset.min(bytes.len());
+        let line_start = bytes[..offset].iter().rposition(|byte| *byte == b'\n').map_or(0, |index| index + 1);
+        let line = bytes[..line_start].iter().filter(|byte| **byte == b'\n').count() + 1;
+        let column = String::from_utf8_lossy(&bytes[line_start..offset]).chars().count() + 1;
+
+        Self {
+            message,
+            path: Some(path.to_string()),
+            line,
+            column: Some(column),
+            byte_offset: Some(offset),
+            syntax: None,
+            excerpt: Some(excerpt(bytes, line - 1)),
+        }
+    }
+
+    pub(crate) fn with_syntax(self, syntax: &str) -> Self {
+        Self { syntax: Some(syntax.to_string()), ..self }
+    }
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Fills in the file of the location `error` carries when it has none yet.
+/// `content` is what was read from `path`, starting after `line_offset`
+/// lines of it.
+pub(crate) fn locate(mut error: anyhow::Error, path: &str, content: &str, line_offset: usize) -> anyhow::Error {
+    if let Some(location) = error.downcast_mut::<ErrorLocation>().filter(|location| location.path.is_none()) {
+        let (first, text) = excerpt(content.as_bytes(), location.line.saturating_sub(line_offset + 1));
+        location.path = Some(path.to_string());
+        location.excerpt = Some((line_offset + first, text));
+    }
+    error
+}
+
+/// Adds `message` to `error` from parsing the TOML file at `path`, with the
+/// place the parser stopped at when it says where that is.
+pub(crate) fn toml_context(error: anyhow::Error, message: String, path: &Path, content: &str) -> anyhow::Error {
+    let span = error.downcast_ref::<toml::de::Error>().and_then(toml::de::Error::span);
+    match span {
+        Some(span) => {
+            let location = ErrorLocation::at_offset(message, &path.to_string_lossy(), content.as_bytes(), span.start);
+            error.context(location.with_syntax("TOML"))
+        }
+        None => error.context(message),
+    }
+}
+
+/// The lines of `bytes` around the one at 0-based `index`, with the 1-based
+/// number of the first of them.
+fn excerpt(bytes: &[u8], index: usize) -> (usize, String) {
+    let first = index.saturating_sub(CONTEXT_LINES);
+    let lines: Vec<&[u8]> = bytes.split_inclusive(|byte| *byte == b'\n')
+        .skip(first)
+        .take(index - first + CONTEXT_LINES + 1)
+        .collect();
+
+    (first + 1, String::from_utf8_lossy(&lines.concat()).into_owned())
+}
+
+/// Renders where `error` happened, the way rustc shows its errors: the file
+/// and position, the lines around it highlighted as their syntax, the
+/// faulty line's number marked and a caret under the offending column, or
+/// under the whole line when there is none. None when the error doesn't
+/// say which file and line it is about.
+pub fn render_error_snippet(error: &anyhow::Error, profile: OutputProfile) -> Option<String> {
+    // the assets are only loaded for an error that can be shown
+    let location = error.downcast_ref::<ErrorLocation>()?;
+    location.path.as_ref().and(location.excerpt.as_ref())?;
+    snippet(&SyntaxHighlighter::new(), error, profile)
+}
+
+/// [`render_error_snippet`] with the syntaxes of `highlighter`.
+fn snippet(highlighter: &SyntaxHighlighter, error: &anyhow::Error, profile: OutputProfile) -> Option<String> {
+    let location = error.downcast_ref::<ErrorLocation>()?;
+    let path = location.path.as_deref()?;
+    let (first, excerpt) = location.excerpt.as_ref()?;
+
+    let config = HighlighterConfig {
+        force_syntax: location.syntax.clone().filter(|syntax| highlighter.available_syntaxes().contains(&syntax.as_str())),
+        profile,
+        tab_width: Some(SNIPPET_TAB_WIDTH),
+        newline: NewlineMode::Ensure,
+        ..HighlighterConfig::default()
+    };
+    let highlighted = highlighter.highlight_content(excerpt, path, &config).ok()?;
+
+    let color = Severity::Error.color();
+    let (dim, reset) = (profile.sgr("\x1b[2m"), profile.sgr("\x1b[0m"));
+    let error_style = if profile.colors { format!("\x1b[1;38;2;{};{};{}m", color.r, color.g, color.b) } else { String::new() };
+    let bar = profile.symbol("│", "|");
+    let width = (first + excerpt.lines().count()).saturating_sub(1).max(location.line).to_string().len();
+    let position = match location.column {
+        Some(column) => format!("{}:{}:{}", path, location.line, column),
+        None => format!("{}:{}", path, location.line),
+    };
+
+    let mut snippet = format!("{}{}-->{} {}\n", " ".repeat(width), dim, reset, position);
+    snippet.push_str(&format!("{}{}{}{}\n", " ".repeat(width + 1), dim, bar, reset));
+    for (index, (row, line)) in highlighted.lines().zip(excerpt.lines()).enumerate() {
+        let number = first + index;
+        let number_style = if number == location.line { error_style.as_str() } else { dim };
+        // the row's own escapes are reset before the next line's gutter
+        snippet.push_str(&format!(
+            "{}{:>width$}{} {}{}{} {}{}\n",
+            number_style, number, reset, dim, bar, reset, row, reset, width = width,
+        ));
+
+        if number != location.line {
+            continue;
+        }
+        let line = line.trim_end_matches('\r');
+        let (indent, carets) = match location.column {
+            Some(column) => {
+                let before: String = line.chars().take(column - 1).collect();
+                (display_width(&before, SNIPPET_TAB_WIDTH, profile.ambiguous_width), 1)
+            }
+            None => {
+                let code = line.trim_start();
+                let indent = display_width(&line[..line.len() - code.len()], SNIPPET_TAB_WIDTH, profile.ambiguous_width);
+                (indent, display_width(code, SNIPPET_TAB_WIDTH, profile.ambiguous_width).max(1))
+            }
+        };
+        snippet.push_str(&format!(
+            "{}{}{}{} {}{}{}{}\n",
+            " ".repeat(width + 1), dim, bar, reset, " ".repeat(indent), error_style, "^".repeat(carets), reset,
+        ));
+    }
+
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    #[test]
+    fn an_offset_is_located_by_line_and_character_column() {
+        let bytes = ["first\nsé\u{1F600}x".as_bytes(), b"\xff\n"].concat();
+        let offset = bytes.len() - 2;
+        let location = ErrorLocation::at_offset("bad".to_string(), "a.txt", &bytes, offset);
+
+        assert_eq!((location.line, location.column, location.byte_offset), (2, Some(5), Some(offset)));
+        assert_eq!(location.excerpt, Some((1, String::from_utf8_lossy(&bytes).into_owned())));
+        assert_eq!(location.to_string(), "bad");
+    }
+
+    #[test]
+    fn excerpts_keep_three_lines_on_either_side() {
+        let content: String = (1..=10).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(excerpt(content.as_bytes(), 5), (3, "3\n4\n5\n6\n7\n8\n9\n".to_string()));
+        assert_eq!(excerpt(content.as_bytes(), 0), (1, "1\n2\n3\n4\n".to_string()));
+        assert_eq!(excerpt(content.as_bytes(), 9), (7, "7\n8\n9\n10\n".to_string()));
+    }
+
+    #[test]
+    fn the_snippet_marks_the_line_and_column() {
+        let bytes = b"[a]\nkey = = 1\nother = 2\n";
+        let error = anyhow::Error::msg("expected a value")
+            .context(ErrorLocation::at_offset("Failed to parse".to_string(), "config.toml", bytes, 10).with_syntax("TOML"));
+
+        assert_eq!(error.to_string(), "Failed to parse");
+        assert_eq!(
+            snippet(&HIGHLIGHTER, &error, OutputProfile::DUMB).unwrap(),
+            " --> config.toml:2:7\n  |\n1 | [a]\n2 | key = = 1\n  |       ^\n3 | other = 2\n",
+        );
+    }
+
+    #[test]
+    fn a_line_without_a_column_is_underlined_whole_once_its_file_is_known() {
+        let error = anyhow::Error::msg("no match").context(ErrorLocation::line("Failed to highlight".to_string(), 12));
+        assert_eq!(render_error_snippet(&error, OutputProfile::DUMB), None);
+
+        let content = "fn main() {\n\tlet x = 1;\n}\n";
+        let error = locate(error, "main.rs", content, 10);
+        assert_eq!(
+            snippet(&HIGHLIGHTER, &error, OutputProfile::DUMB).unwrap(),
+            "  --> main.rs:12\n   |\n11 | fn main() {\n12 |     let x = 1;\n   |     ^^^^^^^^^^\n13 | }\n",
+        );
+        // a location is only filled in once
+        let relocated = locate(error, "other.rs", "x\n", 0);
+        assert_eq!(relocated.downcast_ref::<ErrorLocation>().unwrap().path.as_deref(), Some("main.rs"));
+    }
+
+    #[test]
+    fn toml_errors_point_at_the_parser_span() {
+        let content = "a = 1\nb = \n";
+        let error = toml::from_str::<toml::Table>(content).unwrap_err();
+        let error = toml_context(error.into(), "Failed to parse 'c.toml'".to_string(), Path::new("c.toml"), content);
+
+        let location = error.downcast_ref::<ErrorLocation>().unwrap();
+        assert_eq!((location.line, location.syntax.as_deref()), (2, Some("TOML")));
+        assert_eq!(error.to_string(), "Failed to parse 'c.toml'");
+        assert!(snippet(&HIGHLIGHTER, &error, OutputProfile::DUMB).unwrap().starts_with(" --> c.toml:2:"));
+    }
+}
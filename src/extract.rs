@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Result};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use std::fs;
+
+use crate::input::{self, DecodeOptions, Input};
+use crate::{guard, DetectStrategy, HighlighterConfig, SyntaxHighlighter};
+
+/// Options for [`SyntaxHighlighter::extract_text`].
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// WHATWG label of the input encoding; sniffed from the BOM (falling
+    /// back to UTF-8) when unset
+    pub encoding: Option<String>,
+    /// Transparently decompress gzip input
+    pub decompress: bool,
+    /// Which signal picks the reported syntax
+    pub detect: DetectStrategy,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            encoding: None,
+            decompress: true,
+            detect: DetectStrategy::Auto,
+        }
+    }
+}
+
+/// Where a line starts, in the normalized text and in the original bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineOffset {
+    /// Byte offset into [`ExtractedText::content`]
+    pub normalized: usize,
+    /// Byte offset into the input after decompression, BOM included
+    pub original: usize,
+}
+
+/// A file's text after decoding, without any highlighting.
+#[derive(Debug, Clone)]
+pub struct ExtractedText {
+    /// The content as UTF-8, BOM stripped
+    pub content: String,
+    /// One entry per line, in order
+    pub lines: Vec<LineOffset>,
+    /// Name of the encoding the input was decoded from
+    pub encoding: &'static str,
+    /// Whether the input was gzip-compressed
+    pub decompressed: bool,
+    /// Name of the syntax ccat would highlight the file with
+    pub syntax: String,
+}
+
+impl SyntaxHighlighter {
+    /// Reads and decodes `path` the way ccat's input layer does, returning the
+    /// normalized text with a line map back into the original bytes.
+    pub fn extract_text(&self, path: &str, options: &ExtractOptions) -> Result<ExtractedText> {
+        let raw = fs::read(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        let decode = DecodeOptions { encoding: options.encoding.clone(), decompress: options.decompress };
+
+        // the line map needs the bytes the text was decoded from
+        let (bytes, decompressed) = input::decompress(path, raw, &decode)?;
+        let given_utf16 = options.encoding.as_deref()
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .is_some_and(|encoding| encoding == UTF_16LE || encoding == UTF_16BE);
+        if !given_utf16 && guard::looks_binary(&bytes) {
+            bail!("'{}' looks like a binary file", path);
+        }
+
+        let Input { content, mut decoding, .. } = input::decode(path, bytes.clone(), None, &DecodeOptions { decompress: false, ..decode })?;
+        decoding.decompressed = decompressed;
+
+        let syntax = self.detect(&content, decoding.detect_path(path), &HighlighterConfig {
+            detect: options.detect,
+            ..HighlighterConfig::default()
+        })?.syntax;
+
+        Ok(ExtractedText {
+            lines: line_offsets(&content, &bytes, decoding.bom_len, decoding.encoding),
+            content,
+            encoding: decoding.encoding.name(),
+            decompressed: decoding.decompressed,
+            syntax,
+        })
+    }
+}
+
+/// Pairs each line start in `content` with the matching one in `bytes`.
+///
+/// Decoding never drops or invents line feeds, so the n-th `\n` in the text
+/// corresponds to the n-th encoded line feed in the input.
+fn line_offsets(content: &str, bytes: &[u8], bom_len: usize, encoding: &'static Encoding) -> Vec<LineOffset> {
+    let normalized = content.match_indices('\n').map(|(offset, _)| offset + 1);
+
+    let original: Vec<usize> = if encoding == UTF_16LE || encoding == UTF_16BE {
+        let newline: [u8; 2] = if encoding == UTF_16LE { [b'\n', 0] } else { [0, b'\n'] };
+        bytes[bom_len..].chunks_exact(2)
+            .enumerate()
+            .filter(|(_, unit)| *unit == newline)
+            .map(|(i, _)| bom_len + i * 2 + 2)
+            .collect()
+    } else {
+        bytes.iter()
+            .enumerate()
+            .skip(bom_len)
+            .filter(|(_, byte)| **byte == b'\n')
+            .map(|(i, _)| i + 1)
+            .collect()
+    };
+
+    if content.is_empty() {
+        return Vec::new();
+    }
+
+    let mut lines = vec![LineOffset { normalized: 0, original: bom_len }];
+    lines.extend(normalized.zip(original)
+        // a trailing newline ends the last line rather than starting a new one
+        .filter(|(normalized, _)| *normalized < content.len())
+        .map(|(normalized, original)| LineOffset { normalized, original }));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    fn extract(bytes: &[u8], name: &str) -> (ExtractedText, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, bytes).unwrap();
+        let extracted = HIGHLIGHTER.extract_text(path.to_str().unwrap(), &ExtractOptions::default()).unwrap();
+        (extracted, bytes.to_vec())
+    }
+
+    /// The lines of `text` with where each starts.
+    fn starts(text: &str) -> Vec<(usize, &str)> {
+        let mut offset = 0;
+        text.split_inclusive('\n').map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line)
+        }).collect()
+    }
+
+    #[test]
+    fn utf16le_offsets_point_at_the_same_lines() {
+        let text = "first\nsécond\n\nlast";
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        let (extracted, bytes) = extract(&bytes, "notes.txt");
+
+        assert_eq!(extracted.content, text);
+        assert_eq!(extracted.encoding, "UTF-16LE");
+        assert_eq!(extracted.lines.len(), 4);
+        for (offset, (start, line)) in extracted.lines.iter().zip(starts(text)) {
+            assert_eq!(offset.normalized, start);
+            let units: Vec<u16> = bytes[offset.original..].chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take(line.encode_utf16().count())
+                .collect();
+            assert_eq!(String::from_utf16(&units).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn gzip_offsets_point_into_the_decompressed_bytes() {
+        let text = "fn main() {\n    println!(\"hi\");\n}\n";
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let (extracted, _) = extract(&encoder.finish().unwrap(), "main.rs.gz");
+
+        assert!(extracted.decompressed);
+        assert_eq!(extracted.syntax, "Rust");
+        assert_eq!(extracted.content, text);
+        let expected: Vec<LineOffset> = starts(text).into_iter()
+            .map(|(start, _)| LineOffset { normalized: start, original: start })
+            .collect();
+        assert_eq!(extracted.lines, expected);
+    }
+
+    #[test]
+    fn a_utf8_bom_shifts_the_original_offsets() {
+        let (extracted, _) = extract(b"\xef\xbb\xbfa\nb\n", "a.txt");
+        assert_eq!(extracted.content, "a\nb\n");
+        assert_eq!(extracted.lines, [LineOffset { normalized: 0, original: 3 }, LineOffset { normalized: 2, original: 5 }]);
+    }
+
+    #[test]
+    fn binary_files_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blob.bin");
+        fs::write(&path, b"\x00\x01\x02").unwrap();
+        assert!(HIGHLIGHTER.extract_text(path.to_str().unwrap(), &ExtractOptions::default()).is_err());
+    }
+}
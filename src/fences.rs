@@ -0,0 +1,323 @@
+use std::path::Path;
+use syntect::util::LinesWithEndings;
+
+/// A markup language whose documents embed source blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// `` ``` `` and `~~~` fences
+    #[default]
+    Markdown,
+    /// `#+BEGIN_SRC lang` ... `#+END_SRC`
+    Org,
+    /// `.. code-block:: lang` directives followed by an indented block
+    Rst,
+}
+
+impl Dialect {
+    /// Picks the dialect from the file extension, defaulting to Markdown.
+    pub fn for_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("org") => Self::Org,
+            Some(ext) if ext.eq_ignore_ascii_case("rst") || ext.eq_ignore_ascii_case("rest") => Self::Rst,
+            _ => Self::Markdown,
+        }
+    }
+
+    /// Returns every source block of `document`, in order.
+    pub fn source_blocks(self, document: &str) -> Vec<Fence> {
+        match self {
+            Self::Markdown => markdown_blocks(document),
+            Self::Org => org_blocks(document),
+            Self::Rst => rst_blocks(document),
+        }
+    }
+}
+
+/// A source block taken out of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fence {
+    /// The block's language tag, empty when there is none
+    pub language: String,
+    /// Line number of the first line of code in the document
+    pub start_line: usize,
+    /// The block's content, without the fence lines
+    pub code: String,
+}
+
+struct Opening {
+    marker: char,
+    length: usize,
+    indent: usize,
+    language: String,
+    start_line: usize,
+    code: String,
+}
+
+/// Returns the source blocks of `document` whose language is `language`
+/// (case-insensitive), or every block when `language` is `*`.
+pub fn extract_fences(document: &str, dialect: Dialect, language: &str) -> Vec<Fence> {
+    let mut fences = dialect.source_blocks(document);
+    fences.retain(|fence| language == "*" || fence.language.eq_ignore_ascii_case(language));
+    fences
+}
+
+/// Follows the CommonMark rules for fences: a block is closed only by a fence
+/// of the same character at least as long as the opening one, so shorter or
+/// different fences inside it are content. A block left open runs to the end
+/// of the document.
+fn markdown_blocks(markdown: &str) -> Vec<Fence> {
+    let mut fences = Vec::new();
+    let mut open: Option<Opening> = None;
+
+    for (index, line) in LinesWithEndings::from(markdown).enumerate() {
+        match open.as_mut() {
+            Some(block) => {
+                if is_closing(line, block.marker, block.length) {
+                    let block = open.take().expect("block is open");
+                    fences.push(Fence { language: block.language, start_line: block.start_line, code: block.code });
+                } else {
+                    // content lines lose up to as much indentation as the opening fence had
+                    let strip = line.len() - line.trim_start_matches(' ').len();
+                    block.code.push_str(&line[strip.min(block.indent)..]);
+                }
+            }
+            None => {
+                if let Some((marker, length, indent, info)) = parse_opening(line) {
+                    let language = info.split(|c: char| c.is_whitespace() || c == ',')
+                        .next()
+                        .unwrap_or("")
+                        .trim_matches(['{', '}', '.'])
+                        .to_string();
+                    open = Some(Opening { marker, length, indent, language, start_line: index + 2, code: String::new() });
+                }
+            }
+        }
+    }
+
+    if let Some(block) = open {
+        fences.push(Fence { language: block.language, start_line: block.start_line, code: block.code });
+    }
+
+    fences
+}
+
+/// Splits a fence line into its indentation, marker run and the rest.
+fn split_fence(line: &str) -> Option<(char, usize, usize, &str)> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let rest = line.trim_start_matches(' ');
+    let indent = line.len() - rest.len();
+    if indent > 3 {
+        return None;
+    }
+
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = rest.len() - rest.trim_start_matches(marker).len();
+    (length >= 3).then(|| (marker, length, indent, &rest[length..]))
+}
+
+fn parse_opening(line: &str) -> Option<(char, usize, usize, &str)> {
+    let (marker, length, indent, info) = split_fence(line)?;
+
+    // a backtick fence's info string may not contain backticks
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+
+    Some((marker, length, indent, info.trim()))
+}
+
+fn is_closing(line: &str, marker: char, length: usize) -> bool {
+    split_fence(line).is_some_and(|(closing_marker, closing_length, _, rest)| {
+        closing_marker == marker && closing_length >= length && rest.trim().is_empty()
+    })
+}
+
+/// Org blocks run from `#+BEGIN_SRC lang :header args` to `#+END_SRC`, both
+/// case-insensitive and possibly indented. Like Org itself, the content loses
+/// its common indentation.
+fn org_blocks(org: &str) -> Vec<Fence> {
+    let mut fences = Vec::new();
+    let mut open: Option<(String, usize, Vec<&str>)> = None;
+
+    for (index, line) in LinesWithEndings::from(org).enumerate() {
+        let trimmed = line.trim();
+
+        match open.as_mut() {
+            Some(_) if has_prefix_ignore_case(trimmed, "#+end_src") => {
+                let (language, start_line, lines) = open.take().expect("block is open");
+                fences.push(Fence { language, start_line, code: dedent(&lines) });
+            }
+            Some((_, _, lines)) => lines.push(line),
+            None if has_prefix_ignore_case(trimmed, "#+begin_src") => {
+                let language = trimmed["#+begin_src".len()..]
+                    .split_whitespace()
+                    .next()
+                    .filter(|word| !word.starts_with(':'))
+                    .unwrap_or("")
+                    .to_string();
+                open = Some((language, index + 2, Vec::new()));
+            }
+            None => {}
+        }
+    }
+
+    if let Some((language, start_line, lines)) = open {
+        fences.push(Fence { language, start_line, code: dedent(&lines) });
+    }
+    fences
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Joins `lines`, removing the indentation all non-blank lines share.
+fn dedent(lines: &[&str]) -> String {
+    let common = lines.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_of(line))
+        .min()
+        .unwrap_or(0);
+
+    lines.iter()
+        .map(|line| if line.trim().is_empty() { line.trim_start_matches([' ', '\t']) } else { &line[common..] })
+        .collect()
+}
+
+fn has_prefix_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// reST blocks are `code-block`, `code` or `sourcecode` directives. Option
+/// lines such as `:linenos:` come first, then the content: every following
+/// line indented deeper than the directive, with the common indentation and
+/// surrounding blank lines removed.
+fn rst_blocks(rst: &str) -> Vec<Fence> {
+    let lines: Vec<&str> = LinesWithEndings::from(rst).collect();
+    let mut fences = Vec::new();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let directive = line.trim().strip_prefix("..")
+            .and_then(|rest| rest.trim_start().split_once("::"))
+            .filter(|(name, _)| matches!(name.trim(), "code-block" | "code" | "sourcecode"));
+
+        let Some((_, language)) = directive else {
+            index += 1;
+            continue;
+        };
+        let language = language.trim().to_string();
+        let indent = indent_of(line);
+        index += 1;
+
+        // the body is every blank or deeper-indented line after the directive
+        let body_start = index;
+        while index < lines.len() && (lines[index].trim().is_empty() || indent_of(lines[index]) > indent) {
+            index += 1;
+        }
+        let mut body = &lines[body_start..index];
+
+        // option lines come before the first blank line
+        let options = body.iter()
+            .take_while(|line| line.trim().starts_with(':'))
+            .count();
+        body = &body[options..];
+
+        let leading_blank = body.iter().take_while(|line| line.trim().is_empty()).count();
+        let trailing_blank = body.iter().rev().take_while(|line| line.trim().is_empty()).count();
+        if leading_blank == body.len() {
+            continue;
+        }
+        let start_line = body_start + options + leading_blank + 1;
+        let code = dedent(&body[leading_blank..body.len() - trailing_blank]);
+
+        fences.push(Fence { language, start_line, code });
+    }
+
+    fences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(document: &str, language: &str) -> Vec<(String, usize, String)> {
+        extract_fences(document, Dialect::Markdown, language).into_iter()
+            .map(|fence| (fence.language, fence.start_line, fence.code))
+            .collect()
+    }
+
+    #[test]
+    fn only_fences_of_the_language_are_extracted() {
+        let document = "# Title\n\n```rust\nfn a() {}\n```\n\n```\nuntagged\n```\n\n~~~Python\nprint(1)\n~~~\n\n```rust,ignore\nfn b() {}\n```\n";
+        assert_eq!(markdown(document, "rust"), [
+            ("rust".to_string(), 4, "fn a() {}\n".to_string()),
+            ("rust".to_string(), 16, "fn b() {}\n".to_string()),
+        ]);
+        assert_eq!(markdown(document, "python"), [("Python".to_string(), 12, "print(1)\n".to_string())]);
+        assert_eq!(markdown(document, "*").len(), 4);
+    }
+
+    #[test]
+    fn shorter_and_other_fences_inside_a_block_are_content() {
+        let document = "````markdown\n```rust\nfn inner() {}\n```\n~~~\n````\n";
+        assert_eq!(markdown(document, "*"), [("markdown".to_string(), 2, "```rust\nfn inner() {}\n```\n~~~\n".to_string())]);
+    }
+
+    #[test]
+    fn indented_fences_lose_their_indentation_and_code_blocks_are_skipped() {
+        let document = "  ```rust\n  let a = 1;\n    let b = 2;\n  ```\n\n    indented code\n";
+        assert_eq!(markdown(document, "*"), [("rust".to_string(), 2, "let a = 1;\n  let b = 2;\n".to_string())]);
+    }
+
+    #[test]
+    fn a_block_left_open_runs_to_the_end() {
+        assert_eq!(markdown("```sh\necho hi\n", "sh"), [("sh".to_string(), 2, "echo hi\n".to_string())]);
+    }
+
+    #[test]
+    fn backtick_info_strings_cannot_hold_backticks() {
+        assert!(markdown("``` a`b\ncode\n", "*").is_empty());
+        assert_eq!(markdown("~~~ {.rust}\ncode\n~~~\n", "rust").len(), 1);
+    }
+
+    fn blocks(dialect: Dialect, document: &str) -> Vec<(String, usize, String)> {
+        dialect.source_blocks(document).into_iter()
+            .map(|fence| (fence.language, fence.start_line, fence.code))
+            .collect()
+    }
+
+    #[test]
+    fn the_dialect_follows_the_extension() {
+        assert_eq!(Dialect::for_path("notes/todo.org"), Dialect::Org);
+        assert_eq!(Dialect::for_path("README.RST"), Dialect::Rst);
+        assert_eq!(Dialect::for_path("guide.rest"), Dialect::Rst);
+        assert_eq!(Dialect::for_path("README.md"), Dialect::Markdown);
+        assert_eq!(Dialect::for_path("Makefile"), Dialect::Markdown);
+    }
+
+    #[test]
+    fn org_blocks_skip_header_arguments_and_lose_their_indentation() {
+        assert_eq!(blocks(Dialect::Org, include_str!("../tests/fixtures/blocks.org")), [
+            ("rust".to_string(), 4, "fn main() {\n    println!(\"org\");\n}\n".to_string()),
+            ("python".to_string(), 13, "def greet():\n    return \"hi\"\n".to_string()),
+            (String::new(), 18, "untagged\n".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn rst_blocks_skip_options_and_lose_their_indentation() {
+        assert_eq!(blocks(Dialect::Rst, include_str!("../tests/fixtures/blocks.rst")), [
+            ("rust".to_string(), 8, "fn main() {\n    println!(\"rst\");\n}\n".to_string()),
+            ("python".to_string(), 18, "def greet():\n    return \"hi\"\n".to_string()),
+            ("sh".to_string(), 25, "echo done\n".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn a_directive_without_content_is_no_block() {
+        assert!(blocks(Dialect::Rst, ".. code-block:: rust\n   :linenos:\n\nProse.\n").is_empty());
+        assert_eq!(blocks(Dialect::Org, "#+BEGIN_SRC sh\necho open\n"), [("sh".to_string(), 2, "echo open\n".to_string())]);
+    }
+}
@@ -0,0 +1,586 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// How much of the start and of the end of a file the parsers get to see.
+const WINDOW: u64 = 64 * 1024;
+
+/// A binary format recognized by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Png,
+    Jpeg,
+    Gif,
+    Pdf,
+    Wav,
+    Flac,
+    Mp4,
+}
+
+impl FileKind {
+    /// Recognizes the format from the first bytes of a file.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if bytes.starts_with(b"%PDF-") {
+            Some(Self::Pdf)
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE") {
+            Some(Self::Wav)
+        } else if bytes.starts_with(b"fLaC") {
+            Some(Self::Flac)
+        } else if bytes.get(4..8) == Some(b"ftyp") {
+            Some(Self::Mp4)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG image",
+            Self::Jpeg => "JPEG image",
+            Self::Gif => "GIF image",
+            Self::Pdf => "PDF document",
+            Self::Wav => "WAV audio",
+            Self::Flac => "FLAC audio",
+            Self::Mp4 => "MP4 media",
+        }
+    }
+}
+
+/// What a binary file's headers say about it, shown in place of its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataSummary {
+    pub kind: FileKind,
+    /// Facts such as `640x480` or `12 pages`, in the order they are shown
+    pub details: Vec<String>,
+}
+
+impl MetadataSummary {
+    /// Reads the headers of `path`. Files of no known kind and files whose
+    /// headers don't parse give None; only failing to read is an error.
+    pub fn read(path: &str) -> Result<Option<Self>> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        let length = file.metadata()
+            .with_context(|| format!("Failed to read file '{}'", path))?
+            .len();
+
+        let mut head = Vec::new();
+        (&mut file).take(WINDOW).read_to_end(&mut head)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+
+        let mut tail = Vec::new();
+        if length > WINDOW {
+            file.seek(SeekFrom::Start(length.saturating_sub(WINDOW).max(WINDOW)))
+                .and_then(|_| file.read_to_end(&mut tail))
+                .with_context(|| format!("Failed to read file '{}'", path))?;
+        }
+
+        Ok(FileKind::sniff(&head).and_then(|kind| Self::parse(kind, &head, &tail)))
+    }
+
+    /// Parses the headers of a `kind` file from its first bytes, `head`, and
+    /// its last ones, `tail`, which is empty when `head` is the whole file.
+    pub fn parse(kind: FileKind, head: &[u8], tail: &[u8]) -> Option<Self> {
+        let details = match kind {
+            FileKind::Png => png(head),
+            FileKind::Jpeg => jpeg(head),
+            FileKind::Gif => gif(head),
+            FileKind::Pdf => pdf(head, tail),
+            FileKind::Wav => wav(head),
+            FileKind::Flac => flac(head),
+            FileKind::Mp4 => mp4(head, tail),
+        }?;
+
+        Some(Self { kind, details })
+    }
+}
+
+impl fmt::Display for MetadataSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind.name())?;
+        for detail in &self.details {
+            write!(f, ", {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn dimensions(width: impl fmt::Display, height: impl fmt::Display) -> String {
+    format!("{}x{}", width, height)
+}
+
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds {
+        0..3600 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        _ => format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+fn channels(count: u32) -> String {
+    match count {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        count => format!("{} channels", count),
+    }
+}
+
+/// The IHDR chunk always comes first: width, height, bit depth, color type.
+fn png(bytes: &[u8]) -> Option<Vec<String>> {
+    if bytes.get(12..16)? != b"IHDR" {
+        return None;
+    }
+
+    let color = match bytes.get(25)? {
+        0 => "grayscale",
+        2 => "RGB",
+        3 => "indexed",
+        4 => "grayscale+alpha",
+        6 => "RGBA",
+        _ => return None,
+    };
+    let mut details = vec![
+        dimensions(be_u32(bytes, 16)?, be_u32(bytes, 20)?),
+        format!("{}, {}-bit", color, bytes.get(24)?),
+    ];
+    // an animation control chunk comes before the first image data
+    if find(bytes, b"acTL").is_some_and(|at| find(bytes, b"IDAT").is_none_or(|data| at < data)) {
+        details.push("animated".to_string());
+    }
+
+    Some(details)
+}
+
+/// Walks the marker segments up to the start-of-frame one.
+fn jpeg(bytes: &[u8]) -> Option<Vec<String>> {
+    let mut at = 2;
+
+    loop {
+        if *bytes.get(at)? != 0xff {
+            return None;
+        }
+        let marker = *bytes.get(at + 1)?;
+        if marker == 0xff {
+            // fill byte before a marker
+            at += 1;
+            continue;
+        }
+        let length = be_u16(bytes, at + 2)? as usize;
+
+        // SOF0-SOF15, except DHT, JPG and DAC which share the range
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let height = be_u16(bytes, at + 5)?;
+            let width = be_u16(bytes, at + 7)?;
+            let color = match bytes.get(at + 9)? {
+                1 => "grayscale",
+                3 => "YCbCr",
+                4 => "CMYK",
+                _ => return None,
+            };
+            let mut details = vec![dimensions(width, height), color.to_string()];
+            if marker == 0xc2 {
+                details.push("progressive".to_string());
+            }
+            return Some(details);
+        }
+
+        at += 2 + length;
+    }
+}
+
+/// The logical screen descriptor follows the signature.
+fn gif(bytes: &[u8]) -> Option<Vec<String>> {
+    let flags = *bytes.get(10)?;
+    let mut details = vec![dimensions(le_u16(bytes, 6)?, le_u16(bytes, 8)?)];
+    if flags & 0x80 != 0 {
+        details.push(format!("{} colors", 2u32 << (flags & 0x07)));
+    }
+    details.push(format!("version {}", String::from_utf8_lossy(&bytes[3..6])));
+
+    Some(details)
+}
+
+/// Best effort: the page tree's `/Count` and the info dictionary's `/Title`
+/// are only found when they are not inside a compressed object stream.
+fn pdf(head: &[u8], tail: &[u8]) -> Option<Vec<String>> {
+    let version = head.get(5..8).map(String::from_utf8_lossy)?;
+    let mut details = vec![format!("version {}", version)];
+
+    let text = [head, tail].concat();
+    if let Some(pages) = pdf_page_count(&text) {
+        details.push(if pages == 1 { "1 page".to_string() } else { format!("{} pages", pages) });
+    }
+    if let Some(title) = find(&text, b"/Title").and_then(|at| pdf_string(&text[at + 6..])) {
+        details.push(format!("\"{}\"", title));
+    }
+
+    Some(details)
+}
+
+/// The largest `/Count` of a `/Type /Pages` dictionary, which is the root's.
+fn pdf_page_count(text: &[u8]) -> Option<u64> {
+    let mut count = None;
+    let mut offset = 0;
+
+    while let Some(at) = find(&text[offset..], b"/Count").map(|at| offset + at) {
+        offset = at + 6;
+
+        let start = text[..at].windows(2).rposition(|window| window == b"<<").unwrap_or(0);
+        let end = find(&text[at..], b">>").map_or(text.len(), |end| at + end);
+        let dictionary: Vec<u8> = text[start..end].iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+        if find(&dictionary, b"/Type/Pages").is_none() {
+            continue;
+        }
+
+        let value: String = text[offset..].iter()
+            .skip_while(|byte| byte.is_ascii_whitespace())
+            .take_while(|byte| byte.is_ascii_digit())
+            .map(|&byte| byte as char)
+            .collect();
+        count = count.max(value.parse::<u64>().ok());
+    }
+
+    count
+}
+
+/// Reads a literal `(string)` or hex `<string>` at the start of `bytes`,
+/// decoding UTF-16 when it has a byte order mark.
+fn pdf_string(bytes: &[u8]) -> Option<String> {
+    let start = bytes.iter().position(|byte| !byte.is_ascii_whitespace())?;
+    let bytes = &bytes[start..];
+
+    let raw: Vec<u8> = match bytes.first()? {
+        b'(' => {
+            let mut raw = Vec::new();
+            let mut depth = 0;
+            let mut chars = bytes[1..].iter();
+            while let Some(&byte) = chars.next() {
+                match byte {
+                    b'\\' => match chars.next()? {
+                        b'n' => raw.push(b'\n'),
+                        b'r' => raw.push(b'\r'),
+                        b't' => raw.push(b'\t'),
+                        &other => raw.push(other),
+                    },
+                    b'(' => {
+                        depth += 1;
+                        raw.push(byte);
+                    }
+                    b')' if depth == 0 => break,
+                    b')' => {
+                        depth -= 1;
+                        raw.push(byte);
+                    }
+                    _ => raw.push(byte),
+                }
+            }
+            raw
+        }
+        b'<' => {
+            let end = bytes.iter().position(|&byte| byte == b'>')?;
+            let hex: Vec<u8> = bytes[1..end].iter().copied().filter(u8::is_ascii_hexdigit).collect();
+            hex.chunks(2)
+                .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect::<Option<_>>()?
+        }
+        _ => return None,
+    };
+
+    let title = match raw.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => raw.iter().map(|&byte| byte as char).collect(),
+    };
+    let title = title.trim();
+
+    (!title.is_empty()).then(|| title.chars().filter(|c| !c.is_control()).collect())
+}
+
+/// Walks the RIFF chunks for `fmt ` and `data`.
+fn wav(bytes: &[u8]) -> Option<Vec<String>> {
+    let mut at = 12;
+    let mut format = None;
+
+    loop {
+        let id = bytes.get(at..at + 4)?;
+        let size = le_u32(bytes, at + 4)? as usize;
+
+        match id {
+            b"fmt " => {
+                let channel_count = le_u16(bytes, at + 10)?;
+                let sample_rate = le_u32(bytes, at + 12)?;
+                let byte_rate = le_u32(bytes, at + 16)?;
+                let bits = le_u16(bytes, at + 22)?;
+                format = Some((channel_count, sample_rate, byte_rate, bits));
+            }
+            b"data" => {
+                let (channel_count, sample_rate, byte_rate, bits) = format?;
+                let mut details = vec![
+                    format!("{} Hz", sample_rate),
+                    format!("{}-bit", bits),
+                    channels(channel_count.into()),
+                ];
+                if byte_rate > 0 {
+                    details.push(duration(size as f64 / byte_rate as f64));
+                }
+                return Some(details);
+            }
+            _ => {}
+        }
+
+        // chunks are padded to an even size
+        at += 8 + size + size % 2;
+    }
+}
+
+/// STREAMINFO is always the first metadata block.
+fn flac(bytes: &[u8]) -> Option<Vec<String>> {
+    if bytes.get(4)? & 0x7f != 0 {
+        return None;
+    }
+
+    // after the block sizes: 20 bits of sample rate, 3 of channels - 1,
+    // 5 of bits per sample - 1 and 36 of total samples
+    let packed = be_u64(bytes, 18)?;
+    let sample_rate = (packed >> 44) as u32;
+    let channel_count = ((packed >> 41) & 0x07) as u32 + 1;
+    let bits = ((packed >> 36) & 0x1f) as u32 + 1;
+    let samples = packed & 0xf_ffff_ffff;
+    if sample_rate == 0 {
+        return None;
+    }
+
+    let mut details = vec![format!("{} Hz", sample_rate), format!("{}-bit", bits), channels(channel_count)];
+    if samples > 0 {
+        details.push(duration(samples as f64 / sample_rate as f64));
+    }
+    Some(details)
+}
+
+/// The brand from `ftyp`, the duration from `mvhd` and the codecs of the
+/// tracks' `stsd` sample entries, which may sit at either end of the file.
+fn mp4(head: &[u8], tail: &[u8]) -> Option<Vec<String>> {
+    let brand = String::from_utf8_lossy(head.get(8..12)?).trim().to_string();
+    let mut details = vec![brand];
+
+    for bytes in [head, tail] {
+        if let Some(at) = find(bytes, b"mvhd") {
+            let version = *bytes.get(at + 4)?;
+            let (timescale, length) = match version {
+                0 => (be_u32(bytes, at + 16)?, be_u32(bytes, at + 20)?.into()),
+                _ => (be_u32(bytes, at + 24)?, be_u64(bytes, at + 28)?),
+            };
+            if timescale > 0 {
+                details.push(duration(length as f64 / timescale as f64));
+            }
+            break;
+        }
+    }
+
+    let mut codecs: Vec<String> = Vec::new();
+    for bytes in [head, tail] {
+        let mut rest = bytes;
+        while let Some(at) = find(rest, b"stsd") {
+            // version and flags, the entry count, then the first entry's size and format
+            if let Some(format) = rest.get(at + 16..at + 20) {
+                let codec = String::from_utf8_lossy(format).into_owned();
+                if format.iter().all(u8::is_ascii_alphanumeric) && !codecs.contains(&codec) {
+                    codecs.push(codec);
+                }
+            }
+            rest = &rest[at + 4..];
+        }
+    }
+    if !codecs.is_empty() {
+        details.push(codecs.join("+"));
+    }
+
+    Some(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(bytes: &[u8]) -> Option<String> {
+        let kind = FileKind::sniff(bytes)?;
+        MetadataSummary::parse(kind, bytes, &[]).map(|summary| summary.to_string())
+    }
+
+    fn png(color: u8, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend(640u32.to_be_bytes());
+        bytes.extend(480u32.to_be_bytes());
+        bytes.extend([8, color, 0, 0, 0]);
+        bytes.extend(chunks.concat());
+        bytes
+    }
+
+    #[test]
+    fn formats_are_sniffed_from_their_magic_bytes() {
+        assert_eq!(FileKind::sniff(b"\x89PNG\r\n\x1a\n"), Some(FileKind::Png));
+        assert_eq!(FileKind::sniff(&[0xff, 0xd8, 0xff, 0xe0]), Some(FileKind::Jpeg));
+        assert_eq!(FileKind::sniff(b"GIF87a"), Some(FileKind::Gif));
+        assert_eq!(FileKind::sniff(b"%PDF-1.4"), Some(FileKind::Pdf));
+        assert_eq!(FileKind::sniff(b"RIFF\0\0\0\0WAVEfmt "), Some(FileKind::Wav));
+        assert_eq!(FileKind::sniff(b"RIFF\0\0\0\0AVI LIST"), None);
+        assert_eq!(FileKind::sniff(b"fLaC"), Some(FileKind::Flac));
+        assert_eq!(FileKind::sniff(b"\0\0\0\x18ftypmp42"), Some(FileKind::Mp4));
+        assert_eq!(FileKind::sniff(b"\x7fELF"), None);
+        assert_eq!(FileKind::sniff(b""), None);
+    }
+
+    #[test]
+    fn png_headers_give_size_color_and_animation() {
+        assert_eq!(summary(&png(6, &[])).unwrap(), "PNG image, 640x480, RGBA, 8-bit");
+        assert_eq!(summary(&png(3, &[b"\0\0\0\x08acTL", b"\0\0\0\0IDAT"])).unwrap(), "PNG image, 640x480, indexed, 8-bit, animated");
+        // an acTL after the image data is no animation control
+        assert_eq!(summary(&png(0, &[b"\0\0\0\0IDAT", b"acTL"])).unwrap(), "PNG image, 640x480, grayscale, 8-bit");
+        assert_eq!(summary(&png(5, &[])), None);
+        assert_eq!(summary(&png(2, &[])[..20]), None);
+    }
+
+    #[test]
+    fn jpeg_segments_are_walked_to_the_frame() {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0, 0];
+        // a fill byte, then a DHT segment sharing the SOF range
+        jpeg.extend([0xff, 0xff, 0xc4, 0x00, 0x03, 0]);
+        jpeg.extend([0xff, 0xc2, 0x00, 0x11, 8, 0x01, 0xe0, 0x02, 0x80, 3]);
+        assert_eq!(summary(&jpeg).unwrap(), "JPEG image, 640x480, YCbCr, progressive");
+
+        jpeg[15] = 0xc0;
+        jpeg[23] = 1;
+        assert_eq!(summary(&jpeg).unwrap(), "JPEG image, 640x480, grayscale");
+        assert_eq!(summary(&jpeg[..20]), None);
+        assert_eq!(summary(&[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0, 0, 0x12]), None);
+    }
+
+    #[test]
+    fn gif_screens_give_size_and_colors() {
+        assert_eq!(summary(b"GIF89a\x0a\x00\x14\x00\x82").unwrap(), "GIF image, 10x20, 8 colors, version 89a");
+        assert_eq!(summary(b"GIF87a\x01\x01\x01\x00\x00").unwrap(), "GIF image, 257x1, version 87a");
+        assert_eq!(summary(b"GIF87a\x01\x01"), None);
+    }
+
+    #[test]
+    fn pdf_pages_come_from_the_page_tree_and_titles_are_decoded() {
+        let pdf = b"%PDF-1.7\n1 0 obj << /Type /Pages /Kids [] /Count 12 >> endobj\n2 0 obj << /Type /Page /Count 99 >> endobj\n3 0 obj << /Type/Pages /Count 3 >>\n<< /Title (A \\(nested\\) title\\n) >>";
+        assert_eq!(summary(pdf).unwrap(), "PDF document, version 1.7, 12 pages, \"A (nested) title\"");
+
+        let pdf = b"%PDF-2.0\n<< /Count 1 /Type /Pages >> << /Title <FEFF 0048 0069> >>";
+        assert_eq!(summary(pdf).unwrap(), "PDF document, version 2.0, 1 page, \"Hi\"");
+        assert_eq!(summary(b"%PDF-1.4\n<< /Title () >>").unwrap(), "PDF document, version 1.4");
+    }
+
+    #[test]
+    fn wav_chunks_give_the_format_and_duration() {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x02\0".to_vec();
+        wav.extend(44100u32.to_le_bytes());
+        wav.extend(176400u32.to_le_bytes());
+        wav.extend([4, 0, 16, 0]);
+        // an odd-sized chunk is padded
+        wav.extend(b"LIST\x03\0\0\0abc\0");
+        wav.extend(b"data");
+        wav.extend((176400u32 * 90).to_le_bytes());
+        assert_eq!(summary(&wav).unwrap(), "WAV audio, 44100 Hz, 16-bit, stereo, 1:30");
+
+        // data before any fmt chunk says nothing
+        assert_eq!(summary(b"RIFF\0\0\0\0WAVEdata\0\0\0\0"), None);
+    }
+
+    #[test]
+    fn flac_streaminfo_is_unpacked() {
+        let samples = 48000u64 * 3725;
+        let packed = (48000u64 << 44) | (23 << 36) | samples;
+        let mut flac = b"fLaC\x80\0\0\x22".to_vec();
+        flac.extend([0; 10]);
+        flac.extend(packed.to_be_bytes());
+        assert_eq!(summary(&flac).unwrap(), "FLAC audio, 48000 Hz, 24-bit, mono, 1:02:05");
+
+        // the first block must be STREAMINFO
+        flac[4] = 0x04;
+        assert_eq!(summary(&flac), None);
+    }
+
+    #[test]
+    fn mp4_boxes_are_found_at_either_end() {
+        let mut head = b"\0\0\0\x18ftypisom\0\0\0\0".to_vec();
+        head.extend(b"stsd\0\0\0\0\0\0\0\x01\0\0\0\0mp4a");
+        let mut tail = b"mvhd\0\0\0\0\0\0\0\0\0\0\0\0".to_vec();
+        tail.extend(1000u32.to_be_bytes());
+        tail.extend(65000u32.to_be_bytes());
+        tail.extend(b"stsd\0\0\0\0\0\0\0\x01\0\0\0\0avc1stsd\0\0\0\0\0\0\0\x01\0\0\0\0mp4a");
+
+        let summary = MetadataSummary::parse(FileKind::Mp4, &head, &tail).unwrap();
+        assert_eq!(summary.to_string(), "MP4 media, isom, 1:05, mp4a+avc1");
+
+        let mut version_1 = b"\0\0\0\x18ftypM4A mvhd\x01".to_vec();
+        version_1.extend([0; 19]);
+        version_1.extend(600u32.to_be_bytes());
+        version_1.extend((600u64 * 4000).to_be_bytes());
+        let summary = MetadataSummary::parse(FileKind::Mp4, &version_1, &[]).unwrap();
+        assert_eq!(summary.details, ["M4A", "1:06:40"]);
+    }
+
+    #[test]
+    fn files_are_read_at_both_ends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.pdf");
+        let mut pdf = b"%PDF-1.5\n".to_vec();
+        pdf.resize(WINDOW as usize + 1000, b' ');
+        pdf.extend(b"<< /Type /Pages /Count 7 >>");
+        std::fs::write(&path, &pdf).unwrap();
+        let summary = MetadataSummary::read(path.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(summary.to_string(), "PDF document, version 1.5, 7 pages");
+
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\ncorrupt").unwrap();
+        assert_eq!(MetadataSummary::read(path.to_str().unwrap()).unwrap(), None);
+        std::fs::write(&path, b"plain text").unwrap();
+        assert_eq!(MetadataSummary::read(path.to_str().unwrap()).unwrap(), None);
+
+        let missing = dir.path().join("missing.png");
+        let error = MetadataSummary::read(missing.to_str().unwrap()).unwrap_err().to_string();
+        assert_eq!(error, format!("Failed to read file '{}'", missing.display()));
+    }
+
+    #[test]
+    fn durations_and_channels_read_naturally() {
+        assert_eq!(duration(0.4), "0:00");
+        assert_eq!(duration(3599.4), "59:59");
+        assert_eq!(duration(3600.0), "1:00:00");
+        assert_eq!((channels(1), channels(2), channels(6)), ("mono".to_string(), "stereo".to_string(), "6 channels".to_string()));
+    }
+}
@@ -0,0 +1,97 @@
+use syntect::parsing::{Scope, ScopeStack, ScopeStackOp};
+
+use crate::brackets;
+
+/// An editor fold marker: `#region` and `#endregion` directives as in C#
+/// and C++, `region` and `endregion` comments as VS Code and JetBrains
+/// editors take them, and vim's `{{{` and `}}}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FoldMarker {
+    Start,
+    End,
+}
+
+/// The fold marker on `line`, if it has one. Apart from directives, which
+/// start the line, markers are only recognized inside comments, so strings
+/// and code that happen to contain them don't fold. `stack` is the scope
+/// stack at the start of the line and `ops` are the parser operations for it.
+pub(crate) fn marker(line: &str, stack: &ScopeStack, ops: &[(usize, ScopeStackOp)]) -> Option<FoldMarker> {
+    let trimmed = line.trim_start();
+    let directive = match trimmed.strip_prefix("#pragma") {
+        Some(pragma) => pragma.trim_start(),
+        None => trimmed.strip_prefix('#').unwrap_or(""),
+    };
+    if let Some(marker) = region_word(directive) {
+        return Some(marker);
+    }
+
+    let comment = Scope::new("comment").expect("valid scope");
+    let mut opens = 0;
+    let mut closes = 0;
+    for range in brackets::scope_ranges(stack, ops, line.len(), &[comment]) {
+        let text = &line[range];
+        // a comment that is only a region marker, after its own punctuation
+        let word = text.trim_start_matches(|character: char| character.is_whitespace() || "/*#;-%!'<".contains(character));
+        if let Some(marker) = region_word(word) {
+            return Some(marker);
+        }
+        opens += text.matches("{{{").count();
+        closes += text.matches("}}}").count();
+    }
+
+    match opens.cmp(&closes) {
+        std::cmp::Ordering::Greater => Some(FoldMarker::Start),
+        std::cmp::Ordering::Less => Some(FoldMarker::End),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Whether `text` starts with the word `region` or `endregion`.
+fn region_word(text: &str) -> Option<FoldMarker> {
+    let is_word_end = |rest: &str| !rest.starts_with(|character: char| character.is_alphanumeric() || character == '_');
+
+    match (text.strip_prefix("endregion"), text.strip_prefix("region")) {
+        (Some(rest), _) if is_word_end(rest) => Some(FoldMarker::End),
+        (_, Some(rest)) if is_word_end(rest) => Some(FoldMarker::Start),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::parsing::{ParseState, SyntaxSet};
+    use syntect::util::LinesWithEndings;
+
+    /// The marker of each line of `content`, parsed as `extension`.
+    fn markers(content: &str, extension: &str) -> Vec<Option<FoldMarker>> {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let mut state = ParseState::new(syntax_set.find_syntax_by_extension(extension).unwrap());
+        let mut stack = ScopeStack::new();
+        LinesWithEndings::from(content).map(|line| {
+            let ops = state.parse_line(line, &syntax_set).unwrap();
+            let marker = marker(line, &stack, &ops);
+            for (_, op) in &ops {
+                stack.apply(op).unwrap();
+            }
+            marker
+        }).collect()
+    }
+
+    #[test]
+    fn directives_start_and_end_regions() {
+        let content = "#region Fields\nint a;\n  #pragma region inner\n#endregion\n#regional\n";
+        assert_eq!(markers(content, "cs"), [Some(FoldMarker::Start), None, Some(FoldMarker::Start), Some(FoldMarker::End), None]);
+    }
+
+    #[test]
+    fn comment_markers_only_count_inside_comments() {
+        let content = "// region Setup\nlet s = \"// region\";\n// endregion\n/* {{{ */\nx({{{ }}});\n// }}}\n";
+        assert_eq!(
+            markers(content, "rs"),
+            [Some(FoldMarker::Start), None, Some(FoldMarker::End), Some(FoldMarker::Start), None, Some(FoldMarker::End)],
+        );
+        // a comment mentioning the word isn't a marker
+        assert_eq!(markers("# the region code\n# {{{ }}}\n", "py"), [None, None]);
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+
+use crate::OutputProfile;
+
+/// A terminal escape protocol for showing images inline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// kitty's graphics protocol, also spoken by WezTerm, Ghostty and Konsole
+    Kitty,
+    /// iTerm2's inline images, also spoken by WezTerm and mintty
+    Iterm2,
+}
+
+/// Bytes of base64 per kitty escape; the protocol allows at most 4096.
+const KITTY_CHUNK: usize = 4096;
+
+/// Pixels per terminal cell assumed when sizing an image, which also caps
+/// how many pixels are sent.
+const CELL_WIDTH: u32 = 10;
+const CELL_HEIGHT: u32 = 20;
+
+impl ImageProtocol {
+    /// Guesses the protocol the terminal speaks from `$TERM`, `$TERM_PROGRAM`
+    /// and `$LC_TERMINAL`, which terminals set for themselves.
+    pub fn detect(term: Option<&str>, term_program: Option<&str>, lc_terminal: Option<&str>) -> Option<Self> {
+        match (term.unwrap_or(""), term_program.unwrap_or(""), lc_terminal.unwrap_or("")) {
+            (term, _, _) if term.contains("kitty") || term.contains("ghostty") => Some(Self::Kitty),
+            (_, "WezTerm" | "ghostty", _) => Some(Self::Kitty),
+            (_, "iTerm.app", _) | (_, _, "iTerm2") => Some(Self::Iterm2),
+            _ => None,
+        }
+    }
+
+    /// Reads the protocol from the environment, see [`detect`](Self::detect).
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok();
+        Self::detect(var("TERM").as_deref(), var("TERM_PROGRAM").as_deref(), var("LC_TERMINAL").as_deref())
+    }
+
+    /// Renders the image in `path`, its first frame for an animation, scaled
+    /// down to fit `columns` by `rows` cells, followed by a line ending. The
+    /// escapes go through `profile`'s passthrough.
+    pub fn render_file(self, path: &str, columns: usize, rows: Option<usize>, profile: &OutputProfile) -> Result<String> {
+        let image = image::ImageReader::open(path)
+            .with_context(|| format!("Failed to read image '{}'", path))?
+            .with_guessed_format()
+            .with_context(|| format!("Failed to read image '{}'", path))?
+            .decode()
+            .with_context(|| format!("Failed to decode image '{}'", path))?;
+
+        self.render(&image, columns, rows, profile)
+    }
+
+    /// Renders `image` scaled down to fit `columns` by `rows` cells.
+    pub fn render(self, image: &DynamicImage, columns: usize, rows: Option<usize>, profile: &OutputProfile) -> Result<String> {
+        let (columns, rows) = fit(image.width(), image.height(), columns, rows);
+        let (max_width, max_height) = (columns as u32 * CELL_WIDTH, rows as u32 * CELL_HEIGHT);
+        let resized;
+        let image = if image.width() > max_width || image.height() > max_height {
+            resized = image.resize(max_width, max_height, FilterType::Triangle);
+            &resized
+        } else {
+            image
+        };
+
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .context("Failed to encode image")?;
+
+        Ok(match self {
+            Self::Kitty => kitty(&png, columns, rows, profile),
+            Self::Iterm2 => iterm2(&png, columns, rows, profile),
+        })
+    }
+}
+
+/// The size in cells of a `width` by `height` pixel image shown at most
+/// `columns` wide and `rows` high, keeping its aspect ratio and never
+/// scaling it up.
+fn fit(width: u32, height: u32, columns: usize, rows: Option<usize>) -> (usize, usize) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let natural = (width / CELL_WIDTH as f64).ceil();
+
+    let mut scale = (columns.max(1) as f64 / natural).min(1.0);
+    let natural_rows = height / CELL_HEIGHT as f64;
+    if let Some(rows) = rows {
+        scale = scale.min(rows.max(1) as f64 / natural_rows);
+    }
+
+    let columns = (natural * scale).round().max(1.0) as usize;
+    let rows = (natural_rows * scale).round().max(1.0) as usize;
+    (columns, rows)
+}
+
+/// A PNG transmitted and displayed at once (`a=T`), split into chunks with
+/// `m=1` on every one but the last.
+fn kitty(png: &[u8], columns: usize, rows: usize, profile: &OutputProfile) -> String {
+    let payload = base64(png);
+    let chunks: Vec<&str> = payload.as_bytes()
+        .chunks(KITTY_CHUNK)
+        .map(|chunk| std::str::from_utf8(chunk).expect("base64 is ascii"))
+        .collect();
+
+    let mut output = String::with_capacity(payload.len() + chunks.len() * 16);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        let sequence = if index == 0 {
+            format!("\x1b_Gf=100,a=T,c={},r={},m={};{}\x1b\\", columns, rows, more, chunk)
+        } else {
+            format!("\x1b_Gm={};{}\x1b\\", more, chunk)
+        };
+        output.push_str(&profile.passthrough(&sequence));
+    }
+    output.push('\n');
+    output
+}
+
+/// A file sent inline in a single OSC 1337 sequence.
+fn iterm2(png: &[u8], columns: usize, rows: usize, profile: &OutputProfile) -> String {
+    let sequence = format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        png.len(), columns, rows, base64(png),
+    );
+    profile.passthrough(&sequence) + "\n"
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let triple = u32::from(chunk[0]) << 16
+            | u32::from(chunk.get(1).copied().unwrap_or(0)) << 8
+            | u32::from(chunk.get(2).copied().unwrap_or(0));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Multiplexer;
+
+    fn fixture() -> String {
+        format!("{}/tests/fixtures/pixels.png", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// An image whose PNG doesn't compress, so it takes several kitty chunks.
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut state = 0x2545_f491_u32;
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let [r, g, b, _] = state.to_be_bytes();
+            image::Rgb([r, g, b])
+        }))
+    }
+
+    #[test]
+    fn the_protocol_is_guessed_from_the_environment() {
+        assert_eq!(ImageProtocol::detect(Some("xterm-kitty"), None, None), Some(ImageProtocol::Kitty));
+        assert_eq!(ImageProtocol::detect(Some("xterm-256color"), Some("WezTerm"), None), Some(ImageProtocol::Kitty));
+        assert_eq!(ImageProtocol::detect(Some("xterm-256color"), Some("iTerm.app"), None), Some(ImageProtocol::Iterm2));
+        // over ssh only LC_TERMINAL survives
+        assert_eq!(ImageProtocol::detect(Some("xterm-256color"), None, Some("iTerm2")), Some(ImageProtocol::Iterm2));
+        assert_eq!(ImageProtocol::detect(Some("xterm-256color"), Some("Apple_Terminal"), None), None);
+        assert_eq!(ImageProtocol::detect(None, None, None), None);
+    }
+
+    #[test]
+    fn images_fit_the_cells_without_growing() {
+        // 400x200 pixels are 40 cells wide and 10 rows high
+        assert_eq!(fit(400, 200, 80, None), (40, 10));
+        assert_eq!(fit(400, 200, 20, None), (20, 5));
+        assert_eq!(fit(400, 200, 80, Some(4)), (16, 4));
+        assert_eq!(fit(1, 1, 80, Some(24)), (1, 1));
+        assert_eq!(fit(0, 0, 0, Some(0)), (1, 1));
+    }
+
+    #[test]
+    fn base64_pads_the_last_group() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar\xff"), "Zm9vYmFy/w==");
+    }
+
+    #[test]
+    fn kitty_sends_the_png_in_chunks() {
+        let output = ImageProtocol::Kitty.render(&noise(120, 60), 80, None, &OutputProfile::FULL).unwrap();
+
+        let body = output.strip_suffix('\n').unwrap();
+        let sequences: Vec<&str> = body.split_inclusive("\x1b\\").collect();
+        assert!(sequences.len() > 2, "{} chunks", sequences.len());
+        assert!(sequences[0].starts_with("\x1b_Gf=100,a=T,c=12,r=3,m=1;"));
+        for (index, sequence) in sequences.iter().enumerate().skip(1) {
+            let more = if index + 1 < sequences.len() { 1 } else { 0 };
+            assert!(sequence.starts_with(&format!("\x1b_Gm={};", more)), "{:?}", &sequence[..8]);
+        }
+
+        let payload: String = sequences.iter()
+            .map(|sequence| sequence.split_once(';').unwrap().1.strip_suffix("\x1b\\").unwrap())
+            .collect();
+        assert!(sequences.iter().all(|sequence| sequence.split_once(';').unwrap().1.len() <= KITTY_CHUNK + 2));
+        // a PNG's signature in base64
+        assert!(payload.starts_with("iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn iterm2_sends_the_file_in_one_sequence() {
+        let output = ImageProtocol::Iterm2.render_file(&fixture(), 80, None, &OutputProfile::FULL).unwrap();
+
+        let rest = output.strip_prefix("\x1b]1337;File=inline=1;size=").unwrap();
+        let (size, rest) = rest.split_once(';').unwrap();
+        let (options, payload) = rest.split_once(':').unwrap();
+        assert_eq!(options, "width=1;height=1;preserveAspectRatio=1");
+        let payload = payload.strip_suffix("\x07\n").unwrap();
+        assert_eq!(payload.len(), size.parse::<usize>().unwrap().div_ceil(3) * 4);
+        assert!(payload.starts_with("iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn the_escapes_go_through_the_multiplexer() {
+        let profile = OutputProfile { multiplexer: Some(Multiplexer::Tmux), ..OutputProfile::FULL };
+        let output = ImageProtocol::Kitty.render_file(&fixture(), 80, None, &profile).unwrap();
+
+        assert!(output.starts_with("\x1bPtmux;\x1b\x1b_Gf=100,a=T,c=1,r=1,m=0;"));
+        assert!(output.ends_with("\x1b\x1b\\\x1b\\\n"));
+    }
+
+    #[test]
+    fn unreadable_images_are_errors() {
+        let error = ImageProtocol::Kitty.render_file(&format!("{}/tests/fixtures/sample.rs", env!("CARGO_MANIFEST_DIR")), 80, None, &OutputProfile::FULL);
+        assert!(error.unwrap_err().to_string().starts_with("Failed to"));
+    }
+}
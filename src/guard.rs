@@ -0,0 +1,66 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::Read;
+
+use crate::input;
+
+/// How many leading bytes are checked for NULs when sniffing for binary files.
+pub(crate) const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Whether `bytes`, the start of a file, look like binary data: a NUL in the
+/// sniffed prefix, unless a UTF-16 byte order mark explains it.
+pub(crate) fn looks_binary(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+        return false;
+    }
+
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// Reads up to the first [`BINARY_SNIFF_LEN`] bytes of `path`.
+pub(crate) fn read_prefix(path: &str) -> Result<Vec<u8>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read file '{}'", path))?;
+
+    let mut prefix = Vec::with_capacity(BINARY_SNIFF_LEN);
+    file.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut prefix)
+        .with_context(|| format!("Failed to read file '{}'", path))?;
+
+    Ok(prefix)
+}
+
+/// Whether `bytes`, the start of a file, are text, as [`is_text_file`]
+/// judges a file. Gzip is judged by what it decompresses to.
+pub fn is_text(bytes: &[u8]) -> bool {
+    match input::gunzip_prefix(bytes, BINARY_SNIFF_LEN) {
+        Some(decompressed) => !looks_binary(&decompressed),
+        None => !looks_binary(bytes),
+    }
+}
+
+/// Checks whether `path` is text by reading only its first few kilobytes.
+pub fn is_text_file(path: &str) -> Result<bool> {
+    Ok(is_text(&read_prefix(path)?))
+}
+
+/// Parses a size like `512`, `64K`, `2MB` or `1.5GiB`. Both decimal and
+/// binary suffixes mean powers of 1024, as in most file managers.
+pub fn parse_size(spec: &str) -> Result<u64> {
+    let spec = spec.trim();
+    let split = spec.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(spec.len());
+    let (number, unit) = spec.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => bail!("Invalid size '{}', expected e.g. 512K or 2MB", spec),
+    };
+
+    let number: f64 = number.parse()
+        .with_context(|| format!("Invalid size '{}', expected e.g. 512K or 2MB", spec))?;
+
+    Ok((number * multiplier as f64) as u64)
+}
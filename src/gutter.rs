@@ -0,0 +1,239 @@
+use crate::{HighlighterConfig, Severity, Span, Style};
+use crate::width::{self, AmbiguousWidth};
+use anyhow::Result;
+use serde::Serialize;
+
+/// Narrowest line number field, so short files line up with longer ones.
+const MIN_LINE_NUMBER_WIDTH: usize = 4;
+
+/// Tab stops terminals use, for measuring unexpanded tabs.
+pub(crate) const TERMINAL_TAB_WIDTH: usize = 8;
+
+/// Which side of the code the gutter goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GutterSide {
+    #[default]
+    Left,
+    /// After the code, aligned to the right edge
+    Right,
+}
+
+/// The base line numbers are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberBase {
+    #[default]
+    Dec,
+    /// With a `0x` prefix
+    Hex,
+    /// With a `0o` prefix
+    Oct,
+}
+
+impl NumberBase {
+    fn prefix(self) -> &'static str {
+        match self {
+            NumberBase::Dec => "",
+            NumberBase::Hex => "0x",
+            NumberBase::Oct => "0o",
+        }
+    }
+
+    fn digits(self, number: usize) -> String {
+        match self {
+            NumberBase::Dec => number.to_string(),
+            NumberBase::Hex => format!("{:x}", number),
+            NumberBase::Oct => format!("{:o}", number),
+        }
+    }
+}
+
+/// `number` in `base`, right aligned in a field of `width` columns, its
+/// prefix included; with `pad` the digits are padded with zeros after the
+/// prefix instead, as in `0x004a`. Every number the gutter shows goes
+/// through here, so they read the same wherever they are.
+pub fn format_gutter_number(number: usize, base: NumberBase, width: usize, pad: bool) -> String {
+    let prefix = base.prefix();
+    let digits = base.digits(number);
+    match pad {
+        true => format!("{}{:0>width$}", prefix, digits, width = width.saturating_sub(prefix.len())),
+        false => format!("{:>width$}", format!("{}{}", prefix, digits), width = width),
+    }
+}
+
+/// Lays out the line number gutter for a file, kept apart from the content
+/// so a viewer can draw it in its own pane.
+#[derive(Debug, Clone)]
+pub struct GutterRenderer {
+    number_width: usize,
+    delimiter: Option<char>,
+    padding: bool,
+    base: NumberBase,
+    /// Pad numbers with zeros rather than spaces
+    zeros: bool,
+    /// Leave room for a diagnostic badge before the number
+    badges: bool,
+    ambiguous: AmbiguousWidth,
+}
+
+impl GutterRenderer {
+    /// Sizes the gutter for a file of `line_count` lines, following the
+    /// `line_number_width`, `gutter_delimiter`, `gutter_padding`,
+    /// `number_base` and `number_pad_zero` options.
+    pub fn new(line_count: usize, config: &HighlighterConfig) -> Result<Self> {
+        let base = config.number_base;
+        let widest = format_gutter_number(line_count, base, 0, false).len();
+        let number_width = match config.line_number_width {
+            Some(width) => {
+                if !config.line_number_overflow && widest > width {
+                    anyhow::bail!("Line number width {} is too small for line {}", width, format_gutter_number(line_count, base, 0, false));
+                }
+                width
+            }
+            None => widest.max(base.prefix().len() + MIN_LINE_NUMBER_WIDTH),
+        };
+
+        Ok(Self {
+            number_width,
+            delimiter: config.gutter_delimiter,
+            padding: config.gutter_padding,
+            base,
+            zeros: config.number_pad_zero,
+            badges: !config.diagnostics.is_empty(),
+            ambiguous: config.profile.ambiguous_width,
+        })
+    }
+
+    /// Columns the line number field is padded to.
+    pub fn number_width(&self) -> usize {
+        self.number_width
+    }
+
+    /// Columns the gutter takes, or 0 without padding, where it varies by line.
+    pub fn width(&self) -> usize {
+        if self.padding { self.badge(None).len() + self.number_width + width::display_width(&self.delimiter(), TERMINAL_TAB_WIDTH, self.ambiguous) } else { 0 }
+    }
+
+    /// The badge column: the letter of `severity` and a space, blank without
+    /// one, and empty when the file has no diagnostics.
+    pub fn badge(&self, severity: Option<Severity>) -> String {
+        match (self.badges, severity) {
+            (false, _) => String::new(),
+            (true, Some(severity)) => format!("{} ", severity.badge()),
+            (true, None) => "  ".to_string(),
+        }
+    }
+
+    /// The line number field; blank for continuation rows, which have no number.
+    pub fn number(&self, number: Option<usize>) -> String {
+        match (number, self.padding) {
+            (Some(number), true) => format_gutter_number(number, self.base, self.number_width, self.zeros),
+            (Some(number), false) => format_gutter_number(number, self.base, 0, false),
+            (None, true) => " ".repeat(self.number_width),
+            (None, false) => String::new(),
+        }
+    }
+
+    /// The field for a run of lines shown once, as `first-last`, which may
+    /// be wider than the field.
+    pub fn range(&self, first: usize, last: usize) -> String {
+        let range = format!("{}-{}", format_gutter_number(first, self.base, 0, false), format_gutter_number(last, self.base, 0, false));
+        if self.padding { format!("{:>width$}", range, width = self.number_width) } else { range }
+    }
+
+    /// What separates the gutter from the content.
+    pub fn delimiter(&self) -> String {
+        match self.delimiter {
+            Some(delimiter) => delimiter.to_string(),
+            None => " | ".to_string(),
+        }
+    }
+
+    /// The gutter as text, number then delimiter.
+    pub fn render(&self, number: Option<usize>) -> String {
+        self.number(number) + &self.delimiter()
+    }
+
+    /// The gutter as styled cells for a viewer to draw: the number field and
+    /// the delimiter.
+    pub fn cells(&self, number: Option<usize>, style: Style) -> [Span; 2] {
+        [
+            Span { style, text: self.number(number) },
+            Span { style, text: self.delimiter() },
+        ]
+    }
+}
+
+/// Puts `gutter` after the first line of the rendered `row`, padded to end
+/// at column `edge`. A note the row put on a line of its own stays below.
+pub(crate) fn place_right(row: &str, gutter: &str, edge: usize, ambiguous: AmbiguousWidth) -> String {
+    let first_end = row.find(['\r', '\n']).unwrap_or(row.len());
+    let (first, rest) = row.split_at(first_end);
+
+    let used = width::visible_width(first, TERMINAL_TAB_WIDTH, ambiguous) + width::visible_width(gutter, TERMINAL_TAB_WIDTH, ambiguous);
+    let padding = edge.saturating_sub(used);
+
+    format!("{}{}{}{}", first, " ".repeat(padding), gutter, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_right_aligned_with_their_prefix() {
+        assert_eq!(format_gutter_number(42, NumberBase::Dec, 4, false), "  42");
+        assert_eq!(format_gutter_number(74, NumberBase::Hex, 6, true), "0x004a");
+        assert_eq!(format_gutter_number(8, NumberBase::Oct, 5, false), " 0o10");
+        assert_eq!(format_gutter_number(12345, NumberBase::Dec, 2, false), "12345");
+    }
+
+    #[test]
+    fn the_gutter_is_sized_to_the_line_count() {
+        let config = HighlighterConfig::default();
+        assert_eq!(GutterRenderer::new(9, &config).unwrap().number_width(), MIN_LINE_NUMBER_WIDTH);
+        assert_eq!(GutterRenderer::new(123_456, &config).unwrap().number_width(), 6);
+
+        let pinned = HighlighterConfig { line_number_width: Some(3), ..HighlighterConfig::default() };
+        assert!(GutterRenderer::new(1000, &pinned).is_err());
+        let overflowing = HighlighterConfig { line_number_overflow: true, ..pinned };
+        assert_eq!(GutterRenderer::new(1000, &overflowing).unwrap().render(Some(1000)), "1000 | ");
+    }
+
+    #[test]
+    fn an_unpadded_gutter_takes_no_fixed_width() {
+        let config = HighlighterConfig { gutter_delimiter: Some('\t'), gutter_padding: false, ..HighlighterConfig::default() };
+        let gutter = GutterRenderer::new(500, &config).unwrap();
+        assert_eq!(gutter.render(Some(42)), "42\t");
+        assert_eq!(gutter.render(None), "\t");
+        assert_eq!(gutter.width(), 0);
+        assert_eq!(gutter.range(3, 7), "3-7");
+    }
+
+    #[test]
+    fn hex_gutters_widen_past_each_power_of_sixteen() {
+        let hex = HighlighterConfig { number_base: NumberBase::Hex, ..HighlighterConfig::default() };
+        assert_eq!(GutterRenderer::new(0xffff, &hex).unwrap().number_width(), 6);
+        assert_eq!(GutterRenderer::new(0x10000, &hex).unwrap().number_width(), 7);
+        // the prefix doesn't eat into the minimum width
+        assert_eq!(GutterRenderer::new(0xf, &hex).unwrap().number_width(), 2 + MIN_LINE_NUMBER_WIDTH);
+
+        let decimal = HighlighterConfig::default();
+        assert_eq!(GutterRenderer::new(9999, &decimal).unwrap().number_width(), 4);
+        assert_eq!(GutterRenderer::new(10_000, &decimal).unwrap().number_width(), 5);
+    }
+
+    #[test]
+    fn every_number_follows_the_base_and_padding() {
+        let config = HighlighterConfig { number_base: NumberBase::Hex, number_pad_zero: true, ..HighlighterConfig::default() };
+        let gutter = GutterRenderer::new(300, &config).unwrap();
+        assert_eq!(gutter.render(Some(74)), "0x004a | ");
+        assert_eq!(gutter.render(None), "       | ");
+        assert_eq!(gutter.range(10, 255), "0xa-0xff");
+
+        let octal = HighlighterConfig { number_base: NumberBase::Oct, line_number_width: Some(4), ..HighlighterConfig::default() };
+        assert!(GutterRenderer::new(0o100, &octal).is_err());
+        assert_eq!(GutterRenderer::new(0o77, &octal).unwrap().render(Some(7)), " 0o7 | ");
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::OutputProfile;
+
+/// Bytes shown per row of a dump.
+const ROW: usize = 16;
+
+/// How [`hexdump`] lays out its rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexdumpConfig {
+    /// Offset of the first byte in the file, printed in the offset column
+    pub offset: u64,
+    pub profile: OutputProfile,
+}
+
+impl Default for HexdumpConfig {
+    fn default() -> Self {
+        Self { offset: 0, profile: OutputProfile::FULL }
+    }
+}
+
+/// A half-open window `start..end` of a file's bytes; either end may be
+/// left open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    /// Parses `START:END`, `START:` or `:END`, where each bound is a size
+    /// such as `512` or `4K`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((start, end)) = spec.split_once(':') else {
+            bail!("Invalid byte range '{}', expected START:END, e.g. 0:4K", spec);
+        };
+
+        let bound = |bound: &str| -> Result<Option<u64>> {
+            if bound.trim().is_empty() {
+                return Ok(None);
+            }
+            crate::parse_size(bound)
+                .map(Some)
+                .with_context(|| format!("Invalid byte range '{}'", spec))
+        };
+        let range = Self { start: bound(start)?.unwrap_or(0), end: bound(end)? };
+
+        if range.end.is_some_and(|end| end < range.start) {
+            bail!("Invalid byte range '{}', the end is before the start", spec);
+        }
+        Ok(range)
+    }
+
+    /// Reads the bytes of `path` in this range, seeking past the ones before it.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        file.seek(SeekFrom::Start(self.start))
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+
+        let mut bytes = Vec::new();
+        match self.end {
+            Some(end) => file.take(end - self.start).read_to_end(&mut bytes),
+            None => file.read_to_end(&mut bytes),
+        }
+        .with_context(|| format!("Failed to read file '{}'", path))?;
+
+        Ok(bytes)
+    }
+
+    /// The bytes in this range of `bytes`, a whole file already read.
+    pub fn slice<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        let start = usize::try_from(self.start).unwrap_or(usize::MAX).min(bytes.len());
+        let end = self.end.map_or(bytes.len(), |end| usize::try_from(end).unwrap_or(usize::MAX).min(bytes.len()));
+
+        &bytes[start..end]
+    }
+}
+
+/// Renders `bytes` as a classic hex and ASCII dump: an offset, sixteen
+/// bytes in two groups of eight, and the same bytes as ASCII between bars,
+/// like `hexdump -C`. Bytes that aren't printable ASCII show as `.` in the
+/// ASCII column and are dimmed in both. A last row gives the offset just
+/// past the end.
+pub fn hexdump(bytes: &[u8], config: &HexdumpConfig) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(ROW) * 80 + 10);
+
+    for (index, row) in bytes.chunks(ROW).enumerate() {
+        output.push_str(&format!("{:08x}  ", config.offset + (index * ROW) as u64));
+
+        let mut hex = Dimmer::new(config.profile);
+        for column in 0..ROW {
+            if column == ROW / 2 {
+                hex.push(" ", false);
+            }
+            match row.get(column) {
+                Some(&byte) => hex.push(&format!("{:02x} ", byte), !printable(byte)),
+                None => hex.push("   ", false),
+            }
+        }
+        output.push_str(&hex.finish());
+
+        let mut ascii = Dimmer::new(config.profile);
+        ascii.push(" |", false);
+        for &byte in row {
+            let shown = if printable(byte) { byte as char } else { '.' };
+            ascii.push(shown.encode_utf8(&mut [0; 4]), !printable(byte));
+        }
+        ascii.push("|", false);
+        output.push_str(&ascii.finish());
+        output.push('\n');
+    }
+
+    output.push_str(&format!("{:08x}\n", config.offset + bytes.len() as u64));
+    output
+}
+
+fn printable(byte: u8) -> bool {
+    byte.is_ascii_graphic() || byte == b' '
+}
+
+/// Builds a column, dimming runs of text with one escape per run rather
+/// than one per byte.
+struct Dimmer {
+    profile: OutputProfile,
+    text: String,
+    dim: bool,
+}
+
+impl Dimmer {
+    fn new(profile: OutputProfile) -> Self {
+        Self { profile, text: String::new(), dim: false }
+    }
+
+    fn push(&mut self, text: &str, dim: bool) {
+        // padding between dimmed bytes stays inside the run
+        let dim = dim || (self.dim && text.trim().is_empty());
+        if dim != self.dim {
+            self.text.push_str(self.profile.sgr(if dim { "\x1b[2m" } else { "\x1b[22m" }));
+            self.dim = dim;
+        }
+        self.text.push_str(text);
+    }
+
+    fn finish(mut self) -> String {
+        if self.dim {
+            self.text.push_str(self.profile.sgr("\x1b[22m"));
+        }
+        self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOB: &[u8] = b"ELF\x00\x01\x02hello, world!\xff\x7fABCDEFGH";
+
+    fn plain(offset: u64) -> HexdumpConfig {
+        HexdumpConfig { offset, profile: OutputProfile::DUMB }
+    }
+
+    #[test]
+    fn rows_have_an_offset_two_groups_and_an_ascii_gutter() {
+        assert_eq!(hexdump(BLOB, &plain(0)), concat!(
+            "00000000  45 4c 46 00 01 02 68 65  6c 6c 6f 2c 20 77 6f 72  |ELF...hello, wor|\n",
+            "00000010  6c 64 21 ff 7f 41 42 43  44 45 46 47 48           |ld!..ABCDEFGH|\n",
+            "0000001d\n",
+        ));
+    }
+
+    #[test]
+    fn offsets_start_where_the_bytes_were_read_from() {
+        assert_eq!(hexdump(b"ab", &plain(0x1ff0)), "00001ff0  61 62                                             |ab|\n00001ff2\n");
+        assert_eq!(hexdump(b"", &plain(7)), "00000007\n");
+    }
+
+    #[test]
+    fn non_printing_bytes_are_dimmed_a_run_at_a_time() {
+        let dump = hexdump(b"a\x00\x01b", &HexdumpConfig::default());
+        let first = dump.lines().next().unwrap();
+        assert_eq!(first, format!("00000000  61 \x1b[2m00 01 \x1b[22m62 {}|a\x1b[2m..\x1b[22mb|", " ".repeat(38)));
+    }
+
+    #[test]
+    fn byte_ranges_take_sizes_and_open_ends() {
+        assert_eq!(ByteRange::parse("0:4K").unwrap(), ByteRange { start: 0, end: Some(4096) });
+        assert_eq!(ByteRange::parse("1M:").unwrap(), ByteRange { start: 1 << 20, end: None });
+        assert_eq!(ByteRange::parse(":512").unwrap(), ByteRange { start: 0, end: Some(512) });
+        assert!(ByteRange::parse("512").is_err());
+        assert!(ByteRange::parse("8:4").is_err());
+        assert!(ByteRange::parse("x:4").is_err());
+    }
+
+    #[test]
+    fn slicing_and_reading_agree() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, BLOB).unwrap();
+        let path = file.path().to_str().unwrap();
+
+        for spec in ["4:20", ":3", "20:", "28:40", "100:"] {
+            let range = ByteRange::parse(spec).unwrap();
+            assert_eq!(range.read(path).unwrap(), range.slice(BLOB), "{}", spec);
+        }
+        assert_eq!(ByteRange::parse("4:8").unwrap().slice(BLOB), b"\x01\x02he");
+    }
+}
@@ -0,0 +1,162 @@
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::OutputProfile;
+
+/// A URL in text: a scheme and everything up to whitespace, quotes or angle
+/// brackets, which usually delimit it.
+static URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(
+    r#"\b(?:https?|ftp|file)://[^\s<>"'`]+"#,
+).expect("URL pattern is valid"));
+
+/// How gutter hyperlinks address a file and line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorUrl {
+    /// `file:///path#L42`
+    File,
+    /// `vscode://file/path:42`
+    Vscode,
+    /// `idea://open?file=/path&line=42`
+    Idea,
+    /// `subl://open?url=file:///path&line=42`
+    Sublime,
+    /// A template where `{path}` and `{line}` are substituted
+    Custom(String),
+}
+
+impl Serialize for EditorUrl {
+    /// As the scheme name or template it parses from.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Self::File => "file",
+            Self::Vscode => "vscode",
+            Self::Idea => "idea",
+            Self::Sublime => "subl",
+            Self::Custom(template) => template,
+        })
+    }
+}
+
+impl EditorUrl {
+    /// Parses a scheme name, treating anything unrecognized as a custom template.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "file" => Self::File,
+            "vscode" => Self::Vscode,
+            "idea" => Self::Idea,
+            "subl" | "sublime" => Self::Sublime,
+            template => Self::Custom(template.to_string()),
+        }
+    }
+
+    pub fn url(&self, path: &Path, line: usize) -> String {
+        let path = percent_encode(&path.to_string_lossy());
+
+        match self {
+            Self::File => format!("file://{}#L{}", path, line),
+            Self::Vscode => format!("vscode://file{}:{}", path, line),
+            Self::Idea => format!("idea://open?file={}&line={}", path, line),
+            Self::Sublime => format!("subl://open?url=file://{}&line={}", path, line),
+            Self::Custom(template) => template
+                .replace("{path}", &path)
+                .replace("{line}", &line.to_string()),
+        }
+    }
+}
+
+/// Wraps `text` in an OSC 8 hyperlink to `url`. The escapes opening and
+/// closing the link go through `profile`'s passthrough one at a time, so
+/// `text` stays visible to a multiplexer.
+pub(crate) fn osc8(url: &str, text: &str, profile: &OutputProfile) -> String {
+    format!(
+        "{}{}{}",
+        profile.passthrough(&format!("\x1b]8;;{}\x1b\\", url)),
+        text,
+        profile.passthrough("\x1b]8;;\x1b\\"),
+    )
+}
+
+/// The byte ranges of the URLs in `text`. Punctuation ending a sentence
+/// after a URL is left out, as is a closing bracket it doesn't open.
+pub(crate) fn url_spans(text: &str) -> Vec<(usize, usize)> {
+    URL.find_iter(text)
+        .map(|found| {
+            let mut url = found.as_str();
+            loop {
+                let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+                let trimmed = match trimmed.strip_suffix([')', ']', '}']) {
+                    Some(rest) if !rest.contains(['(', '[', '{']) => rest,
+                    _ => trimmed,
+                };
+                if trimmed.len() == url.len() {
+                    break;
+                }
+                url = trimmed;
+            }
+            (found.start(), found.start() + url.len())
+        })
+        .collect()
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_address_the_line_in_each_scheme() {
+        let path = Path::new("/src/my file.rs");
+        assert_eq!(EditorUrl::File.url(path, 42), "file:///src/my%20file.rs#L42");
+        assert_eq!(EditorUrl::Vscode.url(path, 42), "vscode://file/src/my%20file.rs:42");
+        assert_eq!(EditorUrl::Idea.url(path, 42), "idea://open?file=/src/my%20file.rs&line=42");
+        assert_eq!(EditorUrl::parse("sublime").url(path, 42), "subl://open?url=file:///src/my%20file.rs&line=42");
+        assert_eq!(EditorUrl::parse("edit://{path}?at={line}").url(path, 7), "edit:///src/my%20file.rs?at=7");
+    }
+
+    #[test]
+    fn schemes_serialize_as_the_names_they_parse_from() {
+        for name in ["file", "vscode", "idea", "subl", "edit://{path}?at={line}"] {
+            assert_eq!(serde_json::to_string(&EditorUrl::parse(name)).unwrap(), format!("{:?}", name));
+        }
+    }
+
+    #[test]
+    fn url_spans_leave_out_trailing_punctuation_and_unopened_brackets() {
+        let spans = |text: &str| url_spans(text).into_iter().map(|(start, end)| text[start..end].to_string()).collect::<Vec<_>>();
+
+        assert_eq!(spans("see https://example.com/a, then ftp://host/b."), ["https://example.com/a", "ftp://host/b"]);
+        assert_eq!(spans("(at https://example.com/x)"), ["https://example.com/x"]);
+        assert_eq!(spans("https://en.wikipedia.org/wiki/Rust_(language)!"), ["https://en.wikipedia.org/wiki/Rust_(language)"]);
+        assert_eq!(spans("<file:///tmp/a.txt> and \"http://q\""), ["file:///tmp/a.txt", "http://q"]);
+        assert!(spans("no links, just mailto:me and www.example.com").is_empty());
+    }
+
+    #[test]
+    fn osc8_wraps_the_text_in_a_link() {
+        let link = osc8("file:///a.rs#L3", "3", &OutputProfile::FULL);
+        assert_eq!(link, "\x1b]8;;file:///a.rs#L3\x1b\\3\x1b]8;;\x1b\\");
+    }
+
+    #[test]
+    fn each_half_of_a_link_is_wrapped_for_tmux() {
+        let profile = OutputProfile { multiplexer: Some(crate::Multiplexer::Tmux), ..OutputProfile::FULL };
+        let link = osc8("file:///a.rs#L3", "3", &profile);
+        assert_eq!(link, "\x1bPtmux;\x1b\x1b]8;;file:///a.rs#L3\x1b\x1b\\\x1b\\3\x1bPtmux;\x1b\x1b]8;;\x1b\x1b\\\x1b\\");
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use std::borrow::Cow;
+use syntect::highlighting::{Highlighter, Theme};
+use syntect::util::LinesWithEndings;
+
+use crate::stream::render_line;
+use crate::{error_snippet, Checkpoint, HighlighterConfig, LinePipeline, SyntaxHighlighter};
+
+/// Lines between the pipeline states an [`IncrementalSession`] keeps, which
+/// bounds both their memory and how many unchanged lines are fed again.
+pub const CHECKPOINT_INTERVAL: usize = 256;
+
+/// Highlights successive versions of one file, feeding the pipeline again
+/// only from the first line that changed since the last version: the lines
+/// before it keep their rendering, and the pipeline restarts from the last
+/// state kept at or before it. Another syntax starts over from the top.
+///
+/// Each version renders as [`highlight_reader`](SyntaxHighlighter::highlight_reader)
+/// renders its lines, without the layout options.
+pub struct IncrementalSession<'a> {
+    highlighter: &'a SyntaxHighlighter,
+    config: &'a HighlighterConfig,
+    theme: Cow<'a, Theme>,
+    interval: usize,
+    /// The syntax the lines below were highlighted as
+    syntax: Option<String>,
+    /// The lines of the last version, with their endings
+    lines: Vec<String>,
+    /// What the pipeline let through while each of those lines was fed
+    rendered: Vec<String>,
+    /// Pipeline states before every `interval`th line past the first
+    checkpoints: Vec<(usize, Checkpoint<'a>)>,
+    reused: usize,
+}
+
+impl<'a> IncrementalSession<'a> {
+    /// A session keeping the pipeline state every [`CHECKPOINT_INTERVAL`] lines.
+    pub fn new(highlighter: &'a SyntaxHighlighter, config: &'a HighlighterConfig) -> Result<Self> {
+        Self::with_interval(highlighter, config, CHECKPOINT_INTERVAL)
+    }
+
+    /// A session keeping the pipeline state every `interval` lines instead
+    /// of every [`CHECKPOINT_INTERVAL`].
+    pub fn with_interval(highlighter: &'a SyntaxHighlighter, config: &'a HighlighterConfig, interval: usize) -> Result<Self> {
+        Ok(Self {
+            highlighter,
+            config,
+            theme: highlighter.resolve_theme(config)?,
+            interval: interval.max(1),
+            syntax: None,
+            lines: Vec::new(),
+            rendered: Vec::new(),
+            checkpoints: Vec::new(),
+            reused: 0,
+        })
+    }
+
+    /// Renders `content`, the new version of the file at `file_path`.
+    pub fn render(&mut self, content: &str, file_path: &str) -> Result<String> {
+        let config = self.config;
+        let (syntax, _) = self.highlighter.resolve_syntax(content, file_path, config)?;
+        let lines: Vec<&str> = LinesWithEndings::from(content).collect();
+
+        let unchanged = match self.syntax.as_deref() == Some(syntax.name.as_str()) {
+            true => self.lines.iter().zip(&lines).take_while(|(old, new)| old == new).count(),
+            false => 0,
+        };
+        // a file cut short above a checkpoint leaves it past the first change
+        self.checkpoints.retain(|(line, _)| *line <= unchanged);
+        let mut pipeline = LinePipeline::new(&self.highlighter.syntax_set, syntax, config, &self.theme);
+        let start = match self.checkpoints.last() {
+            Some((line, checkpoint)) => {
+                pipeline.restore(checkpoint);
+                *line
+            }
+            None => 0,
+        };
+        self.syntax = Some(syntax.name.clone());
+        self.lines.truncate(start);
+        self.rendered.truncate(start);
+        self.reused = start;
+
+        let highlighter = Highlighter::new(&self.theme);
+        for (index, line) in lines.iter().enumerate().skip(start) {
+            if index > start && index % self.interval == 0 {
+                self.checkpoints.push((index, pipeline.checkpoint()));
+            }
+            let mut rendered = String::new();
+            pipeline.line(&highlighter, config.line_offset + index, line, None, &mut |number, ranges, _| {
+                rendered.push_str(&render_line(config, number, ranges));
+                Ok(())
+            })
+            .map_err(|error| error_snippet::locate(error, file_path, content, config.line_offset))?;
+            self.lines.push(line.to_string());
+            self.rendered.push(rendered);
+        }
+
+        let mut output = self.rendered.concat();
+        pipeline.finish(&mut |number, ranges, _| {
+            output.push_str(&render_line(config, number, ranges));
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    /// How many lines at the start of the last version kept the rendering
+    /// of the one before.
+    pub fn reused_lines(&self) -> usize {
+        self.reused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dedup, OutputProfile};
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    fn config() -> HighlighterConfig {
+        HighlighterConfig { profile: OutputProfile { colors: true, ..OutputProfile::DUMB }, ..HighlighterConfig::default() }
+    }
+
+    /// `content` rendered from scratch.
+    fn full(content: &str, syntax: &str, config: &HighlighterConfig) -> String {
+        HIGHLIGHTER.highlight_reader(content.as_bytes(), config, syntax).unwrap()
+            .collect::<Result<String>>().unwrap()
+    }
+
+    fn source(lines: usize) -> Vec<String> {
+        (0..lines).map(|line| format!("fn f{0}() {{ let s = \"{0}\"; }}\n", line)).collect()
+    }
+
+    #[test]
+    fn an_edit_reuses_the_lines_before_the_checkpoint_below_it() {
+        let config = config();
+        let mut session = IncrementalSession::with_interval(&HIGHLIGHTER, &config, 10).unwrap();
+        let mut lines = source(100);
+        assert_eq!(session.render(&lines.concat(), "a.rs").unwrap(), full(&lines.concat(), "Rust", &config));
+        assert_eq!(session.reused_lines(), 0);
+
+        lines[57] = "let changed = 1;\n".to_string();
+        assert_eq!(session.render(&lines.concat(), "a.rs").unwrap(), full(&lines.concat(), "Rust", &config));
+        assert_eq!(session.reused_lines(), 50);
+
+        // the same version again only replays from the last checkpoint
+        assert_eq!(session.render(&lines.concat(), "a.rs").unwrap(), full(&lines.concat(), "Rust", &config));
+        assert_eq!(session.reused_lines(), 90);
+    }
+
+    #[test]
+    fn state_carried_past_the_edit_is_highlighted_again() {
+        let config = config();
+        let mut session = IncrementalSession::with_interval(&HIGHLIGHTER, &config, 4).unwrap();
+        let mut lines = source(20);
+        session.render(&lines.concat(), "a.rs").unwrap();
+
+        // an unclosed comment recolors everything after it
+        lines[9] = "/*\n".to_string();
+        let commented = session.render(&lines.concat(), "a.rs").unwrap();
+        assert_eq!(commented, full(&lines.concat(), "Rust", &config));
+        assert_eq!(session.reused_lines(), 8);
+
+        lines[9] = "\n".to_string();
+        assert_eq!(session.render(&lines.concat(), "a.rs").unwrap(), full(&lines.concat(), "Rust", &config));
+    }
+
+    #[test]
+    fn a_shorter_file_drops_the_checkpoints_past_its_end() {
+        let config = config();
+        let mut session = IncrementalSession::with_interval(&HIGHLIGHTER, &config, 10).unwrap();
+        let lines = source(100);
+        session.render(&lines.concat(), "a.rs").unwrap();
+
+        let shorter = lines[..35].concat();
+        assert_eq!(session.render(&shorter, "a.rs").unwrap(), full(&shorter, "Rust", &config));
+        assert_eq!(session.reused_lines(), 30);
+
+        // a last line losing its newline is a changed line
+        let unterminated = shorter.trim_end();
+        assert_eq!(session.render(unterminated, "a.rs").unwrap(), full(unterminated, "Rust", &config));
+        assert_eq!(session.reused_lines(), 30);
+
+        let longer = lines.concat();
+        assert_eq!(session.render(&longer, "a.rs").unwrap(), full(&longer, "Rust", &config));
+        assert_eq!(session.reused_lines(), 30);
+    }
+
+    #[test]
+    fn another_syntax_starts_over() {
+        let config = config();
+        let mut session = IncrementalSession::with_interval(&HIGHLIGHTER, &config, 10).unwrap();
+        let content = source(30).concat();
+        session.render(&content, "a.rs").unwrap();
+
+        assert_eq!(session.render(&content, "a.py").unwrap(), full(&content, "Python", &config));
+        assert_eq!(session.reused_lines(), 0);
+        session.render(&content, "a.py").unwrap();
+        assert_eq!(session.reused_lines(), 20);
+    }
+
+    #[test]
+    fn runs_held_back_at_a_checkpoint_come_back_with_it() {
+        let config = HighlighterConfig { dedup: Some(Dedup::new(false)), ..config() };
+        let mut session = IncrementalSession::with_interval(&HIGHLIGHTER, &config, 5).unwrap();
+        let mut lines = vec!["same\n".to_string(); 12];
+        lines.push("other\n".to_string());
+        assert_eq!(session.render(&lines.concat(), "a.txt").unwrap(), full(&lines.concat(), "Plain Text", &config));
+
+        lines[11] = "edited\n".to_string();
+        assert_eq!(session.render(&lines.concat(), "a.txt").unwrap(), full(&lines.concat(), "Plain Text", &config));
+        assert_eq!(session.reused_lines(), 10);
+    }
+}
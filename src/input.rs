@@ -0,0 +1,495 @@
+use anyhow::{bail, Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error_snippet::ErrorLocation;
+
+/// Bytes read from the file at a time when hashing it.
+const CHUNK: usize = 64 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A file's content as read from disk.
+pub struct Input {
+    pub content: String,
+    /// Set when the file shrank while it was being read
+    pub truncated: Option<Truncation>,
+    /// How the bytes were turned into `content`
+    pub decoding: Decoding,
+}
+
+/// How a file's bytes were turned into text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoding {
+    /// The encoding the text was decoded from
+    pub encoding: &'static Encoding,
+    /// Length of the byte order mark that was stripped, 0 for none
+    pub bom_len: usize,
+    /// Whether the bytes were gzip-compressed
+    pub decompressed: bool,
+}
+
+impl Default for Decoding {
+    fn default() -> Self {
+        Self { encoding: UTF_8, bom_len: 0, decompressed: false }
+    }
+}
+
+impl Decoding {
+    /// The name to detect the syntax of `path` from, `main.rs` for a
+    /// decompressed `main.rs.gz`.
+    pub fn detect_path<'a>(&self, path: &'a str) -> &'a str {
+        match self.decompressed {
+            true => uncompressed_name(path),
+            false => path,
+        }
+    }
+}
+
+/// `path` without the `.gz` a gzip-compressed file is named with.
+pub(crate) fn uncompressed_name(path: &str) -> &str {
+    path.strip_suffix(".gz").unwrap_or(path)
+}
+
+/// How to decode a file's bytes.
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// WHATWG label of the encoding; sniffed from the BOM (falling back to
+    /// UTF-8) when unset
+    pub encoding: Option<String>,
+    /// Transparently decompress gzip input
+    pub decompress: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self { encoding: None, decompress: true }
+    }
+}
+
+/// How much of a file was read before it was truncated underneath us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    pub expected_bytes: u64,
+    pub read_bytes: u64,
+}
+
+impl Truncation {
+    /// Estimates how many lines the file had from the lines that were read.
+    pub fn estimated_lines(&self, read_lines: usize) -> usize {
+        if self.read_bytes == 0 {
+            return read_lines;
+        }
+
+        (read_lines as u64 * self.expected_bytes / self.read_bytes) as usize
+    }
+}
+
+/// Reads `path`, finishing cleanly with whatever was read if the file gets
+/// truncated (e.g. rotated) while reading. Gzip is decompressed and a BOM
+/// picks the encoding; anything else has to be UTF-8.
+pub fn read_input(path: &str) -> Result<Input> {
+    read(path, None)
+}
+
+/// Like [`read_input`], also returning the SHA-256 of the bytes as they
+/// were read, hashed chunk by chunk during the same read.
+pub fn read_input_hashed(path: &str) -> Result<(Input, [u8; 32])> {
+    let mut hasher = Sha256::new();
+    let input = read(path, Some(&mut hasher))?;
+
+    Ok((input, hasher.finalize().into()))
+}
+
+/// The content of `path` from `bytes` read from it some other way, such as
+/// by another process, with their SHA-256.
+pub fn decode_input(path: &str, bytes: Vec<u8>) -> Result<(Input, [u8; 32])> {
+    let digest = Sha256::digest(&bytes).into();
+
+    Ok((decode(path, bytes, None, &DecodeOptions::default())?, digest))
+}
+
+fn read(path: &str, hasher: Option<&mut Sha256>) -> Result<Input> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read file '{}'", path))?;
+    let expected_bytes = file.metadata()
+        .with_context(|| format!("Failed to read file '{}'", path))?
+        .len();
+
+    read_from(path, file, expected_bytes, hasher)
+}
+
+/// Reads `file`, which had `expected_bytes` when it was opened, to its end.
+fn read_from(path: &str, mut file: impl Read, expected_bytes: u64, hasher: Option<&mut Sha256>) -> Result<Input> {
+    let mut bytes = Vec::with_capacity(expected_bytes as usize);
+    match hasher {
+        Some(hasher) => loop {
+            let start = bytes.len();
+            let read = (&mut file).take(CHUNK as u64).read_to_end(&mut bytes)
+                .with_context(|| format!("Failed to read file '{}'", path))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&bytes[start..]);
+        },
+        None => {
+            file.read_to_end(&mut bytes)
+                .with_context(|| format!("Failed to read file '{}'", path))?;
+        }
+    }
+
+    let read_bytes = bytes.len() as u64;
+    let truncated = (read_bytes < expected_bytes)
+        .then_some(Truncation { expected_bytes, read_bytes });
+
+    decode(path, bytes, truncated, &DecodeOptions::default())
+}
+
+/// `bytes` decompressed when `options` allow it and they are gzip, along
+/// with whether they were.
+pub(crate) fn decompress(path: &str, bytes: Vec<u8>, options: &DecodeOptions) -> Result<(Vec<u8>, bool)> {
+    if !options.decompress || !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok((bytes, false));
+    }
+
+    let mut decompressed = Vec::new();
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)
+        .with_context(|| format!("Failed to decompress '{}'", path))?;
+
+    Ok((decompressed, true))
+}
+
+/// The first `len` bytes of what `bytes`, the start of a file, decompress
+/// to when they are gzip, for sniffing; as many as the start gives when it
+/// is cut short. None when they aren't gzip.
+pub(crate) fn gunzip_prefix(bytes: &[u8], len: usize) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return None;
+    }
+
+    let mut decoder = GzDecoder::new(bytes);
+    let mut prefix = vec![0; len];
+    let mut filled = 0;
+    while filled < len {
+        match decoder.read(&mut prefix[filled..]) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => filled += read,
+        }
+    }
+    prefix.truncate(filled);
+
+    Some(prefix)
+}
+
+/// The encoding `bytes` are in and the length of their BOM.
+fn sniff_encoding(bytes: &[u8], options: &DecodeOptions) -> Result<(&'static Encoding, usize)> {
+    let Some(label) = &options.encoding else {
+        return Ok(Encoding::for_bom(bytes).unwrap_or((UTF_8, 0)));
+    };
+    let Some(encoding) = Encoding::for_label(label.as_bytes()) else {
+        bail!("Unsupported input encoding '{}'", label);
+    };
+    // a BOM is stripped only when it is the given encoding's
+    let bom_len = Encoding::for_bom(bytes)
+        .filter(|(bom_encoding, _)| *bom_encoding == encoding)
+        .map_or(0, |(_, len)| len);
+
+    Ok((encoding, bom_len))
+}
+
+/// Turns the bytes of `path` into its text: decompressed, then decoded from
+/// the encoding its BOM or `options` give, without the BOM. UTF-8 has to be
+/// valid, except for a character cut short by a truncation.
+pub(crate) fn decode(path: &str, bytes: Vec<u8>, truncated: Option<Truncation>, options: &DecodeOptions) -> Result<Input> {
+    let (mut bytes, decompressed) = decompress(path, bytes, options)?;
+    let (encoding, bom_len) = sniff_encoding(&bytes, options)?;
+    let decoding = Decoding { encoding, bom_len, decompressed };
+
+    if encoding != UTF_8 {
+        let (content, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Ok(Input { content: content.into_owned(), truncated, decoding });
+    }
+
+    bytes.drain(..bom_len);
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        // a truncation can land in the middle of a character
+        Err(error) if truncated.is_some() && error.utf8_error().error_len().is_none() => {
+            let valid = error.utf8_error().valid_up_to();
+            let mut bytes = error.into_bytes();
+            bytes.truncate(valid);
+            String::from_utf8(bytes).expect("prefix is valid utf-8")
+        }
+        Err(error) => {
+            let location = ErrorLocation::at_offset(format!("Failed to read file '{}'", path), path, error.as_bytes(), error.utf8_error().valid_up_to());
+            return Err(anyhow::Error::new(error).context(location));
+        }
+    };
+
+    Ok(Input { content, truncated, decoding })
+}
+
+/// Whether the file at `path` is gzip-compressed, which [`read_input`]
+/// decompresses but nothing seeking through the file does.
+pub fn is_compressed(path: &str) -> bool {
+    let mut magic = [0; 2];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == GZIP_MAGIC
+}
+
+/// Whether `path` is something other than a regular file or a directory,
+/// such as the pipe process substitution passes as `/dev/fd/63`, which
+/// can only be read once.
+pub fn is_stream(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+}
+
+/// A file made readable under another name, in a temporary directory of its
+/// own that is removed when this is dropped.
+///
+/// The name is what syntax detection, `--syntax-for` globs and policies see,
+/// and a stream is copied there whole so it can be read as often as a file.
+pub struct SpooledInput {
+    dir: PathBuf,
+    path: String,
+}
+
+impl SpooledInput {
+    /// Copies what `path` gives, read once to its end, to a file called `name`.
+    pub fn spool(path: &str, name: &str) -> Result<Self> {
+        let spooled = Self::create(name)?;
+        let mut source = File::open(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        let mut copy = fs::OpenOptions::new().write(true).create_new(true).open(&spooled.path)
+            .with_context(|| format!("Failed to buffer '{}' at '{}'", path, spooled.path))?;
+        std::io::copy(&mut source, &mut copy)
+            .with_context(|| format!("Failed to buffer '{}' at '{}'", path, spooled.path))?;
+
+        Ok(spooled)
+    }
+
+    /// Makes the regular file `path` readable as `name` without copying it,
+    /// through a symbolic link where the platform has them.
+    pub fn link(path: &str, name: &str) -> Result<Self> {
+        let spooled = Self::create(name)?;
+        let target = fs::canonicalize(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &spooled.path)
+            .with_context(|| format!("Failed to link '{}' as '{}'", path, spooled.path))?;
+        #[cfg(not(unix))]
+        fs::copy(&target, &spooled.path)
+            .with_context(|| format!("Failed to copy '{}' to '{}'", path, spooled.path))?;
+
+        Ok(spooled)
+    }
+
+    /// A path called `name` in a fresh temporary directory, with nothing
+    /// there yet.
+    pub(crate) fn create(name: &str) -> Result<Self> {
+        let file_name = Path::new(name).file_name()
+            .with_context(|| format!("Invalid file name '{}'", name))?;
+        // a fresh directory, so nothing else can have put a file or link at the path
+        let dir = std::env::temp_dir().join(format!("ccat-{}-{:08x}", std::process::id(), rand::random::<u32>()));
+        fs::create_dir(&dir)
+            .with_context(|| format!("Failed to create temporary directory '{}'", dir.display()))?;
+        let path = dir.join(file_name).to_string_lossy().into_owned();
+
+        Ok(Self { dir, path })
+    }
+
+    /// Where the file can be read under its name.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for SpooledInput {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Reads a file, having another thread truncate it to `length` bytes
+    /// once the first `first_read` bytes have been read.
+    struct TruncatedMidRead {
+        file: File,
+        first_read: usize,
+        truncate: Option<(PathBuf, u64)>,
+    }
+
+    impl Read for TruncatedMidRead {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((path, length)) = self.truncate.take() else {
+                return self.file.read(buf);
+            };
+
+            let limit = buf.len().min(self.first_read);
+            self.file.read_exact(&mut buf[..limit])?;
+            let (done, truncated) = mpsc::channel();
+            thread::spawn(move || {
+                File::options().write(true).open(path).unwrap().set_len(length).unwrap();
+                done.send(()).unwrap();
+            });
+            truncated.recv().unwrap();
+            Ok(limit)
+        }
+    }
+
+    fn truncated_mid_read(content: &[u8], first_read: usize, length: u64, hashed: bool) -> (Input, Vec<u8>) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        let path = file.path().to_path_buf();
+
+        let reader = TruncatedMidRead {
+            file: File::open(&path).unwrap(),
+            first_read,
+            truncate: Some((path.clone(), length)),
+        };
+        let mut hasher = Sha256::new();
+        let input = read_from("rotated.log", reader, content.len() as u64, hashed.then_some(&mut hasher)).unwrap();
+        (input, fs::read(&path).unwrap())
+    }
+
+    #[test]
+    fn a_file_truncated_while_reading_ends_with_what_was_read() {
+        let content = "a log line\n".repeat(3000);
+        let (input, _) = truncated_mid_read(content.as_bytes(), 11 * 1204, 11 * 1204, false);
+
+        assert_eq!(input.content.lines().count(), 1204);
+        assert_eq!(input.truncated, Some(Truncation { expected_bytes: 33000, read_bytes: 11 * 1204 }));
+        assert_eq!(input.truncated.unwrap().estimated_lines(1204), 3000);
+    }
+
+    #[test]
+    fn a_hashed_read_notices_the_truncation_too() {
+        let content = "x".repeat(4 * CHUNK);
+        let (input, left) = truncated_mid_read(content.as_bytes(), CHUNK, CHUNK as u64, true);
+
+        assert_eq!(input.content.len(), CHUNK);
+        assert_eq!(left.len(), CHUNK);
+        assert!(input.truncated.is_some());
+    }
+
+    #[test]
+    fn a_truncation_inside_a_character_drops_its_start() {
+        let (input, _) = truncated_mid_read("ab\u{e9}".as_bytes(), 3, 3, false);
+
+        assert_eq!(input.content, "ab");
+        assert!(input.truncated.is_some());
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn a_bom_picks_the_encoding_and_is_stripped() {
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("hé\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let input = decode("a.txt", utf16, None, &DecodeOptions::default()).unwrap();
+        assert_eq!(input.content, "hé\n");
+        assert_eq!(input.decoding, Decoding { encoding: encoding_rs::UTF_16LE, bom_len: 2, decompressed: false });
+
+        let input = decode("a.txt", b"\xef\xbb\xbfplain\n".to_vec(), None, &DecodeOptions::default()).unwrap();
+        assert_eq!(input.content, "plain\n");
+        assert_eq!(input.decoding.bom_len, 3);
+    }
+
+    #[test]
+    fn gzip_is_decompressed_before_decoding() {
+        let input = decode("main.rs.gz", gzip(b"fn main() {}\n"), None, &DecodeOptions::default()).unwrap();
+        assert_eq!(input.content, "fn main() {}\n");
+        assert!(input.decoding.decompressed);
+        assert_eq!(input.decoding.detect_path("src/main.rs.gz"), "src/main.rs");
+
+        let options = DecodeOptions { decompress: false, ..DecodeOptions::default() };
+        assert!(decode("main.rs.gz", gzip(b"fn main() {}\n"), None, &options).is_err());
+    }
+
+    #[test]
+    fn a_given_encoding_overrides_the_bom_sniffing() {
+        let options = DecodeOptions { encoding: Some("windows-1252".to_string()), ..DecodeOptions::default() };
+        let input = decode("legacy.txt", b"caf\xe9\n".to_vec(), None, &options).unwrap();
+        assert_eq!(input.content, "café\n");
+        assert_eq!(input.decoding.bom_len, 0);
+
+        let options = DecodeOptions { encoding: Some("klingon".to_string()), ..DecodeOptions::default() };
+        assert!(decode("a.txt", b"a".to_vec(), None, &options).is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error_located_in_the_file() {
+        let error = decode("bad.txt", b"ok\nb\xffd\n".to_vec(), None, &DecodeOptions::default()).err().unwrap();
+        assert_eq!(error.downcast_ref::<ErrorLocation>().map(|location| location.line), Some(2));
+    }
+
+    #[test]
+    fn the_prefix_of_a_gzip_file_is_sniffed_decompressed() {
+        let compressed = gzip(&b"text ".repeat(4000));
+        assert_eq!(gunzip_prefix(&compressed, 10).unwrap(), b"text text ");
+        // cut short, it gives what it can
+        assert!(gunzip_prefix(&compressed[..compressed.len() / 2], 100_000).is_some_and(|prefix| !prefix.is_empty()));
+        assert_eq!(gunzip_prefix(b"plain", 10), None);
+    }
+
+    #[test]
+    fn a_file_that_is_not_truncated_is_read_whole() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"whole\n").unwrap();
+        let input = read_input(file.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(input.content, "whole\n");
+        assert_eq!(input.truncated, None);
+    }
+
+    #[test]
+    fn only_files_and_directories_are_not_streams() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(!is_stream(file.path().to_str().unwrap()));
+        assert!(!is_stream(env!("CARGO_MANIFEST_DIR")));
+        assert!(!is_stream("no/such/file"));
+        #[cfg(unix)]
+        {
+            assert!(is_stream("/dev/null"));
+            let spooled = SpooledInput::spool("/dev/null", "empty").unwrap();
+            assert_eq!(fs::read(spooled.path()).unwrap(), b"");
+        }
+    }
+
+    #[test]
+    fn spooled_and_linked_inputs_are_read_under_their_name_until_dropped() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"{}\n").unwrap();
+        let source = file.path().to_str().unwrap();
+
+        let spooled = SpooledInput::spool(source, "dir/old.json").unwrap();
+        let linked = SpooledInput::link(source, "new.json").unwrap();
+        assert!(spooled.path().ends_with("/old.json"), "{}", spooled.path());
+        assert!(linked.path().ends_with("/new.json"), "{}", linked.path());
+        assert_eq!(fs::read_to_string(spooled.path()).unwrap(), "{}\n");
+        assert_eq!(fs::read_to_string(linked.path()).unwrap(), "{}\n");
+
+        let dir = Path::new(spooled.path()).parent().unwrap().to_path_buf();
+        drop(spooled);
+        assert!(!dir.exists());
+        assert!(Path::new(source).exists());
+    }
+
+    #[test]
+    fn a_name_without_a_file_name_cannot_be_spooled() {
+        assert_eq!(SpooledInput::link(env!("CARGO_MANIFEST_DIR"), "..").err().unwrap().to_string(), "Invalid file name '..'");
+    }
+}
@@ -1,44 +1,441 @@
 use anyhow::{Context, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::{BufRead, Read, Seek, SeekFrom};
 use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use std::time::{Duration, Instant};
+use syntect::highlighting::{Color, HighlightIterator, HighlightState, Highlighter, Theme, ThemeSet};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
+mod accessible;
+mod annotations;
+mod assets;
+mod background;
+mod batch;
+mod brackets;
+mod budget;
+mod color;
+mod config;
+mod contact_sheet;
+mod dates;
+mod dedup;
+mod detect;
+mod diagnostics;
+mod diff;
+mod dirs;
+mod editor;
+mod editorconfig;
+mod elevate;
+mod error_snippet;
+mod extract;
+mod fences;
+mod filekind;
+mod fold;
+#[cfg(feature = "images")]
+mod graphics;
+mod guard;
+mod gutter;
+mod hexdump;
+mod hyperlink;
+mod incremental;
+mod input;
+mod line_index;
+mod lines;
+mod mail;
+mod multiplexer;
+mod newline;
+mod outline;
+mod output;
+mod output_cache;
+#[cfg(feature = "pager")]
+mod pager;
+mod palette;
+mod patch;
+mod policy;
+mod preprocessor;
+mod profile;
+mod project;
+mod provenance;
+mod region;
+mod reveal;
+mod scope_override;
+mod scopes;
+mod select;
+mod stream;
+mod style;
+mod syntax_rules;
+mod tabs;
+mod template;
+mod theme_diff;
+mod timing;
+mod tree;
+mod trim;
+mod vscode_theme;
+mod width;
+mod wrap;
+mod zones;
+
+pub use accessible::Accessibility;
+use accessible::ColorTransform;
+pub use annotations::{out_of_range, Annotation, Annotations};
+pub use assets::{AssetOrigin, AssetSources, Shadowed};
+use assets::Provenance;
+use brackets::RainbowBrackets;
+pub use background::TerminalBackground;
+pub use batch::{BatchInput, BatchJob, BatchOptions, BatchReport, HighlightOutput};
+pub use brackets::configured_palette;
+pub use budget::{MemoryBudget, Reservation};
+pub use config::{config_path, load_config};
+pub use contact_sheet::{ExportWrap, PREVIEW_SNIPPET, PREVIEW_SNIPPET_PATH};
+pub use dates::DateFormat;
+pub use dedup::{Dedup, Run, RunKind};
+pub use detect::{DetectStep, DetectStrategy, Detection, DetectionMethod};
+pub use diagnostics::{Diagnostic, DiagnosticFormat, DiagnosticSpan, Diagnostics, RustcJson, Severity};
+pub use diff::{LineChange, LineDiff};
+pub use dirs::{cache_dir, config_dir};
+pub use editor::EditorCommand;
+pub use editorconfig::EditorConfig;
+pub use elevate::{is_permission_denied, Elevation};
+pub use error_snippet::{render_error_snippet, ErrorLocation};
+pub use extract::{ExtractOptions, ExtractedText, LineOffset};
+pub use fences::{extract_fences, Dialect, Fence};
+pub use filekind::{FileKind, MetadataSummary};
+use fold::FoldMarker;
+#[cfg(feature = "images")]
+pub use graphics::ImageProtocol;
+pub use guard::{is_text, is_text_file, parse_size};
+pub use gutter::{format_gutter_number, GutterRenderer, GutterSide, NumberBase};
+pub use hexdump::{hexdump, ByteRange, HexdumpConfig};
+pub use hyperlink::EditorUrl;
+pub use incremental::{IncrementalSession, CHECKPOINT_INTERVAL};
+pub use input::{decode_input, is_compressed, is_stream, read_input, read_input_hashed, DecodeOptions, Decoding, Input, SpooledInput, Truncation};
+pub use line_index::{read_lines, LineIndex, LineRange, LineWindow};
+pub use lines::{HighlightedLine, Span};
+pub use mail::is_mail;
+pub use multiplexer::{Escape, Multiplexer};
+pub use newline::{LineEnding, NewlineMode};
+pub use outline::Outline;
+pub use output::encode_output;
+pub use output_cache::{fingerprint, CacheEntry, CacheSettings, CachedOutput, OutputCache};
+#[cfg(feature = "pager")]
+pub use pager::page;
+pub use policy::{Policies, PolicyRule, RenderMode};
+pub use preprocessor::{is_trusted_path, Preprocessed, Preprocessor, Preprocessors};
+pub use profile::{OutputProfile, Profile};
+use profile::Underline;
+pub use project::{ProjectConfig, Projects, Trust, TrustStore, PROJECT_CONFIG};
+pub use provenance::{FileProvenance, GitState, ProvenanceMode};
+pub use region::Region;
+pub use reveal::Reveal;
+pub use scope_override::ScopeOverride;
+pub use scopes::ScopeLayout;
+pub use select::{Comparison, Predicate};
+pub use style::{Rgb, Style};
+use region::RegionTracker;
+pub use syntax_rules::{SyntaxRule, SyntaxRules};
+pub use vscode_theme::theme_from_vscode_json;
+pub use width::{display_width, grapheme_width, visible_width, AmbiguousWidth};
+pub use wrap::WrapMode;
+pub use zones::SemanticZones;
+pub use template::OutputTemplate;
+use template::TemplateFields;
+pub use timing::{LineTiming, TimingReport, Timings};
+pub use tree::{TreeEntry, TreeFilter};
+
+/// Re-exports of the syntect types ccat is built on, for callers that need
+/// to work with them directly. These follow syntect's versioning, not ccat's.
+#[cfg(feature = "syntect-types")]
+pub mod raw {
+    pub use syntect::highlighting::{Color, Style, Theme, ThemeSet};
+    pub use syntect::parsing::{SyntaxReference, SyntaxSet};
+}
+
+/// Default for [`HighlighterConfig::max_line_length`], long enough for any
+/// hand-written code but short of minified assets.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 16 * 1024;
+
+/// Granularity of the output buffer's reservations against [`HighlighterConfig::memory_budget`].
+const OUTPUT_RESERVATION_CHUNK: usize = 64 * 1024;
+
+/// How a file is highlighted and laid out. It serializes for showing the
+/// configuration in effect, leaving out what is compiled or read from input
+/// files: regions, selections, annotations, diagnostics, the diff, the
+/// outline and run-time state.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct HighlighterConfig {
     pub theme: String,
+    /// Colors that replace the theme's for tokens matching a scope selector
+    pub scope_overrides: Vec<ScopeOverride>,
+    /// Draw tokens in at most this many of the theme's colors
+    pub max_colors: Option<usize>,
     pub show_line_numbers: bool,
     pub force_syntax: Option<String>,
+    /// How to detect the syntax when none is forced
+    pub detect: DetectStrategy,
+    /// Detection steps to try in this order instead of the ones of `detect`
+    pub detect_order: Option<Vec<DetectStep>>,
+    /// Syntax names first-line detection may not pick; they still match by extension
+    pub firstline_exclude: Vec<String>,
+    /// Only show lines between these markers
+    #[serde(skip)]
+    pub between: Option<Region>,
+    /// Show every matching region instead of just the first
+    pub all_regions: bool,
+    /// Only show lines matching this predicate
+    #[serde(skip)]
+    pub select: Option<Predicate>,
+    /// Hide a `#!` first line, which the syntax is still detected from
+    pub strip_shebang: Option<StripShebang>,
+    /// Lines of the file before `content` when it is only part of one, so
+    /// line numbers continue from there
+    pub line_offset: usize,
+    /// Added to every line number shown, so numbering can go on from the
+    /// files before
+    pub number_offset: usize,
+    /// Lines at the start of `content` that are only highlighted to set up
+    /// the parser for the ones after them, and not shown
+    pub lookback_lines: usize,
+    /// Adjust colors for contrast or a color vision deficiency
+    pub accessibility: Option<Accessibility>,
+    /// Color bracket pairs by nesting depth
+    pub rainbow_brackets: bool,
+    /// Colors to cycle through for bracket depths instead of the theme-derived palette
+    #[serde(serialize_with = "style::serialize_hex")]
+    pub rainbow_palette: Option<Vec<Rgb>>,
+    /// Notes to render next to lines of the file being highlighted
+    #[serde(skip)]
+    pub annotations: Vec<Annotation>,
+    /// Compiler messages about the file, whose spans are underlined and
+    /// whose text is shown under the line they point at
+    #[serde(skip)]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Differences from an older version of the file, shown as signs before
+    /// changed lines and the removed lines struck through
+    #[serde(skip)]
+    pub diff: Option<LineDiff>,
+    /// Width of the terminal for wrapping and aligning notes, None when not writing to one
+    pub terminal_width: Option<usize>,
+    /// Make gutter line numbers OSC 8 hyperlinks that open the file at that line
+    pub line_hyperlinks: Option<EditorUrl>,
+    /// Underline the URLs in the text, in their own color, without linking them
+    pub underline_urls: bool,
+    /// Resolve hyperlinked paths to absolute ones; off, they are used as given
+    pub canonicalize_paths: bool,
+    /// What kinds of escapes and decorations the output may contain
+    pub profile: OutputProfile,
+    /// Wrap long lines at `terminal_width`
+    pub wrap: Option<WrapMode>,
+    /// Number each wrapped row instead of each source line
+    pub number_wrapped: bool,
+    /// Fit the rows of HTML output to this many columns
+    pub export_width: Option<usize>,
+    /// How HTML output fits the lines wider than `export_width`
+    pub export_wrap: ExportWrap,
+    /// Strip trailing whitespace and escapes from the end of every output line
+    pub trim_output: bool,
+    /// Whether the output ends with a line ending
+    pub newline: NewlineMode,
+    /// Line terminators to rewrite the output's to
+    pub line_endings: LineEnding,
+    /// Expand tabs to stops this many columns apart
+    pub tab_width: Option<usize>,
+    /// Show each tab as a dim arrow padded out to its stop, expanding tabs
+    /// at the terminal's 8 columns without a `tab_width`
+    pub tab_arrows: bool,
+    /// Spell out invisible and confusable characters, such as non-breaking
+    /// spaces and Cyrillic letters in Latin words, in string literals and
+    /// optionally comments
+    pub reveal: Option<Reveal>,
+    /// Show every line in the theme's plain style without parsing it, only
+    /// laying it out
+    pub no_highlight: bool,
+    /// Lines longer than this many bytes are shown without highlighting
+    pub max_line_length: Option<usize>,
+    /// Once a line takes longer than this to highlight, the lines after it
+    /// are shown without highlighting. A line can't be stopped partway, so
+    /// this bounds the time of all but that one.
+    pub line_timeout: Option<Duration>,
+    /// Fixed width for the line number field instead of one fitting the largest number
+    pub line_number_width: Option<usize>,
+    /// Let numbers wider than `line_number_width` overflow instead of failing
+    pub line_number_overflow: bool,
+    /// Separate the gutter from the content with this character instead of ` | `
+    pub gutter_delimiter: Option<char>,
+    /// Pad line numbers to a common width; without padding the gutter is left
+    /// out of width calculations
+    pub gutter_padding: bool,
+    /// Which side of the code the gutter is drawn on; on the right it is
+    /// aligned to `terminal_width`, or past the widest line without one
+    pub gutter_side: GutterSide,
+    /// The base line numbers are written in
+    pub number_base: NumberBase,
+    /// Pad line numbers to the gutter's width with zeros rather than spaces
+    pub number_pad_zero: bool,
+    /// Lay each row out with this template in place of the gutter, which
+    /// is then drawn whether or not `show_line_numbers` is set
+    pub output_template: Option<OutputTemplate>,
+    /// Budget the highlighted output is buffered against
+    #[serde(skip)]
+    pub memory_budget: MemoryBudget,
+    /// Emit lines last to first, keeping their own numbers. Lines are
+    /// highlighted in order and `between` and `select` apply before reversing.
+    pub reverse: bool,
+    /// Show runs of identical consecutive lines once, followed by a count
+    #[serde(skip)]
+    pub dedup: Option<Dedup>,
+    /// Show at most this many consecutive blank (empty or whitespace-only)
+    /// lines, followed by a count of the rest; ignored with `dedup`, which
+    /// already shows a run of blank lines once
+    pub collapse_blank: Option<usize>,
+    /// Show only the lines of this outline, each entry followed by how many
+    /// lines of its body were folded away; `dedup` and `collapse_blank` are
+    /// ignored with it
+    #[serde(skip)]
+    pub outline: Option<Outline>,
+    /// Collapse regions between editor fold markers, such as `#region` and
+    /// `#endregion` or vim's `{{{` and `}}}`, into their first line, whose
+    /// gutter shows the region's extent
+    pub fold_markers: bool,
+    /// Leave the regions whose first line matches this expanded
+    #[serde(skip)]
+    pub unfold: Option<Regex>,
 }
 
 impl Default for HighlighterConfig {
     fn default() -> Self {
         Self {
             theme: "base16-ocean.dark".to_string(),
+            scope_overrides: Vec::new(),
+            max_colors: None,
             show_line_numbers: false,
             force_syntax: None,
+            detect: DetectStrategy::default(),
+            detect_order: None,
+            firstline_exclude: Vec::new(),
+            between: None,
+            all_regions: false,
+            select: None,
+            strip_shebang: None,
+            line_offset: 0,
+            number_offset: 0,
+            lookback_lines: 0,
+            accessibility: None,
+            rainbow_brackets: false,
+            rainbow_palette: None,
+            annotations: Vec::new(),
+            diagnostics: Vec::new(),
+            diff: None,
+            terminal_width: None,
+            line_hyperlinks: None,
+            underline_urls: false,
+            canonicalize_paths: true,
+            profile: OutputProfile::default(),
+            wrap: None,
+            number_wrapped: false,
+            export_width: None,
+            export_wrap: ExportWrap::Wrap,
+            trim_output: false,
+            newline: NewlineMode::default(),
+            line_endings: LineEnding::default(),
+            tab_width: None,
+            tab_arrows: false,
+            reveal: None,
+            no_highlight: false,
+            max_line_length: Some(DEFAULT_MAX_LINE_LENGTH),
+            line_timeout: None,
+            line_number_width: None,
+            line_number_overflow: false,
+            gutter_delimiter: None,
+            gutter_padding: true,
+            gutter_side: GutterSide::default(),
+            number_base: NumberBase::default(),
+            number_pad_zero: false,
+            output_template: None,
+            memory_budget: MemoryBudget::unlimited(),
+            reverse: false,
+            dedup: None,
+            collapse_blank: None,
+            outline: None,
+            fold_markers: false,
+            unfold: None,
         }
     }
 }
 
+/// How lines are numbered under a hidden shebang line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StripShebang {
+    /// Number the shown lines from 1
+    #[default]
+    Renumber,
+    /// Keep the file's own numbers, starting at 2
+    KeepNumbers,
+}
+
+/// Whether a theme has a dark or a light background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeBrightness {
+    Dark,
+    Light,
+}
+
 pub struct SyntaxHighlighter {
     syntax_set: SyntaxSet,
     theme_set: ThemeSet,
+    provenance: Provenance,
 }
 
 impl SyntaxHighlighter {
     pub fn new() -> Self {
-        Self {
-            syntax_set: SyntaxSet::load_defaults_newlines(),
-            theme_set: ThemeSet::load_defaults(),
-        }
+        Self::with_sources(&AssetSources::default())
+            .expect("builtin assets always load")
     }
     
     pub fn available_themes(&self) -> Vec<&String> {
         self.theme_set.themes.keys().collect()
     }
     
+    /// The background color of the theme `name`, None when there is no such
+    /// theme or it doesn't set one.
+    pub fn theme_background(&self, name: &str) -> Option<Rgb> {
+        self.theme_set.themes.get(name)?.settings.background.map(Rgb::from)
+    }
+    
+    pub fn theme_brightness(&self, name: &str) -> Option<ThemeBrightness> {
+        let theme = self.theme_set.themes.get(name)?;
+        let background = theme.settings.background.unwrap_or(Color::BLACK);
+        
+        if color::luminance(background) < 0.5 {
+            Some(ThemeBrightness::Dark)
+        } else {
+            Some(ThemeBrightness::Light)
+        }
+    }
+    
+    /// Picks a random theme, optionally restricted to dark or light ones.
+    /// The same seed always picks the same theme.
+    pub fn random_theme(&self, brightness: Option<ThemeBrightness>, seed: Option<u64>) -> Option<&str> {
+        let candidates: Vec<&str> = self.theme_set.themes.keys()
+            .map(String::as_str)
+            .filter(|name| brightness.is_none() || self.theme_brightness(name) == brightness)
+            .collect();
+        
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        
+        candidates.choose(&mut rng).copied()
+    }
+    
     pub fn available_syntaxes(&self) -> Vec<&str> {
         self.syntax_set.syntaxes()
             .iter()
@@ -46,45 +443,396 @@ impl SyntaxHighlighter {
             .collect()
     }
     
+    /// Returns the syntax a Markdown fence language tag such as `rust` or `py` refers to.
+    pub fn syntax_for_token(&self, token: &str) -> Option<&str> {
+        self.syntax_set.find_syntax_by_token(token)
+            .map(|syntax| syntax.name.as_str())
+    }
+
+    /// Returns the file extensions that are detected as the given syntax,
+    /// combining syntect's own extensions with the custom mappings.
+    pub fn extensions_for_syntax(&self, name: &str) -> Vec<String> {
+        let mut extensions: Vec<String> = self.syntax_set.find_syntax_by_name(name)
+            .map(|syntax| syntax.file_extensions.clone())
+            .unwrap_or_default();
+
+        let mut mapped: Vec<&str> = self.get_custom_mappings()
+            .into_iter()
+            .filter(|(_, syntax_name)| *syntax_name == name)
+            .map(|(extension, _)| extension)
+            .collect();
+        mapped.sort_unstable();
+
+        for extension in mapped {
+            if !extensions.iter().any(|existing| existing == extension) {
+                extensions.push(extension.to_string());
+            }
+        }
+
+        extensions
+    }
+
     pub fn highlight_file(&self, file_path: &str, config: &HighlighterConfig) -> Result<String> {
-        let content = std::fs::read_to_string(file_path)
-            .with_context(|| format!("Failed to read file '{}'", file_path))?;
+        let input = read_input(file_path)?;
         
-        self.highlight_content(&content, file_path, config)
+        self.highlight_content(&input.content, input.decoding.detect_path(file_path), config)
     }
     
     pub fn highlight_content(&self, content: &str, file_path: &str, config: &HighlighterConfig) -> Result<String> {
-        let theme = self.theme_set.themes.get(&config.theme)
-            .with_context(|| format!("Theme '{}' not found", config.theme))?;
+        self.highlight_content_timed(content, file_path, config, None)
+    }
+    
+    /// Like [`highlight_content`](Self::highlight_content), also recording detection
+    /// and per-line highlight times into `timings` when given.
+    pub fn highlight_content_timed(&self, content: &str, file_path: &str, config: &HighlighterConfig, timings: Option<&mut Timings>) -> Result<String> {
+        self.highlight_detected(content, file_path, config, timings).map(|(text, _)| text)
+    }
+    
+    /// Like [`highlight_content_timed`](Self::highlight_content_timed), also
+    /// giving the syntax the content was highlighted as.
+    fn highlight_detected(&self, content: &str, file_path: &str, config: &HighlighterConfig, mut timings: Option<&mut Timings>) -> Result<(String, &SyntaxReference)> {
+        let detect_started = Instant::now();
+        let (syntax, _) = self.resolve_syntax(content, file_path, config)?;
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.detect = detect_started.elapsed();
+        }
+        let theme = self.resolve_theme(config)?;
+        let theme = theme.as_ref();
         
-        let syntax = {
-            if let Some(syntax_name) = &config.force_syntax {
-                self.syntax_set.find_syntax_by_name(syntax_name)
-                    .with_context(|| format!("Syntax '{}' not found", syntax_name))?
+        let mut result = String::new();
+        let mut reservation = config.memory_budget.reserve(0, "highlighted output")?;
+        let line_highlight = theme.settings.line_highlight
+            .or(theme.settings.selection)
+            .unwrap_or(Color { r: 0x40, g: 0x40, b: 0x40, a: 0xff });
+        let gutter = GutterRenderer::new(config.number_offset + config.line_offset + content.lines().count(), config)?;
+        let template = config.output_template.as_ref().map(|template| {
+            let fields = TemplateFields {
+                path: file_path,
+                lang: &syntax.name,
+                sep: gutter.delimiter(),
+                reset: config.profile.sgr("\x1b[0m"),
+                ambiguous: config.profile.ambiguous_width,
+            };
+            (template, fields)
+        });
+        let gutter_width = match &template {
+            Some((template, fields)) => template.width(&format_gutter_number(config.number_offset + config.line_offset + content.lines().count(), config.number_base, 0, false), fields),
+            None if config.show_line_numbers => gutter.width(),
+            None => 0,
+        };
+        let signs = config.diff.as_ref().map(|diff| (diff, diff::DiffSigns::new(theme)));
+        let sign_width = if signs.is_some() { diff::DiffSigns::WIDTH } else { 0 };
+        let line_count = config.line_offset + content.lines().count();
+        let number_shift = usize::from(config.strip_shebang == Some(StripShebang::Renumber) && hides_shebang(config, config.line_offset, content));
+        // the number a line of the file is shown with
+        let shown = |line: usize| line + config.number_offset - number_shift;
+        let link_path = config.line_hyperlinks.as_ref().map(|_| {
+            if config.canonicalize_paths {
+                std::fs::canonicalize(file_path).unwrap_or_else(|_| file_path.into())
             } else {
-                self.detect_syntax(content, file_path)
+                file_path.into()
+            }
+        });
+        let wrap = config.wrap.zip(config.terminal_width)
+            .map(|(mode, width)| (mode, width.saturating_sub(gutter_width + sign_width)));
+        let mut visual_row = 0;
+        let mut line_starts = Vec::new();
+        let right_edge = (config.show_line_numbers && config.gutter_side == GutterSide::Right && template.is_none()).then(|| {
+            config.terminal_width.map(|width| width.saturating_sub(sign_width)).unwrap_or_else(|| {
+                let tab_width = config.tab_width.unwrap_or(gutter::TERMINAL_TAB_WIDTH);
+                content.lines().map(|line| width::measure(line, tab_width, &config.profile)).max().unwrap_or(0) + gutter_width
+            })
+        });
+        // notes are aligned to the content's edge, which a right gutter moves in
+        let (note_gutter_width, note_width) = match right_edge {
+            Some(edge) => (sign_width, Some(edge.saturating_sub(gutter_width) + sign_width).filter(|_| config.terminal_width.is_some())),
+            None => (gutter_width + sign_width, config.terminal_width),
+        };
+        let layout = RowLayout { right_edge, template: template.as_ref(), base: config.number_base, ambiguous: config.profile.ambiguous_width };
+        let blank_sign = " ".repeat(sign_width);
+        let blank_gutter = || config.show_line_numbers
+            .then(|| terminal_gutter(&gutter, gutter.number(None), None, None, None, config));
+        let push_removed = |result: &mut String, line: usize| {
+            let Some((diff, signs)) = &signs else {
+                return;
+            };
+            for removed in diff.removed_before(line) {
+                if !result.is_empty() && !result.ends_with('\n') {
+                    result.push('\n');
+                }
+                result.push_str(&signs.removed(&config.profile));
+                push_row(result, &layout, None, blank_gutter(), &diff::DiffSigns::removed_line(removed, &config.profile));
             }
         };
-
-        let mut h = HighlightLines::new(syntax, theme);
-        let mut result = String::new();
         
-        for (line_num, line) in LinesWithEndings::from(content).enumerate() {
-            let ranges: Vec<(Style, &str)> = h.highlight_line(line, &self.syntax_set)
-                .with_context(|| "Failed to highlight line")?;
+        let marker_style = marker_style(theme);
+        
+        let pipeline = LinePipeline::new(&self.syntax_set, syntax, config, theme);
+        self.highlight_each(content, file_path, pipeline, theme, timings, |line_number, ranges, run| {
+            let line_annotations: Vec<&Annotation> = config.annotations.iter()
+                .filter(|annotation| annotation.lines.contains(&line_number))
+                .collect();
+            let notes: Vec<&str> = line_annotations.iter()
+                .filter(|annotation| *annotation.lines.start() == line_number)
+                .map(|annotation| annotation.note.as_str())
+                .collect();
+            let note = (!notes.is_empty()).then(|| notes.join("; "));
+            let severity = diagnostics::line_severity(&config.diagnostics, line_number);
+            let underline = severity.map(|severity| Underline::Curly(severity.color()))
+                .or(config.underline_urls.then_some(Underline::Straight));
+            
+            let rows = match wrap {
+                Some((mode, width)) => wrap::wrap_ranges(ranges, width, mode, config.profile.ambiguous_width, config.profile.zero_width_escapes),
+                None => vec![ranges.to_vec()],
+            };
+            let last_row = rows.len() - 1;
+            
+            if config.reverse {
+                line_starts.push(result.len());
+            }
+            push_removed(&mut result, line_number);
+            
+            for (row_index, row) in rows.iter().enumerate() {
+                visual_row += 1;
+                
+                let gutter_text = config.show_line_numbers.then(|| {
+                    let text = match (config.number_wrapped, run) {
+                        (true, _) => gutter.number(Some(visual_row)),
+                        (false, Some(run)) if row_index == 0 && matches!(run.kind, RunKind::Identical | RunKind::Region) => gutter.range(shown(line_number), shown(run.last)),
+                        (false, _) => gutter.number((row_index == 0).then_some(shown(line_number))),
+                    };
+                    let linked = config.number_wrapped || row_index == 0;
+                    let badge = severity.filter(|_| row_index == 0);
+                    terminal_gutter(&gutter, text, badge, linked.then_some(line_number), link_path.as_deref(), config)
+                });
+                
+                let row_text = if line_annotations.is_empty() {
+                    config.profile.escape_underlined(row, false, underline)
+                } else {
+                    let note = note.as_deref().filter(|_| row_index == last_row);
+                    annotations::render_line(row, line_highlight, note, note_gutter_width, note_width, underline, &config.profile)
+                };
+                match &signs {
+                    Some((diff, signs)) if row_index == 0 => result.push_str(&signs.line(diff.change(line_number), &config.profile)),
+                    _ => result.push_str(&blank_sign),
+                }
+                push_row(&mut result, &layout, (row_index == 0).then_some(shown(line_number)), gutter_text, &row_text);
+                
+                if row_index != last_row {
+                    result.push('\n');
+                }
+            }
+            
+            for diagnostic in config.diagnostics.iter().filter(|diagnostic| diagnostic.line() == Some(line_number)) {
+                if !result.ends_with('\n') {
+                    result.push('\n');
+                }
+                let gutter_text = blank_gutter();
+                let color = diagnostic.severity.color();
+                let message = format!(
+                    "{}{}{} {}: {}{}\n",
+                    config.profile.sgr("\x1b[2m"),
+                    if config.profile.colors { format!("\x1b[38;2;{};{};{}m", color.r, color.g, color.b) } else { String::new() },
+                    config.profile.symbol("└", "`-"),
+                    diagnostic.severity.label(),
+                    diagnostic.message.lines().next().unwrap_or_default(),
+                    config.profile.sgr("\x1b[0m"),
+                );
+                result.push_str(&blank_sign);
+                push_row(&mut result, &layout, None, gutter_text, &message);
+            }
+            
+            let marker = run.and_then(|run| match run.kind {
+                RunKind::Identical => Some(format!("{} {}", config.profile.symbol("×", "x"), dedup::group_digits(run.count))),
+                RunKind::Blank => {
+                    let (ellipsis, hidden) = (config.profile.symbol("⋯", "..."), run.count - 1);
+                    let lines = if hidden == 1 { "line" } else { "lines" };
+                    Some(format!("{} ({} more blank {}) {}", ellipsis, dedup::group_digits(hidden), lines, ellipsis))
+                }
+                RunKind::Folded => {
+                    let hidden = run.count - 1;
+                    let lines = if hidden == 1 { "line" } else { "lines" };
+                    Some(format!("{} {} {}", config.profile.symbol("…", "..."), dedup::group_digits(hidden), lines))
+                }
+                // a region's count is already on its first line
+                RunKind::Region => None,
+            });
+            if let Some(marker) = marker {
+                if !result.ends_with('\n') {
+                    result.push('\n');
+                }
+                // the count gets a blank gutter, it is not a line of the file
+                let gutter_text = blank_gutter();
+                let marker = config.profile.escape(&[(marker_style, marker.as_str()), (marker_style, "\n")], false);
+                result.push_str(&blank_sign);
+                push_row(&mut result, &layout, None, gutter_text, &marker);
+            }
+            
+            if run.map_or(line_number, |run| run.last) == line_count {
+                push_removed(&mut result, line_count + 1);
+            }
             
-            if config.show_line_numbers {
-                result.push_str(&format!("{:4} | ", line_num + 1));
+            // reserving in chunks keeps the shared counter off the per-line path
+            if result.len() > reservation.bytes() {
+                reservation.grow_to(result.len().next_multiple_of(OUTPUT_RESERVATION_CHUNK), "highlighted output")?;
             }
             
-            let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
-            result.push_str(&escaped);
+            Ok(())
+        })?;
+        
+        if config.reverse {
+            // the reversed copy is built while the output is still held, so
+            // without room for both, the output waits on disk instead
+            result = match config.memory_budget.try_reserve(result.len() + 1) {
+                Some(_copy) => reverse_lines(&result, &line_starts),
+                None => reverse_lines_spooled(result, &line_starts)?,
+            };
+        }
+        
+        if config.trim_output {
+            result = trim::trim_output(&result);
+        }
+        
+        let result = newline::fix_ending(result, content, config.newline, &config.profile);
+        Ok((newline::normalize(result, config.line_endings), syntax))
+    }
+    
+    /// Highlights `content` into one [`HighlightedLine`] per shown line, leaving
+    /// the layout to the caller. The gutter, wrapping, `reverse`, `trim_output`
+    /// and `profile` options only apply to [`highlight_content`](Self::highlight_content).
+    pub fn highlight_lines(&self, content: &str, file_path: &str, config: &HighlighterConfig) -> Result<Vec<HighlightedLine>> {
+        let (syntax, _) = self.resolve_syntax(content, file_path, config)?;
+        let theme = self.resolve_theme(config)?;
+        let mut lines = Vec::new();
+        
+        let pipeline = LinePipeline::new(&self.syntax_set, syntax, config, &theme);
+        self.highlight_each(content, file_path, pipeline, &theme, None, |number, ranges, run| {
+            let spans = ranges.iter()
+                .map(|(style, text)| Span { style: (*style).into(), text: text.trim_end_matches(['\n', '\r']).to_string() })
+                .filter(|span| !span.text.is_empty())
+                .collect();
+            let notes = config.annotations.iter()
+                .filter(|annotation| *annotation.lines.start() == number)
+                .map(|annotation| annotation.note.clone())
+                .collect();
+            let diagnostics = config.diagnostics.iter()
+                .filter(|diagnostic| diagnostic.line() == Some(number))
+                .cloned()
+                .collect();
+            
+            lines.push(HighlightedLine { number, spans, notes, diagnostics, run });
+            Ok(())
+        })?;
+        
+        Ok(lines)
+    }
+    
+    /// Highlights the lines of `reader` as `syntax` one at a time, reading the
+    /// next line only when the next item is requested. Each item is a line
+    /// as [`highlight_content`](Self::highlight_content) renders it without
+    /// the layout options: the gutter, wrapping, annotations, dedup markers
+    /// (a collapsed run yields its first line), `reverse` and `trim_output`.
+    ///
+    /// The iterator owns `reader` but borrows the highlighter and `config`,
+    /// so both must outlive it. It yields an error for a line that cannot be
+    /// read or highlighted and ends after it.
+    pub fn highlight_reader<'a, R: BufRead + 'a>(&'a self, reader: R, config: &'a HighlighterConfig, syntax: &str) -> Result<impl Iterator<Item = Result<String>> + 'a> {
+        let syntax = self.syntax_set.find_syntax_by_name(syntax)
+            .with_context(|| format!("Syntax '{}' not found", syntax))?;
+        let theme = self.resolve_theme(config)?;
+        let pipeline = LinePipeline::new(&self.syntax_set, syntax, config, &theme);
+        
+        Ok(stream::HighlightReader::new(reader, config, theme, pipeline))
+    }
+    
+    /// The configured theme with the scope overrides applied.
+    fn resolve_theme(&self, config: &HighlighterConfig) -> Result<Cow<'_, Theme>> {
+        let theme = self.theme_set.themes.get(&config.theme)
+            .with_context(|| format!("Theme '{}' not found", config.theme))?;
+        
+        Ok(palette::limit(scope_override::apply(theme, &config.scope_overrides), config.max_colors))
+    }
+    
+    /// Runs `pipeline` over `content`, calling `emit` with the number and
+    /// styled ranges of every line `between` and `select` let through.
+    /// With `dedup`, a run of identical lines is emitted once, with its extent,
+    /// and with `collapse_blank` the last blank line kept stands for the rest.
+    fn highlight_each(
+        &self,
+        content: &str,
+        file_path: &str,
+        mut pipeline: LinePipeline<'_>,
+        theme: &Theme,
+        mut timings: Option<&mut Timings>,
+        mut emit: impl FnMut(usize, &[(syntect::highlighting::Style, &str)], Option<Run>) -> Result<()>,
+    ) -> Result<()> {
+        let highlighter = Highlighter::new(theme);
+        let config = pipeline.config;
+        
+        for (line_num, line) in LinesWithEndings::from(content).enumerate() {
+            pipeline.line(&highlighter, config.line_offset + line_num, line, timings.as_deref_mut(), &mut emit)
+                .map_err(|error| error_snippet::locate(error, file_path, content, config.line_offset))?;
+        }
+        
+        pipeline.finish(&mut emit)
+    }
+    
+
+    /// Reports which syntax `highlight_content` would use for this file and why.
+    pub fn detect(&self, content: &str, file_path: &str, config: &HighlighterConfig) -> Result<Detection> {
+        let (syntax, method) = self.resolve_syntax(content, file_path, config)?;
+        
+        Ok(Detection {
+            syntax: syntax.name.clone(),
+            method,
+        })
+    }
+    
+    /// Detects the syntax of the file at `file_path` from its name and first
+    /// few kilobytes only, decompressed for gzip, returning None when it
+    /// looks binary.
+    pub fn detect_file(&self, file_path: &str, config: &HighlighterConfig) -> Result<Option<Detection>> {
+        let prefix = guard::read_prefix(file_path)?;
+        let (prefix, file_path) = match input::gunzip_prefix(&prefix, guard::BINARY_SNIFF_LEN) {
+            Some(decompressed) => (decompressed, input::uncompressed_name(file_path)),
+            None => (prefix, file_path),
+        };
+        if guard::looks_binary(&prefix) {
+            return Ok(None);
+        }
+        
+        self.detect(&String::from_utf8_lossy(&prefix), file_path, config).map(Some)
+    }
+    
+    fn resolve_syntax(&self, content: &str, file_path: &str, config: &HighlighterConfig) -> Result<(&SyntaxReference, DetectionMethod)> {
+        if let Some(syntax_name) = &config.force_syntax {
+            let syntax = self.syntax_set.find_syntax_by_name(syntax_name)
+                .with_context(|| format!("Syntax '{}' not found", syntax_name))?;
+            return Ok((syntax, DetectionMethod::Forced));
         }
         
-        Ok(result)
+        let steps = config.detect_order.as_deref().unwrap_or(config.detect.steps());
+        Ok(self.detect_syntax(content, file_path, steps, &config.firstline_exclude))
+    }
+    
+    /// Tries `steps` in order, falling back to plain text when none matches.
+    fn detect_syntax(&self, content: &str, file_path: &str, steps: &[DetectStep], firstline_exclude: &[String]) -> (&SyntaxReference, DetectionMethod) {
+        let first_line = || self.syntax_set.find_syntax_by_first_line(content)
+            .filter(|syntax| !firstline_exclude.contains(&syntax.name))
+            .map(|syntax| (syntax, DetectionMethod::FirstLine));
+        
+        let detected = steps.iter().find_map(|step| match step {
+            DetectStep::Extension => self.syntax_by_extension(file_path),
+            DetectStep::Filename => self.syntax_by_filename(file_path),
+            DetectStep::Shebang => self.syntax_by_shebang(content),
+            DetectStep::FirstLine => first_line(),
+            DetectStep::Content => self.syntax_by_shebang(content).or_else(first_line),
+        });
+        
+        detected.unwrap_or_else(|| (self.syntax_set.find_syntax_plain_text(), DetectionMethod::PlainText))
     }
     
-    fn detect_syntax(&self, content: &str, file_path: &str) -> &syntect::parsing::SyntaxReference {
+    fn syntax_by_extension(&self, file_path: &str) -> Option<(&SyntaxReference, DetectionMethod)> {
         let extension = Path::new(file_path)
             .extension()
             .and_then(|ext| ext.to_str())
@@ -94,13 +842,27 @@ impl SyntaxHighlighter {
         
         if let Some(syntax_name) = custom_mappings.get(extension) {
             if let Some(syntax) = self.syntax_set.find_syntax_by_name(syntax_name) {
-                return syntax;
+                return Some((syntax, DetectionMethod::Mapping));
             }
         }
         
         self.syntax_set.find_syntax_by_extension(extension)
-            .or_else(|| self.syntax_set.find_syntax_by_first_line(content))
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+            .map(|syntax| (syntax, DetectionMethod::Extension))
+    }
+    
+    fn syntax_by_filename(&self, file_path: &str) -> Option<(&SyntaxReference, DetectionMethod)> {
+        let name = Path::new(file_path).file_name()?.to_str()?;
+        
+        self.syntax_set.find_syntax_by_extension(name)
+            .map(|syntax| (syntax, DetectionMethod::Filename))
+    }
+    
+    fn syntax_by_shebang(&self, content: &str) -> Option<(&SyntaxReference, DetectionMethod)> {
+        let interpreter = detect::shebang_interpreter(content)?;
+        
+        self.syntax_set.find_syntax_by_name(detect::interpreter_syntax(interpreter))
+            .or_else(|| self.syntax_set.find_syntax_by_token(interpreter))
+            .map(|syntax| (syntax, DetectionMethod::Shebang))
     }
     
     fn get_custom_mappings(&self) -> HashMap<&str, &str> {
@@ -150,8 +912,1008 @@ impl SyntaxHighlighter {
     }
 }
 
+const ERROR_SNIPPET_CHARS: usize = 40;
+
+/// The number of the first line of `content` that `config`'s `between` and
+/// `select` let through, if any.
+pub fn first_shown_line(content: &str, config: &HighlighterConfig) -> Option<usize> {
+    let mut region = config.between.as_ref()
+        .map(|region| RegionTracker::new(region, config.all_regions));
+    
+    LinesWithEndings::from(content)
+        .enumerate()
+        .position(|(index, line)| {
+            !hides_shebang(config, index, line)
+                && region.as_mut().is_none_or(|region| region.accept(line))
+                && config.select.as_ref().is_none_or(|select| select.matches(line))
+        })
+        .map(|index| index + 1)
+}
+
+/// Whether `config` hides `line`, the `index`th (0-based), as a shebang.
+fn hides_shebang(config: &HighlighterConfig, index: usize, line: &str) -> bool {
+    index == 0 && config.strip_shebang.is_some() && line.starts_with("#!")
+}
+
+/// Reassembles `rendered` with the output of each source line, starting at
+/// `line_starts`, in reverse order. The last line gets a line ending if it
+/// had none so it doesn't run into the one before it.
+fn reverse_lines(rendered: &str, line_starts: &[usize]) -> String {
+    let mut result = String::with_capacity(rendered.len() + 1);
+    let mut end = rendered.len();
+    
+    for &start in line_starts.iter().rev() {
+        let line = &rendered[start..end];
+        result.push_str(line);
+        if !line.ends_with('\n') {
+            result.push('\n');
+        }
+        end = start;
+    }
+    
+    result
+}
+
+/// Like [`reverse_lines`], with `rendered` written to a temporary file and
+/// freed first, then read back a line at a time from the end, so only the
+/// reversed copy is in memory.
+fn reverse_lines_spooled(rendered: String, line_starts: &[usize]) -> Result<String> {
+    let spooled = SpooledInput::create("reversed")?;
+    std::fs::write(spooled.path(), &rendered)
+        .with_context(|| format!("Failed to buffer the reversed output at '{}'", spooled.path()))?;
+    let mut end = rendered.len();
+    drop(rendered);
+
+    let mut file = std::fs::File::open(spooled.path())
+        .with_context(|| format!("Failed to read the buffered output at '{}'", spooled.path()))?;
+    let mut result = String::with_capacity(end + 1);
+    let mut line = Vec::new();
+    for &start in line_starts.iter().rev() {
+        line.resize(end - start, 0);
+        file.seek(SeekFrom::Start(start as u64))
+            .and_then(|_| file.read_exact(&mut line))
+            .with_context(|| format!("Failed to read the buffered output at '{}'", spooled.path()))?;
+        // the lines start at char boundaries of what was written
+        let line = std::str::from_utf8(&line).context("The buffered output is not UTF-8")?;
+        result.push_str(line);
+        if !line.ends_with('\n') {
+            result.push('\n');
+        }
+        end = start;
+    }
+
+    Ok(result)
+}
+
+/// The line shown for a run of lines, with the run's extent so far.
+#[derive(Clone)]
+struct PendingRun {
+    number: usize,
+    key: String,
+    ranges: Vec<(syntect::highlighting::Style, String)>,
+    last: usize,
+    count: usize,
+    kind: RunKind,
+}
+
+impl PendingRun {
+    fn new(number: usize, key: &str, ranges: &[(syntect::highlighting::Style, &str)], kind: RunKind) -> Self {
+        Self {
+            number,
+            key: key.to_string(),
+            ranges: ranges.iter().map(|(style, text)| (*style, text.to_string())).collect(),
+            last: number,
+            count: 1,
+            kind,
+        }
+    }
+    
+    /// Adds `text` to the end of the line, before its line ending.
+    fn append(&mut self, style: syntect::highlighting::Style, text: String) {
+        let ending = match self.ranges.last_mut() {
+            Some((last_style, last)) => {
+                let content = last.trim_end_matches(['\n', '\r']).len();
+                let ending = last.split_off(content);
+                (!ending.is_empty()).then_some((*last_style, ending))
+            }
+            None => None,
+        };
+        self.ranges.push((style, text));
+        self.ranges.extend(ending);
+    }
+    
+    /// Emits the line, recording an identical run in `dedup`.
+    fn emit(self, dedup: Option<&Dedup>, emit: &mut Emit<'_>) -> Result<()> {
+        let ranges: Vec<_> = self.ranges.iter().map(|(style, text)| (*style, text.as_str())).collect();
+        let run = (self.count > 1).then_some(Run { last: self.last, count: self.count, kind: self.kind });
+        if let (Some(run), Some(dedup)) = (&run, dedup) {
+            dedup.record(run);
+        }
+        
+        emit(self.number, &ranges, run)
+    }
+}
+
+type Emit<'e> = dyn FnMut(usize, &[(syntect::highlighting::Style, &str)], Option<Run>) -> Result<()> + 'e;
+
+/// A [`LinePipeline`] as it was between two lines.
+struct Checkpoint<'a>(LinePipeline<'a>);
+
+/// The per-line stages of the highlighting pipeline with the state they
+/// carry from one line to the next, fed one line at a time.
+#[derive(Clone)]
+struct LinePipeline<'a> {
+    syntax_set: &'a SyntaxSet,
+    syntax: &'a SyntaxReference,
+    config: &'a HighlighterConfig,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+    region: Option<RegionTracker<'a>>,
+    rainbow: Option<RainbowBrackets>,
+    color_transform: Option<ColorTransform>,
+    marker_style: syntect::highlighting::Style,
+    /// The line shown for the current run, kept until a different line ends it
+    pending: Option<PendingRun>,
+    /// Blank lines emitted or held back since the last other line
+    blanks: usize,
+    /// How many fold regions the current line is inside, counting from the
+    /// collapsed one
+    folded_regions: usize,
+    /// Whether a line outlived `line_timeout`, leaving the rest unhighlighted
+    timed_out: bool,
+    timeout_noticed: bool,
+}
+
+impl<'a> LinePipeline<'a> {
+    fn new(syntax_set: &'a SyntaxSet, syntax: &'a SyntaxReference, config: &'a HighlighterConfig, theme: &Theme) -> Self {
+        let highlighter = Highlighter::new(theme);
+        let plain_style = highlighter.get_default();
+        
+        Self {
+            syntax_set,
+            syntax,
+            config,
+            parse_state: ParseState::new(syntax),
+            highlight_state: HighlightState::new(&highlighter, ScopeStack::new()),
+            region: config.between.as_ref()
+                .map(|region| RegionTracker::new(region, config.all_regions)),
+            rainbow: config.rainbow_brackets
+                .then(|| RainbowBrackets::new(theme, config.rainbow_palette.as_deref())),
+            color_transform: config.accessibility
+                .map(|mode| ColorTransform::new(mode, plain_style.background)),
+            marker_style: marker_style(theme),
+            pending: None,
+            blanks: 0,
+            folded_regions: 0,
+            timed_out: false,
+            timeout_noticed: false,
+        }
+    }
+    
+    /// The state the pipeline carries to the next line, for
+    /// [`restore`](Self::restore) to go back to.
+    fn checkpoint(&self) -> Checkpoint<'a> {
+        Checkpoint(self.clone())
+    }
+    
+    /// Puts the pipeline back to where it was at `checkpoint`, so the lines
+    /// after it can be fed again.
+    fn restore(&mut self, checkpoint: &Checkpoint<'a>) {
+        *self = checkpoint.0.clone();
+    }
+    
+    /// Runs line `line_num` (0-based) through the pipeline, calling `emit` with
+    /// whatever it lets through. `highlighter` must be for the same theme every time.
+    fn line(
+        &mut self,
+        highlighter: &Highlighter,
+        line_num: usize,
+        line: &str,
+        timings: Option<&mut Timings>,
+        emit: &mut Emit<'_>,
+    ) -> Result<()> {
+        let config = self.config;
+        let plain_style = highlighter.get_default();
+        let too_long = config.max_line_length
+            .is_some_and(|max| line.len() > max);
+        let mut fold_marker = None;
+        let mut reveal_spans = None;
+        
+        let ranges = if config.no_highlight {
+            vec![(plain_style, line)]
+        } else if self.timed_out {
+            let text = line.trim_end_matches(['\n', '\r']);
+            // the first line past the slow one says why highlighting stopped
+            let notice = match self.timeout_noticed {
+                true => "",
+                false => config.profile.symbol(" ⟨not highlighted, a line above took too long⟩", " <not highlighted, a line above took too long>"),
+            };
+            self.timeout_noticed = true;
+            vec![(plain_style, text), (self.marker_style, notice), (plain_style, &line[text.len()..])]
+        } else if too_long {
+            // long lines skip the parser entirely, so the state is not advanced for them
+            let text = line.trim_end_matches(['\n', '\r']);
+            vec![
+                (plain_style, text),
+                (self.marker_style, config.profile.symbol(" ⟨not highlighted⟩", " <not highlighted>")),
+                (plain_style, &line[text.len()..]),
+            ]
+        } else {
+            let line_started = (timings.is_some() || config.line_timeout.is_some()).then(Instant::now);
+            
+            // every line goes through the highlighter so the parse state stays correct
+            let ops = self.parse_state.parse_line(line, self.syntax_set)
+                .map_err(|error| highlight_error(error, line_num + 1, line, &self.syntax.name))?;
+            let literals = self.rainbow.is_some()
+                .then(|| brackets::literal_ranges(&self.highlight_state.path, &ops, line.len()));
+            if config.fold_markers {
+                fold_marker = fold::marker(line, &self.highlight_state.path, &ops);
+            }
+            if let Some(reveal) = config.reveal.filter(|_| reveal::may_reveal(line)) {
+                reveal_spans = Some(brackets::scope_ranges(&self.highlight_state.path, &ops, line.len(), &reveal.scopes()));
+            }
+            let ranges: Vec<_> = HighlightIterator::new(&mut self.highlight_state, &ops, line, highlighter)
+                .collect();
+            
+            let elapsed = line_started.map(|started| started.elapsed());
+            if let (Some(timings), Some(elapsed)) = (timings, elapsed) {
+                timings.highlight += elapsed;
+                timings.record_line(line_num + 1, elapsed);
+            }
+            self.timed_out = config.line_timeout.zip(elapsed).is_some_and(|(timeout, elapsed)| elapsed > timeout);
+            
+            match (self.rainbow.as_mut(), literals) {
+                (Some(rainbow), Some(literals)) => rainbow.apply(ranges, &literals),
+                _ => ranges,
+            }
+        };
+        
+        let ranges = match self.color_transform.as_mut() {
+            Some(transform) => transform.apply(ranges),
+            None => ranges,
+        };
+        
+        // a line a diagnostic points into is underlined for it, not its URLs
+        let ranges = if config.underline_urls && diagnostics::line_severity(&config.diagnostics, line_num + 1).is_none() {
+            diagnostics::underline_spans(ranges, &hyperlink::url_spans(line))
+        } else if !config.diagnostics.is_empty() {
+            diagnostics::mark_spans(ranges, line, line_num + 1, &config.diagnostics)
+        } else {
+            ranges
+        };
+        
+        if hides_shebang(config, line_num, line) || line_num < config.line_offset + config.lookback_lines {
+            return Ok(());
+        }
+        
+        if let Some(region) = self.region.as_mut() {
+            if !region.accept(line) {
+                return Ok(());
+            }
+        }
+        
+        if config.select.as_ref().is_some_and(|select| !select.matches(line)) {
+            return Ok(());
+        }
+        
+        // after the diagnostics, whose columns are the line's own
+        let revealed;
+        let ranges = match reveal_spans.filter(|spans| !spans.is_empty()) {
+            Some(spans) => {
+                revealed = reveal::reveal(&ranges, &spans, self.marker_style, &config.profile);
+                revealed.ranges()
+            }
+            None => ranges,
+        };
+
+        let expanded;
+        let tab_width = config.tab_width.or(config.tab_arrows.then_some(gutter::TERMINAL_TAB_WIDTH));
+        let arrow = config.tab_arrows.then(|| (self.marker_style.foreground, config.profile.symbol("→", ">")));
+        let ranges = match tab_width {
+            Some(tab_width) if line.contains('\t') => {
+                expanded = tabs::expand_tabs(&ranges, tab_width, arrow, config.profile.ambiguous_width, config.profile.zero_width_escapes);
+                expanded.ranges()
+            }
+            _ => ranges,
+        };
+        
+        if config.fold_markers && self.fold_region(line_num + 1, line, fold_marker, &ranges, emit)? {
+            return Ok(());
+        }
+        
+        if let Some(outline) = &config.outline {
+            return self.fold(line_num + 1, outline.shows(line_num - config.line_offset), &ranges, emit);
+        }
+        
+        match (&config.dedup, config.collapse_blank) {
+            (Some(dedup), _) => {
+                let key = dedup.key(line);
+                match self.pending.as_mut() {
+                    Some(run) if run.key == key => {
+                        run.last = line_num + 1;
+                        run.count += 1;
+                    }
+                    _ => {
+                        if let Some(run) = self.pending.take() {
+                            run.emit(Some(dedup), emit)?;
+                        }
+                        self.pending = Some(PendingRun::new(line_num + 1, key, &ranges, RunKind::Identical));
+                    }
+                }
+                Ok(())
+            }
+            (None, Some(max_blank)) => self.collapse_blank(line_num + 1, line, &ranges, max_blank.max(1), emit),
+            (None, None) => emit(line_num + 1, &ranges, None),
+        }
+    }
+    
+    /// Emits the first `max_blank` lines of a run of blank lines, holding the
+    /// last of them back to count the rest against until another line comes.
+    fn collapse_blank(&mut self, number: usize, line: &str, ranges: &[(syntect::highlighting::Style, &str)], max_blank: usize, emit: &mut Emit<'_>) -> Result<()> {
+        if !line.trim().is_empty() {
+            self.blanks = 0;
+            if let Some(run) = self.pending.take() {
+                run.emit(None, emit)?;
+            }
+            return emit(number, ranges, None);
+        }
+        
+        self.blanks += 1;
+        match self.pending.as_mut() {
+            Some(run) => {
+                run.last = number;
+                run.count += 1;
+                Ok(())
+            }
+            None if self.blanks == max_blank => {
+                self.pending = Some(PendingRun::new(number, "", ranges, RunKind::Blank));
+                Ok(())
+            }
+            None => emit(number, ranges, None),
+        }
+    }
+    
+    /// Emits the lines an outline shows, holding each back to count the
+    /// lines folded under it until the next shown one comes.
+    fn fold(&mut self, number: usize, shown: bool, ranges: &[(syntect::highlighting::Style, &str)], emit: &mut Emit<'_>) -> Result<()> {
+        match self.pending.as_mut() {
+            Some(run) if !shown => {
+                run.last = number;
+                run.count += 1;
+                Ok(())
+            }
+            _ if !shown => emit(number, ranges, None),
+            _ => {
+                if let Some(run) = self.pending.take() {
+                    run.emit(None, emit)?;
+                }
+                self.pending = Some(PendingRun::new(number, "", ranges, RunKind::Folded));
+                Ok(())
+            }
+        }
+    }
+    
+    /// Collapses a fold region into its first line, returning whether the
+    /// line was taken. The region is emitted when its last line comes, with
+    /// how many lines it hid appended to the first; a run pending before it
+    /// is emitted first.
+    fn fold_region(&mut self, number: usize, line: &str, marker: Option<FoldMarker>, ranges: &[(syntect::highlighting::Style, &str)], emit: &mut Emit<'_>) -> Result<bool> {
+        if self.folded_regions > 0 {
+            if let Some(run) = self.pending.as_mut() {
+                run.last = number;
+                run.count += 1;
+            }
+            match marker {
+                Some(FoldMarker::Start) => self.folded_regions += 1,
+                Some(FoldMarker::End) => self.folded_regions -= 1,
+                None => {}
+            }
+            if self.folded_regions == 0 {
+                self.close_region(emit)?;
+            }
+            return Ok(true);
+        }
+        
+        let expanded = self.config.unfold.as_ref().is_some_and(|unfold| unfold.is_match(line));
+        if marker != Some(FoldMarker::Start) || expanded {
+            return Ok(false);
+        }
+        
+        self.finish(emit)?;
+        self.blanks = 0;
+        self.folded_regions = 1;
+        self.pending = Some(PendingRun::new(number, "", ranges, RunKind::Region));
+        Ok(true)
+    }
+    
+    /// Emits the collapsed region, noting how many lines it hid.
+    fn close_region(&mut self, emit: &mut Emit<'_>) -> Result<()> {
+        let Some(mut run) = self.pending.take() else {
+            return Ok(());
+        };
+        let hidden = run.count - 1;
+        let lines = if hidden == 1 { "line" } else { "lines" };
+        let note = format!(" {} {} {}", self.config.profile.symbol("⋯", "..."), dedup::group_digits(hidden), lines);
+        run.append(self.marker_style, note);
+        
+        run.emit(None, emit)
+    }
+    
+    /// Emits the run still pending at the end of the input.
+    fn finish(&mut self, emit: &mut Emit<'_>) -> Result<()> {
+        // a region left open runs to the end
+        if self.folded_regions > 0 {
+            self.folded_regions = 0;
+            return self.close_region(emit);
+        }
+        
+        match self.pending.take() {
+            Some(run) => {
+                let dedup = self.config.dedup.as_ref().filter(|_| run.kind == RunKind::Identical);
+                run.emit(dedup, emit)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// The style of ccat's own markers, such as for lines left unhighlighted.
+fn marker_style(theme: &Theme) -> syntect::highlighting::Style {
+    let plain_style = Highlighter::new(theme).get_default();
+    
+    syntect::highlighting::Style {
+        foreground: theme.settings.gutter_foreground
+            .unwrap_or_else(|| color::blend(plain_style.foreground, plain_style.background, 0.5)),
+        ..plain_style
+    }
+}
+
+/// Renders the gutter for terminal output from its `text`, making it an
+/// OSC 8 hyperlink to `line` when enabled and given, after the `badge` of the
+/// line's most serious diagnostic.
+fn terminal_gutter(gutter: &GutterRenderer, text: String, badge: Option<Severity>, line: Option<usize>, link_path: Option<&Path>, config: &HighlighterConfig) -> String {
+    let text = match (&config.line_hyperlinks, link_path, line) {
+        (Some(editor), Some(path), Some(line)) if config.profile.sends(Escape::Hyperlink) => {
+            hyperlink::osc8(&editor.url(path, line), &text, &config.profile)
+        }
+        _ => text,
+    };
+    
+    let badge = match badge {
+        Some(severity) if config.profile.colors => {
+            let color = severity.color();
+            format!("\x1b[38;2;{};{};{}m{}\x1b[0m", color.r, color.g, color.b, gutter.badge(Some(severity)))
+        }
+        badge => gutter.badge(badge),
+    };
+    
+    match config.gutter_side {
+        GutterSide::Left => badge + &text + &gutter.delimiter(),
+        GutterSide::Right => gutter.delimiter() + &badge + &text,
+    }
+}
+
+/// How [`push_row`] lays rows out around their gutter.
+struct RowLayout<'a> {
+    right_edge: Option<usize>,
+    template: Option<&'a (&'a OutputTemplate, TemplateFields<'a>)>,
+    base: NumberBase,
+    ambiguous: AmbiguousWidth,
+}
+
+/// Appends a rendered row, line `line` of the file when it starts one,
+/// with its gutter: through the output template when there is one, else
+/// before it, or with a right edge after it, padded so the gutter ends at
+/// that column.
+fn push_row(result: &mut String, layout: &RowLayout, line: Option<usize>, gutter_text: Option<String>, row: &str) {
+    if let Some((template, fields)) = layout.template {
+        let end = row.find(['\r', '\n']).unwrap_or(row.len());
+        result.push_str(&template.render(line.map(|line| format_gutter_number(line, layout.base, 0, false)).as_deref(), &row[..end], fields));
+        result.push_str(&row[end..]);
+        return;
+    }
+
+    match (gutter_text, layout.right_edge) {
+        (Some(gutter_text), Some(edge)) => result.push_str(&gutter::place_right(row, &gutter_text, edge, layout.ambiguous)),
+        (Some(gutter_text), None) => {
+            result.push_str(&gutter_text);
+            result.push_str(row);
+        }
+        (None, _) => result.push_str(row),
+    }
+}
+
+fn highlight_error(error: syntect::parsing::ParsingError, line_number: usize, line: &str, syntax_name: &str) -> anyhow::Error {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut snippet: String = line.chars().take(ERROR_SNIPPET_CHARS).collect();
+    if line.chars().count() > ERROR_SNIPPET_CHARS {
+        snippet.push('…');
+    }
+    
+    let message = format!("Failed to highlight line {} as {}: {:?}", line_number, syntax_name, snippet);
+    anyhow::Error::new(error).context(ErrorLocation::line(message, line_number))
+}
+
 impl Default for SyntaxHighlighter {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    #[test]
+    fn extensions_for_syntax_lists_the_detected_extensions() {
+        let rust = HIGHLIGHTER.extensions_for_syntax("Rust");
+        assert!(rust.iter().any(|extension| extension == "rs"), "{:?}", rust);
+
+        let cpp = HIGHLIGHTER.extensions_for_syntax("C++");
+        for extension in ["cpp", "cc", "hpp"] {
+            assert!(cpp.iter().any(|existing| existing == extension), "{} not in {:?}", extension, cpp);
+        }
+    }
+
+    #[test]
+    fn extensions_for_syntax_is_empty_for_an_unknown_syntax() {
+        assert!(HIGHLIGHTER.extensions_for_syntax("No Such Syntax").is_empty());
+    }
+
+    #[test]
+    fn hyperlinked_line_numbers_link_each_line() {
+        let config = HighlighterConfig {
+            show_line_numbers: true,
+            line_hyperlinks: Some(EditorUrl::File),
+            canonicalize_paths: false,
+            ..HighlighterConfig::default()
+        };
+        let output = HIGHLIGHTER.highlight_content("a\nb\nc\n", "/tmp/linked.txt", &config).unwrap();
+
+        let third = output.lines().nth(2).unwrap();
+        let start = third.find("\x1b]8;;").expect("the gutter opens a link");
+        let url_end = start + third[start..].find("\x1b\\").unwrap();
+        assert_eq!(&third[start + 5..url_end], "file:///tmp/linked.txt#L3");
+        let text_end = url_end + third[url_end..].find("\x1b]8;;\x1b\\").expect("the link is closed");
+        assert!(third[url_end + 2..text_end].contains('3'));
+    }
+
+    #[test]
+    fn the_same_seed_picks_the_same_theme() {
+        let picks: Vec<_> = (0..20).map(|seed| HIGHLIGHTER.random_theme(None, Some(seed))).collect();
+        let again: Vec<_> = (0..20).map(|seed| HIGHLIGHTER.random_theme(None, Some(seed))).collect();
+        assert_eq!(picks, again);
+        assert!(picks.iter().all(Option::is_some));
+        // twenty seeds landing on one theme would mean the seed is ignored
+        assert!(picks.iter().any(|pick| *pick != picks[0]));
+    }
+
+    #[test]
+    fn random_themes_keep_to_the_brightness_asked_for() {
+        for seed in 0..20 {
+            let theme = HIGHLIGHTER.random_theme(Some(ThemeBrightness::Light), Some(seed)).unwrap();
+            assert_eq!(HIGHLIGHTER.theme_brightness(theme), Some(ThemeBrightness::Light), "{}", theme);
+        }
+    }
+
+    #[test]
+    fn highlight_errors_name_the_line_syntax_and_cause() {
+        let line = format!("let long = {};\n", "x".repeat(60));
+        let error = highlight_error(syntect::parsing::ParsingError::BadMatchIndex(7), 12, &line, "Rust");
+
+        let message = error.to_string();
+        assert!(message.starts_with("Failed to highlight line 12 as Rust: "), "{}", message);
+        // the snippet is cut to ERROR_SNIPPET_CHARS characters
+        assert!(message.contains(&format!("{}…", &line[..ERROR_SNIPPET_CHARS])), "{}", message);
+        assert_eq!(error.downcast_ref::<ErrorLocation>().map(|location| location.line), Some(12));
+        assert_eq!(error.root_cause().to_string(), "Bad index to match_at: 7");
+    }
+
+    #[test]
+    fn located_errors_carry_the_file() {
+        let error = highlight_error(syntect::parsing::ParsingError::MissingMainContext, 3, "c\n", "Plain Text");
+        let located = error_snippet::locate(error, "notes.txt", "a\nb\nc\nd\n", 0);
+        let location = located.downcast_ref::<ErrorLocation>().unwrap();
+        assert_eq!(location.path.as_deref(), Some("notes.txt"));
+        assert_eq!(location.line, 3);
+    }
+
+    #[test]
+    fn detect_shebang_ignores_the_extension() {
+        let script = "#!/bin/bash\necho hello\n";
+        let config = HighlighterConfig { detect: DetectStrategy::Shebang, ..HighlighterConfig::default() };
+        let detection = HIGHLIGHTER.detect(script, "notes.txt", &config).unwrap();
+        assert_eq!(detection, Detection { syntax: "Bourne Again Shell (bash)".to_string(), method: DetectionMethod::Shebang });
+
+        let detection = HIGHLIGHTER.detect(script, "notes.txt", &HighlighterConfig::default()).unwrap();
+        assert_eq!(detection.syntax, "Plain Text");
+    }
+
+    #[test]
+    fn a_single_strategy_falls_back_to_plain_text() {
+        let config = HighlighterConfig { detect: DetectStrategy::Shebang, ..HighlighterConfig::default() };
+        let detection = HIGHLIGHTER.detect("fn main() {}\n", "main.rs", &config).unwrap();
+        assert_eq!(detection.method, DetectionMethod::PlainText);
+    }
+
+    #[test]
+    fn a_line_past_max_line_length_is_shown_plainly() {
+        let long = format!("var a = \"{}\";", "x".repeat(50_000));
+        let content = format!("{}\nvar b = 1;\n", long);
+
+        let started = std::time::Instant::now();
+        let output = HIGHLIGHTER.highlight_content(&content, "min.js", &HighlighterConfig::default()).unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "took {:?}", started.elapsed());
+
+        let mut lines = output.lines();
+        let first = lines.next().unwrap();
+        // no escapes inside the line means it wasn't split into tokens
+        assert!(first.contains(&long), "the long line is highlighted");
+        assert!(first.contains("not highlighted"));
+        assert!(lines.next().unwrap().matches("\x1b[38;2;").count() > 1, "later lines are still highlighted");
+    }
+
+    #[test]
+    fn max_line_length_can_be_lowered_or_lifted() {
+        let line = format!("var a = \"{}\";\n", "x".repeat(200));
+        let highlighted = |max_line_length| {
+            let config = HighlighterConfig { max_line_length, ..HighlighterConfig::default() };
+            !HIGHLIGHTER.highlight_content(&line, "min.js", &config).unwrap().contains("not highlighted")
+        };
+        assert!(!highlighted(Some(100)));
+        assert!(highlighted(Some(300)));
+        assert!(highlighted(None));
+    }
+
+    #[test]
+    fn a_scope_override_recolors_comments_only() {
+        let content = "// note\nlet x = 1;\n";
+        let config = HighlighterConfig {
+            scope_overrides: vec![ScopeOverride::parse("comment=#123456").unwrap()],
+            ..HighlighterConfig::default()
+        };
+        let plain = HIGHLIGHTER.highlight_content(content, "a.rs", &HighlighterConfig::default()).unwrap();
+        let overridden = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap();
+
+        let (comment, code) = overridden.split_once('\n').unwrap();
+        // the theme's rule for the `//` punctuation is more specific than `comment`
+        assert!(comment.contains("\x1b[38;2;18;52;86m note"), "{:?}", comment);
+        assert!(!plain.contains("38;2;18;52;86m"));
+        assert_eq!(code, plain.split_once('\n').unwrap().1);
+    }
+
+    #[test]
+    fn reverse_prints_lines_last_to_first_with_their_colors() {
+        let content = "fn main() {\n    let a = 1;\n    // note\n    a + 1;\n}\n";
+        let config = HighlighterConfig { show_line_numbers: true, ..HighlighterConfig::default() };
+        let forward: Vec<String> = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap().lines().map(str::to_string).collect();
+        let reversed = HIGHLIGHTER.highlight_content(content, "a.rs", &HighlighterConfig { reverse: true, ..config }).unwrap();
+
+        assert_eq!(reversed.lines().collect::<Vec<_>>(), forward.iter().rev().map(String::as_str).collect::<Vec<_>>());
+        let numbers: Vec<String> = reversed.lines().map(|line| trim::strip_escapes(line).trim_start().chars().take(1).collect()).collect();
+        assert_eq!(numbers, ["5", "4", "3", "2", "1"]);
+    }
+
+    #[test]
+    fn a_reverse_over_the_memory_budget_goes_through_a_file() {
+        let rendered = "one\ntwo\n\nfour ✓\nfive";
+        let line_starts = [0, 4, 8, 9, 18];
+        assert_eq!(reverse_lines_spooled(rendered.to_string(), &line_starts).unwrap(), reverse_lines(rendered, &line_starts));
+        assert_eq!(reverse_lines(rendered, &line_starts), "five\nfour ✓\n\ntwo\none\n");
+
+        // about 100K of output, which a 160K budget holds once but not twice
+        let content: String = (0..700).map(|line| format!("let value_{} = {};\n", line, line)).collect();
+        let config = HighlighterConfig { reverse: true, ..HighlighterConfig::default() };
+        let unlimited = HIGHLIGHTER.highlight_content(&content, "a.rs", &config).unwrap();
+        assert!((100_000..128 * 1024).contains(&unlimited.len()), "{}", unlimited.len());
+
+        let memory_budget = MemoryBudget::new(160 * 1024);
+        let spooled = HIGHLIGHTER.highlight_content(&content, "a.rs", &HighlighterConfig { memory_budget: memory_budget.clone(), ..config }).unwrap();
+        assert_eq!(spooled, unlimited);
+        assert_eq!(memory_budget.used(), 0);
+    }
+
+    #[test]
+    fn highlighted_lines_carry_no_layout() {
+        let content = "fn main() {\r\n    let long_name = 1;\r\n}";
+        let config = HighlighterConfig {
+            show_line_numbers: true,
+            wrap: Some(WrapMode::Character),
+            terminal_width: Some(10),
+            line_offset: 40,
+            annotations: vec![Annotation { lines: 42..=43, note: "binding".to_string() }],
+            ..HighlighterConfig::default()
+        };
+        let lines = HIGHLIGHTER.highlight_lines(content, "a.rs", &config).unwrap();
+
+        let texts: Vec<String> = lines.iter().map(|line| line.spans.iter().map(|span| span.text.as_str()).collect()).collect();
+        assert_eq!(texts, ["fn main() {", "    let long_name = 1;", "}"]);
+        // the number is in the file the content was taken from
+        assert_eq!(lines.iter().map(|line| line.number).collect::<Vec<_>>(), [41, 42, 43]);
+        assert!(lines.iter().flat_map(|line| &line.spans).all(|span| !span.text.contains(['\x1b', '|'])));
+        assert_eq!(lines.iter().map(|line| line.notes.len()).collect::<Vec<_>>(), [0, 1, 0]);
+
+        let keyword = &lines[0].spans[0];
+        assert_eq!(keyword.text, "fn");
+        assert_ne!(keyword.style, lines[0].spans[1].style);
+    }
+
+    #[test]
+    fn the_gutter_cells_line_up_with_the_terminal_gutter() {
+        let config = HighlighterConfig { show_line_numbers: true, profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+        let content = "a\nb\n";
+        let gutter = GutterRenderer::new(2, &config).unwrap();
+        let lines = HIGHLIGHTER.highlight_lines(content, "a.txt", &config).unwrap();
+
+        let assembled: String = lines.iter()
+            .map(|line| {
+                let cells: String = gutter.cells(Some(line.number), line.spans[0].style).iter().map(|cell| cell.text.as_str()).collect();
+                let text: String = line.spans.iter().map(|span| span.text.as_str()).collect();
+                format!("{}{}\n", cells, text)
+            })
+            .collect();
+        assert_eq!(assembled, HIGHLIGHTER.highlight_content(content, "a.txt", &config).unwrap());
+    }
+
+    #[test]
+    fn a_denied_syntax_is_not_detected_from_the_first_line() {
+        let content = "<?xml version=\"1.0\"?>\n<data/>\n";
+        let detected = HIGHLIGHTER.detect(content, "export", &HighlighterConfig::default()).unwrap();
+        assert_eq!((detected.syntax.as_str(), detected.method), ("XML", DetectionMethod::FirstLine));
+
+        let config = HighlighterConfig { firstline_exclude: vec!["XML".to_string()], ..HighlighterConfig::default() };
+        let denied = HIGHLIGHTER.detect(content, "export", &config).unwrap();
+        assert_eq!((denied.syntax.as_str(), denied.method), ("Plain Text", DetectionMethod::PlainText));
+        // the extension still picks it
+        assert_eq!(HIGHLIGHTER.detect(content, "export.xml", &config).unwrap().syntax, "XML");
+    }
+
+    #[test]
+    fn identical_runs_show_once_with_a_range_and_an_unnumbered_count() {
+        let dedup = Dedup::new(false);
+        let config = HighlighterConfig { show_line_numbers: true, dedup: Some(dedup.clone()), profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+        let content = format!("start\n{}end\n", "retry\n".repeat(4812));
+        let output = HIGHLIGHTER.highlight_content(&content, "a.log", &config).unwrap();
+
+        assert_eq!(output, "   1 | start\n2-4813 | retry\n     | x 4,812\n4814 | end\n");
+        assert_eq!(dedup.collapsed(), 4811);
+    }
+
+    #[test]
+    fn right_side_gutters_end_at_the_terminal_edge() {
+        let config = HighlighterConfig {
+            show_line_numbers: true,
+            gutter_side: GutterSide::Right,
+            terminal_width: Some(30),
+            profile: OutputProfile::DUMB,
+            ..HighlighterConfig::default()
+        };
+        let content = "fn main() {\n    let café = \"über\";\n\tx();\n}\n";
+        let output = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap();
+
+        let rows: Vec<&str> = output.lines().collect();
+        assert_eq!(rows.len(), 4);
+        for (index, row) in rows.iter().enumerate() {
+            assert_eq!(visible_width(row, gutter::TERMINAL_TAB_WIDTH, AmbiguousWidth::Narrow), 30, "{:?}", row);
+            assert!(row.ends_with(&format!(" | {:>4}", index + 1)), "{:?}", row);
+        }
+    }
+
+    #[test]
+    fn right_side_gutters_stay_at_the_edge_on_wrapped_rows() {
+        let config = HighlighterConfig {
+            show_line_numbers: true,
+            gutter_side: GutterSide::Right,
+            terminal_width: Some(20),
+            wrap: Some(WrapMode::Character),
+            profile: OutputProfile::DUMB,
+            ..HighlighterConfig::default()
+        };
+        let output = HIGHLIGHTER.highlight_content(&"x".repeat(20), "a.txt", &config).unwrap();
+        assert_eq!(output, format!("{} |    1\n{}       |     ", "x".repeat(13), "x".repeat(7)));
+    }
+
+    #[test]
+    fn the_reader_yields_the_lines_highlight_content_renders() {
+        let config = HighlighterConfig::default();
+        let content = "/* a comment\n   that goes on */\nfn main() {\n    let s = \"text\";\n}\n";
+        let lines: Vec<String> = HIGHLIGHTER.highlight_reader(content.as_bytes(), &config, "Rust").unwrap()
+            .collect::<Result<_>>().unwrap();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines.concat(), HIGHLIGHTER.highlight_content(content, "main.rs", &config).unwrap());
+    }
+
+    #[test]
+    fn the_reader_reads_a_line_per_item_and_reports_read_errors() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+
+        let config = HighlighterConfig { profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+        let reader = std::io::BufReader::new("fn a() {}\n".as_bytes().chain(Failing));
+        let mut lines = HIGHLIGHTER.highlight_reader(reader, &config, "Rust").unwrap();
+
+        assert_eq!(lines.next().unwrap().unwrap(), "fn a() {}\n");
+        assert_eq!(lines.next().unwrap().unwrap_err().to_string(), "Failed to read line 2");
+        assert!(lines.next().is_none());
+
+        let unknown = HIGHLIGHTER.highlight_reader("".as_bytes(), &config, "Klingon").err().unwrap();
+        assert_eq!(unknown.to_string(), "Syntax 'Klingon' not found");
+    }
+
+    #[test]
+    fn the_ending_is_the_same_whatever_rows_are_added_or_moved() {
+        let render = |content: &str, newline: NewlineMode, reverse: bool| {
+            let config = HighlighterConfig { show_line_numbers: true, reverse, newline, profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+            HIGHLIGHTER.highlight_content(content, "a.txt", &config).unwrap()
+        };
+
+        for reverse in [false, true] {
+            assert!(!render("one\ntwo", NewlineMode::Raw, reverse).ends_with('\n'));
+            assert!(render("one\ntwo\n", NewlineMode::Raw, reverse).ends_with('\n'));
+            for content in ["one\ntwo", "one\ntwo\n"] {
+                let output = render(content, NewlineMode::Ensure, reverse);
+                assert!(output.ends_with('\n') && !output.ends_with("\n\n"), "{:?}", output);
+            }
+        }
+        assert_eq!(render("one\ntwo", NewlineMode::Ensure, true), "   2 | two\n   1 | one\n");
+    }
+
+    #[test]
+    fn a_custom_order_lets_the_shebang_beat_a_misleading_extension() {
+        let content = "#!/usr/bin/env python3\nprint('hi')\n";
+        let detect = |detect_order: Option<Vec<DetectStep>>| {
+            let config = HighlighterConfig { detect_order, ..HighlighterConfig::default() };
+            let detection = HIGHLIGHTER.detect(content, "build.sh", &config).unwrap();
+            (detection.syntax, detection.method)
+        };
+
+        assert_eq!(detect(None), ("Bourne Again Shell (bash)".to_string(), DetectionMethod::Extension));
+        assert_eq!(detect(Some(DetectStep::parse_order("shebang,extension").unwrap())), ("Python".to_string(), DetectionMethod::Shebang));
+        // steps that miss fall through to the next
+        assert_eq!(detect(Some(DetectStep::parse_order("filename,shebang").unwrap())), ("Python".to_string(), DetectionMethod::Shebang));
+        assert_eq!(detect(Some(vec![DetectStep::Filename])), ("Plain Text".to_string(), DetectionMethod::PlainText));
+    }
+
+    #[test]
+    fn the_filename_step_matches_whole_names() {
+        let config = HighlighterConfig { detect_order: Some(vec![DetectStep::Filename]), ..HighlighterConfig::default() };
+        let detection = HIGHLIGHTER.detect("all:\n\ttrue\n", "dir/Makefile", &config).unwrap();
+        assert_eq!((detection.syntax.as_str(), detection.method), ("Makefile", DetectionMethod::Filename));
+    }
+
+    #[test]
+    fn a_stripped_shebang_still_picks_the_syntax() {
+        let script = "#!/bin/bash\necho hi\nexit 0\n";
+        let config = HighlighterConfig { strip_shebang: Some(StripShebang::Renumber), ..HighlighterConfig::default() };
+        let stripped = HIGHLIGHTER.highlight_content(script, "run", &config).unwrap();
+
+        let bash = HighlighterConfig { force_syntax: Some("Bourne Again Shell (bash)".to_string()), ..HighlighterConfig::default() };
+        assert_eq!(stripped, HIGHLIGHTER.highlight_content("echo hi\nexit 0\n", "run", &bash).unwrap());
+        assert_ne!(stripped, HIGHLIGHTER.highlight_content("echo hi\nexit 0\n", "run", &HighlighterConfig::default()).unwrap());
+    }
+
+    #[test]
+    fn the_lines_after_a_stripped_shebang_are_renumbered_or_keep_theirs() {
+        let script = "#!/bin/bash\necho hi\nexit 0\n";
+        let numbered = |strip_shebang| {
+            let config = HighlighterConfig { strip_shebang, show_line_numbers: true, profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+            HIGHLIGHTER.highlight_content(script, "run", &config).unwrap()
+        };
+
+        assert_eq!(numbered(Some(StripShebang::Renumber)), "   1 | echo hi\n   2 | exit 0\n");
+        assert_eq!(numbered(Some(StripShebang::KeepNumbers)), "   2 | echo hi\n   3 | exit 0\n");
+        assert_eq!(numbered(None), "   1 | #!/bin/bash\n   2 | echo hi\n   3 | exit 0\n");
+        // only a first line is a shebang
+        let config = HighlighterConfig { strip_shebang: Some(StripShebang::Renumber), profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+        assert_eq!(HIGHLIGHTER.highlight_content("echo\n#!/bin/sh\n", "run", &config).unwrap(), "echo\n#!/bin/sh\n");
+    }
+
+    #[test]
+    fn runs_of_blank_lines_are_cut_to_collapse_blank_with_a_count() {
+        let collapsed = |collapse_blank, content: &str| {
+            let config = HighlighterConfig { show_line_numbers: true, collapse_blank: Some(collapse_blank), profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+            HIGHLIGHTER.highlight_content(content, "notes.txt", &config).unwrap()
+        };
+        let content = format!("a\n{}b\n", "\n".repeat(10));
+
+        assert_eq!(collapsed(2, &content), "   1 | a\n   2 | \n   3 | \n     | ... (8 more blank lines) ...\n  12 | b\n");
+        // --squeeze-blank is the N=1 case
+        assert_eq!(collapsed(1, "a\n\n \t\nb\n"), "   1 | a\n   2 | \n     | ... (1 more blank line) ...\n   4 | b\n");
+        assert_eq!(collapsed(2, "a\n\n\nb\n\n"), "   1 | a\n   2 | \n   3 | \n   4 | b\n   5 | \n");
+        // a run at the end of the input is counted too
+        assert_eq!(collapsed(1, "a\n\n\n\n"), "   1 | a\n   2 | \n     | ... (2 more blank lines) ...\n");
+    }
+
+    #[test]
+    fn hidden_blank_lines_still_advance_the_highlighting() {
+        let config = HighlighterConfig { collapse_blank: Some(1), ..HighlighterConfig::default() };
+        let content = "/*\n\n\n\nstill a comment */\nfn main() {}\n";
+        let collapsed = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap();
+        let full = HIGHLIGHTER.highlight_content(content, "a.rs", &HighlighterConfig::default()).unwrap();
+
+        assert_eq!(collapsed.lines().rev().take(2).collect::<Vec<_>>(), full.lines().rev().take(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_region_folds_into_its_first_line_with_its_range_in_the_gutter() {
+        let content = "class A {\n    #region Fields\n    int a;\n    #region Inner\n    int b;\n    #endregion\n    #endregion\n    void M() {}\n    #region Open\n    int c;\n}\n";
+        let folded = |unfold: Option<&str>| {
+            let config = HighlighterConfig {
+                fold_markers: true,
+                unfold: unfold.map(|unfold| Regex::new(unfold).unwrap()),
+                show_line_numbers: true,
+                profile: OutputProfile::DUMB,
+                ..HighlighterConfig::default()
+            };
+            HIGHLIGHTER.highlight_content(content, "a.cs", &config).unwrap()
+        };
+
+        // nested regions fold with the outer one, and one left open runs to the end
+        assert_eq!(folded(None), "   1 | class A {\n 2-7 |     #region Fields ... 5 lines\n   8 |     void M() {}\n9-11 |     #region Open ... 2 lines\n");
+        assert!(folded(Some("Fields")).starts_with("   1 | class A {\n   2 |     #region Fields\n   3 |     int a;\n 4-6 |     #region Inner ... 2 lines\n"));
+    }
+
+    #[test]
+    fn language_names_resolve_by_extension_then_by_name() {
+        assert_eq!(HIGHLIGHTER.syntax_for_token("python"), Some("Python"));
+        assert_eq!(HIGHLIGHTER.syntax_for_token("py"), Some("Python"));
+        assert_eq!(HIGHLIGHTER.syntax_for_token("sh"), Some("Bourne Again Shell (bash)"));
+        assert_eq!(HIGHLIGHTER.syntax_for_token("RUST"), Some("Rust"));
+        assert_eq!(HIGHLIGHTER.syntax_for_token("klingon"), None);
+    }
+
+    #[test]
+    fn urls_are_underlined_in_their_own_colors() {
+        let content = "// see https://example.com/docs.\n";
+        let config = HighlighterConfig { underline_urls: true, ..HighlighterConfig::default() };
+        let underlined = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap();
+        let plain = HIGHLIGHTER.highlight_content(content, "a.rs", &HighlighterConfig::default()).unwrap();
+
+        let comment = plain.split("\x1b[38;2;").nth(1).unwrap().split_once('m').unwrap().0;
+        assert!(underlined.contains(&format!("\x1b[4m\x1b[38;2;{}mhttps://example.com/docs", comment)), "{:?}", underlined);
+        assert_eq!(underlined.matches("\x1b[4m").count(), 1);
+        assert!(!plain.contains("\x1b[4m"));
+    }
+
+    #[test]
+    fn no_highlight_draws_each_line_in_one_plain_style() {
+        let content = "fn main() {\n    let x = \"a\";\n}\n";
+        let config = HighlighterConfig { no_highlight: true, ..HighlighterConfig::default() };
+        let output = HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap();
+        for line in output.lines() {
+            assert_eq!(line.matches("\x1b[38;2;").count(), 1, "{:?}", line);
+        }
+
+        let dumb = HighlighterConfig { profile: OutputProfile::DUMB, show_line_numbers: true, ..config };
+        assert_eq!(HIGHLIGHTER.highlight_content(content, "a.rs", &dumb).unwrap(), "   1 | fn main() {\n   2 |     let x = \"a\";\n   3 | }\n");
+    }
+
+    #[test]
+    fn a_highlighter_without_syntaxes_takes_everything_as_plain_text() {
+        let highlighter = SyntaxHighlighter::without_syntaxes(&AssetSources::default()).unwrap();
+        assert_eq!(highlighter.available_syntaxes(), ["Plain Text"]);
+        let detection = highlighter.detect("#!/bin/sh\necho hi\n", "run.sh", &HighlighterConfig::default()).unwrap();
+        assert_eq!(detection.syntax, "Plain Text");
+        assert!(!highlighter.available_themes().is_empty());
+    }
+
+    #[test]
+    fn reveal_spells_out_hidden_characters_in_strings_alone() {
+        let content = "let x\u{a0}= \"a\u{a0}b\"; // c\u{a0}d\n";
+        let reveal = |reveal: Reveal| {
+            let config = HighlighterConfig { reveal: Some(reveal), profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+            HIGHLIGHTER.highlight_content(content, "a.rs", &config).unwrap()
+        };
+
+        assert_eq!(reveal(Reveal::Strings), "let x\u{a0}= \"a_<U+00A0>b\"; // c\u{a0}d\n");
+        assert_eq!(reveal(Reveal::Comments), "let x\u{a0}= \"a_<U+00A0>b\"; // c_<U+00A0>d\n");
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{bail, Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Lines between two samples of a [`LineIndex`].
+const STRIDE: u64 = 4096;
+
+/// Start of an index file, changed whenever the format does.
+const MAGIC: &[u8; 8] = b"ccatidx1";
+
+/// Bytes read at a time while indexing or scanning.
+const BUFFER: usize = 1024 * 1024;
+
+/// 1-based inclusive line numbers to show, either end left open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl LineRange {
+    /// Parses `START:END`, `START:` or `:END`, e.g. `5000000:5000100`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((start, end)) = spec.split_once(':') else {
+            bail!("Invalid line range '{}', expected START:END", spec);
+        };
+        let number = |text: &str| text.trim().replace('_', "").parse::<usize>()
+            .with_context(|| format!("Invalid line number '{}' in line range '{}'", text.trim(), spec));
+
+        let start = if start.trim().is_empty() { 1 } else { number(start)? };
+        let end = if end.trim().is_empty() { None } else { Some(number(end)?) };
+        if start == 0 {
+            bail!("Invalid line range '{}', lines are numbered from 1", spec);
+        }
+        if end.is_some_and(|end| end < start) {
+            bail!("Invalid line range '{}', the end is before the start", spec);
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// Where every few thousandth line of a file starts, so a range of lines
+/// can be read by seeking close to it instead of reading everything before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// Byte offsets of the lines `0`, `STRIDE`, `2 * STRIDE` and so on, 0-based
+    offsets: Vec<u64>,
+}
+
+impl LineIndex {
+    /// Reads the whole file at `path` once to index it.
+    pub fn build(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+        let mut buffer = vec![0; BUFFER];
+        let mut offsets = vec![0];
+        let (mut position, mut line) = (0u64, 0u64);
+
+        loop {
+            let read = file.read(&mut buffer)
+                .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+            if read == 0 {
+                break;
+            }
+            for (i, _) in buffer[..read].iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+                line += 1;
+                if line % STRIDE == 0 {
+                    offsets.push(position + i as u64 + 1);
+                }
+            }
+            position += read as u64;
+        }
+
+        Ok(Self { offsets })
+    }
+
+    /// Loads the index of `path` from `cache_dir`, building and storing it
+    /// there on a miss. Entries are keyed by the file's path, size and
+    /// modification time, so a changed file gets a new index. Without a
+    /// cache directory the index is built every time.
+    pub fn load(path: &Path, cache_dir: Option<&Path>) -> Result<Self> {
+        let Some(cache_dir) = cache_dir else {
+            return Self::build(path);
+        };
+
+        let absolute = fs::canonicalize(path)
+            .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+        let metadata = fs::metadata(&absolute)
+            .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+        let mut hasher = DefaultHasher::new();
+        absolute.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .hash(&mut hasher);
+        let cache_file = cache_dir.join(format!("{:016x}.lineindex", hasher.finish()));
+
+        // an unreadable cache entry is treated as a miss and overwritten
+        if let Some(index) = fs::read(&cache_file).ok().and_then(|bytes| Self::decode(&bytes)) {
+            return Ok(index);
+        }
+
+        let index = Self::build(path)?;
+        fs::create_dir_all(cache_dir)
+            .with_context(|| format!("Failed to create line index cache '{}'", cache_dir.display()))?;
+        // written aside first so a concurrent reader never sees half an index
+        let partial = cache_file.with_extension(format!("{}.partial", std::process::id()));
+        fs::write(&partial, index.encode())
+            .and_then(|_| fs::rename(&partial, &cache_file))
+            .with_context(|| format!("Failed to write line index cache '{}'", cache_file.display()))?;
+
+        Ok(index)
+    }
+
+    /// The byte offset and 0-based number of the last sampled line at or
+    /// before 0-based `line`.
+    fn seek_point(&self, line: u64) -> (u64, u64) {
+        let sample = ((line / STRIDE) as usize).min(self.offsets.len() - 1);
+        (self.offsets[sample], sample as u64 * STRIDE)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 8 * (self.offsets.len() + 1));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&STRIDE.to_le_bytes());
+        for offset in &self.offsets {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let rest = bytes.strip_prefix(MAGIC)?;
+        let (stride, rest) = rest.split_first_chunk::<8>()?;
+        if u64::from_le_bytes(*stride) != STRIDE || rest.is_empty() || rest.len() % 8 != 0 {
+            return None;
+        }
+
+        let offsets = rest.chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes")))
+            .collect();
+        Some(Self { offsets })
+    }
+}
+
+/// Some lines of a file, read by [`read_lines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineWindow {
+    pub content: String,
+    /// Lines of the file before `content`
+    pub line_offset: usize,
+    /// Lines at the start of `content` before the requested ones, there
+    /// to set up highlighting for them
+    pub lookback: usize,
+}
+
+impl LineWindow {
+    /// Whether highlighting starts after the start of the file, so it can
+    /// differ from highlighting the whole file when something opened before
+    /// the window, such as a block comment, is still open in it.
+    pub fn is_approximate(&self) -> bool {
+        self.line_offset > 0
+    }
+}
+
+/// Reads the lines of `range` from the file at `path` with its [`LineIndex`],
+/// plus up to `lookback` lines before them, or all of them with None. Only
+/// the bytes from the last index sample before the first line read on are
+/// read. The window is empty when the range starts past the end.
+pub fn read_lines(path: &Path, index: &LineIndex, range: LineRange, lookback: Option<usize>) -> Result<LineWindow> {
+    let first = (range.start - 1) as u64;
+    let read_from = match lookback {
+        Some(lookback) => first.saturating_sub(lookback as u64),
+        None => 0,
+    };
+    let (offset, mut line) = index.seek_point(read_from);
+
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+    file.seek(SeekFrom::Start(offset))
+        .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+    let mut reader = BufReader::with_capacity(BUFFER, file);
+
+    let mut bytes = Vec::new();
+    let mut scratch = Vec::new();
+    loop {
+        if range.end.is_some_and(|end| line >= end as u64) {
+            break;
+        }
+        // the lines before the window are only skipped
+        let target = if line < read_from {
+            scratch.clear();
+            &mut scratch
+        } else {
+            &mut bytes
+        };
+        let read = reader.read_until(b'\n', target)
+            .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        line += 1;
+    }
+
+    let content = String::from_utf8(bytes)
+        .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+    let line_offset = read_from as usize;
+
+    Ok(LineWindow { lookback: (first - read_from) as usize, content, line_offset })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file of `lines` numbered lines of varying length.
+    fn numbered(lines: usize) -> (tempfile::NamedTempFile, String) {
+        let content: String = (1..=lines).map(|line| format!("line {}{}\n", line, "x".repeat(line % 7))).collect();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, content.as_bytes()).unwrap();
+        (file, content)
+    }
+
+    fn expected(content: &str, from: usize, to: Option<usize>) -> String {
+        content.split_inclusive('\n').skip(from).take(to.map_or(usize::MAX, |to| to - from)).collect()
+    }
+
+    #[test]
+    fn ranges_take_open_ends_and_underscores() {
+        assert_eq!(LineRange::parse("5_000_000:5_000_100").unwrap(), LineRange { start: 5_000_000, end: Some(5_000_100) });
+        assert_eq!(LineRange::parse("100:").unwrap(), LineRange { start: 100, end: None });
+        assert_eq!(LineRange::parse(":50").unwrap(), LineRange { start: 1, end: Some(50) });
+        assert!(LineRange::parse("0:5").is_err());
+        assert!(LineRange::parse("9:5").is_err());
+        assert!(LineRange::parse("5").is_err());
+        assert!(LineRange::parse("a:5").is_err());
+    }
+
+    #[test]
+    fn every_stride_of_lines_is_sampled() {
+        let (file, content) = numbered(10_000);
+        let index = LineIndex::build(file.path()).unwrap();
+
+        assert_eq!(index.offsets.len(), 3);
+        for (sample, offset) in index.offsets.iter().enumerate() {
+            assert_eq!(*offset as usize, expected(&content, 0, Some(sample * STRIDE as usize)).len());
+        }
+        assert_eq!(index.seek_point(9_000), (index.offsets[2], 8192));
+        assert_eq!(index.seek_point(4095), (0, 0));
+    }
+
+    #[test]
+    fn windows_match_reading_the_whole_file() {
+        let (file, content) = numbered(10_000);
+        let index = LineIndex::build(file.path()).unwrap();
+
+        for (range, lookback, from) in [
+            ("9000:9010", Some(200), 8799),
+            ("9000:9010", Some(0), 8999),
+            ("9000:9010", None, 0),
+            ("4097:4097", Some(0), 4096),
+            ("10:20", Some(200), 0),
+            ("9990:", Some(5), 9984),
+        ] {
+            let parsed = LineRange::parse(range).unwrap();
+            let window = read_lines(file.path(), &index, parsed, lookback).unwrap();
+
+            assert_eq!(window.line_offset, from, "{}", range);
+            assert_eq!(window.lookback, parsed.start - 1 - from, "{}", range);
+            assert_eq!(window.content, expected(&content, from, parsed.end), "{}", range);
+            assert_eq!(window.is_approximate(), from > 0, "{}", range);
+        }
+
+        let past = read_lines(file.path(), &index, LineRange::parse("20000:20010").unwrap(), Some(0)).unwrap();
+        assert!(past.content.is_empty());
+    }
+
+    #[test]
+    fn the_cache_is_keyed_by_path_size_and_mtime() {
+        let (file, _) = numbered(5000);
+        let cache = tempfile::tempdir().unwrap();
+        let entries = || fs::read_dir(cache.path()).unwrap().count();
+
+        let index = LineIndex::load(file.path(), Some(cache.path())).unwrap();
+        assert_eq!(index, LineIndex::build(file.path()).unwrap());
+        assert_eq!(entries(), 1);
+        assert_eq!(LineIndex::load(file.path(), Some(cache.path())).unwrap(), index);
+        assert_eq!(entries(), 1);
+
+        // a changed file gets an index of its own
+        fs::write(file.path(), "one\ntwo\n").unwrap();
+        assert_eq!(LineIndex::load(file.path(), Some(cache.path())).unwrap().offsets, [0]);
+        assert_eq!(entries(), 2);
+    }
+
+    #[test]
+    fn unreadable_cache_entries_are_rebuilt() {
+        let (file, _) = numbered(5000);
+        let cache = tempfile::tempdir().unwrap();
+        let index = LineIndex::load(file.path(), Some(cache.path())).unwrap();
+
+        let entry = fs::read_dir(cache.path()).unwrap().next().unwrap().unwrap().path();
+        fs::write(&entry, b"ccatidx1garbage").unwrap();
+        assert_eq!(LineIndex::load(file.path(), Some(cache.path())).unwrap(), index);
+        assert_eq!(LineIndex::decode(&fs::read(&entry).unwrap()), Some(index));
+
+        assert_eq!(LineIndex::decode(b""), None);
+        assert_eq!(LineIndex::decode(b"ccatidx2\0\x10\0\0\0\0\0\0\0\0\0\0\0\0\0\0"), None);
+    }
+}
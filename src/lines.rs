@@ -0,0 +1,28 @@
+use crate::{Diagnostic, Run, Style};
+
+/// A piece of a [`HighlightedLine`] in one style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub style: Style,
+    pub text: String,
+}
+
+/// One source line, highlighted but not laid out: no gutter, no wrapping and
+/// no line ending. [`GutterRenderer`](crate::GutterRenderer) draws the gutter
+/// from `number`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightedLine {
+    /// The line's number in the file, starting at 1
+    pub number: usize,
+    pub spans: Vec<Span>,
+    /// Notes from [`HighlighterConfig::annotations`](crate::HighlighterConfig::annotations) starting at this line
+    pub notes: Vec<String>,
+    /// Diagnostics shown under this line; the spans they point at have the
+    /// underline style in `spans`
+    pub diagnostics: Vec<Diagnostic>,
+    /// With [`HighlighterConfig::dedup`](crate::HighlighterConfig::dedup), the
+    /// identical lines that follow and are shown as this one; with
+    /// [`HighlighterConfig::collapse_blank`](crate::HighlighterConfig::collapse_blank),
+    /// the blank lines after this one that aren't shown
+    pub run: Option<Run>,
+}
@@ -0,0 +1,497 @@
+use anyhow::Result;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::width::display_width;
+use crate::{HighlighterConfig, OutputProfile, SyntaxHighlighter};
+
+/// Extensions of files always shown as mail.
+const MAIL_EXTENSIONS: &[&str] = &["patch", "eml", "mbox"];
+
+/// Width of the rule between messages without a terminal width.
+const RULE_WIDTH: usize = 72;
+
+/// Headers that tell a mail from some other run of `Name: value` lines.
+const MAIL_HEADERS: &[&str] = &["subject", "date", "message-id"];
+
+/// The `From ` line an mbox starts each message with, e.g.
+/// `From 3f2a9c1 Mon Sep 17 00:00:00 2001`.
+static FROM_LINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^From \S+ +\S+ .*:").expect("the From_ regex is valid"));
+
+static HEADER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[!-9;-~]+:").expect("the header regex is valid"));
+
+/// An RFC 2047 encoded word, e.g. `=?UTF-8?B?w6l0w6k=?=`.
+static ENCODED_WORD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?=").expect("the encoded word regex is valid")
+});
+
+/// Whether the file at `file_path` is a mail, a mailbox or a patch to be
+/// shown with [`SyntaxHighlighter::highlight_mail`]: by its extension, or
+/// by starting with an mbox `From ` line or a block of mail headers.
+pub fn is_mail(file_path: &str, content: &str) -> bool {
+    let extension = Path::new(file_path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    if extension.is_some_and(|extension| MAIL_EXTENSIONS.contains(&extension.as_str())) {
+        return true;
+    }
+    if content.lines().next().is_some_and(|line| FROM_LINE.is_match(line)) {
+        return true;
+    }
+
+    let (headers, _) = parse_headers(content);
+    let has = |name: &str| headers.iter().any(|(header, _)| header.eq_ignore_ascii_case(name));
+    has("from") && MAIL_HEADERS.iter().any(|name| has(name))
+}
+
+impl SyntaxHighlighter {
+    /// Renders `content` as mail: each message's headers dim with the
+    /// subject in bold, its text as it is, and any patch it carries through
+    /// [`highlight_patch`](Self::highlight_patch). Quoted-printable and
+    /// base64 text parts are decoded, other parts are named in a notice.
+    /// The messages of a mailbox are separated by rules giving their index.
+    pub fn highlight_mail(&self, content: &str, config: &HighlighterConfig) -> Result<String> {
+        let messages = split_messages(content);
+        let profile = &config.profile;
+        let mut output = String::new();
+
+        for (index, message) in messages.iter().enumerate() {
+            if messages.len() > 1 {
+                if index > 0 {
+                    output.push('\n');
+                }
+                output.push_str(&rule(index + 1, messages.len(), config.terminal_width.unwrap_or(RULE_WIDTH), profile));
+            }
+
+            let mut message = *message;
+            if let Some(line) = message.lines().next().filter(|line| FROM_LINE.is_match(line)) {
+                output.push_str(&dim(line, profile));
+                message = message.split_once('\n').map_or("", |(_, rest)| rest);
+            }
+
+            let (headers, body) = parse_headers(message);
+            for (name, value) in &headers {
+                let value = decode_words(value);
+                match name.eq_ignore_ascii_case("subject") {
+                    true => output.push_str(&format!(
+                        "{}{}:{} {}{}{}\n",
+                        profile.sgr("\x1b[2m"), name, profile.sgr("\x1b[0m"), profile.sgr("\x1b[1m"), value, profile.sgr("\x1b[0m"),
+                    )),
+                    false => output.push_str(&dim(&format!("{}: {}", name, value), profile)),
+                }
+            }
+            if !headers.is_empty() {
+                output.push('\n');
+            }
+
+            output.push_str(&self.mail_part(&headers, body, config)?);
+        }
+
+        Ok(output)
+    }
+
+    /// The body of a message or MIME part with `headers`.
+    fn mail_part(&self, headers: &[(String, String)], body: &str, config: &HighlighterConfig) -> Result<String> {
+        let content_type = header(headers, "content-type").unwrap_or("text/plain");
+        let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+
+        if mime_type.starts_with("multipart/") {
+            let Some(boundary) = parameter(content_type, "boundary") else {
+                return self.mail_body(body, config);
+            };
+            let mut output = String::new();
+            for part in split_parts(body, &boundary) {
+                let (part_headers, part_body) = parse_headers(part);
+                output.push_str(&self.mail_part(&part_headers, part_body, config)?);
+            }
+            return Ok(output);
+        }
+
+        let text = mime_type.starts_with("text/") && mime_type != "text/html";
+        if !text {
+            let name = header(headers, "content-disposition").and_then(|value| parameter(value, "filename"))
+                .or_else(|| parameter(content_type, "name"))
+                .unwrap_or_else(|| "unnamed".to_string());
+            return Ok(dim(&format!("[attachment: {}, {}]", decode_words(&name), mime_type), &config.profile));
+        }
+
+        let encoding = header(headers, "content-transfer-encoding").unwrap_or_default().trim().to_lowercase();
+        let bytes = match encoding.as_str() {
+            "quoted-printable" => decode_quoted_printable(body.as_bytes()),
+            "base64" => decode_base64(body),
+            _ => return self.mail_body(body, config),
+        };
+        let charset = parameter(content_type, "charset")
+            .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(UTF_8);
+
+        self.mail_body(&charset.decode(&bytes).0, config)
+    }
+
+    /// A message's text: the commit message as it is, the `---` line and
+    /// diffstat below it dim, the patch highlighted, and the signature dim.
+    fn mail_body(&self, body: &str, config: &HighlighterConfig) -> Result<String> {
+        let profile = &config.profile;
+        let lines: Vec<&str> = body.split_inclusive('\n').collect();
+        let text = |line: &str| line.trim_end_matches(['\n', '\r']).to_string();
+
+        let diff_start = (0..lines.len()).find(|index| {
+            let line = lines[*index];
+            line.starts_with("diff ") || line.starts_with("Index: ")
+                || (line.starts_with("--- ") && lines.get(index + 1).is_some_and(|next| next.starts_with("+++ ")))
+        });
+        let end = diff_start.unwrap_or(lines.len());
+        let signature = (end..lines.len()).rev().find(|index| text(lines[*index]) == "-- ").unwrap_or(lines.len());
+        // the diffstat follows the last `---` above the patch
+        let stat = diff_start.and_then(|start| (0..start).rev().find(|index| text(lines[*index]) == "---")).unwrap_or(end);
+
+        let mut output = String::new();
+        for line in &lines[..stat] {
+            output.push_str(&format!("{}\n", text(line)));
+        }
+        for line in &lines[stat..end] {
+            output.push_str(&dim(&text(line), profile));
+        }
+        if end < signature {
+            output.push_str(&self.highlight_patch(&lines[end..signature].concat(), config)?);
+        }
+        for line in &lines[signature.max(end)..] {
+            output.push_str(&dim(&text(line), profile));
+        }
+
+        Ok(output)
+    }
+}
+
+/// The messages of an mbox, each starting at a `From ` line that begins the
+/// file or follows a blank line; the whole content when there are none.
+fn split_messages(content: &str) -> Vec<&str> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    let mut after_blank = true;
+    for line in content.split_inclusive('\n') {
+        if after_blank && FROM_LINE.is_match(line) {
+            starts.push(offset);
+        }
+        after_blank = line.trim_end_matches(['\n', '\r']).is_empty();
+        offset += line.len();
+    }
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+
+    starts.iter()
+        .zip(starts.iter().skip(1).chain([&content.len()]))
+        .map(|(start, end)| &content[*start..*end])
+        .collect()
+}
+
+/// The headers `text` starts with, unfolded, and the body after the blank
+/// line ending them. Text not starting with a block of headers ending in a
+/// blank line has none and is all body.
+fn parse_headers(text: &str) -> (Vec<(String, String)>, &str) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        offset += line.len();
+        if trimmed.is_empty() {
+            return match headers.is_empty() {
+                true => (headers, text),
+                false => (headers, &text[offset..]),
+            };
+        }
+        if trimmed.starts_with([' ', '\t']) && !headers.is_empty() {
+            let value = &mut headers.last_mut().expect("there is a header to continue").1;
+            value.push(' ');
+            value.push_str(trimmed.trim());
+        } else if HEADER.is_match(trimmed) {
+            let (name, value) = trimmed.split_once(':').expect("a header has a colon");
+            headers.push((name.to_string(), value.trim().to_string()));
+        } else {
+            break;
+        }
+    }
+
+    (Vec::new(), text)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+/// The parameter `name` of a header value such as
+/// `text/plain; charset="utf-8"`, unquoted.
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The parts of a multipart body between its `--boundary` lines.
+fn split_parts<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == delimiter || trimmed == format!("{}--", delimiter) {
+            if let Some(start) = start {
+                parts.push(&body[start..offset]);
+            }
+            if trimmed != delimiter {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts.extend(start.map(|start| &body[start..]));
+    parts
+}
+
+/// Decodes the RFC 2047 encoded words of a header value, dropping the
+/// whitespace between adjacent ones.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut last = 0;
+
+    for word in ENCODED_WORD.captures_iter(value) {
+        let range = word.get(0).expect("a match has a whole").range();
+        let between = &value[last..range.start];
+        if last == 0 || !between.trim().is_empty() {
+            decoded.push_str(between);
+        }
+        last = range.end;
+
+        let bytes = match &word[2] {
+            "B" | "b" => decode_base64(&word[3]),
+            _ => decode_quoted_printable(word[3].replace('_', " ").as_bytes()),
+        };
+        let charset = Encoding::for_label(word[1].split('*').next().unwrap_or_default().as_bytes()).unwrap_or(UTF_8);
+        decoded.push_str(&charset.decode(&bytes).0);
+    }
+    decoded.push_str(&value[last..]);
+
+    decoded
+}
+
+fn decode_quoted_printable(text: &[u8]) -> Vec<u8> {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut index = 0;
+
+    while index < text.len() {
+        match &text[index..] {
+            // a soft line break joins two lines
+            [b'=', b'\r', b'\n', ..] => index += 3,
+            [b'=', b'\n', ..] => index += 2,
+            [b'=', high, low, ..] if hex(*high).is_some() && hex(*low).is_some() => {
+                bytes.push(hex(*high).unwrap_or_default() << 4 | hex(*low).unwrap_or_default());
+                index += 3;
+            }
+            [byte, ..] => {
+                bytes.push(*byte);
+                index += 1;
+            }
+            [] => break,
+        }
+    }
+    bytes
+}
+
+/// Decodes base64, skipping line breaks and anything else outside its
+/// alphabet, up to the first padding.
+fn decode_base64(text: &str) -> Vec<u8> {
+    let value = |byte: u8| match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in text.bytes().take_while(|byte| *byte != b'=') {
+        let Some(value) = value(byte) else {
+            continue;
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    bytes
+}
+
+/// The rule shown above message `index` of `count`.
+fn rule(index: usize, count: usize, width: usize, profile: &OutputProfile) -> String {
+    let line = profile.symbol("─", "-");
+    let label = format!("{} {}/{} ", line.repeat(2), index, count);
+    let rest = width.saturating_sub(display_width(&label, 1, profile.ambiguous_width));
+    dim(&format!("{}{}", label, line.repeat(rest)), profile)
+}
+
+fn dim(text: &str, profile: &OutputProfile) -> String {
+    if text.is_empty() {
+        return "\n".to_string();
+    }
+    format!("{}{}{}\n", profile.sgr("\x1b[2m"), text, profile.sgr("\x1b[0m"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    const PATCH: &str = "From 3f2a9c1 Mon Sep 17 00:00:00 2001\n\
+        From: Ada <ada@example.com>\n\
+        Date: Mon, 1 Jan 2024 10:00:00 +0000\n\
+        Subject: [PATCH] Add a greeting\n\
+        \n\
+        Say hello.\n\
+        ---\n \
+        src/main.rs | 1 +\n\
+        \n\
+        diff --git a/src/main.rs b/src/main.rs\n\
+        --- a/src/main.rs\n\
+        +++ b/src/main.rs\n\
+        @@ -1 +1,2 @@\n \
+        fn main() {}\n\
+        +fn hello() {}\n\
+        -- \n\
+        2.43.0\n";
+
+    fn dumb() -> HighlighterConfig {
+        HighlighterConfig { profile: OutputProfile::DUMB, ..HighlighterConfig::default() }
+    }
+
+    #[test]
+    fn mail_is_told_by_extension_or_shape() {
+        assert!(is_mail("0001-fix.patch", "anything"));
+        assert!(is_mail("inbox.MBOX", ""));
+        assert!(is_mail("message", PATCH));
+        assert!(is_mail("message", "From: ada@example.com\nSubject: hi\n\nbody\n"));
+        // a From header alone, or other headers alone, aren't a mail
+        assert!(!is_mail("notes.txt", "From: ada@example.com\n\nbody\n"));
+        assert!(!is_mail("notes.txt", "Subject: hi\nDate: today\n\nbody\n"));
+        assert!(!is_mail("main.rs", "fn main() {}\n"));
+    }
+
+    #[test]
+    fn an_mbox_splits_at_from_lines_after_blank_lines() {
+        let mbox = "From a Mon Jan 1 00:00:00 2024\nSubject: one\n\nFrom here on\n\nFrom b Mon Jan 1 00:00:00 2024\nSubject: two\n\nbody\n";
+        let messages = split_messages(mbox);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("From here on\n\n"));
+        assert!(messages[1].starts_with("From b "));
+
+        assert_eq!(split_messages("no mbox\n"), vec!["no mbox\n"]);
+    }
+
+    #[test]
+    fn headers_are_unfolded_and_end_at_a_blank_line() {
+        let (headers, body) = parse_headers("Subject: a long\n\tsubject\nFrom: ada\n\nbody\n");
+        assert_eq!(headers, vec![("Subject".to_string(), "a long subject".to_string()), ("From".to_string(), "ada".to_string())]);
+        assert_eq!(body, "body\n");
+
+        let (headers, body) = parse_headers("not a header\n\nbody\n");
+        assert!(headers.is_empty());
+        assert_eq!(body, "not a header\n\nbody\n");
+        assert_eq!(header(&[("Content-Type".to_string(), "text/plain".to_string())], "content-type"), Some("text/plain"));
+    }
+
+    #[test]
+    fn parameters_are_unquoted() {
+        assert_eq!(parameter("text/plain; charset=\"utf-8\"", "CHARSET").as_deref(), Some("utf-8"));
+        assert_eq!(parameter("multipart/mixed; boundary=xyz", "boundary").as_deref(), Some("xyz"));
+        assert_eq!(parameter("text/plain", "charset"), None);
+    }
+
+    #[test]
+    fn multipart_bodies_split_at_their_boundaries() {
+        let body = "preamble\n--xyz\nfirst\n--xyz\nsecond\n--xyz--\nepilogue\n";
+        assert_eq!(split_parts(body, "xyz"), vec!["first\n", "second\n"]);
+    }
+
+    #[test]
+    fn encoded_words_are_decoded() {
+        assert_eq!(decode_words("=?UTF-8?B?w6l0w6k=?="), "été");
+        assert_eq!(decode_words("=?iso-8859-1?Q?caf=E9_cr=E8me?="), "café crème");
+        // the space between adjacent words goes, the one before them stays
+        assert_eq!(decode_words("Re: =?UTF-8?Q?a?= =?UTF-8?Q?b?="), "Re: ab");
+        assert_eq!(decode_words("plain"), "plain");
+    }
+
+    #[test]
+    fn quoted_printable_and_base64_are_decoded() {
+        assert_eq!(decode_quoted_printable(b"caf=C3=A9 soft=\nbreak =zz"), "café softbreak =zz".as_bytes());
+        assert_eq!(decode_quoted_printable(b"a=\r\nb"), b"ab");
+        assert_eq!(decode_base64("aGVs\nbG8="), b"hello");
+        assert_eq!(decode_base64("aGk"), b"hi");
+        assert_eq!(decode_base64(""), b"");
+    }
+
+    #[test]
+    fn a_patch_mail_renders_its_headers_message_and_diff() {
+        let output = HIGHLIGHTER.highlight_mail(PATCH, &dumb()).unwrap();
+        assert_eq!(
+            output,
+            "From 3f2a9c1 Mon Sep 17 00:00:00 2001\n\
+             From: Ada <ada@example.com>\n\
+             Date: Mon, 1 Jan 2024 10:00:00 +0000\n\
+             Subject: [PATCH] Add a greeting\n\
+             \n\
+             Say hello.\n\
+             ---\n \
+             src/main.rs | 1 +\n\
+             \n\
+             diff --git a/src/main.rs b/src/main.rs\n\
+             --- a/src/main.rs\n\
+             +++ b/src/main.rs\n\
+             @@ -1 +1,2 @@\n \
+             fn main() {}\n\
+             +fn hello() {}\n\
+             -- \n\
+             2.43.0\n",
+        );
+    }
+
+    #[test]
+    fn the_subject_is_bold_and_other_headers_dim() {
+        let config = HighlighterConfig { profile: OutputProfile { colors: true, ..OutputProfile::DUMB }, ..HighlighterConfig::default() };
+        let output = HIGHLIGHTER.highlight_mail("From: ada\nSubject: =?UTF-8?Q?h=C3=A9?=\n\nbody\n", &config).unwrap();
+        assert!(output.starts_with("\x1b[2mFrom: ada\x1b[0m\n\x1b[2mSubject:\x1b[0m \x1b[1mhé\x1b[0m\n\nbody\n"));
+    }
+
+    #[test]
+    fn the_messages_of_a_mailbox_are_numbered() {
+        let mbox = format!("{}\n{}", PATCH, PATCH.replace("Add a greeting", "Second"));
+        let config = HighlighterConfig { terminal_width: Some(12), ..dumb() };
+        let output = HIGHLIGHTER.highlight_mail(&mbox, &config).unwrap();
+        assert!(output.starts_with("-- 1/2 -----\nFrom 3f2a9c1"));
+        assert!(output.contains("\n\n-- 2/2 -----\nFrom 3f2a9c1"));
+        assert!(output.contains("Subject: [PATCH] Second\n"));
+    }
+
+    #[test]
+    fn encoded_text_parts_are_decoded_and_attachments_named() {
+        let mail = "From: ada\nSubject: parts\nContent-Type: multipart/mixed; boundary=\"b\"\n\n\
+            --b\nContent-Type: text/plain; charset=iso-8859-1\nContent-Transfer-Encoding: quoted-printable\n\ncaf=E9\n\
+            --b\nContent-Type: text/plain\nContent-Transfer-Encoding: base64\n\naGVsbG8K\n\
+            --b\nContent-Type: image/png\nContent-Disposition: attachment; filename=\"cat.png\"\n\n iVBORw0K\n\
+            --b--\n";
+        let output = HIGHLIGHTER.highlight_mail(mail, &dumb()).unwrap();
+        assert!(output.ends_with("\ncafé\nhello\n[attachment: cat.png, image/png]\n"), "{}", output);
+    }
+}
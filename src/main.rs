@@ -1,80 +1,2881 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use ccat::{Accessibility, Annotations, AssetSources, BatchJob, BatchOptions, ByteRange, CacheSettings, DateFormat, Dedup, DetectStep, DetectStrategy, DetectionMethod, DiagnosticFormat, Diagnostics, Dialect, EditorCommand, EditorUrl, Elevation, ExportWrap, FileProvenance, GutterRenderer, GutterSide, HexdumpConfig, HighlighterConfig, LineEnding, MemoryBudget, MetadataSummary, NewlineMode, CachedOutput, NumberBase, OutputCache, OutputProfile, OutputTemplate, Policies, Predicate, Preprocessed, Preprocessors, Reservation, Profile, ProjectConfig, Projects, ProvenanceMode, RenderMode, Region, Reveal, Rgb, RustcJson, ScopeLayout, ScopeOverride, SemanticZones, Shadowed, SpooledInput, StripShebang, SyntaxHighlighter, SyntaxRules, ThemeBrightness, Timings, TreeEntry, TreeFilter, Truncation, Trust, TrustStore, WrapMode};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-#[derive(Parser)]
+#[derive(Parser, Serialize)]
+#[serde(rename_all = "kebab-case")]
 #[command(name = "ccat")]
 #[command(about = "A colorized cat command for displaying source code files with syntax highlighting.")]
 #[command(version = "0.1.0")]
 #[command(author = "Adversing")]
+#[command(after_help = "Exit status: 0 on success, 1 on error, 2 on invalid usage, 3 when --if-text declined a binary file, 4 when --if-smaller-than declined a large file (the last decline wins). Declined files produce no output and the remaining files are still shown.")]
 struct Args {
-    /// The file to display
-    file: String,
-    
-    /// Theme to use for highlighting
-    #[arg(short, long, default_value = "base16-ocean.dark")]
-    theme: String,
-    
-    /// Force a specific syntax (overrides file extension detection)
+    /// The files to display
+    #[arg(value_name = "FILE", required_unless_present_any = ["list_themes", "list_syntaxes", "files0_from", "contact_sheet", "config_print", "trust", "clear_render_cache"])]
+    files: Vec<String>,
+
+    /// Also display the NUL-delimited paths read from this file ('-' for stdin), e.g. from find -print0
+    #[arg(long, value_name = "PATH")]
+    files0_from: Option<String>,
+
+    /// Skip files that don't look like text, printing nothing for them and exiting with status 3
+    #[arg(long)]
+    if_text: bool,
+
+    /// Skip files not smaller than SIZE (e.g. 512K, 2MB), printing nothing for them and exiting with status 4
+    #[arg(long, value_name = "SIZE")]
+    if_smaller_than: Option<String>,
+
+    /// For binary files, show what their headers say (images, PDFs, audio and video) or only that they are binary
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    binary_metadata: BinaryMetadata,
+
+    /// Show binary files as a hex and ASCII dump instead of a notice
+    #[arg(long)]
+    hex: bool,
+
+    /// With --hex, dump only the bytes START:END (e.g. 0:4K, 1M:, :512)
+    #[arg(long, value_name = "START:END", requires = "hex")]
+    range: Option<String>,
+
+    /// Only read and highlight the bytes START:END of the file, such as 100:200 or 1M:, seeking to them; what comes before is unseen, so colors may be inexact. With --hex, dump them instead
+    #[arg(long, value_name = "START:END", conflicts_with_all = ["range", "line_range", "provenance"])]
+    bytes: Option<String>,
+
+    /// Theme to use for highlighting, by name or as the path of a .tmTheme or VS Code .json theme file [default: base16-ocean.dark]
     #[arg(short, long)]
-    syntax: Option<String>,
-    
+    theme: Option<String>,
+
+    /// Show the file under themes A and B side by side, each in half the terminal, to compare them
+    #[arg(long, value_name = "A,B")]
+    theme_diff: Option<String>,
+
+    /// List each token with the scopes the syntax gives it, in columns or interleaved with the lines, for debugging syntaxes and themes
+    #[arg(long, alias = "syntax-test", value_name = "LAYOUT", num_args = 0..=1, require_equals = true, default_missing_value = "columns", conflicts_with_all = ["theme_diff", "extract_fences"])]
+    show_scopes: Option<ScopeLayoutArg>,
+
+    /// Recolor tokens matching a scope selector, e.g. "comment=#888888" (repeatable)
+    #[arg(long, value_name = "SELECTOR=COLOR")]
+    override_scope: Vec<String>,
+
+    /// Load a .tmTheme or VS Code .json theme file, named after its file stem; used as the theme unless --theme is given
+    #[arg(long, value_name = "PATH")]
+    theme_file: Vec<PathBuf>,
+
+    /// Cache parsed --theme-file and user themes in this directory
+    #[arg(long, value_name = "DIR")]
+    theme_cache_dir: Option<PathBuf>,
+
+    /// List the available themes (with -v, where each came from)
+    #[arg(long)]
+    list_themes: bool,
+
+    /// Render a snippet under every available theme into one captioned page, written to --output or stdout
+    #[arg(long)]
+    contact_sheet: bool,
+
+    /// The snippet --contact-sheet renders instead of a built-in Rust one
+    #[arg(long, value_name = "PATH", requires = "contact_sheet")]
+    preview_file: Option<String>,
+
+    /// Fit the rows of --contact-sheet's HTML to N columns
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), requires = "contact_sheet")]
+    export_width: Option<u16>,
+
+    /// How --export-width fits longer lines: wrap them (with --wrap's mode), chop them with a …, or leave them to scroll
+    #[arg(long, value_name = "MODE", default_value = "wrap", requires = "export_width")]
+    export_wrap: ExportWrapArg,
+
+    /// List the available syntaxes (with -v, where each came from)
+    #[arg(long)]
+    list_syntaxes: bool,
+
+    /// Print the configuration files would be rendered with, from the config file, the environment and these options, as TOML and exit
+    #[arg(long)]
+    config_print: bool,
+
+    /// Trust the .ccat.toml in DIR as it is now, letting it run preprocessors and an editor command, and exit
+    #[arg(long, value_name = "DIR")]
+    trust: Option<PathBuf>,
+
+    /// Remove every output --cache stored, and exit
+    #[arg(long)]
+    clear_render_cache: bool,
+
+    /// Use a random theme, optionally only a dark or light one
+    #[arg(long, value_name = "KIND", num_args = 0..=1, require_equals = true, default_missing_value = "any", conflicts_with = "theme")]
+    random_theme: Option<RandomTheme>,
+
+    /// Seed for --random-theme so the same theme is picked every run
+    #[arg(long, value_name = "N", requires = "random_theme")]
+    theme_seed: Option<u64>,
+
+    /// Force a specific syntax (overrides file extension detection); N:SYNTAX forces it for the Nth file only (repeatable)
+    #[arg(short, long, value_name = "[N:]SYNTAX")]
+    syntax: Vec<String>,
+
+    /// Force a syntax by a language name, alias or extension such as 'python', 'py' or 'sh', like --syntax [env: CCAT_LANGUAGE, below the flags]
+    #[arg(long, value_name = "LANG", conflicts_with = "syntax")]
+    language: Option<String>,
+
+    /// Detect the syntax and label the header as if the file were called NAME; N:NAME names the Nth file only (repeatable)
+    #[arg(long, value_name = "[N:]NAME")]
+    file_name: Vec<String>,
+
+    /// Show the second file with the lines changed from the first marked and the removed ones struck through
+    #[arg(long)]
+    compare: bool,
+
+    /// Show a unified line diff of two files, each changed line as the old one struck through and the new one, highlighted as the first file
+    #[arg(long, conflicts_with = "compare")]
+    line_diff: bool,
+
+    /// Only show the file if its detected syntax is one of these (repeatable, case-insensitive)
+    #[arg(long, value_name = "NAME")]
+    only_syntax: Vec<String>,
+
+    /// Show nothing if the file's detected syntax is one of these (repeatable, case-insensitive)
+    #[arg(long, value_name = "NAME")]
+    skip_syntax: Vec<String>,
+
+    /// Never pick these syntaxes from a file's first line, only by extension (comma-separated or repeatable)
+    #[arg(long, visible_alias = "exclude-syntax-from-firstline", value_name = "NAME", value_delimiter = ',')]
+    no_firstline_syntax: Vec<String>,
+
+    /// Print each file's path and detected syntax instead of highlighting it
+    #[arg(long)]
+    detect_only: bool,
+
+    /// After each file, show a footer with its absolute path, size, modification time, SHA-256 and Git state, or only the SHA-256
+    #[arg(long, value_enum, value_name = "WHAT", num_args = 0..=1, require_equals = true, default_missing_value = "full")]
+    provenance: Option<ProvenanceArg>,
+
+    /// Show timestamps as relative ("3 days ago"), iso, locale (the date order of $LC_TIME) or custom:<strftime>, all in UTC [default: iso]
+    #[arg(long, value_name = "FORMAT")]
+    date_format: Option<String>,
+
+    /// Show directories as a tree of their files, each with its syntax (from the extension) and line count
+    #[arg(long)]
+    tree: bool,
+
+    /// With --tree, also show the first N lines of each file, highlighted
+    #[arg(long, value_name = "N", requires = "tree")]
+    preview: Option<usize>,
+
+    /// With --tree, list entries at most N levels below each directory
+    #[arg(long, value_name = "N", requires = "tree")]
+    max_depth: Option<usize>,
+
+    /// With --tree, list only files matching this glob; without a '/' it matches the name (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "tree")]
+    glob: Vec<String>,
+
+    /// With --tree, leave out files and directories matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "tree")]
+    exclude: Vec<String>,
+
+    /// Show the files grouped by detected syntax under a heading per group, largest groups or names first
+    #[arg(long, value_enum, value_name = "ORDER", num_args = 0..=1, require_equals = true, default_missing_value = "count")]
+    group_by_language: Option<GroupOrder>,
+
+    /// Don't put a header above each file when showing several
+    #[arg(long)]
+    no_headers: bool,
+
+    /// Number the lines of each file on from the last line of the file before
+    #[arg(long, conflicts_with_all = ["line_range", "cache"])]
+    number_continuous: bool,
+
+    /// Show the files as one continuous document, each still highlighted as its own syntax: no headers or other separators, only a line ending where a file lacks one
+    #[arg(long, conflicts_with = "group_by_language")]
+    join: bool,
+
+    /// Output format: text or json for --detect-only (default text), html or png for --contact-sheet (default html)
+    #[arg(long, value_enum)]
+    format: Option<FormatArg>,
+
+    /// Use only this strategy to detect the syntax
+    #[arg(long, value_enum, default_value_t = DetectArg::Auto)]
+    detect: DetectArg,
+
+    /// Try these detection steps in order instead of --detect, e.g. shebang,extension (steps: extension, filename, shebang, firstline, content)
+    #[arg(long, value_name = "STEPS", conflicts_with = "detect")]
+    detect_order: Option<String>,
+
+    /// Force a syntax for files matching a glob (GLOB=SYNTAX, repeatable)
+    #[arg(long, value_name = "GLOB=SYNTAX", conflicts_with_all = ["syntax", "language"])]
+    syntax_for: Vec<String>,
+
+    /// Render files matching a glob as skip, summary, plain or full; overrides [policies] in the config file (GLOB=MODE, repeatable)
+    #[arg(long, value_name = "GLOB=MODE")]
+    policy: Vec<String>,
+
+    /// Run [preprocessors] from the config file even on files outside the current and home directories or in directories others can write to
+    #[arg(long)]
+    allow_preprocessors: bool,
+
+    /// Read the files you may not through `sudo cat`, which alone runs as root, asking for a password once; with `sudo-prompt = true` in the config file, this happens without the flag when stdin is a terminal. $CCAT_SUDO names another program to use
+    #[arg(long)]
+    sudo: bool,
+
     /// Show line numbers
     #[arg(short, long)]
     line_numbers: bool,
+
+    /// Pin the line number field to this width
+    #[arg(long, value_name = "N", requires = "line_numbers")]
+    line_number_width: Option<usize>,
+
+    /// Allow line numbers wider than --line-number-width instead of failing
+    #[arg(long, requires = "line_number_width")]
+    ln_overflow: bool,
+
+    /// Separate line numbers from the content with this character instead of " | " (e.g. a tab)
+    #[arg(long, value_name = "CHAR", requires = "line_numbers")]
+    gutter_delimiter: Option<char>,
+
+    /// Print line numbers without padding them to a common width
+    #[arg(long, requires = "line_numbers", conflicts_with = "line_number_width")]
+    no_gutter_padding: bool,
+
+    /// Put line numbers before the code, or after it aligned to the right edge
+    #[arg(long, value_enum, default_value_t = GutterSideArg::Left, requires = "line_numbers")]
+    gutter_side: GutterSideArg,
+
+    /// Write line numbers in this base, hex with a 0x prefix and oct with 0o
+    #[arg(long, value_enum, value_name = "BASE", default_value_t = NumberBaseArg::Dec, requires = "line_numbers")]
+    number_base: NumberBaseArg,
+
+    /// Pad line numbers to the gutter's width with zeros, e.g. 0x004a
+    #[arg(long, requires = "line_numbers", conflicts_with = "no_gutter_padding")]
+    number_pad_zero: bool,
+
+    /// Lay out each row with TEMPLATE instead of the gutter, e.g. "{path}:{ln}: {code}", from {ln}, {sep}, {code}, {path} and {lang}, each optionally padded as in {ln:>5}
+    #[arg(long, value_name = "TEMPLATE")]
+    output_template: Option<String>,
+
+    /// Only show these lines, e.g. 5000000:5000100, 100: or :50, seeking close to them through a line index cached per file
+    #[arg(long, value_name = "START:END", conflicts_with = "provenance")]
+    line_range: Option<String>,
+
+    /// Start highlighting this many lines before --line-range, since the highlighting state can't be picked up mid-file
+    #[arg(long, value_name = "LINES", default_value_t = 200, requires = "line_range")]
+    lookback: usize,
+
+    /// Highlight from the start of the file up to --line-range so the colors are exactly those of the whole file
+    #[arg(long, requires = "line_range")]
+    exact_highlighting: bool,
+
+    /// Only show the lines between two markers (START_PATTERN:END_PATTERN)
+    #[arg(long, value_name = "START:END")]
+    between: Option<String>,
+
+    /// Show every region matched by --between instead of just the first
+    #[arg(long, requires = "between")]
+    all_regions: bool,
+
+    /// Only show lines matching a predicate, e.g. "len > 120" or "matches /TODO/ and not blank"
+    #[arg(long, value_name = "EXPR")]
+    select: Option<String>,
+
+    /// Hide a #! first line after detecting the syntax from it, numbering the rest from 1 or keeping their own numbers
+    #[arg(long, value_enum, value_name = "NUMBERS", num_args = 0..=1, require_equals = true, default_missing_value = "renumber")]
+    strip_shebang: Option<StripShebangArg>,
+
+    /// Print lines last to first, like tac; --between and --select pick the lines before they are reversed
+    #[arg(long)]
+    reverse: bool,
+
+    /// Show runs of identical consecutive lines once, with the run's line range and a count
+    #[arg(long)]
+    dedup_consecutive: bool,
+
+    /// Ignore a leading timestamp when comparing lines for --dedup-consecutive
+    #[arg(long, requires = "dedup_consecutive")]
+    dedup_ignore_timestamps: bool,
+
+    /// Show at most N consecutive blank lines, then how many more there were
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), conflicts_with = "dedup_consecutive")]
+    collapse_blank: Option<u16>,
+
+    /// Show runs of blank lines as one, like --collapse-blank 1
+    #[arg(long, conflicts_with_all = ["collapse_blank", "dedup_consecutive"])]
+    squeeze_blank: bool,
+
+    /// Only show the keys of a JSON, YAML or TOML file down to --outline-depth, each followed by how many lines its body has
+    #[arg(long, conflicts_with_all = ["dedup_consecutive", "collapse_blank", "squeeze_blank", "extract_fences", "theme_diff", "line_range"])]
+    outline: bool,
+
+    /// How many levels of keys --outline shows
+    #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..), requires = "outline")]
+    outline_depth: u16,
+
+    /// Show the whole body of every --outline entry with a line matching this regex
+    #[arg(long, value_name = "REGEX", requires = "outline")]
+    pattern: Option<String>,
+
+    /// Collapse regions between editor fold markers (#region and #endregion, // region, vim's {{{ and }}}) into their first line
+    #[arg(long, conflicts_with = "outline")]
+    fold_markers: bool,
+
+    /// Leave the --fold-markers regions whose first line matches this regex expanded
+    #[arg(long, value_name = "REGEX", requires = "fold_markers")]
+    unfold: Option<String>,
+
+    /// Only show the source blocks of a Markdown, Org or reST file tagged with this language ('*' for all)
+    #[arg(long, value_name = "LANG", conflicts_with_all = ["syntax", "language", "syntax_for"])]
+    extract_fences: Option<String>,
+
+    /// Split the file before every line matching this regex, e.g. '^---$' for multi-document YAML, and show each part as a document of its own under a header, numbered as lines of the whole file
+    #[arg(long, value_name = "PATTERN", conflicts_with_all = ["extract_fences", "theme_diff", "outline", "line_range"])]
+    split_on: Option<String>,
+
+    /// With --split-on, detect each document's syntax from its own first line, falling back to the file's
+    #[arg(long, requires = "split_on")]
+    detect_per_segment: bool,
+
+    /// Adjust colors for readability: raise contrast, or compensate for a color vision deficiency
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = AccessibleArg::Off)]
+    accessible: AccessibleArg,
+
+    /// Draw tokens in at most N of the theme's colors, merging similar ones, for e-ink and low-contrast displays
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    max_colors: Option<u16>,
+
+    /// Color matching brackets by nesting depth
+    #[arg(long)]
+    rainbow_brackets: bool,
+
+    /// Comma-separated hex colors to use for bracket depths (e.g. "#ff0000,#00ff00"); overrides `rainbow-palette` in the config file
+    #[arg(long, value_name = "COLORS", value_delimiter = ',', requires = "rainbow_brackets")]
+    rainbow_palette: Option<Vec<String>>,
+
+    /// TOML or JSON file of line annotations to render alongside the code
+    #[arg(long, value_name = "PATH")]
+    annotations: Option<String>,
+
+    /// Compiler diagnostics to underline and show under the lines they point at
+    #[arg(long, value_name = "PATH")]
+    diagnostics: Option<String>,
+
+    /// The format of the --diagnostics file
+    #[arg(long, value_name = "FORMAT", default_value = "rustc", requires = "diagnostics")]
+    diagnostics_format: DiagnosticsFormatArg,
+
+    /// Mark the lines changed since this Git revision (e.g. HEAD~3, main) and show the removed ones struck through
+    #[cfg(feature = "git")]
+    #[arg(long, value_name = "REV", conflicts_with_all = ["line_range", "compare", "line_diff"])]
+    since: Option<String>,
+
+    /// Make line numbers hyperlinks that open the file at that line (terminal only)
+    #[arg(long, requires = "line_numbers")]
+    hyperlink_lines: bool,
+
+    /// URL scheme for line hyperlinks: file, vscode, idea, subl, or a template using {path} and {line}
+    #[arg(long, value_name = "SCHEME", default_value = "file")]
+    editor_url: String,
+
+    /// Underline the URLs in the text, keeping their colors, without making them hyperlinks (color terminal only)
+    #[arg(long)]
+    underline_urls: bool,
+
+    /// Show .patch, .eml and .mbox files and mail read from stdin like any other file, instead of as mail with a highlighted patch
+    #[arg(long)]
+    no_mail_rendering: bool,
+
+    /// After printing, open the file in $VISUAL or $EDITOR at LINE, or at the first line matching --pattern, else the first line --between and --select show
+    #[arg(long, value_name = "LINE", num_args = 0..=1, require_equals = true)]
+    edit: Option<Option<usize>>,
+
+    /// Open the file in the editor without printing it, at the --edit line if given
+    #[arg(long)]
+    edit_only: bool,
+
+    /// Wrap lines longer than the terminal width, at any character or preferably between words
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "char")]
+    wrap: Option<WrapArg>,
+
+    /// Number each wrapped row instead of each source line
+    #[arg(long, requires = "wrap")]
+    number_wrapped: bool,
+
+    /// Make the output independent of the environment: width 80 and the full profile unless given, paths as given, no terminal-only extras
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Show the output in a built-in pager on the alternate screen when it doesn't fit
+    #[cfg(feature = "pager")]
+    #[arg(long, conflicts_with_all = ["output", "tee", "time_report", "summary_stdout"])]
+    full_screen: bool,
+
+    /// Terminal width to use instead of the detected one
+    #[arg(long, value_name = "N")]
+    terminal_width: Option<usize>,
+
+    /// Terminal height to fit images to instead of the detected one
+    #[cfg(feature = "images")]
+    #[arg(long, value_name = "N")]
+    terminal_height: Option<usize>,
+
+    /// Show PNG, JPEG and GIF files inline on a terminal with this graphics protocol, or only their metadata with none
+    #[cfg(feature = "images")]
+    #[arg(long, value_enum, value_name = "PROTOCOL", default_value = "auto")]
+    image_protocol: ImageProtocolArg,
+
+    /// Strip trailing whitespace and escapes from every output line
+    #[arg(long)]
+    trim_output: bool,
+
+    /// Always end the output with a line ending (the default on a terminal)
+    #[arg(long, conflicts_with = "no_newline_fix")]
+    ensure_newline: bool,
+
+    /// End the output with a line ending only when the file does (the default in pipes)
+    #[arg(long)]
+    no_newline_fix: bool,
+
+    /// Add a line ending after a last line without one, marked with a dim note on a color terminal
+    #[arg(long, conflicts_with_all = ["no_newline_fix", "ensure_newline"])]
+    ensure_final_newline: bool,
+
+    /// Rewrite every line terminator of the output to LF or CRLF, or keep the file's own
+    #[arg(long, value_enum, value_name = "EOL", default_value_t = EolArg::Keep)]
+    normalize_eol: EolArg,
+
+    /// Expand tabs to stops N columns apart, measured from the first column of code
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    tabs: Option<u16>,
+
+    /// Show each tab as a dim arrow followed by spaces out to the next tab stop
+    #[arg(long)]
+    tabs_as_arrows: bool,
+
+    /// Spell out non-breaking spaces, tabs, zero-width characters and letters of other scripts in Latin words, each with its code point, in string literals only, or in comments too
+    #[arg(long, value_enum, value_name = "WHERE", num_args = 0..=1, require_equals = true, default_missing_value = "strings")]
+    reveal_strings: Option<RevealArg>,
+
+    /// Don't take the tab width from .editorconfig files when --tabs isn't given
+    #[arg(long)]
+    no_editorconfig: bool,
+
+    /// Don't highlight at all, not even loading the syntaxes: show the text as it is, uncolored, with only the line numbers, headers and other decorations asked for
+    #[arg(long, conflicts_with_all = ["syntax", "language", "syntax_for", "extract_fences", "theme_diff", "outline", "fold_markers", "rainbow_brackets", "reveal_strings", "show_scopes"])]
+    no_highlight: bool,
+
+    /// Show lines longer than this many bytes without highlighting (0 for no limit)
+    #[arg(long, value_name = "N", default_value_t = ccat::DEFAULT_MAX_LINE_LENGTH)]
+    max_line_length: usize,
+
+    /// Fail instead of buffering more than SIZE of input and output (e.g. 256M), except that --reverse then spools its output to a temporary file; overrides `max-memory` in the config file
+    #[arg(long, value_name = "SIZE")]
+    max_memory: Option<String>,
+
+    /// Show the rest of a file without highlighting once a line takes longer than MS milliseconds to highlight
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    line_timeout: Option<u64>,
+
+    /// Read untrusted input with strict limits: never run preprocessors, and default --max-memory, --line-timeout and a lower --max-line-length
+    #[arg(long, conflicts_with = "allow_preprocessors")]
+    paranoid: bool,
+
+    /// Print a notice when no syntax matched the file [default: true on a terminal]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    warn_plain: Option<bool>,
+
+    /// Terminal capability profile; auto treats TERM=dumb or a missing TERM as dumb
+    #[arg(long, value_enum, default_value_t = ProfileArg::Auto)]
+    profile: ProfileArg,
+
+    /// On a terminal, set its background to the theme's while printing (OSC 11), resetting it afterwards and on Ctrl-C
+    #[arg(long, alias = "print-theme-background")]
+    set_bg: bool,
+
+    /// Mark each file as a zone of its own with OSC 133, for terminals that jump between and select command output, on a terminal that supports it
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = SemanticZonesArg::Auto)]
+    semantic_zones: SemanticZonesArg,
+
+    /// Wrap hyperlinks and images for this multiplexer instead of detecting one from $TMUX, $STY and $TERM, e.g. when nested or over SSH
+    #[arg(long, value_enum, value_name = "TERMINAL")]
+    assume_terminal: Option<AssumeTerminal>,
+
+    /// Columns East Asian ambiguous characters such as box drawing take, which terminals disagree on
+    #[arg(long, value_enum, default_value_t = AmbiguousWidthArg::Narrow)]
+    ambiguous_width: AmbiguousWidthArg,
+
+    /// Measure escape sequences already in the files, such as colors in a log, as taking no columns when wrapping, fitting and aligning
+    #[arg(long)]
+    keep_ansi_width: bool,
+
+    /// Send one color sequence per change of color instead of one per token, shrinking the output
+    #[arg(long)]
+    merge_adjacent_colors: bool,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Also write the output to this file, like tee
+    #[arg(long, value_name = "PATH")]
+    tee: Option<String>,
+
+    /// What to write to the --tee file: plain text, or the same escapes as the display
+    #[arg(long, value_enum, default_value_t = TeeFormat::Plain, requires = "tee")]
+    tee_format: TeeFormat,
+
+    /// Text encoding of the --output file (e.g. utf-8, utf-16le, windows-1252)
+    #[arg(long, value_name = "ENCODING", default_value = "utf-8", requires = "output")]
+    output_encoding: String,
+
+    /// Start the --output file with a byte order mark
+    #[arg(long, requires = "output")]
+    output_bom: bool,
+
+    /// Print how many files were highlighted, skipped and failed, by syntax, to stderr at the end
+    #[arg(long)]
+    summary: bool,
+
+    /// Print the --summary to stdout instead
+    #[arg(long)]
+    summary_stdout: bool,
+
+    /// Don't print the --summary
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Reuse the output stored for a file under the cache directory when neither it, its .editorconfig and .ccat.toml settings, the flags, the config file nor the themes and syntaxes changed since, e.g. for a fuzzy finder's preview; `cache = true` in the config file turns it on, and `cache-max-size` (default 256M) caps it
+    #[arg(long, visible_alias = "cached", conflicts_with = "tee")]
+    cache: bool,
+
+    /// Print extra information to stderr; -vv adds a timing breakdown
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Write per-file timings as JSON to this path
+    #[arg(long, value_name = "PATH")]
+    time_report: Option<String>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    
-    if !Path::new(&args.file).exists() {
-        anyhow::bail!("File '{}' not found", args.file);
-    }
-    
-    let content = fs::read_to_string(&args.file)
-        .with_context(|| format!("Failed to read file '{}'", args.file))?;
-    
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    
-    let theme = ts.themes.get(&args.theme)
-        .with_context(|| format!("Theme '{}' not found", args.theme))?;
-    
-    let syntax = {
-        if let Some(syntax_name) = args.syntax {
-            ps.find_syntax_by_name(&syntax_name)
-                .with_context(|| format!("Syntax '{}' not found", syntax_name))?
-        } else {
-            let extension = Path::new(&args.file)
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("");
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DetectArg {
+    Auto,
+    Extension,
+    Shebang,
+    Firstline,
+    Content,
+}
+
+impl From<DetectArg> for DetectStrategy {
+    fn from(detect: DetectArg) -> Self {
+        match detect {
+            DetectArg::Auto => DetectStrategy::Auto,
+            DetectArg::Extension => DetectStrategy::Extension,
+            DetectArg::Shebang => DetectStrategy::Shebang,
+            DetectArg::Firstline => DetectStrategy::FirstLine,
+            DetectArg::Content => DetectStrategy::Content,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DiagnosticsFormatArg {
+    /// rustc --error-format=json or cargo --message-format=json
+    Rustc,
+}
+
+impl DiagnosticsFormatArg {
+    fn format(self) -> &'static dyn DiagnosticFormat {
+        match self {
+            Self::Rustc => &RustcJson,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum FormatArg {
+    Text,
+    Json,
+    Html,
+    Png,
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum WrapArg {
+    Char,
+    Word,
+}
+
+impl From<WrapArg> for WrapMode {
+    fn from(wrap: WrapArg) -> Self {
+        match wrap {
+            WrapArg::Char => WrapMode::Character,
+            WrapArg::Word => WrapMode::Word,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ScopeLayoutArg {
+    Columns,
+    Interleaved,
+}
+
+impl From<ScopeLayoutArg> for ScopeLayout {
+    fn from(layout: ScopeLayoutArg) -> Self {
+        match layout {
+            ScopeLayoutArg::Columns => ScopeLayout::Columns,
+            ScopeLayoutArg::Interleaved => ScopeLayout::Interleaved,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ExportWrapArg {
+    Wrap,
+    Chop,
+    Scroll,
+}
+
+impl From<ExportWrapArg> for ExportWrap {
+    fn from(wrap: ExportWrapArg) -> Self {
+        match wrap {
+            ExportWrapArg::Wrap => ExportWrap::Wrap,
+            ExportWrapArg::Chop => ExportWrap::Chop,
+            ExportWrapArg::Scroll => ExportWrap::Scroll,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum GutterSideArg {
+    Left,
+    Right,
+}
+
+impl From<GutterSideArg> for GutterSide {
+    fn from(side: GutterSideArg) -> Self {
+        match side {
+            GutterSideArg::Left => GutterSide::Left,
+            GutterSideArg::Right => GutterSide::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum NumberBaseArg {
+    Dec,
+    Hex,
+    Oct,
+}
+
+impl From<NumberBaseArg> for NumberBase {
+    fn from(base: NumberBaseArg) -> Self {
+        match base {
+            NumberBaseArg::Dec => NumberBase::Dec,
+            NumberBaseArg::Hex => NumberBase::Hex,
+            NumberBaseArg::Oct => NumberBase::Oct,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ProfileArg {
+    Auto,
+    Full,
+    Ascii,
+    Dumb,
+}
+
+impl From<ProfileArg> for Profile {
+    fn from(profile: ProfileArg) -> Self {
+        match profile {
+            ProfileArg::Auto => Profile::Auto,
+            ProfileArg::Full => Profile::Full,
+            ProfileArg::Ascii => Profile::Ascii,
+            ProfileArg::Dumb => Profile::Dumb,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum AssumeTerminal {
+    /// A terminal with no multiplexer in between
+    Direct,
+    Tmux,
+    Screen,
+}
+
+impl AssumeTerminal {
+    fn multiplexer(self) -> Option<ccat::Multiplexer> {
+        match self {
+            Self::Direct => None,
+            Self::Tmux => Some(ccat::Multiplexer::Tmux),
+            Self::Screen => Some(ccat::Multiplexer::Screen),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum AmbiguousWidthArg {
+    Narrow,
+    Wide,
+}
+
+impl From<AmbiguousWidthArg> for ccat::AmbiguousWidth {
+    fn from(width: AmbiguousWidthArg) -> Self {
+        match width {
+            AmbiguousWidthArg::Narrow => Self::Narrow,
+            AmbiguousWidthArg::Wide => Self::Wide,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum AccessibleArg {
+    Off,
+    HighContrast,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl AccessibleArg {
+    fn mode(self) -> Option<Accessibility> {
+        match self {
+            Self::Off => None,
+            Self::HighContrast => Some(Accessibility::HighContrast),
+            Self::Deuteranopia => Some(Accessibility::Deuteranopia),
+            Self::Protanopia => Some(Accessibility::Protanopia),
+            Self::Tritanopia => Some(Accessibility::Tritanopia),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum TeeFormat {
+    Plain,
+    Ansi,
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum EolArg {
+    Lf,
+    Crlf,
+    Keep,
+}
+
+impl From<EolArg> for LineEnding {
+    fn from(eol: EolArg) -> Self {
+        match eol {
+            EolArg::Lf => LineEnding::Lf,
+            EolArg::Crlf => LineEnding::Crlf,
+            EolArg::Keep => LineEnding::Keep,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum StripShebangArg {
+    /// Number the shown lines from 1
+    Renumber,
+    /// Keep the file's own line numbers
+    KeepNumbers,
+}
+
+impl From<StripShebangArg> for StripShebang {
+    fn from(numbers: StripShebangArg) -> Self {
+        match numbers {
+            StripShebangArg::Renumber => StripShebang::Renumber,
+            StripShebangArg::KeepNumbers => StripShebang::KeepNumbers,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum RevealArg {
+    /// String literals
+    Strings,
+    /// String literals and comments
+    Comments,
+}
+
+impl From<RevealArg> for Reveal {
+    fn from(reveal: RevealArg) -> Self {
+        match reveal {
+            RevealArg::Strings => Reveal::Strings,
+            RevealArg::Comments => Reveal::Comments,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ProvenanceArg {
+    /// Path, size, modification time, SHA-256 and Git state
+    Full,
+    /// Only the SHA-256
+    HashOnly,
+}
+
+impl From<ProvenanceArg> for ProvenanceMode {
+    fn from(provenance: ProvenanceArg) -> Self {
+        match provenance {
+            ProvenanceArg::Full => ProvenanceMode::Full,
+            ProvenanceArg::HashOnly => ProvenanceMode::HashOnly,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum GroupOrder {
+    /// Largest groups first
+    Count,
+    /// By syntax name
+    Name,
+}
+
+#[cfg(feature = "images")]
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ImageProtocolArg {
+    /// Detected from $TERM, $TERM_PROGRAM and $LC_TERMINAL
+    Auto,
+    Kitty,
+    Iterm2,
+    None,
+}
 
-            ps.find_syntax_by_extension(extension)
-                .or_else(|| ps.find_syntax_by_first_line(&content))
-                .unwrap_or_else(|| ps.find_syntax_plain_text())
+#[cfg(feature = "images")]
+impl ImageProtocolArg {
+    fn protocol(self) -> Option<ccat::ImageProtocol> {
+        match self {
+            Self::Auto => ccat::ImageProtocol::from_env(),
+            Self::Kitty => Some(ccat::ImageProtocol::Kitty),
+            Self::Iterm2 => Some(ccat::ImageProtocol::Iterm2),
+            Self::None => None,
         }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum SemanticZonesArg {
+    /// On a terminal detected from $TERM, $TERM_PROGRAM and $LC_TERMINAL
+    Auto,
+    Never,
+    /// Also when stdout isn't a terminal, though never into --output or --tee
+    Always,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum BinaryMetadata {
+    Auto,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum RandomTheme {
+    Any,
+    Dark,
+    Light,
+}
+
+/// Terminal width assumed by `--deterministic`.
+const DETERMINISTIC_WIDTH: usize = 80;
+
+/// `--max-memory` under `--paranoid` when none is given.
+const PARANOID_MAX_MEMORY: usize = 256 << 20;
+/// `--line-timeout` under `--paranoid` when none is given, in milliseconds.
+const PARANOID_LINE_TIMEOUT: u64 = 100;
+/// The longest `--max-line-length` `--paranoid` allows.
+const PARANOID_MAX_LINE_LENGTH: usize = 1024;
+
+/// Exit status when `--if-text` declines a file.
+const EXIT_NOT_TEXT: i32 = 3;
+/// Exit status when `--if-smaller-than` declines a file.
+const EXIT_TOO_LARGE: i32 = 4;
+
+/// The config file, and the theme and syntax files and directories the
+/// assets are loaded from.
+fn asset_paths(args: &Args) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = args.theme_file.iter().cloned().chain(theme_path(args)).collect();
+    paths.extend(ccat::config_path());
+    if let Some(dir) = ccat::config_dir() {
+        paths.extend([dir.join("themes"), dir.join("syntaxes")]);
+    }
+    paths
+}
+
+/// The theme file --theme names, when it is the path of a .tmTheme or
+/// .json file rather than a theme's name.
+fn theme_path(args: &Args) -> Option<PathBuf> {
+    let path = Path::new(args.theme.as_deref()?);
+    let extension = path.extension()?;
+
+    (path.is_file() && (extension == "tmTheme" || extension == "json")).then(|| path.to_path_buf())
+}
+
+fn load_highlighter(args: &Args) -> Result<SyntaxHighlighter> {
+    let config_dir = ccat::config_dir();
+    let sources = AssetSources {
+        theme_files: args.theme_file.iter().cloned().chain(theme_path(args)).collect(),
+        theme_dir: config_dir.as_ref().map(|dir| dir.join("themes")),
+        syntax_dir: config_dir.as_ref().map(|dir| dir.join("syntaxes")),
+        theme_cache_dir: args.theme_cache_dir.clone(),
     };
 
-    
-    let mut h = HighlightLines::new(syntax, theme);
-    
-    for (line_num, line) in LinesWithEndings::from(&content).enumerate() {
-        let ranges: Vec<(Style, &str)> = h.highlight_line(line, &ps)
-            .with_context(|| "Failed to highlight line")?;
-        
-        if args.line_numbers {
-            print!("{:4} | ", line_num + 1);
+    let highlighter = match args.no_highlight {
+        true => SyntaxHighlighter::without_syntaxes(&sources)?,
+        false => SyntaxHighlighter::with_sources(&sources)?,
+    };
+    if args.verbose > 0 {
+        for path in highlighter.cached_themes() {
+            eprintln!("theme cache: loaded '{}' from cache", path.display());
         }
-        
-        let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
-        print!("{}", escaped);
     }
-    
+
+    Ok(highlighter)
+}
+
+/// State shared by every file of a run.
+struct Session<'a> {
+    args: &'a Args,
+    highlighter: SyntaxHighlighter,
+    theme: String,
+    scope_overrides: Vec<ScopeOverride>,
+    syntax_rules: SyntaxRules,
+    policies: Policies,
+    preprocessors: Preprocessors,
+    only_syntax: Vec<String>,
+    skip_syntax: Vec<String>,
+    firstline_exclude: Vec<String>,
+    detect_order: Option<Vec<DetectStep>>,
+    annotations: Option<Annotations>,
+    diagnostics: Option<Diagnostics>,
+    memory_budget: MemoryBudget,
+    /// --rainbow-palette, else `rainbow-palette` in the config file
+    rainbow_palette: Option<Vec<Rgb>>,
+    dedup: Option<Dedup>,
+    is_terminal: bool,
+    terminal_width: Option<usize>,
+    profile: OutputProfile,
+    /// --syntax or --language for every file
+    syntax: Option<String>,
+    /// The syntax $CCAT_LANGUAGE names, used when no flag or rule picks one
+    env_syntax: Option<String>,
+    /// --syntax N:SYNTAX, by the path the file is read from
+    file_syntaxes: HashMap<String, String>,
+    /// What to call the files read from another path, such as a buffered
+    /// pipe or one given a --file-name, by that path
+    labels: HashMap<String, String>,
+    /// The files read through sudo, by path; their content is only ever
+    /// kept here, never on disk
+    elevated: HashMap<String, Vec<u8>>,
+    /// With --compare, the content of the first file
+    compare_with: Option<String>,
+    /// The compiled --pattern
+    outline_pattern: Option<regex::Regex>,
+    /// The parsed --split-on
+    split_on: Option<regex::Regex>,
+    /// The parsed --output-template
+    output_template: Option<OutputTemplate>,
+    /// The parsed --date-format
+    date_format: DateFormat,
+    /// The .ccat.toml files above the files, with whether they are trusted
+    projects: Projects,
+}
+
+/// One file's output, ready to be written.
+struct Rendered {
+    output: String,
+    tee: Option<String>,
+    /// Name of the syntax the file was detected as, None when a policy skipped it
+    syntax: Option<String>,
+    /// Program of the preprocessor whose output was shown in place of the file
+    via: Option<String>,
+    timings: Timings,
+    /// Lines of the file in the output, which --number-continuous numbers
+    /// the next file's after
+    lines: usize,
+    /// Keeps the output counted against the memory budget until it is written
+    _reservation: Reservation,
+}
+
+fn main() -> Result<()> {
+    let result = run();
+    if let Err(error) = &result {
+        print_error_snippet(error);
+    }
+    result
+}
+
+/// Shows where in its file `error` happened, when it says, ahead of the
+/// message itself.
+fn print_error_snippet(error: &anyhow::Error) {
+    let profile = match io::stderr().is_terminal() {
+        true => OutputProfile::from_env(Profile::Auto),
+        false => OutputProfile::DUMB,
+    };
+    if let Some(snippet) = ccat::render_error_snippet(error, profile) {
+        eprint!("{}", snippet);
+    }
+}
+
+fn run() -> Result<()> {
+    let args = Args::parse();
+
+    if args.list_themes || args.list_syntaxes {
+        list_assets(&load_highlighter(&args)?, &args);
+        return Ok(());
+    }
+
+    if args.contact_sheet {
+        return write_contact_sheet(&args);
+    }
+    // every setting the config file holds is taken from this one parse
+    let config_file = ccat::load_config(ccat::config_path().as_deref())?;
+    if args.config_print {
+        return print_config(&start_session(&args, config_file.as_ref())?, config_file.as_ref());
+    }
+    if let Some(dir) = &args.trust {
+        return trust_project(dir);
+    }
+    if args.clear_render_cache {
+        return clear_render_cache();
+    }
+    if args.format.is_some() && !args.detect_only {
+        anyhow::bail!("--format needs --detect-only or --contact-sheet");
+    }
+
+    let mut files = args.files.clone();
+    if let Some(list) = &args.files0_from {
+        files.extend(read_file_list(list)?);
+    }
+    if files.is_empty() {
+        anyhow::bail!("No file given");
+    }
+
+    let (name, names) = per_file(&args.file_name, "--file-name", files.len())?;
+    let (_, syntaxes) = per_file(&args.syntax, "--syntax", files.len())?;
+    let mut spooled = Vec::new();
+    // a buffered pipe's copy is new every time, so --cache leaves it be
+    let mut streams = HashSet::new();
+    let mut origins = HashMap::new();
+    let mut labels = HashMap::new();
+    let mut file_syntaxes = HashMap::new();
+    for (index, file) in files.iter_mut().enumerate() {
+        if !Path::new(file).exists() {
+            anyhow::bail!("File '{}' not found", file);
+        }
+        // a pipe can only be read once, and a renamed file is read from a link under its name
+        let name = names.get(&index).or(name.as_ref());
+        let origin = (!ccat::is_stream(file)).then(|| PathBuf::from(&file));
+        if name.is_some() || ccat::is_stream(file) {
+            let label = name.cloned().unwrap_or_else(|| file.clone());
+            let input = match ccat::is_stream(file) {
+                true => SpooledInput::spool(file, &label)?,
+                false => SpooledInput::link(file, &label)?,
+            };
+            *file = input.path().to_string();
+            if origin.is_none() {
+                streams.insert(file.clone());
+            }
+            labels.insert(file.clone(), label);
+            spooled.push(input);
+        }
+        if let Some(origin) = origin {
+            origins.insert(file.clone(), origin);
+        }
+        if let Some(syntax) = syntaxes.get(&index) {
+            file_syntaxes.insert(file.clone(), syntax.clone());
+        }
+    }
+
+    let elevate = args.sudo || (io::stdin().is_terminal() && Elevation::prompt_configured(config_file.as_ref())?);
+    let mut elevated = HashMap::new();
+    if elevate {
+        let mut elevation = Elevation::default();
+        for file in files.iter().filter(|file| ccat::is_permission_denied(file)) {
+            if elevated.contains_key(file) {
+                continue;
+            }
+            match elevation.read(file, |program| eprintln!("'{}' can only be read as root, asking {}", file, program)) {
+                Ok(bytes) => {
+                    elevated.insert(file.clone(), bytes);
+                }
+                // the file then fails to read as it would have
+                Err(error) => eprintln!("warning: {:#}", error),
+            }
+        }
+    }
+
+    // guards run before the assets are loaded so declining a file stays fast
+    let mut exit_code = 0;
+    let mut summary = Summary::default();
+    let mut accepted = Vec::with_capacity(files.len());
+    for file in files {
+        match decline(&args, &elevated, &file)? {
+            Some(code) => {
+                exit_code = code;
+                summary.skipped += 1;
+            }
+            None => accepted.push(file),
+        }
+    }
+
+    // a pipe has no directory to find a project config from
+    let store = TrustStore::load(trust_store_path().as_deref())?;
+    let projects = Projects::discover(
+        accepted.iter().filter_map(|file| origins.get(file).map(|origin| (file.as_str(), origin.as_path()))),
+        &store,
+    )?;
+    warn_untrusted(&projects);
+
+    if args.edit_only {
+        return edit_file(&args, config_file.as_ref(), &accepted, &projects, outline_pattern(&args)?.as_ref());
+    }
+
+    let mut session = start_session(&args, config_file.as_ref())?;
+    session.projects = projects;
+    session.labels = labels;
+    session.elevated = elevated;
+    session.file_syntaxes = file_syntaxes;
+    if args.compare || args.line_diff {
+        let flag = if args.compare { "--compare" } else { "--line-diff" };
+        let [old, new] = accepted.as_slice() else {
+            anyhow::bail!("{} needs two files, the old one and the new one, got {}", flag, accepted.len());
+        };
+        // both sides are highlighted as the first file, whatever the second is called
+        if args.line_diff && forced_syntax(&session, new).is_none() {
+            if let Some(detection) = session.highlighter.detect_file(old, &detection_config(&session, old)?)? {
+                session.file_syntaxes.insert(new.clone(), detection.syntax);
+            }
+        }
+        session.compare_with = Some(ccat::read_input(old)?.content);
+        accepted.remove(0);
+    }
+
+    if args.detect_only {
+        return detect_files(&session, &accepted);
+    }
+    if args.tree {
+        return print_trees(&session, &accepted);
+    }
+
+    #[cfg(feature = "pager")]
+    if args.full_screen {
+        page_files(&session, &accepted)?;
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return Ok(());
+    }
+
+    let mut output_file = args.output.as_ref().map(|path| (path, String::new()));
+    let mut tee_file = match &args.tee {
+        Some(path) => Some(fs::File::create(path)
+            .with_context(|| format!("Failed to write tee output '{}'", path))?),
+        None => None,
+    };
+    let mut reports = Vec::new();
+    let show_headers = accepted.len() > 1 && !args.no_headers && !args.join;
+    let profile = session.profile;
+    let tee_profile = tee_profile(&session);
+    let (accepted, headings) = match args.group_by_language {
+        Some(order) => group_by_language(&session, accepted, order)?,
+        None => (accepted, HashMap::new()),
+    };
+
+    // a group's heading waits for the first of its files that renders
+    let mut pending_heading = None;
+    let background = match session.highlighter.theme_background(&session.theme) {
+        Some(color) if args.set_bg && session.is_terminal => ccat::TerminalBackground::set(color, &profile)?,
+        _ => None,
+    };
+
+    let zones = match args.semantic_zones {
+        SemanticZonesArg::Auto => session.is_terminal && SemanticZones::supported_by_env(),
+        SemanticZonesArg::Never => false,
+        SemanticZonesArg::Always => args.output.is_none(),
+    };
+    let zones = zones.then(|| SemanticZones::new(&profile)).flatten();
+    let settings = CacheSettings::load(config_file.as_ref())?;
+    // the flags --cache can't be used with turn off the config file's cache too
+    let cache = match args.cache || (settings.enabled && args.tee.is_none() && !args.number_continuous) {
+        true => output_cache(&session, settings.max_size)?,
+        false => None,
+    };
+
+    // with --number-continuous, the lines of the files so far
+    let mut number_offset = 0;
+    // whether the last file shown didn't end its last line
+    let mut unterminated = false;
+
+    for (index, file) in accepted.iter().enumerate() {
+        if let Some(heading) = headings.get(&index) {
+            pending_heading = Some(heading);
+        }
+        let offset = if args.number_continuous { number_offset } else { 0 };
+        // what was read through sudo isn't written anywhere it would outlive the run
+        let cacheable = !streams.contains(file) && !session.elevated.contains_key(file);
+        let mut rendered = match render_cached(&session, cache.as_ref().filter(|_| cacheable), file, session.terminal_width, offset) {
+            Ok(Some(rendered)) => rendered,
+            Ok(None) => {
+                summary.skipped += 1;
+                continue;
+            }
+            // one bad file doesn't stop the rest, like cat
+            Err(error) if accepted.len() > 1 => {
+                print_error_snippet(&error);
+                eprintln!("Error: {:#}", error);
+                exit_code = 1;
+                summary.errors += 1;
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let first = summary.highlighted == 0;
+        number_offset += rendered.lines;
+        match &rendered.syntax {
+            Some(syntax) => summary.record(syntax),
+            None => summary.skipped += 1,
+        }
+
+        // a skip notice names the file itself
+        let show_header = show_headers && rendered.syntax.is_some();
+        let heading = pending_heading.take();
+        let header = |profile: &OutputProfile| {
+            // so the file's first line doesn't go on the last line of the one before
+            let mut header = if unterminated { "\n".to_string() } else { String::new() };
+            if let Some((syntax, count)) = heading {
+                header.push_str(&language_heading(syntax, *count, first, profile));
+            }
+            if show_header {
+                let label = match &rendered.via {
+                    Some(program) => format!("{} (via {})", file_label(&session, file), program),
+                    None => file_label(&session, file).to_string(),
+                };
+                header.push_str(&file_header(&label, first || heading.is_some(), profile));
+            }
+            header
+        };
+
+        let write_started = Instant::now();
+        match output_file.as_mut() {
+            Some((_, buffer)) => {
+                buffer.push_str(&header(&profile));
+                buffer.push_str(&rendered.output);
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                match &zones {
+                    Some(zones) => write!(stdout, "{}", zones.wrap(&header(&profile), &rendered.output))?,
+                    None => write!(stdout, "{}{}", header(&profile), rendered.output)?,
+                }
+                stdout.flush()?;
+            }
+        }
+        if let (Some(tee_file), Some(tee)) = (tee_file.as_mut(), &rendered.tee) {
+            write!(tee_file, "{}{}", header(&tee_profile), tee)
+                .with_context(|| format!("Failed to write tee output '{}'", args.tee.as_deref().unwrap_or_default()))?;
+        }
+        rendered.timings.write = write_started.elapsed();
+        if !rendered.output.is_empty() {
+            unterminated = !rendered.output.ends_with('\n');
+        }
+
+        if args.verbose >= 2 {
+            print_timings(file, &rendered.timings);
+        }
+        reports.push(rendered.timings.report(file));
+    }
+    // process::exit below skips destructors, so the background is reset here
+    drop(background);
+    drop(spooled);
+
+    if let Some((path, buffer)) = output_file {
+        let bytes = ccat::encode_output(&buffer, &args.output_encoding, args.output_bom)?;
+        fs::write(path, bytes)
+            .with_context(|| format!("Failed to write output '{}'", path))?;
+    }
+
+    if let Some(path) = &args.time_report {
+        let report = serde_json::to_string_pretty(&reports)?;
+        fs::write(path, report)
+            .with_context(|| format!("Failed to write time report '{}'", path))?;
+    }
+
+    if args.edit.is_some() {
+        edit_file(&args, config_file.as_ref(), &accepted, &session.projects, session.outline_pattern.as_ref())?;
+    }
+
+    summary.collapsed = session.dedup.as_ref().map_or(0, Dedup::collapsed);
+    if (args.summary || args.summary_stdout) && !args.quiet {
+        let summary = summary.to_string();
+        if args.summary_stdout {
+            print!("{}", summary);
+        } else {
+            eprint!("{}", summary);
+        }
+    }
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+
+    Ok(())
+}
+
+/// The compiled --pattern.
+fn outline_pattern(args: &Args) -> Result<Option<regex::Regex>> {
+    args.pattern.as_deref()
+        .map(|pattern| regex::Regex::new(pattern).with_context(|| format!("Invalid --pattern '{}'", pattern)))
+        .transpose()
+}
+
+/// Opens the only file of `files` in the editor for `--edit`, at the first
+/// line matching `pattern` when one does.
+fn edit_file(args: &Args, config_file: Option<&toml::Table>, files: &[String], projects: &Projects, pattern: Option<&regex::Regex>) -> Result<()> {
+    let [file] = files else {
+        anyhow::bail!("--edit opens a single file, got {}", files.len());
+    };
+
+    let line = match args.edit.flatten() {
+        Some(line) => line,
+        None => {
+            let config = HighlighterConfig {
+                between: args.between.as_deref().map(Region::parse).transpose()?,
+                all_regions: args.all_regions,
+                select: args.select.as_deref().map(Predicate::parse).transpose()?,
+                strip_shebang: args.strip_shebang.map(Into::into),
+                ..HighlighterConfig::default()
+            };
+            let content = ccat::read_input(file)?.content;
+            pattern.and_then(|pattern| content.lines().position(|line| pattern.is_match(line)))
+                .map(|index| index + 1)
+                .or_else(|| ccat::first_shown_line(&content, &config))
+                .unwrap_or(1)
+        }
+    };
+
+    let editor = match projects.trusted_for_file(file).and_then(|project| project.editor_command.clone()) {
+        Some(editor) => editor,
+        None => EditorCommand::load(config_file)?,
+    };
+    editor.launch(file, line)
+}
+
+/// Counts of what happened to each file of a run, for `--summary`.
+#[derive(Default)]
+struct Summary {
+    highlighted: usize,
+    skipped: usize,
+    errors: usize,
+    /// Lines --dedup-consecutive folded into the line before them
+    collapsed: usize,
+    by_syntax: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    fn record(&mut self, syntax: &str) {
+        self.highlighted += 1;
+        *self.by_syntax.entry(syntax.to_string()).or_default() += 1;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} highlighted, {} skipped, {} errors", self.highlighted, self.skipped, self.errors)?;
+        if self.collapsed > 0 {
+            writeln!(f, "{} duplicate lines collapsed", self.collapsed)?;
+        }
+
+        let mut syntaxes: Vec<_> = self.by_syntax.iter().collect();
+        syntaxes.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (syntax, count) in syntaxes {
+            writeln!(f, "  {}: {}", syntax, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders an image file inline when the terminal speaks a graphics protocol,
+/// so never when the output is piped. Images that fail to decode are left
+/// to the metadata notice.
+#[cfg(feature = "images")]
+fn render_image(session: &Session, file: &str, metadata: &MetadataSummary, terminal_width: Option<usize>) -> Option<String> {
+    use ccat::FileKind;
+
+    let shown = session.is_terminal && session.profile.sends(ccat::Escape::Graphics);
+    if !shown || !matches!(metadata.kind, FileKind::Png | FileKind::Jpeg | FileKind::Gif) {
+        return None;
+    }
+    let protocol = session.args.image_protocol.protocol()?;
+
+    let height = session.args.terminal_height
+        .or_else(|| terminal_size::terminal_size().map(|(_, height)| height.0 as usize))
+        // leave a row for the prompt
+        .map(|height| height.saturating_sub(1));
+    protocol.render_file(file, terminal_width.unwrap_or(DETERMINISTIC_WIDTH), height, &session.profile).ok()
+}
+
+/// Shows every file in the built-in pager, re-rendering them for each terminal width.
+#[cfg(feature = "pager")]
+fn page_files(session: &Session, files: &[String]) -> Result<()> {
+    ccat::page(|width| {
+        let mut text = String::new();
+
+        for file in files {
+            let Some(rendered) = render_file(session, file, session.args.terminal_width.or(width), 0)? else {
+                continue;
+            };
+            if files.len() > 1 && rendered.syntax.is_some() {
+                text.push_str(&file_header(file_label(session, file), text.is_empty(), &session.profile));
+            }
+            text.push_str(&rendered.output);
+        }
+
+        Ok(text)
+    })
+}
+
+/// One line of `--detect-only --format json` output.
+#[derive(Serialize)]
+struct DetectRecord<'a> {
+    path: &'a str,
+    language: String,
+    method: &'static str,
+}
+
+/// Prints each file's detected syntax, as `path<TAB>syntax` lines or a JSON array.
+fn detect_files(session: &Session, files: &[String]) -> Result<()> {
+    let args = session.args;
+    let format = match args.format {
+        None | Some(FormatArg::Text) => FormatArg::Text,
+        Some(FormatArg::Json) => FormatArg::Json,
+        Some(_) => anyhow::bail!("--detect-only supports --format text or json"),
+    };
+    let mut records = Vec::new();
+    let mut stdout = io::stdout().lock();
+
+    for file in files {
+        let record = match session.highlighter.detect_file(file, &detection_config(session, file)?)? {
+            Some(detection) => {
+                let filtered = (!session.only_syntax.is_empty() && !session.only_syntax.contains(&detection.syntax))
+                    || session.skip_syntax.contains(&detection.syntax);
+                if filtered {
+                    continue;
+                }
+                DetectRecord { path: file_label(session, file), language: detection.syntax, method: detection.method.as_str() }
+            }
+            None => DetectRecord { path: file_label(session, file), language: "binary".to_string(), method: "binary" },
+        };
+
+        match format {
+            FormatArg::Text => writeln!(stdout, "{}\t{}", record.path, record.language)?,
+            _ => records.push(record),
+        }
+    }
+
+    if format == FormatArg::Json {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&records)?)?;
+    }
+
+    Ok(())
+}
+
+/// Prints every directory in `files` as a `--tree`, and any other file as
+/// a tree of its own.
+fn print_trees(session: &Session, files: &[String]) -> Result<()> {
+    let args = session.args;
+    let filter = TreeFilter::new(&args.glob, &args.exclude, args.max_depth)?;
+    let mut stdout = io::stdout().lock();
+
+    for (index, file) in files.iter().enumerate() {
+        let tree = TreeEntry::walk(Path::new(file), &filter)?;
+        let previews = match args.preview {
+            Some(preview_lines) => tree_previews(session, &tree, preview_lines)?,
+            None => HashMap::new(),
+        };
+        let mut output = String::new();
+        if index > 0 {
+            output.push('\n');
+        }
+        tree_node(session, &tree, "", None, &previews, &mut output)?;
+        stdout.write_all(output.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// A `--preview` file's label and highlighted first lines, by path.
+type Previews = HashMap<PathBuf, (String, Option<String>)>;
+
+/// Renders `entry` and the entries below it, each line after `indent`. The
+/// root has no `branch`; every other entry is the `last` of its siblings or not.
+fn tree_node(session: &Session, entry: &TreeEntry, indent: &str, last: Option<bool>, previews: &Previews, output: &mut String) -> Result<()> {
+    let profile = &session.profile;
+    let (dim, bold, reset) = (profile.sgr("\x1b[2m"), profile.sgr("\x1b[1m"), profile.sgr("\x1b[0m"));
+    let (branch, child_indent) = match last {
+        None => (String::new(), indent.to_string()),
+        Some(false) => (profile.symbol("├── ", "|-- ").to_string(), format!("{}{}", indent, profile.symbol("│   ", "|   "))),
+        Some(true) => (profile.symbol("└── ", "`-- ").to_string(), format!("{}    ", indent)),
+    };
+
+    match entry {
+        TreeEntry::Directory { children, .. } => {
+            let count = entry.file_count();
+            let summary = match (count, entry.is_truncated()) {
+                (0, true) => profile.symbol("…", "...").to_string(),
+                (count, truncated) => format!(
+                    "{}{} {}",
+                    count,
+                    if truncated { "+" } else { "" },
+                    if count == 1 && !truncated { "file" } else { "files" },
+                ),
+            };
+            output.push_str(&format!(
+                "{}{}{}{}/{} {}({}){}\n",
+                indent, branch, bold, entry.name().trim_end_matches('/'), reset, dim, summary, reset,
+            ));
+
+            for (index, child) in children.iter().enumerate() {
+                tree_node(session, child, &child_indent, Some(index + 1 == children.len()), previews, output)?;
+            }
+        }
+        TreeEntry::File { path, .. } => {
+            let (label, preview) = match previews.get(path) {
+                Some(labelled) => labelled.clone(),
+                None => tree_file(session, &path.to_string_lossy())?,
+            };
+            output.push_str(&format!("{}{}{}  {}{}{}\n", indent, branch, entry.name(), dim, label, reset));
+
+            for line in preview.iter().flat_map(|preview| preview.split_inclusive('\n')) {
+                output.push_str(&format!("{}  {}", child_indent, line));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The `--preview` of every text file in `tree`, highlighted as one batch.
+/// Each file is read once for its line count and first lines, and its
+/// syntax is the one its preview was highlighted as.
+fn tree_previews(session: &Session, tree: &TreeEntry, preview_lines: usize) -> Result<Previews> {
+    let args = session.args;
+    let mut files = Vec::new();
+    let mut jobs = Vec::new();
+
+    for path in tree.files() {
+        let file = path.to_string_lossy();
+        if !ccat::is_text_file(&file)? {
+            continue;
+        }
+        let content = ccat::read_input(&file)?.content;
+        let head: String = content.split_inclusive('\n').take(preview_lines).collect();
+        let config = HighlighterConfig {
+            theme: session.theme.clone(),
+            scope_overrides: session.scope_overrides.clone(),
+            max_colors: args.max_colors.map(usize::from),
+            profile: session.profile,
+            tab_width: args.tabs.map(usize::from),
+            memory_budget: session.memory_budget.clone(),
+            newline: NewlineMode::Ensure,
+            line_endings: args.normalize_eol.into(),
+            ..detection_config(session, &file)?
+        };
+        files.push((path.to_path_buf(), content.lines().count(), head.is_empty()));
+        jobs.push(BatchJob::content(file, head).with_config(config));
+    }
+
+    let report = session.highlighter.highlight_batch(&jobs, &BatchOptions::default());
+    files.into_iter().zip(report.results)
+        .map(|((path, lines, empty), output)| {
+            let output = output?;
+            let label = format!("{}, {}", output.syntax, lines_label(lines));
+            Ok((path, (label, (!empty).then_some(output.text))))
+        })
+        .collect()
+}
+
+/// A `--tree` file's label without `--preview`: its syntax, from the name
+/// alone, and line count, or its size when it is binary.
+fn tree_file(session: &Session, file: &str) -> Result<(String, Option<String>)> {
+    if !ccat::is_text_file(file)? {
+        let length = fs::metadata(file)
+            .with_context(|| format!("Failed to read file '{}'", file))?
+            .len();
+        return Ok((format!("binary, {} bytes", length), None));
+    }
+
+    let config = HighlighterConfig {
+        detect: DetectStrategy::Extension,
+        detect_order: None,
+        ..detection_config(session, file)?
+    };
+    let detection = session.highlighter.detect("", file, &config)?;
+    Ok((format!("{}, {}", detection.syntax, lines_label(count_lines(file)?)), None))
+}
+
+fn lines_label(lines: usize) -> String {
+    format!("{} {}", lines, if lines == 1 { "line" } else { "lines" })
+}
+
+/// Counts the lines of `file` without keeping its content, like `wc -l`
+/// but counting a last line without an ending too.
+fn count_lines(file: &str) -> Result<usize> {
+    let mut reader = io::BufReader::new(fs::File::open(file)
+        .with_context(|| format!("Failed to read file '{}'", file))?);
+    let (mut lines, mut last) = (0, b'\n');
+
+    loop {
+        let buffer = reader.fill_buf().with_context(|| format!("Failed to read file '{}'", file))?;
+        let Some(&end) = buffer.last() else {
+            break;
+        };
+        lines += buffer.iter().filter(|&&byte| byte == b'\n').count();
+        last = end;
+        let length = buffer.len();
+        reader.consume(length);
+    }
+
+    Ok(lines + usize::from(last != b'\n'))
+}
+
+/// The profile `--tee` output is written with.
+fn tee_profile(session: &Session) -> OutputProfile {
+    match session.args.tee_format {
+        TeeFormat::Plain => OutputProfile::DUMB,
+        // the file is read without the multiplexer in between
+        TeeFormat::Ansi => OutputProfile { multiplexer: None, ..session.profile },
+    }
+}
+
+/// Writes `--contact-sheet` to `--output`, or stdout.
+fn write_contact_sheet(args: &Args) -> Result<()> {
+    match args.format {
+        None | Some(FormatArg::Html) => {}
+        Some(FormatArg::Png) => anyhow::bail!("--contact-sheet --format png is not supported, ccat has no image renderer; use html"),
+        Some(_) => anyhow::bail!("--contact-sheet supports --format html"),
+    }
+
+    let highlighter = load_highlighter(args)?;
+    let (snippet, path) = match &args.preview_file {
+        Some(path) => (ccat::read_input(path)?.content, path.as_str()),
+        None => (ccat::PREVIEW_SNIPPET.to_string(), ccat::PREVIEW_SNIPPET_PATH),
+    };
+    let config = HighlighterConfig {
+        scope_overrides: args.override_scope.iter()
+            .map(|spec| ScopeOverride::parse(spec))
+            .collect::<Result<_>>()?,
+        max_colors: args.max_colors.map(usize::from),
+        force_syntax: match &args.language {
+            Some(language) => Some(language_syntax(&highlighter, language, "--language")?),
+            None => per_file(&args.syntax, "--syntax", 0)?.0,
+        },
+        detect: args.detect.into(),
+        detect_order: args.detect_order.as_deref().map(DetectStep::parse_order).transpose()?,
+        wrap: args.wrap.map(Into::into),
+        export_width: args.export_width.map(usize::from),
+        export_wrap: args.export_wrap.into(),
+        ..HighlighterConfig::default()
+    };
+
+    let html = highlighter.contact_sheet_html(&snippet, path, &config)?;
+    match &args.output {
+        Some(output) => fs::write(output, html)
+            .with_context(|| format!("Failed to write output '{}'", output))?,
+        None => print!("{}", html),
+    }
+
+    Ok(())
+}
+
+/// The heading of a `--group-by-language` group.
+fn language_heading(syntax: &str, count: usize, first: bool, profile: &OutputProfile) -> String {
+    let rule = profile.symbol("═══", "===");
+    format!(
+        "{}{}{} {} ({} {}) {}{}\n",
+        if first { "" } else { "\n" },
+        profile.sgr("\x1b[1m"),
+        rule,
+        syntax,
+        count,
+        if count == 1 { "file" } else { "files" },
+        rule,
+        profile.sgr("\x1b[0m"),
+    )
+}
+
+/// Group headings by the index of the group's first file: its syntax and size.
+type Headings = HashMap<usize, (String, usize)>;
+
+/// Orders `files` by detected syntax for `--group-by-language`, keeping
+/// their order within a group. Files `--only-syntax` or `--skip-syntax`
+/// would drop are left out.
+fn group_by_language(session: &Session, files: Vec<String>, order: GroupOrder) -> Result<(Vec<String>, Headings)> {
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+
+    for file in files {
+        let syntax = match session.highlighter.detect_file(&file, &detection_config(session, &file)?)? {
+            Some(detection) => detection.syntax,
+            None => "Binary".to_string(),
+        };
+        if (!session.only_syntax.is_empty() && !session.only_syntax.contains(&syntax)) || session.skip_syntax.contains(&syntax) {
+            continue;
+        }
+
+        match groups.iter_mut().find(|(name, _)| *name == syntax) {
+            Some((_, group)) => group.push(file),
+            None => groups.push((syntax, vec![file])),
+        }
+    }
+
+    match order {
+        GroupOrder::Count => groups.sort_by(|(a, a_files), (b, b_files)| b_files.len().cmp(&a_files.len()).then_with(|| a.cmp(b))),
+        GroupOrder::Name => groups.sort_by_key(|(syntax, _)| syntax.to_lowercase()),
+    }
+
+    let mut ordered = Vec::new();
+    let mut headings = HashMap::new();
+    for (syntax, group) in groups {
+        headings.insert(ordered.len(), (syntax, group.len()));
+        ordered.extend(group);
+    }
+
+    Ok((ordered, headings))
+}
+
+/// The configuration syntax detection needs for `file`.
+fn detection_config(session: &Session, file: &str) -> Result<HighlighterConfig> {
+    let args = session.args;
+
+    Ok(HighlighterConfig {
+        force_syntax: match forced_syntax(session, file) {
+            Some(syntax) => Some(syntax),
+            None => rule_syntax(session, file)?,
+        },
+        detect: args.detect.into(),
+        detect_order: session.detect_order.clone(),
+        firstline_exclude: session.firstline_exclude.clone(),
+        ..HighlighterConfig::default()
+    })
+}
+
+/// Splits the values of a per-file option such as --file-name into the one
+/// for every file, the last without a number, and those for the Nth of
+/// `file_count` files by their index.
+fn per_file(specs: &[String], option: &str, file_count: usize) -> Result<(Option<String>, HashMap<usize, String>)> {
+    let mut every_file = None;
+    let mut by_file = HashMap::new();
+
+    for spec in specs {
+        match spec.split_once(':').filter(|(number, _)| !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit())) {
+            Some((number, value)) => {
+                let number: usize = number.parse().with_context(|| format!("Invalid {} '{}'", option, spec))?;
+                if number == 0 || number > file_count {
+                    anyhow::bail!("Invalid {} '{}', there is no file {}", option, spec, number);
+                }
+                by_file.insert(number - 1, value.to_string());
+            }
+            None => every_file = Some(spec.clone()),
+        }
+    }
+
+    Ok((every_file, by_file))
+}
+
+/// What to call `file` in headers and notices.
+fn file_label<'a>(session: &'a Session, file: &'a str) -> &'a str {
+    session.labels.get(file).map_or(file, String::as_str)
+}
+
+/// The --syntax forced for `file`, if any.
+fn forced_syntax(session: &Session, file: &str) -> Option<String> {
+    session.file_syntaxes.get(file).or(session.syntax.as_ref()).cloned()
+}
+
+/// The syntax a --syntax-for rule, or failing that a rule of the file's
+/// project config, maps `file` to, and failing both $CCAT_LANGUAGE.
+fn rule_syntax(session: &Session, file: &str) -> Result<Option<String>> {
+    if let Some(syntax) = session.syntax_rules.resolve(file)? {
+        return Ok(Some(syntax.to_string()));
+    }
+
+    let project_syntax = match session.projects.for_file(file) {
+        Some(project) => project.syntax_rules.resolve(&project.relative(file))?.map(str::to_string),
+        None => None,
+    };
+    Ok(project_syntax.or_else(|| session.env_syntax.clone()))
+}
+
+/// The syntax `language` names, looked up like a Markdown fence tag: by
+/// extension, then by name regardless of case.
+fn language_syntax(highlighter: &SyntaxHighlighter, language: &str, source: &str) -> Result<String> {
+    highlighter.syntax_for_token(language)
+        .map(str::to_string)
+        .with_context(|| format!("Unknown language '{}' in {}; see --list-syntaxes", language, source))
+}
+
+/// The line naming each file when several are shown, like head and tail print.
+fn file_header(file: &str, first: bool, profile: &OutputProfile) -> String {
+    format!(
+        "{}{}==> {} <=={}\n",
+        if first { "" } else { "\n" },
+        profile.sgr("\x1b[2m"),
+        file,
+        profile.sgr("\x1b[0m"),
+    )
+}
+
+/// Reads a NUL-delimited list of paths from `source`, or from stdin for `-`.
+fn read_file_list(source: &str) -> Result<Vec<String>> {
+    let bytes = if source == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)
+            .context("Failed to read the file list from stdin")?;
+        bytes
+    } else {
+        fs::read(source)
+            .with_context(|| format!("Failed to read file list '{}'", source))?
+    };
+
+    bytes.split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8(path.to_vec())
+            .with_context(|| format!("File list '{}' contains a path that is not UTF-8", source)))
+        .collect()
+}
+
+/// Returns the exit code to decline `file` with, if a guard flag rejects it.
+fn decline(args: &Args, elevated: &HashMap<String, Vec<u8>>, file: &str) -> Result<Option<i32>> {
+    if let Some(size) = &args.if_smaller_than {
+        let limit = ccat::parse_size(size)?;
+        let length = fs::metadata(file)
+            .with_context(|| format!("Failed to read file '{}'", file))?
+            .len();
+        if length >= limit {
+            return Ok(Some(EXIT_TOO_LARGE));
+        }
+    }
+    if args.if_text && !is_text(elevated, file)? {
+        return Ok(Some(EXIT_NOT_TEXT));
+    }
+
+    Ok(None)
+}
+
+/// Whether `file` is text, judged from its content if it was read through sudo.
+fn is_text(elevated: &HashMap<String, Vec<u8>>, file: &str) -> Result<bool> {
+    match elevated.get(file) {
+        Some(bytes) => Ok(ccat::is_text(bytes)),
+        None => ccat::is_text_file(file),
+    }
+}
+
+fn start_session<'a>(args: &'a Args, config_file: Option<&toml::Table>) -> Result<Session<'a>> {
+    let highlighter = load_highlighter(args)?;
+
+    let theme = match args.random_theme {
+        Some(kind) => {
+            let brightness = match kind {
+                RandomTheme::Any => None,
+                RandomTheme::Dark => Some(ThemeBrightness::Dark),
+                RandomTheme::Light => Some(ThemeBrightness::Light),
+            };
+            if args.deterministic && args.theme_seed.is_none() {
+                anyhow::bail!("--random-theme needs --theme-seed with --deterministic");
+            }
+            let theme = highlighter.random_theme(brightness, args.theme_seed)
+                .context("No theme matches --random-theme")?
+                .to_string();
+
+            if args.verbose > 0 {
+                eprintln!("theme: {}", theme);
+            }
+            theme
+        }
+        // a theme file is loaded under its file stem
+        None => match (theme_path(args).as_ref(), &args.theme, args.theme_file.last()) {
+            (Some(path), _, _) | (None, None, Some(path)) => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            (None, Some(theme), _) => theme.clone(),
+            (None, None, None) => HighlighterConfig::default().theme,
+        },
+    };
+    for warning in highlighter.theme_warnings(&theme) {
+        eprintln!("warning: {}", warning);
+    }
+
+    let syntax_rules = SyntaxRules::parse(&args.syntax_for)?;
+    let syntaxes = highlighter.available_syntaxes();
+    for rule in syntax_rules.iter() {
+        if !syntaxes.contains(&rule.syntax.as_str()) {
+            anyhow::bail!("Syntax '{}' not found (from rule '{}={}')", rule.syntax, rule.pattern, rule.syntax);
+        }
+    }
+    let resolve_names = |names: &[String]| -> Result<Vec<String>> {
+        names.iter()
+            .map(|name| highlighter.syntax_for_token(name)
+                .map(str::to_string)
+                .with_context(|| format!("Syntax '{}' not found", name)))
+            .collect()
+    };
+    let only_syntax = resolve_names(&args.only_syntax)?;
+    let skip_syntax = resolve_names(&args.skip_syntax)?;
+    let firstline_exclude = resolve_names(&args.no_firstline_syntax)?;
+
+    let annotations = args.annotations.as_deref().map(Annotations::from_file).transpose()?;
+    let diagnostics = args.diagnostics.as_deref()
+        .map(|path| Diagnostics::from_file(path, args.diagnostics_format.format()))
+        .transpose()?;
+
+    let max_memory = match &args.max_memory {
+        Some(size) => Some(ccat::parse_size(size)?),
+        None => MemoryBudget::configured_limit(config_file)?,
+    };
+    let memory_budget = match max_memory {
+        Some(limit) => MemoryBudget::new(limit.try_into().unwrap_or(usize::MAX)),
+        None if args.paranoid => MemoryBudget::new(PARANOID_MAX_MEMORY),
+        None => MemoryBudget::unlimited(),
+    };
+
+    let rainbow_palette = match &args.rainbow_palette {
+        Some(colors) => Some(parse_colors(colors)?),
+        None => ccat::configured_palette(config_file)?,
+    };
+
+    let is_terminal = !args.deterministic && args.output.is_none() && io::stdout().is_terminal();
+    let terminal_width = args.terminal_width.or_else(|| {
+        if args.deterministic {
+            return Some(DETERMINISTIC_WIDTH);
+        }
+        is_terminal
+            .then(terminal_size::terminal_size)
+            .flatten()
+            .map(|(width, _)| width.0 as usize)
+    });
+    let profile = match (args.deterministic, args.profile) {
+        (true, ProfileArg::Auto) => OutputProfile::FULL,
+        (_, profile) => OutputProfile::from_env(profile.into()),
+    };
+    let profile = match args.assume_terminal {
+        Some(terminal) => OutputProfile { multiplexer: terminal.multiplexer(), ..profile },
+        None if args.deterministic => OutputProfile { multiplexer: None, ..profile },
+        None => profile,
+    };
+    let profile = OutputProfile { ambiguous_width: args.ambiguous_width.into(), zero_width_escapes: args.keep_ansi_width, merge_colors: args.merge_adjacent_colors, ..profile };
+    let profile = OutputProfile { colors: profile.colors && !args.no_highlight, ..profile };
+    let syntax = match &args.language {
+        Some(language) => Some(language_syntax(&highlighter, language, "--language")?),
+        None => per_file(&args.syntax, "--syntax", usize::MAX)?.0,
+    };
+    let env_syntax = match std::env::var("CCAT_LANGUAGE") {
+        Ok(language) if !language.is_empty() && !args.no_highlight => Some(language_syntax(&highlighter, &language, "$CCAT_LANGUAGE")?),
+        _ => None,
+    };
+
+    Ok(Session {
+        args,
+        highlighter,
+        theme,
+        scope_overrides: args.override_scope.iter()
+            .map(|spec| ScopeOverride::parse(spec))
+            .collect::<Result<_>>()?,
+        syntax_rules,
+        policies: Policies::load(config_file, &args.policy)?,
+        preprocessors: Preprocessors::load(config_file)?,
+        only_syntax,
+        skip_syntax,
+        firstline_exclude,
+        detect_order: args.detect_order.as_deref().map(DetectStep::parse_order).transpose()?,
+        annotations,
+        diagnostics,
+        memory_budget,
+        rainbow_palette,
+        dedup: args.dedup_consecutive.then(|| Dedup::new(args.dedup_ignore_timestamps)),
+        is_terminal,
+        terminal_width,
+        profile,
+        syntax,
+        env_syntax,
+        file_syntaxes: HashMap::new(),
+        labels: HashMap::new(),
+        elevated: HashMap::new(),
+        compare_with: None,
+        outline_pattern: outline_pattern(args)?,
+        split_on: args.split_on.as_deref()
+            .map(|pattern| regex::Regex::new(pattern).with_context(|| format!("Invalid --split-on '{}'", pattern)))
+            .transpose()?,
+        output_template: args.output_template.as_deref().map(OutputTemplate::parse).transpose()?,
+        date_format: DateFormat::parse(args.date_format.as_deref().unwrap_or("iso"))?,
+        projects: Projects::default(),
+    })
+}
+
+/// Renders binary `file`, or the `--range` of it, as a hex dump.
+fn hex_file(session: &Session, file: &str) -> Result<Rendered> {
+    let args = session.args;
+    let range = args.range.as_deref().or(args.bytes.as_deref()).map(ByteRange::parse).transpose()?.unwrap_or_default();
+    let bytes = match session.elevated.get(file) {
+        Some(bytes) => range.slice(bytes).to_vec(),
+        None => range.read(file)?,
+    };
+    let _input_reservation = session.memory_budget.reserve(bytes.len(), &format!("'{}'", file))?;
+
+    let dump = |profile: OutputProfile| ccat::hexdump(&bytes, &HexdumpConfig { offset: range.start, profile });
+    let output = dump(session.profile);
+    let tee = args.tee.as_ref().map(|_| dump(tee_profile(session)));
+    let reservation = session.memory_budget.reserve(output.len() + tee.as_ref().map_or(0, String::len), "hex dump")?;
+
+    Ok(Rendered { output, tee, syntax: None, via: None, timings: Timings::default(), lines: 0, _reservation: reservation })
+}
+
+/// The configuration every file is rendered with, before what comes from
+/// the file itself: its syntax, annotations, diagnostics, diff and EditorConfig.
+fn session_config(session: &Session) -> Result<HighlighterConfig> {
+    let args = session.args;
+
+    Ok(HighlighterConfig {
+        theme: session.theme.clone(),
+        scope_overrides: session.scope_overrides.clone(),
+        max_colors: args.max_colors.map(usize::from),
+        show_line_numbers: args.line_numbers,
+        force_syntax: session.syntax.clone(),
+        detect: args.detect.into(),
+        detect_order: session.detect_order.clone(),
+        firstline_exclude: session.firstline_exclude.clone(),
+        between: args.between.as_deref().map(Region::parse).transpose()?,
+        all_regions: args.all_regions,
+        select: args.select.as_deref().map(Predicate::parse).transpose()?,
+        strip_shebang: args.strip_shebang.map(Into::into),
+        accessibility: args.accessible.mode(),
+        rainbow_brackets: args.rainbow_brackets,
+        rainbow_palette: session.rainbow_palette.clone(),
+        terminal_width: session.terminal_width,
+        line_hyperlinks: (args.hyperlink_lines && session.is_terminal).then(|| EditorUrl::parse(&args.editor_url)),
+        underline_urls: args.underline_urls && session.is_terminal,
+        canonicalize_paths: !args.deterministic,
+        profile: session.profile,
+        wrap: args.wrap.map(Into::into),
+        number_wrapped: args.number_wrapped,
+        trim_output: args.trim_output,
+        newline: newline_mode(session),
+        line_endings: args.normalize_eol.into(),
+        tab_width: args.tabs.map(usize::from),
+        tab_arrows: args.tabs_as_arrows,
+        reveal: args.reveal_strings.map(Into::into),
+        no_highlight: args.no_highlight,
+        max_line_length: max_line_length(args),
+        line_timeout: args.line_timeout
+            .or(args.paranoid.then_some(PARANOID_LINE_TIMEOUT))
+            .map(Duration::from_millis),
+        line_number_width: args.line_number_width,
+        line_number_overflow: args.ln_overflow,
+        gutter_delimiter: args.gutter_delimiter,
+        gutter_padding: !args.no_gutter_padding,
+        gutter_side: args.gutter_side.into(),
+        number_base: args.number_base.into(),
+        number_pad_zero: args.number_pad_zero,
+        output_template: session.output_template.clone(),
+        memory_budget: session.memory_budget.clone(),
+        reverse: args.reverse,
+        dedup: session.dedup.clone(),
+        collapse_blank: match args.squeeze_blank {
+            true => Some(1),
+            false => args.collapse_blank.map(usize::from),
+        },
+        fold_markers: args.fold_markers,
+        unfold: args.unfold.as_deref()
+            .map(|unfold| regex::Regex::new(unfold).with_context(|| format!("Invalid --unfold '{}'", unfold)))
+            .transpose()?,
+        ..HighlighterConfig::default()
+    })
+}
+
+/// Where the projects trusted with --trust are recorded.
+fn trust_store_path() -> Option<PathBuf> {
+    ccat::cache_dir().map(|dir| dir.join("trusted.toml"))
+}
+
+/// Records the project config in `dir` as trusted, for --trust, listing
+/// the programs it may now run.
+fn trust_project(dir: &Path) -> Result<()> {
+    let path = dir.join(ccat::PROJECT_CONFIG);
+    if !path.is_file() {
+        anyhow::bail!("No {} in '{}'", ccat::PROJECT_CONFIG, dir.display());
+    }
+    let project = ProjectConfig::load(&path)?;
+    let mut store = TrustStore::load(trust_store_path().as_deref())?;
+    store.trust(&project)?;
+
+    println!("trusted '{}' (sha256 {})", project.path.display(), project.sha256);
+    for wanted in project.needs_trust() {
+        println!("  {}", wanted);
+    }
+    Ok(())
+}
+
+/// Says what each project config that isn't trusted wants to run, which
+/// is not run: there is no prompt, so nothing runs until --trust.
+fn warn_untrusted(projects: &Projects) {
+    for (project, trust) in projects.untrusted() {
+        let state = match trust {
+            Trust::Changed => "changed since it was trusted and wants",
+            _ => "wants",
+        };
+        eprintln!("warning: '{}' {} to run programs, which it may not until it is trusted:", project.path.display(), state);
+        for wanted in project.needs_trust() {
+            eprintln!("  {}", wanted);
+        }
+        eprintln!("review it and run 'ccat --trust {}' to allow them", project.dir.display());
+    }
+}
+
+/// Prints the configuration files would be rendered with as TOML, for
+/// --config-print: the config file's sections as they were resolved, the
+/// highlighting options and the command line as parsed.
+fn print_config(session: &Session, config_file: Option<&toml::Table>) -> Result<()> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct EffectiveConfig<'a> {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        editor_command: Option<String>,
+        /// The memory budget in bytes, from --max-memory or the config file
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_memory: Option<usize>,
+        highlight: HighlighterConfig,
+        policies: &'a Policies,
+        preprocessors: &'a Preprocessors,
+        command_line: &'a Args,
+    }
+
+    let config = EffectiveConfig {
+        // without a configured command or $EDITOR there is none to show
+        editor_command: EditorCommand::load(config_file)
+            .ok()
+            .map(|editor| editor.template()),
+        max_memory: session.memory_budget.limit(),
+        highlight: session_config(session)?,
+        policies: &session.policies,
+        preprocessors: &session.preprocessors,
+        command_line: session.args,
+    };
+    print!("{}", toml::to_string(&config).context("Failed to print the configuration")?);
+
+    Ok(())
+}
+
+/// Where --cache keeps rendered output.
+fn render_cache_dir() -> Option<PathBuf> {
+    ccat::cache_dir().map(|dir| dir.join("render"))
+}
+
+/// Empties the --cache, for --clear-render-cache.
+fn clear_render_cache() -> Result<()> {
+    let Some(dir) = render_cache_dir() else {
+        anyhow::bail!("There is no cache directory to clear");
+    };
+    let removed = OutputCache::clear(&dir)?;
+    eprintln!("removed {} cached output{} from '{}'", removed, if removed == 1 { "" } else { "s" }, dir.display());
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The `--cache`, keyed by the flags, config file and assets that decide
+/// how files render, holding up to `max_size` bytes, or None without a
+/// cache directory.
+fn output_cache(session: &Session, max_size: u64) -> Result<Option<OutputCache>> {
+    let Some(dir) = render_cache_dir() else {
+        eprintln!("warning: not caching output, there is no cache directory");
+        return Ok(None);
+    };
+
+    // what only changes which files are shown or what is said about them
+    let mut command_line = serde_json::to_value(session.args)?;
+    if let Some(command_line) = command_line.as_object_mut() {
+        for key in ["files", "files0-from", "cache", "verbose", "quiet", "summary", "summary-stdout", "time-report"] {
+            command_line.remove(key);
+        }
+    }
+    let flags = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "highlight": session_config(session)?,
+        "policies": &session.policies,
+        "preprocessors": &session.preprocessors,
+        "command-line": command_line,
+        "assets": ccat::fingerprint(&asset_paths(session.args)),
+    });
+
+    Ok(Some(OutputCache::new(dir, &flags.to_string(), max_size)))
+}
+
+/// The tab width of `file`: --tabs, else its project's, else its
+/// `.editorconfig`'s unless --no-editorconfig.
+fn tab_width(args: &Args, project: Option<&ProjectConfig>, file: &str) -> Result<Option<usize>> {
+    Ok(match args.tabs {
+        Some(tabs) => Some(usize::from(tabs)),
+        None if project.is_some_and(|project| project.tab_width.is_some()) => project.and_then(|project| project.tab_width),
+        None if args.no_editorconfig => None,
+        None => ccat::EditorConfig::for_file(file)?.tab_width(),
+    })
+}
+
+/// What decides how `file` renders besides the [`output_cache`]'s flags:
+/// its tab width and the content and trust of its `.ccat.toml`.
+fn file_settings(session: &Session, file: &str) -> Result<String> {
+    let project = session.projects.for_file(file);
+    Ok(serde_json::json!({
+        "tab-width": tab_width(session.args, project, file)?,
+        "project": project.map(|project| &project.sha256),
+        "trusted": session.projects.trusted_for_file(file).is_some(),
+    }).to_string())
+}
+
+/// [`render_file`] through the `--cache`: the output stored for `file` when
+/// there is one, else the file rendered and stored.
+fn render_cached(session: &Session, cache: Option<&OutputCache>, file: &str, terminal_width: Option<usize>, number_offset: usize) -> Result<Option<Rendered>> {
+    let Some(cache) = cache else {
+        return render_file(session, file, terminal_width, number_offset);
+    };
+    let Some(entry) = cache.entry(Path::new(file), &file_settings(session, file)?) else {
+        return render_file(session, file, terminal_width, number_offset);
+    };
+
+    if let Some(cached) = cache.get(&entry) {
+        if session.args.verbose > 0 {
+            eprintln!("cache hit for '{}'", file_label(session, file));
+        }
+        return Ok(Some(Rendered {
+            _reservation: session.memory_budget.reserve(cached.output.len(), "cached output")?,
+            output: cached.output,
+            tee: None,
+            syntax: Some(cached.syntax),
+            via: cached.via,
+            timings: Timings::default(),
+            lines: 0,
+        }));
+    }
+
+    let rendered = render_file(session, file, terminal_width, number_offset)?;
+    // notices for skipped and binary files are cheap to make again
+    if let Some(Rendered { output, syntax: Some(syntax), via, .. }) = &rendered {
+        let cached = CachedOutput { syntax: syntax.clone(), via: via.clone(), output: output.clone() };
+        match cache.put(&entry, &cached) {
+            Ok(()) if session.args.verbose > 0 => eprintln!("cached the output of '{}'", file_label(session, file)),
+            Ok(()) => {}
+            Err(error) => eprintln!("warning: {:#}", error),
+        }
+    }
+
+    Ok(rendered)
+}
+
+/// Reads and highlights one file, or returns None when it is filtered out.
+fn render_file(session: &Session, file: &str, terminal_width: Option<usize>, number_offset: usize) -> Result<Option<Rendered>> {
+    let args = session.args;
+    let highlighter = &session.highlighter;
+    let label = file_label(session, file);
+
+    let policy = session.policies.resolve(file);
+    let mode = policy.map_or(RenderMode::Full, |rule| rule.mode);
+    if mode == RenderMode::Skip {
+        let pattern = policy.map_or("", |rule| rule.pattern.as_str());
+        let notice = |profile: &OutputProfile| format!(
+            "{}skipped '{}' (policy '{}'){}\n", profile.sgr("\x1b[2m"), label, pattern, profile.sgr("\x1b[0m"),
+        );
+        return Ok(Some(Rendered {
+            output: notice(&session.profile),
+            tee: args.tee.as_ref().map(|_| notice(&tee_profile(session))),
+            syntax: None,
+            via: None,
+            timings: Timings::default(),
+            lines: 0,
+            _reservation: session.memory_budget.reserve(0, "skip notice")?,
+        }));
+    }
+
+    let project = session.projects.for_file(file);
+    // a trusted project's preprocessors come before the user's
+    let project_preprocessor = session.projects.trusted_for_file(file)
+        .and_then(|project| project.preprocessors.resolve(&project.relative(file)));
+    // a preprocessor would read a file read through sudo as the user, who may not
+    let elevated = session.elevated.get(file);
+    let preprocessor = project_preprocessor.or_else(|| session.preprocessors.resolve(file)).filter(|_| elevated.is_none()).filter(|preprocessor| {
+        let allowed = !args.paranoid && (args.allow_preprocessors || ccat::is_trusted_path(file));
+        if args.paranoid {
+            eprintln!("warning: not running preprocessor '{}' on '{}' under --paranoid", preprocessor.pattern, file);
+        } else if !allowed {
+            eprintln!(
+                "warning: not running preprocessor '{}' on '{}', which is outside the current and home directories or in a directory others can write to; --allow-preprocessors runs it",
+                preprocessor.pattern, file
+            );
+        }
+        allowed
+    });
+    let (preprocessed, preprocessor_failure) = match preprocessor.map(|preprocessor| preprocessor.run(file)) {
+        Some(Preprocessed::Text(text)) => (Some(text), None),
+        Some(Preprocessed::Failed(reason)) => (None, Some(reason)),
+        None => (None, None),
+    };
+
+    if preprocessor.is_none() && args.hex && !is_text(&session.elevated, file)? {
+        return hex_file(session, file).map(Some);
+    }
+
+    let metadata = match args.binary_metadata {
+        _ if preprocessor.is_some() || elevated.is_some() => None,
+        BinaryMetadata::Auto => MetadataSummary::read(file)?,
+        BinaryMetadata::Never => None,
+    };
+    // PDFs can start without any NULs, so a recognized format counts as binary too
+    if preprocessor_failure.is_some() || metadata.is_some() || (preprocessor.is_none() && !is_text(&session.elevated, file)?) {
+        let length = fs::metadata(file)
+            .with_context(|| format!("Failed to read file '{}'", file))?
+            .len();
+        let description = match (&metadata, &preprocessor_failure) {
+            (_, Some(reason)) => format!("binary file, {} bytes, not shown ({})", length, reason),
+            (Some(metadata), _) => metadata.to_string(),
+            (None, None) => format!("binary file, {} bytes, not shown", length),
+        };
+        let notice = |profile: &OutputProfile| format!(
+            "{}{}: {}{}\n", profile.sgr("\x1b[2m"), label, description, profile.sgr("\x1b[0m"),
+        );
+        let output = notice(&session.profile);
+        #[cfg(feature = "images")]
+        let output = metadata.as_ref()
+            .and_then(|metadata| render_image(session, file, metadata, terminal_width))
+            .unwrap_or(output);
+
+        return Ok(Some(Rendered {
+            output,
+            tee: args.tee.as_ref().map(|_| notice(&tee_profile(session))),
+            syntax: None,
+            via: None,
+            timings: Timings::default(),
+            lines: 0,
+            _reservation: session.memory_budget.reserve(0, "binary notice")?,
+        }));
+    }
+
+    let force_syntax = match forced_syntax(session, file) {
+        Some(syntax) => Some(syntax),
+        // a preprocessor that failed has returned its notice by now
+        None if preprocessor.is_some_and(|preprocessor| preprocessor.syntax.is_some()) => {
+            preprocessor.and_then(|preprocessor| preprocessor.syntax.clone())
+        }
+        None if mode == RenderMode::Plain => Some("Plain Text".to_string()),
+        None => rule_syntax(session, file)?,
+    };
+
+    let input_size = match &preprocessed {
+        Some(text) => text.len() as u64,
+        None => fs::metadata(file)
+            .with_context(|| format!("Failed to read file '{}'", file))?
+            .len(),
+    };
+    let line_range = args.line_range.as_deref().map(ccat::LineRange::parse).transpose()?;
+    let byte_range = args.bytes.as_deref().map(ByteRange::parse).transpose()?;
+    if preprocessed.is_some() && (line_range.is_some() || byte_range.is_some() || args.provenance.is_some()) {
+        anyhow::bail!("--line-range, --bytes and --provenance read '{}' itself, which is shown through a preprocessor", file);
+    }
+    if elevated.is_none() && (line_range.is_some() || byte_range.is_some()) && ccat::is_compressed(file) {
+        anyhow::bail!("--line-range and --bytes seek through '{}', which is gzip-compressed", file);
+    }
+    if elevated.is_some() && line_range.is_some() {
+        anyhow::bail!("--line-range seeks through '{}', which is read through sudo", file);
+    }
+    // only the window of a line or byte range is held in memory
+    let reserved_size = if line_range.is_some() || byte_range.is_some() { 0 } else { input_size };
+    let mut input_reservation = session.memory_budget.reserve(reserved_size.try_into().unwrap_or(usize::MAX), &format!("'{}'", file))?;
+
+    let read_started = Instant::now();
+    let mut window = None;
+    let mut byte_window = None;
+    let (input, digest) = match (preprocessed, elevated, line_range, byte_range, args.provenance) {
+        (Some(text), _, _, _, _) => (ccat::Input { content: text, truncated: None, decoding: ccat::Decoding::default() }, None),
+        (None, Some(bytes), _, Some(range), _) => {
+            let bytes = range.slice(bytes);
+            byte_window = Some((range.start, bytes.len()));
+            (ccat::Input { content: window_text(bytes, range.start == 0), truncated: None, decoding: ccat::Decoding::default() }, None)
+        }
+        (None, Some(bytes), _, None, provenance) => {
+            let (input, digest) = ccat::decode_input(file, bytes.clone())?;
+            (input, provenance.map(|_| digest))
+        }
+        (None, None, Some(range), _, _) => {
+            let lines = read_window(session, file, range)?;
+            input_reservation.grow_to(lines.content.len(), &format!("'{}'", file))?;
+            window = Some((range, lines.line_offset, lines.lookback));
+            (ccat::Input { content: lines.content, truncated: None, decoding: ccat::Decoding::default() }, None)
+        }
+        (None, None, None, Some(range), _) => {
+            let bytes = range.read(file)?;
+            input_reservation.grow_to(bytes.len(), &format!("'{}'", file))?;
+            byte_window = Some((range.start, bytes.len()));
+            (ccat::Input { content: window_text(&bytes, range.start == 0), truncated: None, decoding: ccat::Decoding::default() }, None)
+        }
+        (None, None, None, None, Some(_)) => ccat::read_input_hashed(file).map(|(input, digest)| (input, Some(digest)))?,
+        (None, None, None, None, None) => (ccat::read_input(file)?, None),
+    };
+    let mut timings = Timings { read: read_started.elapsed(), ..Timings::default() };
+    let content = input.content;
+    let provenance = match digest {
+        Some(digest) => {
+            let size = input.truncated.map_or(content.len() as u64, |truncation| truncation.read_bytes);
+            Some(FileProvenance::new(file, size, digest)?)
+        }
+        None => None,
+    };
+    let footer = |output: &mut String, profile: &OutputProfile| {
+        if let (Some(provenance), Some(mode)) = (&provenance, args.provenance) {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str(&provenance.footer(mode.into(), &session.date_format, profile));
+        }
+    };
+
+    let annotations = match &session.annotations {
+        Some(annotations) => annotations.for_file(file),
+        None => Vec::new(),
+    };
+
+    // a window says nothing about how long the file is
+    let known_lines = if window.is_some() { usize::MAX } else { content.lines().count() };
+    for annotation in ccat::out_of_range(&annotations, known_lines) {
+        eprintln!(
+            "warning: annotation for lines {}-{} is past the end of '{}'",
+            annotation.lines.start(), annotation.lines.end(), file
+        );
+    }
+
+    let mut config = HighlighterConfig {
+        force_syntax,
+        number_offset,
+        line_offset: window.map_or(0, |(_, line_offset, _)| line_offset),
+        lookback_lines: window.map_or(0, |(_, _, lookback)| lookback),
+        annotations,
+        diagnostics: session.diagnostics.as_ref().map(|diagnostics| diagnostics.for_file(file)).unwrap_or_default(),
+        #[cfg(feature = "git")]
+        diff: match &session.compare_with {
+            Some(old) => Some(compare_diff(args, old, &content)),
+            None => args.since.as_deref().map(|rev| ccat::LineDiff::since(file, rev, &content)).transpose()?,
+        },
+        #[cfg(not(feature = "git"))]
+        diff: session.compare_with.as_deref().map(|old| compare_diff(args, old, &content)),
+        terminal_width,
+        tab_width: tab_width(args, project, file)?,
+        ..session_config(session)?
+    };
+    // a theme given on the command line wins over the project's
+    let theme_given = args.theme.is_some() || args.random_theme.is_some() || !args.theme_file.is_empty();
+    if let Some(theme) = project.and_then(|project| project.theme.as_ref()).filter(|_| !theme_given) {
+        config.theme = theme.clone();
+    }
+
+    let partial = window.is_some_and(|(_, line_offset, _)| line_offset > 0) || byte_range.is_some_and(|range| range.start > 0);
+    let detection = match partial {
+        // the window doesn't have the file's first line to detect from
+        true if config.force_syntax.is_none() => {
+            // a file read through sudo is detected from all of it, already in memory
+            let detection = match elevated {
+                Some(bytes) => highlighter.detect(&String::from_utf8_lossy(bytes), file, &config)?,
+                None => match highlighter.detect_file(file, &config)? {
+                    Some(detection) => detection,
+                    None => highlighter.detect(&content, file, &config)?,
+                },
+            };
+            config.force_syntax = Some(detection.syntax.clone());
+            detection
+        }
+        // the highlighter sees the file's own name, so a decompressed one is detected here
+        _ if input.decoding.decompressed && config.force_syntax.is_none() => {
+            let detection = highlighter.detect(&content, input.decoding.detect_path(file), &config)?;
+            config.force_syntax = Some(detection.syntax.clone());
+            detection
+        }
+        _ => highlighter.detect(&content, file, &config)?,
+    };
+
+    let syntax = &detection.syntax;
+    if (!session.only_syntax.is_empty() && !session.only_syntax.contains(syntax)) || session.skip_syntax.contains(syntax) {
+        if args.verbose > 0 {
+            eprintln!("skipped 1 file: '{}' is {}", label, syntax);
+        }
+        return Ok(None);
+    }
+
+    if args.warn_plain.unwrap_or(session.is_terminal) && !args.no_highlight && detection.method == DetectionMethod::PlainText {
+        let target = match Path::new(file).extension() {
+            Some(extension) => format!(".{}", extension.to_string_lossy()),
+            None => format!("'{}'", file),
+        };
+        eprintln!(
+            "{}{} no syntax matched for {} {} rendering as plain text{}",
+            config.profile.sgr("\x1b[2m"),
+            config.profile.symbol("⚠", "!"),
+            target,
+            config.profile.symbol("—", "-"),
+            config.profile.sgr("\x1b[0m"),
+        );
+    }
+
+    if mode == RenderMode::Summary {
+        let summary = |profile: &OutputProfile| format!(
+            "{}{}: {}, {} lines, {} bytes{}\n",
+            profile.sgr("\x1b[2m"), label, detection.syntax, content.lines().count(), input_size, profile.sgr("\x1b[0m"),
+        );
+        return Ok(Some(Rendered {
+            output: summary(&session.profile),
+            tee: args.tee.as_ref().map(|_| summary(&tee_profile(session))),
+            syntax: Some(detection.syntax),
+            via: preprocessor.map(|preprocessor| preprocessor.program.clone()),
+            timings,
+            lines: 0,
+            _reservation: session.memory_budget.reserve(0, "file summary")?,
+        }));
+    }
+
+    if args.outline {
+        let depth = usize::from(args.outline_depth);
+        config.outline = Some(highlighter.outline(&content, file, &config, depth, session.outline_pattern.as_ref())?);
+    }
+
+    let collect_timings = args.verbose >= 2 || args.time_report.is_some();
+    let theme_diff = args.theme_diff.as_deref().map(theme_pair).transpose()?;
+    if let Some((left, _)) = theme_diff {
+        config.theme = left.to_string();
+    }
+    let diff_width = terminal_width.unwrap_or(DETERMINISTIC_WIDTH);
+    let mail = !args.no_mail_rendering && !args.no_highlight && config.force_syntax.is_none() && ccat::is_mail(file, &content);
+    let scope_layout = args.show_scopes.map(ScopeLayout::from);
+    // highlight_fences leaves the last fence's syntax forced, so the tee starts over from this one
+    let force_syntax = config.force_syntax.clone();
+    let output = match (&args.extract_fences, theme_diff, scope_layout) {
+        (Some(language), _, _) => highlight_fences(highlighter, &content, file, language, &mut config)?,
+        (None, Some((_, right)), _) => highlighter.theme_diff(&content, file, &config, right, diff_width)?,
+        (None, None, Some(layout)) => highlighter.show_scopes(&content, file, &config, layout)?,
+        (None, None, None) if mail => highlighter.highlight_mail(&content, &config)?,
+        (None, None, None) if session.split_on.is_some() => highlight_segments(session, &content, file, &mut config)?,
+        (None, None, None) => highlighter.highlight_content_timed(&content, file, &config, collect_timings.then_some(&mut timings))?,
+    };
+
+    // the highlighter's own reservation ends when it returns, so keep holding one for the buffer
+    let reservation = session.memory_budget.reserve(output.len(), "highlighted output")?;
+    let approximation = |profile: &OutputProfile| match (window, byte_window) {
+        (Some((_, line_offset, lookback)), _) if line_offset > 0 && content.lines().count() > lookback => approximation_notice(line_offset, profile),
+        (_, Some((start, length))) if start > 0 => bytes_notice(start, length, profile),
+        _ => String::new(),
+    };
+    let mut output = approximation(&config.profile) + &output;
+    if let Some(truncation) = input.truncated {
+        output.push_str(&truncation_notice(&content, truncation, &config.profile));
+    }
+    footer(&mut output, &config.profile);
+
+    let tee = match &args.tee {
+        Some(_) => {
+            config.force_syntax = force_syntax;
+            config.profile = tee_profile(session);
+            if args.tee_format == TeeFormat::Plain {
+                config.line_hyperlinks = None;
+            }
+            // the tee shows the same lines, they were collapsed once
+            config.dedup = config.dedup.as_ref().map(Dedup::untracked);
+            let mut tee = approximation(&config.profile) + &match (&args.extract_fences, theme_diff, scope_layout) {
+                (Some(language), _, _) => highlight_fences(highlighter, &content, file, language, &mut config)?,
+                (None, Some((_, right)), _) => highlighter.theme_diff(&content, file, &config, right, diff_width)?,
+                (None, None, Some(layout)) => highlighter.show_scopes(&content, file, &config, layout)?,
+                (None, None, None) if mail => highlighter.highlight_mail(&content, &config)?,
+                (None, None, None) if session.split_on.is_some() => highlight_segments(session, &content, file, &mut config)?,
+                (None, None, None) => highlighter.highlight_content(&content, file, &config)?,
+            };
+            if let Some(truncation) = input.truncated {
+                tee.push_str(&truncation_notice(&content, truncation, &config.profile));
+            }
+            footer(&mut tee, &config.profile);
+            Some(tee)
+        }
+        None => None,
+    };
+
+    Ok(Some(Rendered {
+        output,
+        tee,
+        syntax: Some(detection.syntax),
+        via: preprocessor.map(|preprocessor| preprocessor.program.clone()),
+        timings,
+        lines: content.lines().count(),
+        _reservation: reservation,
+    }))
+}
+
+/// The `--max-line-length` in effect, capped under `--paranoid`.
+fn max_line_length(args: &Args) -> Option<usize> {
+    let max = (args.max_line_length != 0).then_some(args.max_line_length);
+    match args.paranoid {
+        true => Some(max.map_or(PARANOID_MAX_LINE_LENGTH, |max| max.min(PARANOID_MAX_LINE_LENGTH))),
+        false => max,
+    }
+}
+
+/// The diff of `content` against the first file of --compare or
+/// --line-diff, the latter showing each changed line as removed and added.
+fn compare_diff(args: &Args, old: &str, content: &str) -> ccat::LineDiff {
+    let diff = ccat::LineDiff::new(old, content);
+    if args.line_diff {
+        diff.unified()
+    } else {
+        diff
+    }
+}
+
+/// Splits a `--theme-diff` value into its two theme names.
+fn theme_pair(spec: &str) -> Result<(&str, &str)> {
+    match spec.split_once(',') {
+        Some((left, right)) if !left.trim().is_empty() && !right.trim().is_empty() => Ok((left.trim(), right.trim())),
+        _ => anyhow::bail!("Invalid --theme-diff '{}', expected two theme names, e.g. base16-ocean.dark,InspiredGitHub", spec),
+    }
+}
+
+/// Highlights each matching source block of `content` with its own language,
+/// preceded by a label giving where it starts.
+fn highlight_fences(highlighter: &SyntaxHighlighter, content: &str, file: &str, language: &str, config: &mut HighlighterConfig) -> Result<String> {
+    let mut output = String::new();
+
+    for fence in ccat::extract_fences(content, Dialect::for_path(file), language) {
+        config.force_syntax = Some(highlighter.syntax_for_token(&fence.language).unwrap_or("Plain Text").to_string());
+
+        let tag = if fence.language.is_empty() { String::new() } else { format!(" ({})", fence.language) };
+        output.push_str(&format!(
+            "{}{}:{}{}{}\n",
+            config.profile.sgr("\x1b[2m"),
+            file,
+            fence.start_line,
+            tag,
+            config.profile.sgr("\x1b[0m"),
+        ));
+        output.push_str(&highlighter.highlight_content(&fence.code, file, config)?);
+    }
+
+    Ok(output)
+}
+
+/// Highlights `content` as the documents `--split-on` divides it into, a
+/// separator line starting each one but the first. Every document gets a
+/// header and is numbered as lines of the whole file, in a gutter as wide
+/// for all of them.
+fn highlight_segments(session: &Session, content: &str, file: &str, config: &mut HighlighterConfig) -> Result<String> {
+    let highlighter = &session.highlighter;
+    let separator = session.split_on.as_ref().expect("only called with --split-on");
+
+    let mut segments = vec![(0, 0)];
+    let mut offset = 0;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        if index > 0 && separator.is_match(line.trim_end_matches(['\n', '\r'])) {
+            segments.push((index, offset));
+        }
+        offset += line.len();
+    }
+
+    let file_syntax = highlighter.detect(content, file, config)?.syntax;
+    if config.line_number_width.is_none() && config.gutter_padding {
+        config.line_number_width = Some(GutterRenderer::new(content.lines().count(), config)?.number_width());
+    }
+    let count = segments.len();
+    let mut output = String::new();
+
+    for (number, &(first_line, start)) in segments.iter().enumerate() {
+        let end = segments.get(number + 1).map_or(content.len(), |&(_, end)| end);
+        let segment = &content[start..end];
+        config.line_offset = first_line;
+        config.force_syntax = None;
+        // a document is detected from its first line after the separator
+        let body = if number == 0 { segment } else { segment.split_once('\n').map_or("", |(_, body)| body) };
+        let syntax = match highlighter.detect(body, "", config)? {
+            detection if session.args.detect_per_segment && detection.method != DetectionMethod::PlainText => detection.syntax,
+            _ => file_syntax.clone(),
+        };
+
+        output.push_str(&format!(
+            "{}{}:{} document {}/{} ({}){}\n",
+            config.profile.sgr("\x1b[2m"),
+            file_label(session, file),
+            first_line + 1,
+            number + 1,
+            count,
+            syntax,
+            config.profile.sgr("\x1b[0m"),
+        ));
+        config.force_syntax = Some(syntax);
+        output.push_str(&highlighter.highlight_content(segment, file, config)?);
+    }
+
+    Ok(output)
+}
+
+/// How the end of the output follows the end of the file: on a terminal it
+/// always ends with a line ending unless `--no-newline-fix` is given.
+fn newline_mode(session: &Session) -> NewlineMode {
+    let args = session.args;
+    if args.ensure_final_newline && session.is_terminal && session.profile.colors {
+        NewlineMode::Mark
+    } else if args.ensure_newline || args.ensure_final_newline || (session.is_terminal && !args.no_newline_fix) {
+        NewlineMode::Ensure
+    } else {
+        NewlineMode::Raw
+    }
+}
+
+/// Reads the lines of `range` from `file` through its cached line index,
+/// with the lookback `--lookback` and `--exact-highlighting` ask for.
+fn read_window(session: &Session, file: &str, range: ccat::LineRange) -> Result<ccat::LineWindow> {
+    let args = session.args;
+    let cache_dir = ccat::cache_dir().map(|dir| dir.join("index"));
+    let index = ccat::LineIndex::load(Path::new(file), cache_dir.as_deref())?;
+    let lookback = (!args.exact_highlighting).then_some(args.lookback);
+    let window = ccat::read_lines(Path::new(file), &index, range, lookback)?;
+
+    if window.content.lines().count() <= window.lookback {
+        eprintln!("warning: '{}' has fewer than {} lines", file, range.start);
+    }
+    Ok(window)
+}
+
+/// The dim line put above a `--line-range` whose highlighting started
+/// `line_offset` lines into the file rather than at its start.
+fn approximation_notice(line_offset: usize, profile: &OutputProfile) -> String {
+    format!(
+        "{}{} highlighted from line {} rather than the top of the file, so colors may be inexact; --exact-highlighting starts at the top{}\n",
+        profile.sgr("\x1b[2m"),
+        profile.symbol("⋯", "..."),
+        line_offset + 1,
+        profile.sgr("\x1b[0m"),
+    )
+}
+
+/// The dim line put above `--bytes` that start `start` bytes into the
+/// file, `length` of them shown.
+fn bytes_notice(start: u64, length: usize, profile: &OutputProfile) -> String {
+    format!(
+        "{}{} bytes {}-{} only, highlighted without the {} before them, so colors may be inexact{}\n",
+        profile.sgr("\x1b[2m"),
+        profile.symbol("⋯", "..."),
+        start,
+        start + length as u64,
+        start,
+        profile.sgr("\x1b[0m"),
+    )
+}
+
+/// `bytes` cut out of a file as text. A character the cut split at either
+/// end is dropped rather than shown as a replacement character, at the
+/// start unless the cut is at the start of the file.
+fn window_text(bytes: &[u8], at_start: bool) -> String {
+    let start = match at_start {
+        true => 0,
+        false => bytes.iter().take(3).take_while(|byte| *byte & 0xc0 == 0x80).count(),
+    };
+    let bytes = &bytes[start..];
+    let end = match std::str::from_utf8(bytes) {
+        Err(error) if error.error_len().is_none() => error.valid_up_to(),
+        _ => bytes.len(),
+    };
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn truncation_notice(content: &str, truncation: Truncation, profile: &OutputProfile) -> String {
+    let read_lines = content.lines().count();
+    let separator = if !content.is_empty() && !content.ends_with('\n') { "\n" } else { "" };
+
+    format!(
+        "{}{}[file truncated while reading: showed {} of ~{} lines]{}\n",
+        separator,
+        profile.sgr("\x1b[2m"),
+        read_lines,
+        truncation.estimated_lines(read_lines),
+        profile.sgr("\x1b[0m"),
+    )
+}
+
+fn list_assets(highlighter: &SyntaxHighlighter, args: &Args) {
+    if args.list_themes {
+        let mut themes = highlighter.available_themes();
+        themes.sort();
+
+        for theme in themes {
+            match highlighter.theme_origin(theme).filter(|_| args.verbose > 0) {
+                Some(origin) => println!("{}\t{}", theme, origin),
+                None => println!("{}", theme),
+            }
+        }
+        if args.verbose > 0 {
+            print_shadowed(highlighter.shadowed_themes());
+        }
+    }
+
+    if args.list_syntaxes {
+        let mut syntaxes = highlighter.available_syntaxes();
+        syntaxes.sort_unstable();
+        syntaxes.dedup();
+
+        for syntax in syntaxes {
+            match highlighter.syntax_origin(syntax).filter(|_| args.verbose > 0) {
+                Some(origin) => println!("{}\t{}", syntax, origin),
+                None => println!("{}", syntax),
+            }
+        }
+        if args.verbose > 0 {
+            print_shadowed(highlighter.shadowed_syntaxes());
+        }
+    }
+}
+
+fn print_shadowed(shadowed: &[Shadowed]) {
+    for entry in shadowed {
+        println!("{}\t{}\t(shadowed by {})", entry.name, entry.origin, entry.shadowed_by);
+    }
+}
+
+fn print_timings(file: &str, timings: &Timings) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+    eprintln!("{}: read {:.2}ms, detect {:.2}ms, highlight {:.2}ms, write {:.2}ms",
+        file, ms(timings.read), ms(timings.detect), ms(timings.highlight), ms(timings.write));
+    for (line, time) in &timings.slowest_lines {
+        eprintln!("  line {}: {:.2}ms", line, ms(*time));
+    }
+}
+
+fn parse_colors(colors: &[String]) -> Result<Vec<Rgb>> {
+    colors.iter()
+        .map(|color| color.trim().parse().map_err(anyhow::Error::msg))
+        .collect()
+}
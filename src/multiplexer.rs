@@ -0,0 +1,112 @@
+use serde::Serialize;
+
+/// A terminal multiplexer between ccat and the terminal, which interprets
+/// escapes itself and drops the ones it doesn't know unless they are
+/// wrapped in its DCS passthrough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Multiplexer {
+    /// tmux, which passes `\ePtmux;…\e\\` on with `allow-passthrough` set
+    Tmux,
+    /// GNU screen, which passes `\eP…\e\\` on
+    Screen,
+}
+
+/// The kinds of escapes ccat sends that a multiplexer may not understand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    /// OSC 8 hyperlinks
+    Hyperlink,
+    /// kitty graphics and iTerm2 inline images
+    Graphics,
+    /// OSC 11 and 111, which set and reset the background color
+    Background,
+    /// OSC 133 semantic zones around each file
+    SemanticZones,
+}
+
+impl Multiplexer {
+    /// Detects the multiplexer from `$TMUX` and `$STY`, which tmux and screen
+    /// set in their panes, falling back to a `$TERM` starting with `tmux` or
+    /// `screen` for sessions that don't carry those, such as over SSH.
+    pub fn detect(tmux: Option<&str>, sty: Option<&str>, term: Option<&str>) -> Option<Self> {
+        let set = |var: Option<&str>| var.is_some_and(|value| !value.is_empty());
+        let term = term.unwrap_or("");
+
+        if set(tmux) || term.starts_with("tmux") {
+            Some(Self::Tmux)
+        } else if set(sty) || term.starts_with("screen") {
+            Some(Self::Screen)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the multiplexer from the environment, see [`detect`](Self::detect).
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok();
+        Self::detect(var("TMUX").as_deref(), var("STY").as_deref(), var("TERM").as_deref())
+    }
+
+    /// Whether `escape` is worth sending through this multiplexer at all.
+    /// screen cuts passthrough strings short and doesn't know where an image
+    /// ends, so graphics are left out there.
+    pub fn sends(self, escape: Escape) -> bool {
+        !matches!((self, escape), (Self::Screen, Escape::Graphics))
+    }
+
+    /// Wraps one complete escape `sequence` so it reaches the terminal as is.
+    pub fn wrap(self, sequence: &str) -> String {
+        match self {
+            // every ESC inside is doubled so tmux doesn't take it as the end
+            Self::Tmux => format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")),
+            // screen ends the passthrough at the first ST, so the inner
+            // sequence has to end with BEL instead
+            Self::Screen => format!("\x1bP{}\x1b\\", sequence.replace("\x1b\\", "\x07")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OutputProfile;
+
+    #[test]
+    fn the_multiplexer_is_found_from_its_variables_or_term() {
+        assert_eq!(Multiplexer::detect(Some("/tmp/tmux-1000/default,1,0"), None, Some("xterm-256color")), Some(Multiplexer::Tmux));
+        assert_eq!(Multiplexer::detect(None, Some("1234.pts-0.host"), Some("xterm")), Some(Multiplexer::Screen));
+        // over ssh only TERM is left
+        assert_eq!(Multiplexer::detect(None, None, Some("tmux-256color")), Some(Multiplexer::Tmux));
+        assert_eq!(Multiplexer::detect(None, None, Some("screen.xterm-256color")), Some(Multiplexer::Screen));
+        assert_eq!(Multiplexer::detect(Some(""), Some(""), Some("xterm-kitty")), None);
+        assert_eq!(Multiplexer::detect(None, None, None), None);
+    }
+
+    #[test]
+    fn tmux_doubles_every_escape_inside() {
+        let profile = OutputProfile { multiplexer: Some(Multiplexer::Tmux), ..OutputProfile::FULL };
+
+        assert_eq!(profile.passthrough("\x1b]8;;file:///a.rs#L3\x1b\\"), "\x1bPtmux;\x1b\x1b]8;;file:///a.rs#L3\x1b\x1b\\\x1b\\");
+        assert_eq!(profile.passthrough("\x1b]11;rgb:28/2c/34\x07"), "\x1bPtmux;\x1b\x1b]11;rgb:28/2c/34\x07\x1b\\");
+        assert_eq!(OutputProfile::FULL.passthrough("\x1b]111\x07"), "\x1b]111\x07");
+    }
+
+    #[test]
+    fn screen_ends_the_inner_sequence_with_bel() {
+        assert_eq!(Multiplexer::Screen.wrap("\x1b]8;;https://example.com\x1b\\"), "\x1bP\x1b]8;;https://example.com\x07\x1b\\");
+        assert_eq!(Multiplexer::Screen.wrap("\x1b]11;rgb:28/2c/34\x07"), "\x1bP\x1b]11;rgb:28/2c/34\x07\x1b\\");
+    }
+
+    #[test]
+    fn screen_gets_no_graphics() {
+        assert!(!Multiplexer::Screen.sends(Escape::Graphics));
+        assert!(Multiplexer::Screen.sends(Escape::Hyperlink));
+        assert!(Multiplexer::Tmux.sends(Escape::Graphics));
+
+        let screen = OutputProfile { multiplexer: Some(Multiplexer::Screen), ..OutputProfile::FULL };
+        assert!(!screen.sends(Escape::Graphics));
+        assert!(screen.sends(Escape::SemanticZones));
+        assert!(!OutputProfile::DUMB.sends(Escape::Hyperlink));
+    }
+}
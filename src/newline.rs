@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use crate::OutputProfile;
+
+/// How the end of the output relates to the end of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NewlineMode {
+    /// End with a line ending exactly when the input does
+    #[default]
+    Raw,
+    /// Always end non-empty output with a line ending
+    Ensure,
+    /// Like `Ensure`, also putting a dim `no newline at end of file` note
+    /// after the last line when the input has no line ending, as git diff does
+    Mark,
+}
+
+/// Which line terminators the output uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEnding {
+    /// The input's own, whatever mix of them it has
+    #[default]
+    Keep,
+    Lf,
+    Crlf,
+}
+
+/// Makes the end of `rendered`, the output for `input`, follow `mode`. Every
+/// renderer goes through this last, so whatever rows it added or moved (a
+/// reversed last line, a dedup marker, a diagnostic) the ending is the same.
+/// Escape sequences after the last visible character don't count.
+pub(crate) fn fix_ending(mut rendered: String, input: &str, mode: NewlineMode, profile: &OutputProfile) -> String {
+    let body = crate::trim::strip_trailing_escapes(&rendered).len();
+    if rendered[..body].is_empty() {
+        return rendered;
+    }
+
+    let has_ending = rendered[..body].ends_with('\n');
+    let wants_ending = match mode {
+        NewlineMode::Raw => input.ends_with('\n'),
+        NewlineMode::Ensure | NewlineMode::Mark => true,
+    };
+
+    match (has_ending, wants_ending) {
+        (false, true) => {
+            let ending = if input.contains("\r\n") { "\r\n" } else { "\n" };
+            rendered.push_str(ending);
+            if mode == NewlineMode::Mark && !input.ends_with('\n') {
+                rendered.push_str(&format!("{}\\ no newline at end of file{}{}", profile.sgr("\x1b[2m"), profile.sgr("\x1b[0m"), ending));
+            }
+        }
+        (true, false) => {
+            let ending = if rendered[..body].ends_with("\r\n") { 2 } else { 1 };
+            rendered.replace_range(body - ending..body, "");
+        }
+        _ => {}
+    }
+
+    rendered
+}
+
+/// Rewrites every line terminator of `rendered` to `ending`, the ones of the
+/// input and those ccat added alike, so files with mixed endings come out
+/// with one kind.
+pub(crate) fn normalize(rendered: String, ending: LineEnding) -> String {
+    match ending {
+        LineEnding::Keep => rendered,
+        LineEnding::Lf if rendered.contains("\r\n") => rendered.replace("\r\n", "\n"),
+        LineEnding::Lf => rendered,
+        LineEnding::Crlf => {
+            let mut normalized = String::with_capacity(rendered.len() + rendered.len() / 32);
+            let mut after_cr = false;
+            for c in rendered.chars() {
+                if c == '\n' && !after_cr {
+                    normalized.push('\r');
+                }
+                normalized.push(c);
+                after_cr = c == '\r';
+            }
+            normalized
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(rendered: &str, input: &str, mode: NewlineMode) -> String {
+        fix_ending(rendered.to_string(), input, mode, &OutputProfile::DUMB)
+    }
+
+    #[test]
+    fn raw_endings_follow_the_input() {
+        assert_eq!(fix("a\nb\n", "a\nb", NewlineMode::Raw), "a\nb");
+        assert_eq!(fix("a\nb", "a\nb\n", NewlineMode::Raw), "a\nb\n");
+        assert_eq!(fix("a\nb\n", "a\nb\n", NewlineMode::Raw), "a\nb\n");
+        assert_eq!(fix("a\r\nb\r\n", "a\r\nb", NewlineMode::Raw), "a\r\nb");
+    }
+
+    #[test]
+    fn ensured_endings_are_added_once() {
+        assert_eq!(fix("a\nb", "a\nb", NewlineMode::Ensure), "a\nb\n");
+        assert_eq!(fix("a\nb\n", "a\nb", NewlineMode::Ensure), "a\nb\n");
+        assert_eq!(fix("a\r\nb", "a\r\nb", NewlineMode::Ensure), "a\r\nb\r\n");
+        assert_eq!(fix("", "", NewlineMode::Ensure), "");
+    }
+
+    #[test]
+    fn trailing_escapes_are_not_content() {
+        assert_eq!(fix("\x1b[31mb\x1b[0m", "b", NewlineMode::Ensure), "\x1b[31mb\x1b[0m\n");
+        assert_eq!(fix("\x1b[31mb\n\x1b[0m", "b", NewlineMode::Raw), "\x1b[31mb\x1b[0m");
+        assert_eq!(fix("\x1b[0m", "", NewlineMode::Ensure), "\x1b[0m");
+    }
+
+    #[test]
+    fn marked_endings_note_a_missing_one() {
+        assert_eq!(fix("a\nb", "a\nb", NewlineMode::Mark), "a\nb\n\\ no newline at end of file\n");
+        assert_eq!(fix("a\nb\n", "a\nb\n", NewlineMode::Mark), "a\nb\n");
+        let colored = fix_ending("b".to_string(), "b", NewlineMode::Mark, &OutputProfile::FULL);
+        assert_eq!(colored, "b\n\x1b[2m\\ no newline at end of file\x1b[0m\n");
+    }
+
+    #[test]
+    fn line_endings_are_rewritten_to_one_kind() {
+        let mixed = "a\r\nb\nc\r\n\nd";
+        assert_eq!(normalize(mixed.to_string(), LineEnding::Lf), "a\nb\nc\n\nd");
+        assert_eq!(normalize(mixed.to_string(), LineEnding::Crlf), "a\r\nb\r\nc\r\n\r\nd");
+        assert_eq!(normalize(mixed.to_string(), LineEnding::Keep), mixed);
+        // a lone carriage return is not a line ending
+        assert_eq!(normalize("a\rb\n".to_string(), LineEnding::Crlf), "a\rb\r\n");
+        assert_eq!(normalize("a\rb\r\n".to_string(), LineEnding::Lf), "a\rb\n");
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::path::Path;
+use syntect::easy::ScopeRangeIterator;
+use syntect::parsing::{ParseState, Scope, ScopeStack, ScopeStackOp};
+use syntect::util::LinesWithEndings;
+
+use crate::{error_snippet, highlight_error, HighlighterConfig, SyntaxHighlighter};
+
+/// Which lines of a structured file its outline shows: the keys down to a
+/// depth, each standing for the body under it, which is folded away.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outline {
+    /// Whether each line of the content is shown, by 0-based index
+    shown: Vec<bool>,
+}
+
+impl Outline {
+    /// Whether the line at 0-based `index` of the content is shown. Lines
+    /// before the first entry, which belong to no entry, are shown too.
+    pub fn shows(&self, index: usize) -> bool {
+        self.shown.get(index).copied().unwrap_or(true)
+    }
+
+    /// Builds the outline from the depth of the key each line starts, None
+    /// for lines that start none. The body of an entry with a line matching
+    /// `pattern` is shown in full.
+    fn new(content: &str, entries: Vec<Option<usize>>, depth: usize, pattern: Option<&Regex>) -> Self {
+        let mut shown: Vec<bool> = entries.iter()
+            .map(|entry| entry.is_some_and(|level| level <= depth))
+            .collect();
+        if let Some(first) = shown.iter().position(|shown| *shown) {
+            shown[..first].fill(true);
+        }
+
+        if let Some(pattern) = pattern {
+            let lines: Vec<&str> = content.lines().collect();
+            let mut start = 0;
+            while start < shown.len() {
+                let end = (start + 1..shown.len()).find(|&index| shown[index]).unwrap_or(shown.len());
+                if lines[start + 1..end].iter().any(|line| pattern.is_match(line)) {
+                    shown[start..end].fill(true);
+                }
+                start = end;
+            }
+        }
+
+        Self { shown }
+    }
+}
+
+impl SyntaxHighlighter {
+    /// The outline of `content` down to `depth` levels of keys, for JSON,
+    /// YAML and TOML. JSON and YAML keys are found from the scopes their
+    /// syntaxes give them; TOML, which has no syntax bundled, is read for
+    /// table headers and keys directly. Only the first key of a line counts,
+    /// so flow-style collections stay in the entry they start on, and
+    /// multi-line strings and block scalars are never read for keys.
+    pub fn outline(&self, content: &str, file_path: &str, config: &HighlighterConfig, depth: usize, pattern: Option<&Regex>) -> Result<Outline> {
+        let (syntax, _) = self.resolve_syntax(content, file_path, config)?;
+        let is_toml = Path::new(file_path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+
+        let entries = match syntax.name.as_str() {
+            "JSON" | "YAML" => {
+                let mut parse_state = ParseState::new(syntax);
+                let mut stack = ScopeStack::new();
+                let mut keys = KeyScopes::new(syntax.name == "YAML");
+                let mut entries = Vec::new();
+                for (line_num, line) in LinesWithEndings::from(content).enumerate() {
+                    let ops = parse_state.parse_line(line, &self.syntax_set)
+                        .map_err(|error| highlight_error(error, line_num + 1, line, &syntax.name))
+                        .map_err(|error| error_snippet::locate(error, file_path, content, 0))?;
+                    let entry = keys.line(line, &ops, &mut stack)
+                        .with_context(|| format!("Failed to outline line {} as {}", line_num + 1, syntax.name))?;
+                    entries.push(entry);
+                }
+                entries
+            }
+            name if name == "TOML" || is_toml => toml_entries(content),
+            name => bail!("Can't outline '{}', which is {}; only JSON, YAML and TOML have an outline", file_path, name),
+        };
+
+        Ok(Outline::new(content, entries, depth, pattern))
+    }
+}
+
+/// Finds the key a JSON or YAML line starts from the scopes of its tokens.
+struct KeyScopes {
+    yaml: bool,
+    /// Column of the key at each open YAML mapping level
+    indents: Vec<usize>,
+    json_key: Scope,
+    json_dictionary: Scope,
+    yaml_separator: Scope,
+    yaml_flow: [Scope; 2],
+    yaml_block_scalar: Scope,
+    yaml_sequence_item: Scope,
+}
+
+impl KeyScopes {
+    fn new(yaml: bool) -> Self {
+        let scope = |name: &str| Scope::new(name).expect("scope names are valid");
+        Self {
+            yaml,
+            indents: Vec::new(),
+            json_key: scope("meta.structure.dictionary.key.json"),
+            json_dictionary: scope("meta.structure.dictionary.json"),
+            yaml_separator: scope("punctuation.separator.key-value.mapping.yaml"),
+            yaml_flow: [scope("meta.flow-mapping.yaml"), scope("meta.flow-sequence.yaml")],
+            yaml_block_scalar: scope("string.unquoted.block.yaml"),
+            yaml_sequence_item: scope("punctuation.definition.block.sequence.item.yaml"),
+        }
+    }
+
+    /// The depth of the key `line` starts, 1 for a top-level one. Every op
+    /// is applied to `stack`, which carries the scopes to the next line.
+    fn line(&mut self, line: &str, ops: &[(usize, ScopeStackOp)], stack: &mut ScopeStack) -> Result<Option<usize>> {
+        let has = |stack: &ScopeStack, scope: Scope| stack.as_slice().iter().any(|item| scope.is_prefix_of(*item));
+        let mut key_column = None;
+        let mut depth = None;
+        let mut settled = false;
+
+        for (range, op) in ScopeRangeIterator::new(ops, line) {
+            stack.apply(op)?;
+            if settled || line[range.clone()].trim().is_empty() {
+                continue;
+            }
+
+            if !self.yaml {
+                if has(stack, self.json_key) {
+                    let level = stack.as_slice().iter().filter(|item| self.json_dictionary.is_prefix_of(**item)).count();
+                    depth = Some(level.max(1));
+                    settled = true;
+                }
+                continue;
+            }
+
+            // a key is whatever follows the sequence markers up to the first
+            // separator, outside flow collections and block scalars
+            if self.yaml_flow.iter().any(|flow| has(stack, *flow)) || has(stack, self.yaml_block_scalar) {
+                settled = true;
+                continue;
+            }
+            if has(stack, self.yaml_sequence_item) {
+                continue;
+            }
+            let column = *key_column.get_or_insert(range.start);
+            if has(stack, self.yaml_separator) {
+                while self.indents.last().is_some_and(|indent| *indent >= column) {
+                    self.indents.pop();
+                }
+                self.indents.push(column);
+                depth = Some(self.indents.len());
+                settled = true;
+            }
+        }
+
+        if self.yaml && line.trim_end() == "---" {
+            self.indents.clear();
+        }
+        Ok(depth)
+    }
+}
+
+/// The depth of the key or table header each line of a TOML file starts:
+/// a header's is its number of dotted parts, a key's that of its table plus
+/// its own. Lines inside multi-line strings and arrays start none.
+fn toml_entries(content: &str) -> Vec<Option<usize>> {
+    let mut table_depth = 0;
+    let mut multiline: Option<&str> = None;
+    let mut open_brackets = 0usize;
+
+    content.lines().map(|line| {
+        let trimmed = line.trim_start();
+        let entry = match (multiline, open_brackets) {
+            (None, 0) if trimmed.starts_with('[') => {
+                let name = trimmed.trim_start_matches('[');
+                let name = name.split(']').next().unwrap_or_default();
+                table_depth = dotted_parts(name);
+                Some(table_depth)
+            }
+            (None, 0) if !trimmed.is_empty() && !trimmed.starts_with('#') => trimmed.split_once('=')
+                .map(|(key, _)| table_depth + dotted_parts(key)),
+            _ => None,
+        };
+
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some(delimiter) = multiline {
+                match rest.find(delimiter) {
+                    Some(end) => {
+                        rest = &rest[end + delimiter.len()..];
+                        multiline = None;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+
+            let Some(start) = rest.find(['"', '\'', '#', '[', ']', '{', '}']) else {
+                break;
+            };
+            let quote = &rest[start..];
+            if quote.starts_with("\"\"\"") || quote.starts_with("'''") {
+                multiline = Some(&quote[..3]);
+                rest = &quote[3..];
+                continue;
+            }
+            match quote.as_bytes()[0] {
+                b'#' => break,
+                b'[' | b'{' => open_brackets += 1,
+                b']' | b'}' => open_brackets = open_brackets.saturating_sub(1),
+                delimiter => {
+                    let end = string_end(&quote[1..], delimiter).map_or(quote.len(), |end| end + 2);
+                    rest = &quote[end..];
+                    continue;
+                }
+            }
+            rest = &quote[1..];
+        }
+
+        entry
+    }).collect()
+}
+
+/// The byte offset of the quote closing a single-line string in `rest`,
+/// skipping escaped quotes in basic strings.
+fn string_end(rest: &str, quote: u8) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' if quote == b'"' => index += 2,
+            byte if byte == quote => return Some(index),
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+/// The number of parts of a dotted TOML key, ignoring dots inside quotes.
+fn dotted_parts(key: &str) -> usize {
+    let mut quote = None;
+    let mut parts = 1;
+    for character in key.trim().chars() {
+        match (quote, character) {
+            (None, '"' | '\'') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, '.') => parts += 1,
+            _ => {}
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    /// The 1-based numbers of the lines the outline shows.
+    fn shown(content: &str, file_path: &str, depth: usize, pattern: Option<&str>) -> Vec<usize> {
+        let pattern = pattern.map(|pattern| Regex::new(pattern).unwrap());
+        let outline = HIGHLIGHTER.outline(content, file_path, &HighlighterConfig::default(), depth, pattern.as_ref()).unwrap();
+        (0..content.lines().count()).filter(|index| outline.shows(*index)).map(|index| index + 1).collect()
+    }
+
+    #[test]
+    fn json_keys_are_shown_down_to_the_depth() {
+        let json = r#"{
+  "name": "ccat",
+  "scripts": {
+    "build": "cargo build",
+    "deep": { "a": 1 }
+  },
+  "tags": ["a", "b"]
+}
+"#;
+        assert_eq!(shown(json, "package.json", 1, None), [1, 2, 3, 7]);
+        assert_eq!(shown(json, "package.json", 2, None), [1, 2, 3, 4, 5, 7]);
+    }
+
+    #[test]
+    fn yaml_depth_follows_indentation_and_skips_block_scalars_and_flow_collections() {
+        let yaml = "\
+# comment before any entry
+server:
+  host: example.com
+  script: |
+    not: a key
+  ports: [80,
+    443]
+  nested:
+    deep: 1
+---
+items:
+  - name: a
+    size: 1
+";
+        assert_eq!(shown(yaml, "config.yaml", 1, None), [1, 2, 11]);
+        assert_eq!(shown(yaml, "config.yaml", 2, None), [1, 2, 3, 4, 6, 8, 11, 12, 13]);
+    }
+
+    #[test]
+    fn toml_headers_and_keys_take_their_depth_from_their_dotted_parts() {
+        let toml = r#"title = "x"
+[package]
+name = "ccat"
+description = """
+[not.a.table]
+fake = 1
+"""
+list = [
+  "a = b",
+]
+[package.metadata]
+a.b = 1
+"quoted.key" = 2
+"#;
+        assert_eq!(shown(toml, "Cargo.toml", 1, None), [1, 2]);
+        assert_eq!(shown(toml, "Cargo.toml", 2, None), [1, 2, 3, 4, 8, 11]);
+        // `a.b` is a fourth level, the dot in the quoted key is not
+        assert_eq!(shown(toml, "Cargo.toml", 3, None), [1, 2, 3, 4, 8, 11, 13]);
+    }
+
+    #[test]
+    fn a_folded_body_matching_the_pattern_is_shown_in_full() {
+        let yaml = "a:\n  x: 1\n  y: 2\nb:\n  needle: 3\n  z: 4\n";
+        assert_eq!(shown(yaml, "c.yaml", 1, None), [1, 4]);
+        assert_eq!(shown(yaml, "c.yaml", 1, Some("needle")), [1, 4, 5, 6]);
+    }
+
+    #[test]
+    fn other_syntaxes_have_no_outline() {
+        let error = HIGHLIGHTER.outline("fn main() {}\n", "main.rs", &HighlighterConfig::default(), 2, None).unwrap_err();
+        assert_eq!(error.to_string(), "Can't outline 'main.rs', which is Rust; only JSON, YAML and TOML have an outline");
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{bail, Result};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Encodes rendered output for writing to a file.
+///
+/// `encoding` is any WHATWG label (`utf-8`, `utf-16le`, `windows-1252`, ...).
+/// With `bom`, a byte order mark is written first; only the UTF encodings have one.
+pub fn encode_output(text: &str, encoding: &str, bom: bool) -> Result<Vec<u8>> {
+    let Some(encoding) = Encoding::for_label(encoding.as_bytes()) else {
+        bail!("Unsupported output encoding '{}'", encoding);
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + 3);
+
+    // encoding_rs only decodes UTF-16, so those are encoded by hand
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let little_endian = encoding == UTF_16LE;
+        if bom {
+            bytes.extend_from_slice(if little_endian { &[0xff, 0xfe] } else { &[0xfe, 0xff] });
+        }
+        for unit in text.encode_utf16() {
+            let unit = if little_endian { unit.to_le_bytes() } else { unit.to_be_bytes() };
+            bytes.extend_from_slice(&unit);
+        }
+        return Ok(bytes);
+    }
+
+    if bom {
+        if encoding != UTF_8 {
+            bail!("Encoding '{}' has no byte order mark", encoding.name());
+        }
+        bytes.extend_from_slice(&[0xef, 0xbb, 0xbf]);
+    }
+
+    if encoding.output_encoding() != encoding {
+        bail!("Unsupported output encoding '{}'", encoding.name());
+    }
+
+    let (encoded, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        bail!("Output contains characters that can't be represented in {}", encoding.name());
+    }
+    bytes.extend_from_slice(&encoded);
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf16_is_encoded_with_its_bom() {
+        assert_eq!(encode_output("hé\n", "utf-16le", true).unwrap(), [0xff, 0xfe, b'h', 0, 0xe9, 0, b'\n', 0]);
+        assert_eq!(encode_output("h", "utf-16be", true).unwrap(), [0xfe, 0xff, 0, b'h']);
+        assert_eq!(encode_output("h", "utf-16le", false).unwrap(), [b'h', 0]);
+    }
+
+    #[test]
+    fn legacy_encodings_have_no_bom() {
+        assert_eq!(encode_output("é", "windows-1252", false).unwrap(), [0xe9]);
+        assert!(encode_output("é", "windows-1252", true).is_err());
+        assert_eq!(encode_output("a", "utf-8", true).unwrap(), [0xef, 0xbb, 0xbf, b'a']);
+    }
+
+    #[test]
+    fn unknown_and_unrepresentable_output_is_an_error() {
+        assert!(encode_output("a", "klingon", false).is_err());
+        assert!(encode_output("→", "windows-1252", false).is_err());
+    }
+}
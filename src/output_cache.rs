@@ -0,0 +1,347 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::guard::parse_size;
+
+/// Start of a cache entry, changed whenever the format does.
+const MAGIC: &str = "ccatout2\n";
+
+/// How much the entries of a cache may take without `cache-max-size`.
+const DEFAULT_MAX_SIZE: u64 = 256 << 20;
+
+/// Files larger than this are rendered every time, never stored.
+const MAX_FILE_SIZE: u64 = 8 << 20;
+
+/// Rendered files kept under a cache directory, so showing a file that
+/// hasn't changed again, as a fuzzy finder's preview does over and over,
+/// skips highlighting it.
+///
+/// Entries are gzipped, and once they take more than the cache's size the
+/// ones least recently used are removed. Each is written aside and renamed
+/// into place, so processes racing on the same file each see a whole entry.
+#[derive(Debug, Clone)]
+pub struct OutputCache {
+    dir: PathBuf,
+    /// Hash of everything besides the file that decides its rendering
+    flags: u64,
+    max_size: u64,
+}
+
+/// The `cache` and `cache-max-size` settings of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheSettings {
+    /// Cache output without --cache
+    pub enabled: bool,
+    pub max_size: u64,
+}
+
+/// A file's output as the cache keeps it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedOutput {
+    /// Name of the syntax the file was highlighted as
+    pub syntax: String,
+    /// Program of the preprocessor the file was shown through
+    pub via: Option<String>,
+    pub output: String,
+}
+
+/// Where a file's output is kept, from [`OutputCache::entry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry(PathBuf);
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self { enabled: false, max_size: DEFAULT_MAX_SIZE }
+    }
+}
+
+impl CacheSettings {
+    /// Reads `cache = true` and `cache-max-size = "64M"` from the config file.
+    pub fn load(config: Option<&toml::Table>) -> Result<Self> {
+        let setting = |key: &str| config.and_then(|config| config.get(key));
+        let enabled = match setting("cache") {
+            Some(toml::Value::Boolean(enabled)) => *enabled,
+            Some(_) => bail!("'cache' in the config file must be true or false"),
+            None => false,
+        };
+        let max_size = match setting("cache-max-size") {
+            Some(toml::Value::String(size)) => parse_size(size)
+                .context("Invalid 'cache-max-size' in the config file")?,
+            Some(toml::Value::Integer(size)) if *size >= 0 => *size as u64,
+            Some(_) => bail!("'cache-max-size' in the config file must be a size such as \"64M\""),
+            None => DEFAULT_MAX_SIZE,
+        };
+
+        Ok(Self { enabled, max_size })
+    }
+}
+
+impl OutputCache {
+    /// A cache in `dir` for output rendered with `flags`, such as the
+    /// options and theme, whose entries may take `max_size` bytes; output
+    /// rendered with other flags is never reused.
+    pub fn new(dir: PathBuf, flags: &str, max_size: u64) -> Self {
+        let mut hasher = DefaultHasher::new();
+        flags.hash(&mut hasher);
+        Self { dir, flags: hasher.finish(), max_size }
+    }
+
+    /// The entry for the file at `path` as it is now, keyed by its path,
+    /// size and modification time and by `settings`, what only applies to
+    /// it such as its `.editorconfig`, so a change to either gets a new one.
+    /// None for what isn't a regular file, such as stdin, which can't be
+    /// keyed, and for files too large to be worth keeping.
+    pub fn entry(&self, path: &Path, settings: &str) -> Option<CacheEntry> {
+        let absolute = fs::canonicalize(path).ok()?;
+        let metadata = fs::metadata(&absolute).ok()
+            .filter(|metadata| metadata.is_file() && metadata.len() <= MAX_FILE_SIZE)?;
+        let mut hasher = DefaultHasher::new();
+        self.flags.hash(&mut hasher);
+        settings.hash(&mut hasher);
+        absolute.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .hash(&mut hasher);
+
+        Some(CacheEntry(self.dir.join(format!("{:016x}.output", hasher.finish()))))
+    }
+
+    /// The output stored for `entry`; a missing or unreadable one is a miss.
+    pub fn get(&self, entry: &CacheEntry) -> Option<CachedOutput> {
+        let compressed = fs::read(&entry.0).ok()?;
+        let mut text = String::new();
+        GzDecoder::new(compressed.as_slice()).read_to_string(&mut text).ok()?;
+        let rest = text.strip_prefix(MAGIC)?;
+        let (syntax, rest) = rest.split_once('\n')?;
+        let (via, output) = rest.split_once('\n')?;
+
+        // a hit counts as a use, so the entry is among the last to go
+        if let Ok(file) = fs::File::options().write(true).open(&entry.0) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(CachedOutput {
+            syntax: syntax.to_string(),
+            via: (!via.is_empty()).then(|| via.to_string()),
+            output: output.to_string(),
+        })
+    }
+
+    /// Stores `output` for `entry`, replacing what was there, then removes
+    /// the least recently used entries past the cache's size.
+    pub fn put(&self, entry: &CacheEntry, output: &CachedOutput) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create output cache '{}'", self.dir.display()))?;
+        let text = format!("{}{}\n{}\n{}", MAGIC, output.syntax, output.via.as_deref().unwrap_or_default(), output.output);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        let compressed = encoder.write_all(text.as_bytes()).and_then(|_| encoder.finish())
+            .with_context(|| format!("Failed to compress output for '{}'", entry.0.display()))?;
+        // written aside first so a concurrent reader never sees half an entry
+        let partial = entry.0.with_extension(format!("{}.partial", std::process::id()));
+        fs::write(&partial, compressed)
+            .and_then(|_| fs::rename(&partial, &entry.0))
+            .with_context(|| format!("Failed to write output cache '{}'", entry.0.display()))?;
+
+        self.evict();
+        Ok(())
+    }
+
+    /// Removes the least recently used entries until the rest fit in the
+    /// cache's size. Another process may remove the same ones meanwhile,
+    /// so failing to is no error.
+    fn evict(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "output"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len(), entry.path()))
+            })
+            .collect();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+
+        for (_, size, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            let _ = fs::remove_file(path);
+            total = total.saturating_sub(size);
+        }
+    }
+
+    /// Removes every entry under `dir`, whatever flags they were rendered
+    /// with, returning how many there were.
+    pub fn clear(dir: &Path) -> Result<usize> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(error) => return Err(error).with_context(|| format!("Failed to read output cache '{}'", dir.display())),
+        };
+
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // and what another process is writing, named <entry>.<pid>.partial
+            if path.extension().is_some_and(|extension| extension == "output" || extension == "partial") {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove '{}'", path.display()))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// The size and modification time of each file at `paths`, and of each
+/// file directly in the directories among them, so a cache keyed by it
+/// misses once any of them changes.
+pub fn fingerprint(paths: &[PathBuf]) -> String {
+    let mut files = Vec::new();
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => files.extend(entries.flatten().map(|entry| entry.path())),
+            Err(_) => files.push(path.clone()),
+        }
+    }
+    files.sort();
+
+    files.iter()
+        .filter_map(|file| {
+            let metadata = fs::metadata(file).ok()?;
+            let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+            Some(format!("{} {} {}.{}\n", file.display(), metadata.len(), modified.as_secs(), modified.subsec_nanos()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn output(text: &str) -> CachedOutput {
+        CachedOutput { syntax: "Rust".to_string(), via: None, output: text.to_string() }
+    }
+
+    #[test]
+    fn settings_come_from_the_config_file() {
+        let config = |text: &str| toml::from_str::<toml::Table>(text).unwrap();
+        assert_eq!(CacheSettings::load(None).unwrap(), CacheSettings::default());
+        assert_eq!(
+            CacheSettings::load(Some(&config("cache = true\ncache-max-size = \"64M\""))).unwrap(),
+            CacheSettings { enabled: true, max_size: 64 << 20 },
+        );
+        assert_eq!(CacheSettings::load(Some(&config("cache-max-size = 1024"))).unwrap().max_size, 1024);
+
+        let error = |text: &str| format!("{:#}", CacheSettings::load(Some(&config(text))).unwrap_err());
+        assert_eq!(error("cache = 1"), "'cache' in the config file must be true or false");
+        assert!(error("cache-max-size = \"lots\"").starts_with("Invalid 'cache-max-size' in the config file"));
+        assert_eq!(error("cache-max-size = -1"), "'cache-max-size' in the config file must be a size such as \"64M\"");
+    }
+
+    #[test]
+    fn stored_output_is_reused_while_the_file_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        fs::write(&file, "fn main() {}\n").unwrap();
+        let cache = OutputCache::new(dir.path().join("cache"), "--theme x", DEFAULT_MAX_SIZE);
+
+        let entry = cache.entry(&file, "").unwrap();
+        assert_eq!(cache.get(&entry), None);
+        let stored = CachedOutput { via: Some("gunzip".to_string()), ..output("\x1b[1mfn\x1b[0m main() {}\n") };
+        cache.put(&entry, &stored).unwrap();
+        assert_eq!(cache.get(&cache.entry(&file, "").unwrap()), Some(stored));
+    }
+
+    #[test]
+    fn a_change_to_the_file_its_settings_or_the_flags_misses() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        fs::write(&file, "fn main() {}\n").unwrap();
+        let cache = OutputCache::new(dir.path().join("cache"), "--theme x", DEFAULT_MAX_SIZE);
+        let entry = cache.entry(&file, "").unwrap();
+        cache.put(&entry, &output("old")).unwrap();
+
+        let other = OutputCache::new(dir.path().join("cache"), "--theme y", DEFAULT_MAX_SIZE);
+        assert_ne!(other.entry(&file, ""), Some(entry.clone()));
+        assert_ne!(cache.entry(&file, "tab-width 2"), Some(entry.clone()));
+
+        let modified = fs::metadata(&file).unwrap().modified().unwrap() + Duration::from_secs(5);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        assert_ne!(cache.entry(&file, ""), Some(entry.clone()));
+        fs::write(&file, "fn main() { loop {} }\n").unwrap();
+        assert_ne!(cache.entry(&file, ""), Some(entry));
+    }
+
+    #[test]
+    fn only_regular_files_have_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = OutputCache::new(dir.path().join("cache"), "", DEFAULT_MAX_SIZE);
+        assert_eq!(cache.entry(dir.path(), ""), None);
+        assert_eq!(cache.entry(&dir.path().join("missing"), ""), None);
+    }
+
+    #[test]
+    fn the_least_recently_used_entries_are_evicted_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..3).map(|index| dir.path().join(format!("{}.txt", index))).collect();
+        for file in &files {
+            fs::write(file, file.display().to_string()).unwrap();
+        }
+        let roomy = OutputCache::new(dir.path().join("cache"), "", DEFAULT_MAX_SIZE);
+        let entries: Vec<CacheEntry> = files.iter().map(|file| roomy.entry(file, "").unwrap()).collect();
+        for (index, entry) in entries.iter().enumerate() {
+            roomy.put(entry, &output(&index.to_string().repeat(64))).unwrap();
+            let used = SystemTime::now() - Duration::from_secs(60 * (3 - index as u64));
+            fs::File::options().write(true).open(&entry.0).unwrap().set_modified(used).unwrap();
+        }
+
+        // room for two entries, so storing the third again drops the first
+        let size = fs::metadata(&entries[0].0).unwrap().len();
+        let tight = OutputCache::new(dir.path().join("cache"), "", size * 2 + size / 2);
+        tight.put(&entries[2], &output(&"2".repeat(64))).unwrap();
+        assert_eq!(tight.get(&entries[0]), None);
+        assert!(tight.get(&entries[1]).is_some());
+        assert!(tight.get(&entries[2]).is_some());
+    }
+
+    #[test]
+    fn clearing_removes_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let cache_dir = dir.path().join("cache");
+        for flags in ["one", "two"] {
+            let cache = OutputCache::new(cache_dir.clone(), flags, DEFAULT_MAX_SIZE);
+            cache.put(&cache.entry(&file, "").unwrap(), &output("a")).unwrap();
+        }
+        fs::write(cache_dir.join("kept.txt"), "").unwrap();
+
+        assert_eq!(OutputCache::clear(&cache_dir).unwrap(), 2);
+        assert_eq!(OutputCache::clear(&cache_dir).unwrap(), 0);
+        assert!(cache_dir.join("kept.txt").exists());
+        assert_eq!(OutputCache::clear(&dir.path().join("missing")).unwrap(), 0);
+    }
+
+    #[test]
+    fn fingerprints_change_with_the_files_in_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = fingerprint(&[dir.path().to_path_buf()]);
+        fs::write(dir.path().join("theme.tmTheme"), "x").unwrap();
+        let after = fingerprint(&[dir.path().to_path_buf()]);
+        assert_ne!(before, after);
+        assert!(after.contains("theme.tmTheme 1 "));
+    }
+}
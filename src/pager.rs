@@ -0,0 +1,288 @@
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use regex::Regex;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Once;
+
+use crate::trim::strip_escapes;
+
+/// Puts the terminal back the way it was: main screen, cooked mode, cursor
+/// shown. Safe to call more than once.
+fn restore_terminal() {
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(io::stdout(), SetAttribute(Attribute::Reset), EnableLineWrap, Show, LeaveAlternateScreen);
+}
+
+/// Restores the terminal when dropped, including while unwinding.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        // a panic message printed on the alternate screen would vanish with it
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                previous(info);
+            }));
+        });
+
+        terminal::enable_raw_mode()?;
+        if let Err(error) = execute!(io::stdout(), EnterAlternateScreen, DisableLineWrap, Hide) {
+            restore_terminal();
+            return Err(error);
+        }
+
+        Ok(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+struct Pager {
+    lines: Vec<String>,
+    /// The lines with escapes removed, for searching
+    plain: Vec<String>,
+    top: usize,
+    height: usize,
+    search: Option<Regex>,
+    message: Option<String>,
+}
+
+impl Pager {
+    fn new(text: &str, height: usize) -> Self {
+        let mut pager = Self {
+            lines: Vec::new(),
+            plain: Vec::new(),
+            top: 0,
+            height,
+            search: None,
+            message: None,
+        };
+        pager.set_text(text);
+        pager
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.lines = text.lines().map(str::to_string).collect();
+        self.plain = self.lines.iter().map(|line| strip_escapes(line)).collect();
+        self.top = self.top.min(self.max_top());
+    }
+
+    /// Rows available for content, leaving one for the status line.
+    fn page(&self) -> usize {
+        self.height.saturating_sub(1).max(1)
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.page())
+    }
+
+    fn scroll(&mut self, rows: isize) {
+        self.top = self.top.saturating_add_signed(rows).min(self.max_top());
+    }
+
+    /// Moves to the next line after the top one matching the search, wrapping around.
+    fn find_next(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        let count = self.lines.len();
+        let found = (1..=count)
+            .map(|offset| (self.top + offset) % count.max(1))
+            .find(|index| search.is_match(&self.plain[*index]));
+
+        match found {
+            Some(index) => self.top = index.min(self.max_top()),
+            None => self.message = Some(format!("Pattern not found: {}", search.as_str())),
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write, prompt: Option<&str>) -> io::Result<()> {
+        for row in 0..self.page() {
+            queue!(out, MoveTo(0, row as u16), Clear(ClearType::CurrentLine))?;
+            if let Some(line) = self.lines.get(self.top + row) {
+                queue!(out, Print(line), SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        let status = match (prompt, &self.message) {
+            (Some(prompt), _) => format!("/{}", prompt),
+            (None, Some(message)) => message.clone(),
+            (None, None) => {
+                let last = (self.top + self.page()).min(self.lines.len());
+                format!("lines {}-{} of {}  (q quit, space/b page, j/k line, g/G ends, / search, n next)", self.top + 1, last, self.lines.len())
+            }
+        };
+        queue!(
+            out,
+            MoveTo(0, self.page() as u16),
+            Clear(ClearType::CurrentLine),
+            SetAttribute(Attribute::Reverse),
+            Print(status),
+            SetAttribute(Attribute::Reset),
+        )?;
+
+        out.flush()
+    }
+}
+
+/// Shows text on the alternate screen with basic less-style navigation,
+/// restoring the screen and scrollback on exit.
+///
+/// `render` is called with the terminal width, again on every resize, so the
+/// text can be wrapped to fit. Output that fits on one screen, and any output
+/// when stdout is not a terminal or has no alternate screen, is printed inline.
+pub fn page(mut render: impl FnMut(Option<usize>) -> Result<String>) -> Result<()> {
+    let size = terminal::size().ok()
+        .filter(|(width, height)| *width > 0 && *height > 0 && io::stdout().is_terminal());
+    let Some((width, height)) = size else {
+        print!("{}", render(None)?);
+        return Ok(());
+    };
+
+    let text = render(Some(width as usize))?;
+    if text.lines().count() < height as usize {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let Ok(_screen) = Screen::enter() else {
+        print!("{}", text);
+        return Ok(());
+    };
+
+    let mut out = io::stdout();
+    let mut pager = Pager::new(&text, height as usize);
+    let mut prompt: Option<String> = None;
+
+    loop {
+        pager.draw(&mut out, prompt.as_deref())?;
+
+        let key = match event::read()? {
+            Event::Key(key @ KeyEvent { kind: KeyEventKind::Press | KeyEventKind::Repeat, .. }) => key,
+            Event::Resize(width, height) => {
+                pager.height = height as usize;
+                pager.set_text(&render(Some(width as usize))?);
+                continue;
+            }
+            _ => continue,
+        };
+
+        // raw mode delivers Ctrl-C as a key instead of a signal
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Ok(());
+        }
+
+        if let Some(pattern) = prompt.as_mut() {
+            match key.code {
+                KeyCode::Enter => {
+                    match Regex::new(pattern) {
+                        Ok(search) => {
+                            pager.search = Some(search);
+                            pager.find_next();
+                        }
+                        Err(_) => pager.message = Some(format!("Invalid pattern: {}", pattern)),
+                    }
+                    prompt = None;
+                }
+                KeyCode::Esc => prompt = None,
+                KeyCode::Backspace if pattern.is_empty() => prompt = None,
+                KeyCode::Backspace => {
+                    pattern.pop();
+                }
+                KeyCode::Char(c) => pattern.push(c),
+                _ => {}
+            }
+            continue;
+        }
+
+        pager.message = None;
+        let page = pager.page() as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char(' ') | KeyCode::Char('f') | KeyCode::PageDown => pager.scroll(page),
+            KeyCode::Char('b') | KeyCode::PageUp => pager.scroll(-page),
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => pager.scroll(1),
+            KeyCode::Char('k') | KeyCode::Up => pager.scroll(-1),
+            KeyCode::Char('g') | KeyCode::Home => pager.top = 0,
+            KeyCode::Char('G') | KeyCode::End => pager.top = pager.max_top(),
+            KeyCode::Char('/') => prompt = Some(String::new()),
+            KeyCode::Char('n') => pager.find_next(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(count: usize) -> String {
+        (1..=count).map(|line| format!("\x1b[1mline {}\x1b[0m\n", line)).collect()
+    }
+
+    #[test]
+    fn scrolling_stops_at_either_end() {
+        let mut pager = Pager::new(&numbered(10), 4);
+        assert_eq!((pager.page(), pager.max_top()), (3, 7));
+
+        pager.scroll(-1);
+        assert_eq!(pager.top, 0);
+        pager.scroll(5);
+        assert_eq!(pager.top, 5);
+        pager.scroll(5);
+        assert_eq!(pager.top, 7);
+    }
+
+    #[test]
+    fn rerendering_shorter_text_keeps_the_top_in_range() {
+        let mut pager = Pager::new(&numbered(10), 4);
+        pager.scroll(7);
+        pager.set_text(&numbered(5));
+        assert_eq!(pager.top, 2);
+    }
+
+    #[test]
+    fn searches_skip_escapes_and_wrap_around() {
+        let mut pager = Pager::new(&numbered(10), 4);
+        pager.search = Some(Regex::new(r"^line [27]$").unwrap());
+        pager.find_next();
+        assert_eq!(pager.top, 1);
+        pager.find_next();
+        // line 7 is past the last full page, which is as far as the top goes
+        assert_eq!(pager.top, 6);
+        pager.find_next();
+        assert_eq!(pager.top, 1);
+
+        pager.search = Some(Regex::new("missing").unwrap());
+        pager.find_next();
+        assert_eq!(pager.top, 1);
+        assert_eq!(pager.message.as_deref(), Some("Pattern not found: missing"));
+    }
+
+    #[test]
+    fn the_status_line_shows_the_visible_lines() {
+        let mut pager = Pager::new(&numbered(10), 4);
+        pager.scroll(2);
+        let mut screen = Vec::new();
+        pager.draw(&mut screen, None).unwrap();
+        let screen = strip_escapes(&String::from_utf8(screen).unwrap());
+        assert!(screen.contains("line 3") && screen.contains("line 5") && !screen.contains("line 6"), "{:?}", screen);
+        assert!(screen.contains("lines 3-5 of 10"));
+
+        let mut prompt = Vec::new();
+        pager.draw(&mut prompt, Some("li")).unwrap();
+        assert!(strip_escapes(&String::from_utf8(prompt).unwrap()).ends_with("/li"));
+    }
+}
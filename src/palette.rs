@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use syntect::highlighting::{Color, Theme};
+
+use crate::color;
+
+/// Rounds of k-means before settling for what it has.
+const MAX_ROUNDS: usize = 32;
+
+/// Remaps the foreground colors of `theme`, its default one and those of
+/// its scopes, onto at most `max_colors` of them, so tokens are drawn in no
+/// more colors than that. The colors are clustered with k-means and each
+/// cluster is drawn in its member closest to the cluster's mean, so only
+/// colors of the theme are used; plain text keeps the default foreground.
+pub(crate) fn limit(theme: Cow<'_, Theme>, max_colors: Option<usize>) -> Cow<'_, Theme> {
+    let Some(max_colors) = max_colors else {
+        return theme;
+    };
+
+    let mut colors: Vec<Color> = Vec::new();
+    let foregrounds = theme.settings.foreground.into_iter()
+        .chain(theme.scopes.iter().filter_map(|item| item.style.foreground));
+    for foreground in foregrounds {
+        if !colors.contains(&foreground) {
+            colors.push(foreground);
+        }
+    }
+    if colors.len() <= max_colors {
+        return theme;
+    }
+
+    let representatives = cluster(&colors, max_colors.max(1));
+    let remap = |color: Color| {
+        *representatives.iter()
+            .min_by_key(|representative| color::distance(color, **representative))
+            .expect("there is at least one representative")
+    };
+
+    let mut theme = theme.into_owned();
+    theme.settings.foreground = theme.settings.foreground.map(remap);
+    for item in &mut theme.scopes {
+        item.style.foreground = item.style.foreground.map(remap);
+    }
+
+    Cow::Owned(theme)
+}
+
+/// Clusters `colors` into `k` groups and returns the member of each closest
+/// to its mean. The first color, the default foreground, stays the center
+/// of its group, and the others start farthest-first from it, so the result
+/// doesn't vary between runs.
+fn cluster(colors: &[Color], k: usize) -> Vec<Color> {
+    let mut centers = vec![colors[0]];
+    while centers.len() < k {
+        let farthest = colors.iter()
+            .max_by_key(|color| centers.iter().map(|center| color::distance(**color, *center)).min())
+            .expect("colors is not empty");
+        centers.push(*farthest);
+    }
+
+    let nearest = |color: Color, centers: &[Color]| (0..centers.len())
+        .min_by_key(|&i| color::distance(color, centers[i]))
+        .expect("there is at least one center");
+
+    let mut assignment: Vec<usize> = colors.iter().map(|color| nearest(*color, &centers)).collect();
+    for _ in 0..MAX_ROUNDS {
+        for (i, center) in centers.iter_mut().enumerate().skip(1) {
+            let members: Vec<Color> = colors.iter().zip(&assignment)
+                .filter(|(_, cluster)| **cluster == i)
+                .map(|(color, _)| *color)
+                .collect();
+            if !members.is_empty() {
+                *center = mean(&members);
+            }
+        }
+
+        let next: Vec<usize> = colors.iter().map(|color| nearest(*color, &centers)).collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+    }
+
+    // each center is snapped to its closest member so the theme's own colors are kept
+    centers.iter().enumerate()
+        .filter_map(|(i, center)| colors.iter().zip(&assignment)
+            .filter(|(_, cluster)| **cluster == i)
+            .map(|(color, _)| *color)
+            .min_by_key(|color| color::distance(*color, *center)))
+        .collect()
+}
+
+fn mean(colors: &[Color]) -> Color {
+    let channel = |channel: fn(&Color) -> u8| {
+        (colors.iter().map(|color| channel(color) as u32).sum::<u32>() as f32 / colors.len() as f32).round() as u8
+    };
+
+    Color { r: channel(|color| color.r), g: channel(|color| color.g), b: channel(|color| color.b), a: 0xff }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::ThemeSet;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 0xff }
+    }
+
+    fn foregrounds(theme: &Theme) -> Vec<Color> {
+        let mut colors: Vec<Color> = theme.settings.foreground.into_iter()
+            .chain(theme.scopes.iter().filter_map(|item| item.style.foreground))
+            .collect();
+        colors.sort_by_key(|color| (color.r, color.g, color.b));
+        colors.dedup();
+        colors
+    }
+
+    #[test]
+    fn themes_within_the_limit_are_left_alone() {
+        let themes = ThemeSet::load_defaults();
+        let theme = &themes.themes["base16-ocean.dark"];
+
+        assert!(matches!(limit(Cow::Borrowed(theme), None), Cow::Borrowed(_)));
+        assert!(matches!(limit(Cow::Borrowed(theme), Some(1000)), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn limited_themes_use_at_most_that_many_of_their_own_colors() {
+        let themes = ThemeSet::load_defaults();
+        for (name, theme) in &themes.themes {
+            let original = foregrounds(theme);
+            for max_colors in [1, 2, 4, 6] {
+                let limited = limit(Cow::Borrowed(theme), Some(max_colors));
+                let colors = foregrounds(&limited);
+                assert!(colors.len() <= max_colors, "{} has {} colors at {}", name, colors.len(), max_colors);
+                assert!(colors.iter().all(|color| original.contains(color)), "{}", name);
+                assert_eq!(limited.settings.foreground, theme.settings.foreground, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn clusters_gather_near_colors() {
+        let colors = [rgb(200, 200, 200), rgb(250, 0, 0), rgb(240, 10, 10), rgb(0, 0, 250), rgb(10, 10, 240)];
+        let representatives = cluster(&colors, 3);
+
+        assert_eq!(representatives.len(), 3);
+        assert_eq!(representatives[0], colors[0]);
+        assert_eq!(representatives.iter().filter(|color| color.r > 200 && color.b < 50).count(), 1);
+        assert_eq!(representatives.iter().filter(|color| color.b > 200 && color.r < 50).count(), 1);
+        assert_eq!(cluster(&colors, 3), representatives);
+    }
+
+    #[test]
+    fn the_mean_rounds_each_channel() {
+        assert_eq!(mean(&[rgb(0, 10, 255), rgb(1, 20, 0)]), rgb(1, 15, 128));
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::Result;
+use syntect::highlighting::Color;
+
+use crate::diff::DiffSigns;
+use crate::{DetectStep, HighlighterConfig, NewlineMode, OutputProfile, SyntaxHighlighter};
+
+/// What a line of a unified diff outside its hunks is, by its start.
+const DIM_HEADERS: &[&str] = &[
+    "index ", "new file mode", "deleted file mode", "old mode", "new mode", "similarity index",
+    "dissimilarity index", "rename from", "rename to", "copy from", "copy to", "Binary files",
+];
+
+impl SyntaxHighlighter {
+    /// Renders the unified diff `patch`, as git, `diff -u` and patch mails
+    /// carry it, with each file's hunks highlighted as that file's language.
+    ///
+    /// The old and new side of a hunk are highlighted apart, so a line
+    /// parses in the state the lines before it on its own side leave. File
+    /// headers are bold, hunk headers dim, and the signs are colored like
+    /// those of a [`LineDiff`](crate::LineDiff). Text around the files, such
+    /// as a commit message, is left as it is.
+    pub fn highlight_patch(&self, patch: &str, config: &HighlighterConfig) -> Result<String> {
+        let signs = DiffSigns::new(self.resolve_theme(config)?.as_ref());
+        let profile = &config.profile;
+        let lines: Vec<&str> = patch.split_inclusive('\n').collect();
+        let mut syntax = String::from("Plain Text");
+        let mut output = String::new();
+        let mut index = 0;
+
+        while index < lines.len() {
+            let text = lines[index].trim_end_matches(['\n', '\r']);
+            index += 1;
+
+            let Some((mut old, mut new)) = hunk_lengths(text) else {
+                if let Some(path) = header_path(text) {
+                    syntax = self.patch_syntax(path);
+                }
+                output.push_str(&header_line(text, &signs, profile));
+                continue;
+            };
+
+            let (ranges, context) = text[2..].split_once("@@").unwrap_or((text, ""));
+            output.push_str(&format!("{}@@{}@@{}{}\n", profile.sgr("\x1b[2m"), ranges, profile.sgr("\x1b[0m"), context));
+
+            // the lengths in the header say where the hunk ends, so a removed
+            // line starting with "--" isn't taken for the next file's header
+            let start = index;
+            while index < lines.len() && (old > 0 || new > 0 || lines[index].starts_with('\\')) {
+                match lines[index].as_bytes()[0] {
+                    b'+' => new = new.saturating_sub(1),
+                    b'-' => old = old.saturating_sub(1),
+                    b'\\' => {}
+                    // mailers often strip the space of a blank context line
+                    b' ' | b'\n' | b'\r' => (old, new) = (old.saturating_sub(1), new.saturating_sub(1)),
+                    _ => break,
+                }
+                index += 1;
+            }
+            output.push_str(&self.hunk(&lines[start..index], &syntax, config, &signs)?);
+        }
+
+        Ok(output)
+    }
+
+    /// The syntax of the file at `path` in a patch, from its name alone.
+    fn patch_syntax(&self, path: &str) -> String {
+        self.detect_syntax("", path, &[DetectStep::Filename, DetectStep::Extension], &[]).0.name.clone()
+    }
+
+    /// The body of a hunk, its lines highlighted as `syntax`.
+    fn hunk(&self, lines: &[&str], syntax: &str, config: &HighlighterConfig, signs: &DiffSigns) -> Result<String> {
+        let (mut old_side, mut new_side) = (String::new(), String::new());
+        for line in lines {
+            let code = line.get(1..).unwrap_or_default();
+            match line.as_bytes()[0] {
+                b'+' => new_side.push_str(code),
+                b'-' => old_side.push_str(code),
+                b'\\' => {}
+                b' ' => {
+                    old_side.push_str(code);
+                    new_side.push_str(code);
+                }
+                _ => {
+                    old_side.push_str(line);
+                    new_side.push_str(line);
+                }
+            }
+        }
+
+        let piece = piece_config(config, syntax);
+        let old_rows = self.highlight_content(&old_side, "", &piece)?;
+        let new_rows = self.highlight_content(&new_side, "", &piece)?;
+        let (mut old_rows, mut new_rows) = (old_rows.lines(), new_rows.lines());
+        let profile = &config.profile;
+        let reset = profile.sgr("\x1b[0m");
+
+        let mut output = String::new();
+        for line in lines {
+            let (sign, row) = match line.as_bytes()[0] {
+                b'+' => (colored('+', signs.added, profile), new_rows.next()),
+                b'-' => (colored('-', signs.removed, profile), old_rows.next()),
+                b'\\' => {
+                    output.push_str(&format!("{}{}{}\n", profile.sgr("\x1b[2m"), line.trim_end_matches(['\n', '\r']), reset));
+                    continue;
+                }
+                _ => {
+                    old_rows.next();
+                    (" ".to_string(), new_rows.next())
+                }
+            };
+            output.push_str(&format!("{}{}{}\n", sign, row.unwrap_or_default(), reset));
+        }
+
+        Ok(output)
+    }
+}
+
+/// The config a piece of a larger document is highlighted with as
+/// `syntax`: the colors and profile of `config`, without its layout.
+pub(crate) fn piece_config(config: &HighlighterConfig, syntax: &str) -> HighlighterConfig {
+    HighlighterConfig {
+        theme: config.theme.clone(),
+        scope_overrides: config.scope_overrides.clone(),
+        max_colors: config.max_colors,
+        force_syntax: Some(syntax.to_string()),
+        accessibility: config.accessibility,
+        profile: config.profile,
+        newline: NewlineMode::Ensure,
+        tab_width: config.tab_width,
+        memory_budget: config.memory_budget.clone(),
+        ..HighlighterConfig::default()
+    }
+}
+
+/// The old and new lengths of the hunk a `@@ -a,b +c,d @@` header starts.
+fn hunk_lengths(text: &str) -> Option<(usize, usize)> {
+    let ranges = text.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let length = |range: &str| match range.split_once(',') {
+        Some((_, length)) => length.parse().ok(),
+        None => range.parse::<usize>().ok().map(|_| 1),
+    };
+
+    Some((length(old)?, length(new)?))
+}
+
+/// The path a file header line names, if it is one: the new file's, or the
+/// old one's when the file was deleted.
+fn header_path(text: &str) -> Option<&str> {
+    let path = if let Some(rest) = text.strip_prefix("diff --git ") {
+        rest.rsplit_once(" b/").map(|(_, path)| path)?
+    } else {
+        let rest = text.strip_prefix("+++ ").or_else(|| text.strip_prefix("--- "))?;
+        // diff -u puts a timestamp after a tab
+        let path = rest.split('\t').next().unwrap_or(rest);
+        path.strip_prefix("a/").or_else(|| path.strip_prefix("b/")).unwrap_or(path)
+    };
+
+    (path != "/dev/null").then_some(path)
+}
+
+/// A line of a patch outside its hunks: file headers are bold, their
+/// details dim, and anything else is left as it is.
+fn header_line(text: &str, signs: &DiffSigns, profile: &OutputProfile) -> String {
+    let reset = profile.sgr("\x1b[0m");
+    let bold = |color: Option<Color>| match color {
+        Some(color) if profile.colors => format!("\x1b[1;38;2;{};{};{}m", color.r, color.g, color.b),
+        _ => profile.sgr("\x1b[1m").to_string(),
+    };
+
+    if text.starts_with("diff ") {
+        format!("{}{}{}\n", bold(None), text, reset)
+    } else if text.starts_with("--- ") {
+        format!("{}{}{}\n", bold(Some(signs.removed)), text, reset)
+    } else if text.starts_with("+++ ") {
+        format!("{}{}{}\n", bold(Some(signs.added)), text, reset)
+    } else if DIM_HEADERS.iter().any(|header| text.starts_with(header)) {
+        format!("{}{}{}\n", profile.sgr("\x1b[2m"), text, reset)
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+fn colored(sign: char, color: Color, profile: &OutputProfile) -> String {
+    match profile.colors {
+        true => format!("\x1b[38;2;{};{};{}m{}", color.r, color.g, color.b, sign),
+        false => sign.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    fn dumb() -> HighlighterConfig {
+        HighlighterConfig { profile: OutputProfile::DUMB, ..HighlighterConfig::default() }
+    }
+
+    #[test]
+    fn hunk_headers_give_both_lengths() {
+        assert_eq!(hunk_lengths("@@ -1,3 +1,4 @@ fn main() {"), Some((3, 4)));
+        assert_eq!(hunk_lengths("@@ -7 +7,0 @@"), Some((1, 0)));
+        assert_eq!(hunk_lengths("@@ nonsense @@"), None);
+        assert_eq!(hunk_lengths("context"), None);
+    }
+
+    #[test]
+    fn file_headers_name_their_path() {
+        assert_eq!(header_path("diff --git a/src/lib.rs b/src/lib.rs"), Some("src/lib.rs"));
+        assert_eq!(header_path("+++ b/README.md"), Some("README.md"));
+        assert_eq!(header_path("--- old.py\t2024-01-01 10:00:00"), Some("old.py"));
+        assert_eq!(header_path("+++ /dev/null"), None);
+        assert_eq!(header_path("index 1234..5678"), None);
+    }
+
+    #[test]
+    fn a_patch_keeps_its_text_without_colors() {
+        let patch = "Some message\n\
+            diff --git a/a.rs b/a.rs\n\
+            index 1234..5678 100644\n\
+            --- a/a.rs\n\
+            +++ b/a.rs\n\
+            @@ -1,2 +1,2 @@ fn main\n \
+            fn main() {}\n\
+            -let a = 1;\n\
+            +let a = 2;\n\
+            \\ No newline at end of file\n";
+        assert_eq!(HIGHLIGHTER.highlight_patch(patch, &dumb()).unwrap(), patch);
+    }
+
+    #[test]
+    fn hunks_end_where_their_lengths_say() {
+        // a removed "-- " line isn't taken for a file header
+        let patch = "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,2 +1 @@\n--- \n keep\n--- a/next.txt\n";
+        let config = HighlighterConfig { profile: OutputProfile { colors: true, ..OutputProfile::DUMB }, ..HighlighterConfig::default() };
+        let output = HIGHLIGHTER.highlight_patch(patch, &config).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[3].starts_with("\x1b[38;2;"), "{:?}", lines[3]);
+        assert!(lines[5].starts_with("\x1b[1;38;2;"), "{:?}", lines[5]);
+    }
+
+    #[test]
+    fn each_file_is_highlighted_as_its_language() {
+        let patch = "+++ b/a.rs\n@@ -0,0 +1 @@\n+fn main() {}\n+++ b/a.txt\n@@ -0,0 +1 @@\n+fn main() {}\n";
+        let config = HighlighterConfig { profile: OutputProfile { colors: true, ..OutputProfile::DUMB }, ..HighlighterConfig::default() };
+        let output = HIGHLIGHTER.highlight_patch(patch, &config).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_ne!(lines[2], lines[5]);
+
+        let rust = piece_config(&config, "Rust");
+        let expected = HIGHLIGHTER.highlight_content("fn main() {}\n", "", &rust).unwrap();
+        assert!(lines[2].contains(expected.trim_end_matches('\n')), "{:?}", lines[2]);
+    }
+
+    #[test]
+    fn pieces_keep_the_colors_but_not_the_layout() {
+        let config = HighlighterConfig { show_line_numbers: true, tab_width: Some(8), ..HighlighterConfig::default() };
+        let piece = piece_config(&config, "Rust");
+        assert_eq!(piece.force_syntax.as_deref(), Some("Rust"));
+        assert!(!piece.show_line_numbers);
+        assert_eq!(piece.tab_width, Some(8));
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobMatcher};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::path::Path;
+
+/// How a file matched by a [`PolicyRule`] is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RenderMode {
+    /// Not read at all, only named in a one-line notice
+    Skip,
+    /// A one-line summary of its syntax and size instead of its content
+    Summary,
+    /// Shown without syntax highlighting
+    Plain,
+    /// Highlighted as usual
+    Full,
+}
+
+impl RenderMode {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "summary" => Ok(Self::Summary),
+            "plain" => Ok(Self::Plain),
+            "full" => Ok(Self::Full),
+            _ => bail!("Invalid render mode '{}', expected skip, summary, plain or full", name),
+        }
+    }
+}
+
+/// A `GLOB=MODE` rule picking how matching files are rendered.
+pub struct PolicyRule {
+    pub pattern: String,
+    pub mode: RenderMode,
+    matcher: GlobMatcher,
+}
+
+impl PolicyRule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (pattern, mode) = spec.rsplit_once('=')
+            .with_context(|| format!("Invalid policy '{}', expected GLOB=MODE", spec))?;
+
+        Self::new(pattern, mode)
+    }
+
+    pub fn new(pattern: &str, mode: &str) -> Result<Self> {
+        let matcher = Glob::new(pattern)
+            .with_context(|| format!("Invalid glob '{}' in policy", pattern))?
+            .compile_matcher();
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            mode: RenderMode::parse(mode)?,
+            matcher,
+        })
+    }
+
+    /// Patterns without a `/` are matched against the file name only.
+    pub fn matches(&self, file_path: &str) -> bool {
+        let path = Path::new(file_path);
+
+        if self.pattern.contains('/') {
+            self.matcher.is_match(path)
+        } else {
+            path.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+/// Rendering policies from the `[policies]` section of the config file, plus
+/// any given on the command line, which take precedence over the file's.
+#[derive(Default)]
+pub struct Policies {
+    configured: Vec<PolicyRule>,
+    overrides: Vec<PolicyRule>,
+}
+
+impl Policies {
+    /// Takes the `[policies]` table of the parsed config file, if there is
+    /// one, and adds the `GLOB=MODE` `overrides`.
+    pub fn load<S: AsRef<str>>(config: Option<&toml::Table>, overrides: &[S]) -> Result<Self> {
+        let configured = match config {
+            Some(config) => Self::from_table(config).context("Invalid [policies] in the config file")?.configured,
+            None => Vec::new(),
+        };
+
+        let overrides = overrides.iter()
+            .map(|spec| PolicyRule::parse(spec.as_ref()))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { configured, overrides })
+    }
+
+    /// Parses the `[policies]` table of a config file, keeping its order.
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Self::from_table(&toml::from_str(content)?)
+    }
+
+    /// Takes the `[policies]` table of a parsed config file, keeping its order.
+    pub fn from_table(config: &toml::Table) -> Result<Self> {
+        let configured = match config.get("policies") {
+            Some(toml::Value::Table(policies)) => policies.iter()
+                .map(|(pattern, mode)| match mode.as_str() {
+                    Some(mode) => PolicyRule::new(pattern, mode),
+                    None => bail!("Policy for '{}' must be a string", pattern),
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => bail!("'policies' must be a table"),
+            None => Vec::new(),
+        };
+
+        Ok(Self { configured, overrides: Vec::new() })
+    }
+
+    /// Returns the most specific rule matching `file_path`: the one with the
+    /// longest pattern, the later one on a tie. Command-line rules are
+    /// consulted first.
+    pub fn resolve(&self, file_path: &str) -> Option<&PolicyRule> {
+        most_specific(&self.overrides, file_path).or_else(|| most_specific(&self.configured, file_path))
+    }
+}
+
+impl Serialize for Policies {
+    /// As a `[policies]` table, a command-line rule in place of a configured
+    /// one for the same glob.
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut rules: Vec<&PolicyRule> = Vec::new();
+        for rule in self.configured.iter().chain(&self.overrides) {
+            rules.retain(|kept| kept.pattern != rule.pattern);
+            rules.push(rule);
+        }
+
+        let mut map = serializer.serialize_map(Some(rules.len()))?;
+        for rule in rules {
+            map.serialize_entry(&rule.pattern, &rule.mode)?;
+        }
+        map.end()
+    }
+}
+
+fn most_specific<'a>(rules: &'a [PolicyRule], file_path: &str) -> Option<&'a PolicyRule> {
+    // max_by_key keeps the last of equal keys, so later rules win ties
+    rules.iter()
+        .filter(|rule| rule.matches(file_path))
+        .max_by_key(|rule| rule.pattern.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(policies: &Policies, file_path: &str) -> Option<(String, RenderMode)> {
+        policies.resolve(file_path).map(|rule| (rule.pattern.clone(), rule.mode))
+    }
+
+    #[test]
+    fn the_longest_matching_glob_wins() {
+        let policies = Policies::from_toml(r#"
+            [policies]
+            "**/*.js" = "plain"
+            "**/*.min.js" = "skip"
+            "**/vendor/**" = "summary"
+        "#).unwrap();
+
+        assert_eq!(resolve(&policies, "src/app.js"), Some(("**/*.js".to_string(), RenderMode::Plain)));
+        assert_eq!(resolve(&policies, "dist/app.min.js"), Some(("**/*.min.js".to_string(), RenderMode::Skip)));
+        // "**/vendor/**" is the longer pattern, however specific "**/*.min.js" reads
+        assert_eq!(resolve(&policies, "lib/vendor/jquery.min.js"), Some(("**/vendor/**".to_string(), RenderMode::Summary)));
+        assert_eq!(resolve(&policies, "lib/vendor/README"), Some(("**/vendor/**".to_string(), RenderMode::Summary)));
+        assert_eq!(resolve(&policies, "src/main.rs"), None);
+    }
+
+    #[test]
+    fn later_rules_break_ties() {
+        let policies = Policies::from_toml("[policies]\n\"*.lock\" = \"summary\"\n\"*.toml\" = \"plain\"\n\"Cargo.*\" = \"full\"\n").unwrap();
+        // "*.toml" and "Cargo.*" are as long, and "Cargo.*" comes later
+        assert_eq!(resolve(&policies, "Cargo.toml").unwrap().1, RenderMode::Full);
+        assert_eq!(resolve(&policies, "Cargo.lock").unwrap().1, RenderMode::Full);
+        assert_eq!(resolve(&policies, "deps/other.lock").unwrap().1, RenderMode::Summary);
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name_only() {
+        let rule = PolicyRule::parse("*.lock=skip").unwrap();
+        assert!(rule.matches("a/b/Cargo.lock"));
+        let rule = PolicyRule::parse("testdata/*=plain").unwrap();
+        assert!(rule.matches("testdata/input.txt"));
+        assert!(!rule.matches("src/testdata/input.txt"));
+    }
+
+    #[test]
+    fn command_line_rules_beat_longer_configured_ones() {
+        let config: toml::Table = toml::from_str("[policies]\n\"**/testdata/**/*.json\" = \"skip\"\n").unwrap();
+        let policies = Policies::load(Some(&config), &["*.json=full"]).unwrap();
+        assert_eq!(resolve(&policies, "a/testdata/b/c.json"), Some(("*.json".to_string(), RenderMode::Full)));
+        assert_eq!(serde_json::to_string(&policies).unwrap(), r#"{"**/testdata/**/*.json":"skip","*.json":"full"}"#);
+    }
+
+    #[test]
+    fn invalid_rules_are_errors() {
+        assert!(PolicyRule::parse("*.js").is_err());
+        assert!(PolicyRule::parse("*.js=hide").is_err());
+        assert!(PolicyRule::parse("[=skip").is_err());
+        assert!(Policies::from_toml("policies = 1").is_err());
+        assert!(Policies::from_toml("[policies]\n\"*.js\" = 1\n").is_err());
+        let config: toml::Table = toml::from_str("policies = 1").unwrap();
+        assert_eq!(Policies::load(Some(&config), &[] as &[&str]).err().unwrap().to_string(), "Invalid [policies] in the config file");
+    }
+}
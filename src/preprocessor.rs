@@ -0,0 +1,468 @@
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobMatcher};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::guard::parse_size;
+
+/// How long a preprocessor may run without a `timeout_ms`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How much output a preprocessor may produce without a `max_output`.
+const DEFAULT_MAX_OUTPUT: u64 = 16 << 20;
+
+/// How much of a failed preprocessor's stderr is kept for the notice.
+const STDERR_SNIPPET: usize = 200;
+
+/// How long the stderr of a failed preprocessor is waited for after it
+/// exits, in case something it started still holds the pipe open.
+const STDERR_GRACE: Duration = Duration::from_millis(200);
+
+/// How often a running preprocessor is checked on.
+const POLL: Duration = Duration::from_millis(10);
+
+/// A `[preprocessors]` entry: a command that turns files matching a glob
+/// into text, which is highlighted in their place.
+///
+/// Like an editor command, the program and its arguments are run directly,
+/// never through a shell, with `{file}` replaced by the file's path in each.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    pub pattern: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Syntax to highlight the output as, detected from it when None
+    pub syntax: Option<String>,
+    pub timeout: Duration,
+    /// Bytes of output beyond which the preprocessor is stopped
+    pub max_output: u64,
+    matcher: GlobMatcher,
+}
+
+/// What running a [`Preprocessor`] gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preprocessed {
+    /// What it wrote to stdout
+    Text(String),
+    /// Why there is no text: it couldn't be started, failed, took too long
+    /// or wrote too much, with the start of its stderr where there was any
+    Failed(String),
+}
+
+impl Preprocessor {
+    /// Builds the entry for `pattern` from its table, e.g.
+    /// `{ command = "fbxdump --text {file}", syntax = "YAML", timeout_ms = 2000 }`.
+    /// The command may also be an array of words. Without a `{file}` word
+    /// the file is passed last.
+    pub fn from_toml(pattern: &str, table: &toml::Table) -> Result<Self> {
+        let words: Vec<String> = match table.get("command") {
+            Some(toml::Value::String(command)) => command.split_whitespace().map(str::to_string).collect(),
+            Some(toml::Value::Array(words)) => words.iter()
+                .map(|word| word.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .with_context(|| format!("The command of preprocessor '{}' must be a string or an array of strings", pattern))?,
+            Some(_) => bail!("The command of preprocessor '{}' must be a string or an array of strings", pattern),
+            None => bail!("Preprocessor '{}' has no command", pattern),
+        };
+        let Some((program, args)) = words.split_first() else {
+            bail!("The command of preprocessor '{}' is empty", pattern);
+        };
+        let mut args = args.to_vec();
+        if !args.iter().any(|arg| arg.contains("{file}")) {
+            args.push("{file}".to_string());
+        }
+
+        let syntax = match table.get("syntax") {
+            Some(syntax) => Some(syntax.as_str()
+                .with_context(|| format!("The syntax of preprocessor '{}' must be a string", pattern))?
+                .to_string()),
+            None => None,
+        };
+        let timeout = match table.get("timeout_ms") {
+            Some(timeout) => {
+                let millis = timeout.as_integer().filter(|millis| *millis > 0)
+                    .with_context(|| format!("The timeout_ms of preprocessor '{}' must be a positive integer", pattern))?;
+                Duration::from_millis(millis as u64)
+            }
+            None => DEFAULT_TIMEOUT,
+        };
+        let max_output = match table.get("max_output") {
+            Some(toml::Value::String(size)) => parse_size(size)?,
+            Some(toml::Value::Integer(bytes)) if *bytes > 0 => *bytes as u64,
+            Some(_) => bail!("The max_output of preprocessor '{}' must be a size such as \"8M\"", pattern),
+            None => DEFAULT_MAX_OUTPUT,
+        };
+
+        let matcher = Glob::new(pattern)
+            .with_context(|| format!("Invalid glob '{}' in preprocessor", pattern))?
+            .compile_matcher();
+
+        Ok(Self { pattern: pattern.to_string(), program: program.clone(), args, syntax, timeout, max_output, matcher })
+    }
+
+    /// Patterns without a `/` are matched against the file name only.
+    pub fn matches(&self, file_path: &str) -> bool {
+        let path = Path::new(file_path);
+
+        if self.pattern.contains('/') {
+            self.matcher.is_match(path)
+        } else {
+            path.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+
+    /// Runs the command on `file_path` with no stdin, capturing its stdout.
+    /// It is killed when it outlives its timeout or writes more than its
+    /// `max_output`, and any output it wrote is then discarded.
+    pub fn run(&self, file_path: &str) -> Preprocessed {
+        let args = self.args.iter().map(|arg| arg.replace("{file}", file_path));
+        let child = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(error) => return Preprocessed::Failed(format!("failed to run '{}': {}", self.program, error)),
+        };
+
+        // both pipes are drained as the command writes, so it never blocks on
+        // a full one; past the cap stdout is closed, which stops most commands
+        let limit = self.max_output;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stdout = thread::spawn(move || {
+            let mut bytes = Vec::new();
+            stdout.take(limit + 1).read_to_end(&mut bytes).map(|_| bytes)
+        });
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_start = Arc::new(Mutex::new(Vec::new()));
+        let kept = Arc::clone(&stderr_start);
+        let (stderr_done, stderr_read) = mpsc::channel();
+        thread::spawn(move || {
+            let mut chunk = [0; 1024];
+            while let Ok(read @ 1..) = stderr.read(&mut chunk) {
+                let mut kept = kept.lock().expect("the stderr reader doesn't panic");
+                let room = (STDERR_SNIPPET * 4).saturating_sub(kept.len());
+                kept.extend_from_slice(&chunk[..read.min(room)]);
+            }
+            let _ = stderr_done.send(());
+        });
+        let stderr_snippet = || snippet(&stderr_start.lock().expect("the stderr reader doesn't panic"));
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                // anything it started may still hold the pipes open, so they aren't waited for
+                Ok(None) if started.elapsed() >= self.timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let reason = format!("'{}' timed out after {} ms", self.program, self.timeout.as_millis());
+                    return Preprocessed::Failed(with_stderr(reason, &stderr_snippet()));
+                }
+                Ok(None) => thread::sleep(POLL),
+                Err(error) => return Preprocessed::Failed(format!("failed to wait for '{}': {}", self.program, error)),
+            }
+        };
+
+        let output = stdout.join().expect("the stdout reader doesn't panic");
+        let output = match output {
+            Ok(output) => output,
+            Err(error) => return Preprocessed::Failed(format!("failed to read the output of '{}': {}", self.program, error)),
+        };
+        if output.len() as u64 > limit {
+            return Preprocessed::Failed(format!("'{}' wrote more than {} bytes", self.program, limit));
+        }
+        if !status.success() {
+            // what it wrote last may not have been read yet when it exited
+            let _ = stderr_read.recv_timeout(STDERR_GRACE);
+            return Preprocessed::Failed(with_stderr(format!("'{}' failed with {}", self.program, status), &stderr_snippet()));
+        }
+
+        match String::from_utf8(output) {
+            Ok(text) => Preprocessed::Text(text),
+            Err(_) => Preprocessed::Failed(format!("'{}' wrote output that is not UTF-8", self.program)),
+        }
+    }
+}
+
+fn with_stderr(reason: String, snippet: &str) -> String {
+    match snippet.is_empty() {
+        true => reason,
+        false => format!("{}: {}", reason, snippet),
+    }
+}
+
+/// The first line of `stderr`, cut to a notice's length.
+fn snippet(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let line = stderr.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    match line.char_indices().nth(STDERR_SNIPPET) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// The preprocessors from the `[preprocessors]` section of the config file.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessors {
+    rules: Vec<Preprocessor>,
+}
+
+impl Preprocessors {
+    /// Takes the `[preprocessors]` table of the parsed config file, if there
+    /// is one.
+    pub fn load(config: Option<&toml::Table>) -> Result<Self> {
+        match config {
+            Some(config) => Self::from_table(config).context("Invalid [preprocessors] in the config file"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Parses the `[preprocessors]` table of a config file.
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Self::from_table(&toml::from_str(content)?)
+    }
+
+    /// Reads the `[preprocessors]` table of a parsed config file.
+    pub fn from_table(config: &toml::Table) -> Result<Self> {
+        let rules = match config.get("preprocessors") {
+            Some(toml::Value::Table(preprocessors)) => preprocessors.iter()
+                .map(|(pattern, entry)| match entry {
+                    toml::Value::Table(table) => Preprocessor::from_toml(pattern, table),
+                    _ => bail!("Preprocessor for '{}' must be a table with a command", pattern),
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => bail!("'preprocessors' must be a table"),
+            None => Vec::new(),
+        };
+
+        Ok(Self { rules })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Preprocessor> {
+        self.rules.iter()
+    }
+
+    /// Returns the preprocessor with the longest pattern matching
+    /// `file_path`, the later one on a tie, as for policies.
+    pub fn resolve(&self, file_path: &str) -> Option<&Preprocessor> {
+        self.rules.iter()
+            .filter(|rule| rule.matches(file_path))
+            .max_by_key(|rule| rule.pattern.len())
+    }
+}
+
+impl Serialize for Preprocessors {
+    /// As a `[preprocessors]` table, with every setting spelled out.
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            command: Vec<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            syntax: Option<&'a str>,
+            timeout_ms: u64,
+            max_output: u64,
+        }
+
+        let mut map = serializer.serialize_map(Some(self.rules.len()))?;
+        for rule in &self.rules {
+            let entry = Entry {
+                command: std::iter::once(&rule.program).chain(&rule.args).map(String::as_str).collect(),
+                syntax: rule.syntax.as_deref(),
+                timeout_ms: rule.timeout.as_millis() as u64,
+                max_output: rule.max_output,
+            };
+            map.serialize_entry(&rule.pattern, &entry)?;
+        }
+        map.end()
+    }
+}
+
+/// Whether preprocessors may run on `file_path` without being allowed
+/// explicitly: it must be inside the current directory or the home
+/// directory, in a directory only its owner can write to.
+pub fn is_trusted_path(file_path: &str) -> bool {
+    let Ok(path) = std::fs::canonicalize(file_path) else {
+        return false;
+    };
+    let roots = [std::env::current_dir().ok(), std::env::var_os("HOME").map(Into::into)];
+    let inside = roots.iter().flatten()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| path.starts_with(root));
+
+    inside && path.parent().is_some_and(|parent| !world_writable(parent))
+}
+
+#[cfg(unix)]
+fn world_writable(dir: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(dir).map_or(true, |metadata| metadata.permissions().mode() & 0o002 != 0)
+}
+
+#[cfg(not(unix))]
+fn world_writable(_dir: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor(pattern: &str, entry: &str) -> Result<Preprocessor> {
+        Preprocessor::from_toml(pattern, &toml::from_str(entry)?)
+    }
+
+    fn shell(script: &str, settings: &str) -> Preprocessor {
+        let entry = format!("command = [\"sh\", \"-c\", {:?}, \"sh\", \"{{file}}\"]\n{}", script, settings);
+        preprocessor("*.bin", &entry).unwrap()
+    }
+
+    #[test]
+    fn commands_are_split_into_words_with_the_file_last() {
+        let words = preprocessor("*.fbx", r#"command = "fbxdump --text""#).unwrap();
+        assert_eq!(words.program, "fbxdump");
+        assert_eq!(words.args, ["--text", "{file}"]);
+        assert_eq!(words.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(words.max_output, DEFAULT_MAX_OUTPUT);
+        assert_eq!(words.syntax, None);
+
+        let array = preprocessor("*.fbx", r#"command = ["dump tool", "{file}", "-"]
+            syntax = "YAML"
+            timeout_ms = 2000
+            max_output = "1K""#).unwrap();
+        assert_eq!(array.program, "dump tool");
+        assert_eq!(array.args, ["{file}", "-"]);
+        assert_eq!(array.syntax.as_deref(), Some("YAML"));
+        assert_eq!(array.timeout, Duration::from_millis(2000));
+        assert_eq!(array.max_output, 1024);
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        let error = |entry: &str| preprocessor("*.fbx", entry).unwrap_err().to_string();
+
+        assert_eq!(error("syntax = \"YAML\""), "Preprocessor '*.fbx' has no command");
+        assert_eq!(error("command = \"\""), "The command of preprocessor '*.fbx' is empty");
+        assert_eq!(error("command = 3"), "The command of preprocessor '*.fbx' must be a string or an array of strings");
+        assert_eq!(error("command = [\"a\", 1]"), "The command of preprocessor '*.fbx' must be a string or an array of strings");
+        assert_eq!(error("command = \"a\"\ntimeout_ms = 0"), "The timeout_ms of preprocessor '*.fbx' must be a positive integer");
+        assert_eq!(error("command = \"a\"\nmax_output = true"), "The max_output of preprocessor '*.fbx' must be a size such as \"8M\"");
+        assert!(preprocessor("[", "command = \"a\"").is_err());
+
+        assert_eq!(Preprocessors::from_toml("preprocessors = 1").unwrap_err().to_string(), "'preprocessors' must be a table");
+        assert_eq!(
+            Preprocessors::from_toml("[preprocessors]\n\"*.fbx\" = \"fbxdump\"").unwrap_err().to_string(),
+            "Preprocessor for '*.fbx' must be a table with a command",
+        );
+    }
+
+    #[test]
+    fn load_reads_only_the_preprocessors_table() {
+        let config: toml::Table = toml::from_str("theme = \"Nord\"\n[preprocessors]\n\"*.fbx\" = { command = \"fbxdump\" }").unwrap();
+        assert_eq!(Preprocessors::load(Some(&config)).unwrap().iter().count(), 1);
+        assert_eq!(Preprocessors::load(None).unwrap().iter().count(), 0);
+
+        let config: toml::Table = toml::from_str("[preprocessors]\n\"*.fbx\" = {}").unwrap();
+        let error = Preprocessors::load(Some(&config)).unwrap_err();
+        assert_eq!(format!("{:#}", error), "Invalid [preprocessors] in the config file: Preprocessor '*.fbx' has no command");
+    }
+
+    #[test]
+    fn preprocessors_serialize_as_the_table_they_are_read_from() {
+        let preprocessors = Preprocessors::from_toml(r#"
+            [preprocessors]
+            "*.fbx" = { command = "fbxdump --text", syntax = "YAML", max_output = "1K" }
+        "#).unwrap();
+        let serialized = toml::to_string(&std::collections::BTreeMap::from([("preprocessors", &preprocessors)])).unwrap();
+
+        assert_eq!(serialized, "[preprocessors.\"*.fbx\"]\ncommand = [\"fbxdump\", \"--text\", \"{file}\"]\nsyntax = \"YAML\"\ntimeout_ms = 10000\nmax_output = 1024\n");
+        let read_back = Preprocessors::from_toml(&serialized).unwrap();
+        assert_eq!(read_back.resolve("a.fbx").map(|rule| (&rule.args, rule.max_output)), Some((&preprocessors.rules[0].args, 1024)));
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name() {
+        let by_name = preprocessor("*.fbx", "command = \"a\"").unwrap();
+        assert!(by_name.matches("models/ship.fbx"));
+        assert!(!by_name.matches("models/ship.fbx.bak"));
+
+        let by_path = preprocessor("models/*.fbx", "command = \"a\"").unwrap();
+        assert!(by_path.matches("models/ship.fbx"));
+        assert!(!by_path.matches("ship.fbx"));
+    }
+
+    #[test]
+    fn the_longest_pattern_wins_and_then_the_later_one() {
+        let preprocessors = Preprocessors::from_toml(r#"
+            [preprocessors]
+            "*.fbx" = { command = "short" }
+            "models/*.fbx" = { command = "first" }
+            "models/?.fbx" = { command = "second" }
+        "#).unwrap();
+        let program = |file: &str| preprocessors.resolve(file).map(|rule| rule.program.as_str());
+
+        assert_eq!(program("ship.fbx"), Some("short"));
+        assert_eq!(program("models/ship.fbx"), Some("first"));
+        assert_eq!(program("models/a.fbx"), Some("second"));
+        assert_eq!(program("ship.obj"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_substitutes_the_file_into_each_argument_without_a_shell() {
+        let echo = preprocessor("*", r#"command = ["echo", "--file={file}", "$HOME"]"#).unwrap();
+        assert_eq!(echo.run("a b;c.bin"), Preprocessed::Text("--file=a b;c.bin $HOME\n".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_keeps_the_start_of_stderr_when_the_command_fails() {
+        let failing = shell("echo partial; echo \"bad header in $1\" >&2; echo more >&2; exit 3", "");
+        assert_eq!(
+            failing.run("model.bin"),
+            Preprocessed::Failed("'sh' failed with exit status: 3: bad header in model.bin".to_string()),
+        );
+
+        let long = shell("printf '%0300d' 0 >&2; exit 1", "").run("model.bin");
+        let Preprocessed::Failed(reason) = long else { panic!("{:?}", long) };
+        assert!(reason.ends_with(&format!("{}…", "0".repeat(STDERR_SNIPPET))), "{}", reason);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_stops_commands_that_outlive_their_timeout() {
+        let slow = shell("echo waiting >&2; sleep 5", "timeout_ms = 100");
+        let started = Instant::now();
+
+        assert_eq!(slow.run("model.bin"), Preprocessed::Failed("'sh' timed out after 100 ms: waiting".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_discards_output_past_the_cap() {
+        let within = shell("printf '%010d' 0", "max_output = 10");
+        assert_eq!(within.run("model.bin"), Preprocessed::Text("0".repeat(10)));
+
+        let over = shell("printf '%011d' 0", "max_output = 10");
+        assert_eq!(over.run("model.bin"), Preprocessed::Failed("'sh' wrote more than 10 bytes".to_string()));
+
+        let binary = shell("printf '\\377'", "");
+        assert_eq!(binary.run("model.bin"), Preprocessed::Failed("'sh' wrote output that is not UTF-8".to_string()));
+    }
+
+    #[test]
+    fn run_reports_a_program_that_cannot_start() {
+        let missing = preprocessor("*", "command = \"ccat-no-such-preprocessor\"").unwrap();
+        let Preprocessed::Failed(reason) = missing.run("model.bin") else { panic!("the program doesn't exist") };
+        assert!(reason.starts_with("failed to run 'ccat-no-such-preprocessor': "), "{}", reason);
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Serialize;
+use syntect::highlighting::{Color, FontStyle, Style};
+use syntect::util::as_24_bit_terminal_escaped;
+
+use crate::multiplexer::{Escape, Multiplexer};
+use crate::width::AmbiguousWidth;
+
+/// Requested terminal capability profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Pick a profile from the environment's `TERM`
+    #[default]
+    Auto,
+    /// Colors, unicode decorations and OSC sequences
+    Full,
+    /// Colors with ASCII-only decorations
+    Ascii,
+    /// No escape sequences at all and ASCII-only decorations
+    Dumb,
+}
+
+/// How ranges with the underline font style are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Underline {
+    /// A curly line in a color, as diagnostics are marked
+    Curly(Color),
+    /// A plain line in the text's own color, as URLs are marked
+    Straight,
+}
+
+/// What the output is allowed to contain, resolved once from a [`Profile`].
+///
+/// All color and decoration code consults this instead of checking the
+/// terminal on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutputProfile {
+    /// Emit SGR color and style sequences
+    pub colors: bool,
+    /// Use unicode characters for decorations
+    pub unicode: bool,
+    /// Emit OSC sequences (hyperlinks, terminal queries)
+    pub osc: bool,
+    /// The multiplexer the output goes through, whose passthrough wraps
+    /// escapes it wouldn't pass on
+    pub multiplexer: Option<Multiplexer>,
+    /// How wide the terminal draws East Asian ambiguous characters
+    pub ambiguous_width: AmbiguousWidth,
+    /// Measure escape sequences already in the text as the terminal draws
+    /// them, taking no columns, instead of counting their printable bytes
+    pub zero_width_escapes: bool,
+    /// Send one SGR sequence per change of color instead of one per
+    /// highlighted range, however many ranges share it
+    pub merge_colors: bool,
+}
+
+impl OutputProfile {
+    pub const FULL: Self = Self { colors: true, unicode: true, osc: true, multiplexer: None, ambiguous_width: AmbiguousWidth::Narrow, zero_width_escapes: false, merge_colors: false };
+    pub const ASCII: Self = Self { colors: true, unicode: false, osc: true, multiplexer: None, ambiguous_width: AmbiguousWidth::Narrow, zero_width_escapes: false, merge_colors: false };
+    pub const DUMB: Self = Self { colors: false, unicode: false, osc: false, multiplexer: None, ambiguous_width: AmbiguousWidth::Narrow, zero_width_escapes: false, merge_colors: false };
+
+    /// Resolves `profile`, treating a missing or `dumb` `term` as a dumb terminal.
+    pub fn resolve(profile: Profile, term: Option<&str>) -> Self {
+        match profile {
+            Profile::Full => Self::FULL,
+            Profile::Ascii => Self::ASCII,
+            Profile::Dumb => Self::DUMB,
+            Profile::Auto => match term {
+                None | Some("") | Some("dumb") => Self::DUMB,
+                Some(_) => Self::FULL,
+            },
+        }
+    }
+
+    /// Resolves `profile` against the current `TERM` environment variable,
+    /// detecting a multiplexer as well.
+    pub fn from_env(profile: Profile) -> Self {
+        Self {
+            multiplexer: Multiplexer::from_env(),
+            ..Self::resolve(profile, std::env::var("TERM").ok().as_deref())
+        }
+    }
+
+    /// Whether `escape` should be sent at all.
+    pub fn sends(&self, escape: Escape) -> bool {
+        self.osc && self.multiplexer.is_none_or(|multiplexer| multiplexer.sends(escape))
+    }
+
+    /// Wraps one complete escape `sequence` in the multiplexer's passthrough,
+    /// so every feature sending one goes through here instead of knowing
+    /// about multiplexers itself.
+    pub fn passthrough(&self, sequence: &str) -> String {
+        match self.multiplexer {
+            Some(multiplexer) => multiplexer.wrap(sequence),
+            None => sequence.to_string(),
+        }
+    }
+
+    /// Picks the unicode or ASCII variant of a decoration.
+    pub fn symbol<'a>(&self, unicode: &'a str, ascii: &'a str) -> &'a str {
+        if self.unicode { unicode } else { ascii }
+    }
+
+    /// Returns `sequence` if colors are enabled, or an empty string otherwise.
+    pub fn sgr<'a>(&self, sequence: &'a str) -> &'a str {
+        if self.colors { sequence } else { "" }
+    }
+
+    /// Renders highlighted ranges, dropping all styling when colors are disabled.
+    pub(crate) fn escape(&self, ranges: &[(Style, &str)], background: bool) -> String {
+        if self.colors {
+            self.escape_colors(ranges, background)
+        } else {
+            ranges.iter().map(|(_, text)| *text).collect()
+        }
+    }
+
+    /// Like [`escape`](Self::escape), also drawing an `underline` under the
+    /// ranges with the underline font style.
+    pub(crate) fn escape_underlined(&self, ranges: &[(Style, &str)], background: bool, underline: Option<Underline>) -> String {
+        let Some(underline) = underline.filter(|_| self.colors) else {
+            return self.escape(ranges, background);
+        };
+        let underlined = |style: &Style| style.font_style.contains(FontStyle::UNDERLINE);
+        let (start, end) = match underline {
+            Underline::Curly(color) => (format!("\x1b[4:3m\x1b[58;2;{};{};{}m", color.r, color.g, color.b), "\x1b[59m\x1b[24m"),
+            Underline::Straight => ("\x1b[4m".to_string(), "\x1b[24m"),
+        };
+
+        let mut result = String::new();
+        for run in ranges.chunk_by(|a, b| underlined(&a.0) == underlined(&b.0)) {
+            if underlined(&run[0].0) {
+                result.push_str(&start);
+                result.push_str(&self.escape_colors(run, background));
+                result.push_str(end);
+            } else {
+                result.push_str(&self.escape_colors(run, background));
+            }
+        }
+        result
+    }
+
+    /// The ranges in 24-bit color, merging the ranges that share one under
+    /// [`merge_colors`](Self::merge_colors).
+    fn escape_colors(&self, ranges: &[(Style, &str)], background: bool) -> String {
+        if !self.merge_colors {
+            return as_24_bit_terminal_escaped(ranges, background);
+        }
+
+        let mut result = String::new();
+        let mut last = String::new();
+        for (style, text) in ranges {
+            // the sequence a range alone would get, so a change of font
+            // style, which isn't sent, is no change
+            let sequence = as_24_bit_terminal_escaped(&[(*style, "")], background);
+            if sequence != last {
+                result.push_str(&sequence);
+                last = sequence;
+            }
+            result.push_str(text);
+        }
+        result
+    }
+}
+
+impl Default for OutputProfile {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_is_dumb_without_a_capable_terminal() {
+        for term in [None, Some(""), Some("dumb")] {
+            assert_eq!(OutputProfile::resolve(Profile::Auto, term), OutputProfile::DUMB, "{:?}", term);
+        }
+        assert_eq!(OutputProfile::resolve(Profile::Auto, Some("xterm-256color")), OutputProfile::FULL);
+        assert_eq!(OutputProfile::resolve(Profile::Full, Some("dumb")), OutputProfile::FULL);
+    }
+
+    #[test]
+    fn the_dumb_profile_draws_no_escapes() {
+        let style = Style::default();
+        let profile = OutputProfile::DUMB;
+        assert_eq!(profile.escape(&[(style, "fn"), (style, " main")], true), "fn main");
+        assert_eq!(profile.sgr("\x1b[2m"), "");
+        assert_eq!(profile.symbol("│", "|"), "|");
+        assert!(!profile.sends(Escape::Hyperlink));
+    }
+
+    #[test]
+    fn ranges_sharing_a_color_get_one_sequence_when_merged() {
+        let red = Style { foreground: Color { r: 255, g: 0, b: 0, a: 255 }, ..Style::default() };
+        let blue = Style { foreground: Color { r: 0, g: 0, b: 255, a: 255 }, ..Style::default() };
+        let ranges = [(red, "fn"), (red, " "), (red, "main"), (blue, "()"), (red, ";")];
+
+        let merged = OutputProfile { merge_colors: true, ..OutputProfile::FULL }.escape(&ranges, false);
+        assert_eq!(merged, "\x1b[38;2;255;0;0mfn main\x1b[38;2;0;0;255m()\x1b[38;2;255;0;0m;");
+        let unmerged = OutputProfile::FULL.escape(&ranges, false);
+        assert_eq!(unmerged.matches("\x1b[38;2;255;0;0m").count(), 4);
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error_snippet::toml_context;
+use crate::{EditorCommand, Preprocessors, SyntaxRules};
+
+/// The name of a project's own config file.
+pub const PROJECT_CONFIG: &str = ".ccat.toml";
+
+/// A `.ccat.toml` found in a directory above a file.
+///
+/// Its theme, tab width and syntax mappings apply as they are. What runs
+/// a program, its preprocessors and editor command, only applies once the
+/// user has trusted the file as it is now with `ccat --trust`, since
+/// anyone can put one in a repository.
+pub struct ProjectConfig {
+    /// Canonical path of the config file
+    pub path: PathBuf,
+    /// The directory it is in, its patterns are relative to
+    pub dir: PathBuf,
+    /// Lowercase hex SHA-256 of the file, which trust is recorded for
+    pub sha256: String,
+    pub theme: Option<String>,
+    pub tab_width: Option<usize>,
+    /// `[syntaxes]`, globs mapped to the syntax to highlight matching files as
+    pub syntax_rules: SyntaxRules,
+    /// `[preprocessors]`, as in the user's config file; needs trust
+    pub preprocessors: Preprocessors,
+    /// `editor-command`, as in the user's config file; needs trust
+    pub editor_command: Option<EditorCommand>,
+}
+
+impl ProjectConfig {
+    /// Finds the `.ccat.toml` closest above `file`, walking up from its
+    /// directory to the root.
+    pub fn discover(file: &Path) -> Result<Option<Self>> {
+        let Ok(file) = fs::canonicalize(file) else {
+            return Ok(None);
+        };
+
+        for dir in file.ancestors().skip(1) {
+            let path = dir.join(PROJECT_CONFIG);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the project config file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let path = fs::canonicalize(path)
+            .with_context(|| format!("Failed to read project config '{}'", path.display()))?;
+        let content = fs::read(&path)
+            .with_context(|| format!("Failed to read project config '{}'", path.display()))?;
+        let sha256 = Sha256::digest(&content).iter().map(|byte| format!("{:02x}", byte)).collect();
+        let content = String::from_utf8(content)
+            .with_context(|| format!("Project config '{}' is not UTF-8", path.display()))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Self::from_toml(&content, path.clone(), dir, sha256)
+            .map_err(|error| toml_context(error, format!("Failed to parse project config '{}'", path.display()), &path, &content))
+    }
+
+    fn from_toml(content: &str, path: PathBuf, dir: PathBuf, sha256: String) -> Result<Self> {
+        let config: toml::Table = toml::from_str(content)?;
+
+        // a setting this version doesn't know might be one that needs trust
+        if let Some(key) = config.keys().find(|key| !["theme", "tabs", "syntaxes", "preprocessors", "editor-command"].contains(&key.as_str())) {
+            bail!("Unknown setting '{}'", key);
+        }
+
+        let theme = match config.get("theme") {
+            Some(theme) => Some(theme.as_str().context("'theme' must be a string")?.to_string()),
+            None => None,
+        };
+        let tab_width = match config.get("tabs") {
+            Some(tabs) => Some(tabs.as_integer()
+                .filter(|tabs| (1..=u16::MAX as i64).contains(tabs))
+                .context("'tabs' must be a positive integer")? as usize),
+            None => None,
+        };
+        let syntax_rules = match config.get("syntaxes") {
+            Some(toml::Value::Table(syntaxes)) => {
+                let specs = syntaxes.iter()
+                    .map(|(pattern, syntax)| match syntax.as_str() {
+                        Some(syntax) => Ok(format!("{}={}", pattern, syntax)),
+                        None => bail!("The syntax for '{}' must be a string", pattern),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                SyntaxRules::parse(&specs)?
+            }
+            Some(_) => bail!("'syntaxes' must be a table"),
+            None => SyntaxRules::default(),
+        };
+        let editor_command = match config.get("editor-command") {
+            Some(toml::Value::String(template)) => Some(EditorCommand::parse(template)?),
+            Some(_) => bail!("'editor-command' must be a string"),
+            None => None,
+        };
+
+        Ok(Self {
+            path,
+            dir,
+            sha256,
+            theme,
+            tab_width,
+            syntax_rules,
+            preprocessors: Preprocessors::from_table(&config)?,
+            editor_command,
+        })
+    }
+
+    /// What in the file would run a program, one description each, empty
+    /// when it needs no trust.
+    pub fn needs_trust(&self) -> Vec<String> {
+        let mut wants: Vec<String> = self.preprocessors.iter()
+            .map(|preprocessor| format!(
+                "preprocessor for '{}': {}",
+                preprocessor.pattern,
+                std::iter::once(&preprocessor.program).chain(&preprocessor.args).cloned().collect::<Vec<_>>().join(" "),
+            ))
+            .collect();
+        wants.extend(self.editor_command.as_ref().map(|editor| format!("editor command: {}", editor.template())));
+        wants
+    }
+
+    /// `file` relative to the project's directory, which its patterns match
+    /// against, or as given when it isn't inside it.
+    pub fn relative(&self, file: &str) -> String {
+        fs::canonicalize(file).ok()
+            .and_then(|path| path.strip_prefix(&self.dir).ok().map(|relative| relative.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| file.to_string())
+    }
+}
+
+/// Whether a [`ProjectConfig`] may run programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    Trusted,
+    /// Never trusted
+    Unknown,
+    /// Trusted when its content was different
+    Changed,
+}
+
+/// The project directories the user has trusted, each with the hash of
+/// its config file at the time, kept in ccat's cache directory.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    path: Option<PathBuf>,
+    trusted: BTreeMap<String, TrustedProject>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TrustFile {
+    #[serde(default)]
+    trusted: BTreeMap<String, TrustedProject>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrustedProject {
+    sha256: String,
+}
+
+impl TrustStore {
+    /// Reads the store at `path`, empty when there is none yet. Without a
+    /// path nothing is trusted.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let trusted = match path.filter(|path| path.is_file()) {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read trust store '{}'", path.display()))?;
+                toml::from_str::<TrustFile>(&content)
+                    .map_err(|error| toml_context(error.into(), format!("Failed to parse trust store '{}'", path.display()), path, &content))?
+                    .trusted
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self { path: path.map(Path::to_path_buf), trusted })
+    }
+
+    pub fn trust_of(&self, project: &ProjectConfig) -> Trust {
+        match self.trusted.get(&project.dir.to_string_lossy().into_owned()) {
+            Some(trusted) if trusted.sha256 == project.sha256 => Trust::Trusted,
+            Some(_) => Trust::Changed,
+            None => Trust::Unknown,
+        }
+    }
+
+    /// Records `project` as trusted with its current content and writes
+    /// the store.
+    pub fn trust(&mut self, project: &ProjectConfig) -> Result<()> {
+        let Some(path) = &self.path else {
+            bail!("No cache directory to keep trusted projects in, set $XDG_CACHE_HOME or $HOME");
+        };
+        self.trusted.insert(project.dir.to_string_lossy().into_owned(), TrustedProject { sha256: project.sha256.clone() });
+
+        let content = toml::to_string(&TrustFile { trusted: self.trusted.clone() })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+        }
+        // written aside and moved in place, so a reader never sees half of it
+        let partial = path.with_extension("toml.partial");
+        fs::write(&partial, content)
+            .with_context(|| format!("Failed to write trust store '{}'", partial.display()))?;
+        fs::rename(&partial, path)
+            .with_context(|| format!("Failed to write trust store '{}'", path.display()))
+    }
+}
+
+/// The project configs of a run's files, each read once however many of
+/// its files there are.
+#[derive(Default)]
+pub struct Projects {
+    configs: Vec<(ProjectConfig, Trust)>,
+    by_file: HashMap<String, usize>,
+}
+
+impl Projects {
+    /// Finds the project config of each `(file, path)`, where `path` is
+    /// where the file really is when `file` is a temporary copy or link.
+    pub fn discover<'a>(files: impl IntoIterator<Item = (&'a str, &'a Path)>, store: &TrustStore) -> Result<Self> {
+        let mut projects = Self::default();
+        for (file, path) in files {
+            let Some(config) = ProjectConfig::discover(path)? else {
+                continue;
+            };
+            let index = match projects.configs.iter().position(|(known, _)| known.path == config.path) {
+                Some(index) => index,
+                None => {
+                    let trust = store.trust_of(&config);
+                    projects.configs.push((config, trust));
+                    projects.configs.len() - 1
+                }
+            };
+            projects.by_file.insert(file.to_string(), index);
+        }
+
+        Ok(projects)
+    }
+
+    /// The project config of `file`, if it has one.
+    pub fn for_file(&self, file: &str) -> Option<&ProjectConfig> {
+        self.by_file.get(file).map(|index| &self.configs[*index].0)
+    }
+
+    /// The project config of `file` if it may run programs.
+    pub fn trusted_for_file(&self, file: &str) -> Option<&ProjectConfig> {
+        self.by_file.get(file)
+            .map(|index| &self.configs[*index])
+            .filter(|(_, trust)| *trust == Trust::Trusted)
+            .map(|(config, _)| config)
+    }
+
+    /// The configs found with settings that need trust, with their trust.
+    pub fn untrusted(&self) -> impl Iterator<Item = (&ProjectConfig, Trust)> {
+        self.configs.iter()
+            .filter(|(config, trust)| *trust != Trust::Trusted && !config.needs_trust().is_empty())
+            .map(|(config, trust)| (config, *trust))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory with `.ccat.toml` files at each `(dir, content)` and an
+    /// empty `src/deep/file.rs`.
+    fn project(configs: &[(&str, &str)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("src/deep")).unwrap();
+        fs::write(root.path().join("src/deep/file.rs"), "").unwrap();
+        for (dir, content) in configs {
+            fs::write(root.path().join(dir).join(PROJECT_CONFIG), content).unwrap();
+        }
+        root
+    }
+
+    const RUNS: &str = "theme = \"Nord\"\ntabs = 2\neditor-command = \"vi +{line} {file}\"\n[preprocessors]\n\"*.bin\" = { command = \"xxd\" }\n";
+
+    #[test]
+    fn the_closest_config_above_the_file_is_found() {
+        let root = project(&[("", "theme = \"Nord\""), ("src", "tabs = 3")]);
+        let found = ProjectConfig::discover(&root.path().join("src/deep/file.rs")).unwrap().unwrap();
+
+        assert_eq!(found.dir, fs::canonicalize(root.path().join("src")).unwrap());
+        assert_eq!((found.theme, found.tab_width), (None, Some(3)));
+        assert!(ProjectConfig::discover(&root.path().join("missing.rs")).unwrap().is_none());
+    }
+
+    #[test]
+    fn settings_are_read_and_what_runs_is_listed_for_trust() {
+        let root = project(&[("", RUNS)]);
+        let config = ProjectConfig::load(&root.path().join(PROJECT_CONFIG)).unwrap();
+
+        assert_eq!((config.theme.as_deref(), config.tab_width), (Some("Nord"), Some(2)));
+        assert_eq!(config.sha256, Sha256::digest(RUNS).iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+        assert_eq!(config.needs_trust(), ["preprocessor for '*.bin': xxd {file}", "editor command: vi +{line} {file}"]);
+        assert_eq!(config.relative(root.path().join("src/deep/file.rs").to_str().unwrap()), "src/deep/file.rs");
+
+        let theme_only = project(&[("", "theme = \"Nord\"\n[syntaxes]\n\"*.tpl\" = \"HTML\"\n")]);
+        assert!(ProjectConfig::load(&theme_only.path().join(PROJECT_CONFIG)).unwrap().needs_trust().is_empty());
+    }
+
+    #[test]
+    fn unknown_and_invalid_settings_are_errors() {
+        let error = |content: &str| {
+            let root = project(&[("", content)]);
+            format!("{:#}", ProjectConfig::load(&root.path().join(PROJECT_CONFIG)).err().unwrap())
+        };
+
+        assert!(error("pager = \"less\"").ends_with("Unknown setting 'pager'"), "{}", error("pager = \"less\""));
+        assert!(error("tabs = 0").ends_with("'tabs' must be a positive integer"));
+        assert!(error("[syntaxes]\n\"*.tpl\" = 1").ends_with("The syntax for '*.tpl' must be a string"));
+    }
+
+    #[test]
+    fn trust_is_kept_for_the_content_it_was_given_for() {
+        let root = project(&[("", RUNS)]);
+        let cache = tempfile::tempdir().unwrap();
+        let store_path = cache.path().join("ccat/trusted.toml");
+        let config = ProjectConfig::load(&root.path().join(PROJECT_CONFIG)).unwrap();
+
+        let mut store = TrustStore::load(Some(&store_path)).unwrap();
+        assert_eq!(store.trust_of(&config), Trust::Unknown);
+        store.trust(&config).unwrap();
+        assert_eq!(TrustStore::load(Some(&store_path)).unwrap().trust_of(&config), Trust::Trusted);
+
+        fs::write(root.path().join(PROJECT_CONFIG), format!("{}\n# edited\n", RUNS)).unwrap();
+        let edited = ProjectConfig::load(&root.path().join(PROJECT_CONFIG)).unwrap();
+        assert_eq!(TrustStore::load(Some(&store_path)).unwrap().trust_of(&edited), Trust::Changed);
+
+        // with nowhere to keep it nothing is trusted
+        let mut nowhere = TrustStore::load(None).unwrap();
+        assert_eq!(nowhere.trust_of(&config), Trust::Unknown);
+        assert!(nowhere.trust(&config).is_err());
+    }
+
+    #[test]
+    fn each_project_is_read_once_and_only_trusted_ones_run() {
+        let root = project(&[("src", RUNS)]);
+        fs::write(root.path().join("src/other.rs"), "").unwrap();
+        fs::write(root.path().join("outside.rs"), "").unwrap();
+        let files: Vec<String> = ["src/deep/file.rs", "src/other.rs", "outside.rs"].iter()
+            .map(|file| root.path().join(file).to_string_lossy().into_owned())
+            .collect();
+        let discover = |store: &TrustStore| Projects::discover(files.iter().map(|file| (file.as_str(), Path::new(file))), store).unwrap();
+
+        let untrusted = discover(&TrustStore::default());
+        assert_eq!(untrusted.configs.len(), 1);
+        assert_eq!(untrusted.for_file(&files[0]).map(|config| &config.path), untrusted.for_file(&files[1]).map(|config| &config.path));
+        assert!(untrusted.for_file(&files[2]).is_none());
+        assert!(untrusted.trusted_for_file(&files[0]).is_none());
+        assert_eq!(untrusted.untrusted().map(|(_, trust)| trust).collect::<Vec<_>>(), [Trust::Unknown]);
+
+        let cache = tempfile::tempdir().unwrap();
+        let mut store = TrustStore::load(Some(&cache.path().join("trusted.toml"))).unwrap();
+        store.trust(untrusted.for_file(&files[0]).unwrap()).unwrap();
+        let trusted = discover(&store);
+        assert!(trusted.trusted_for_file(&files[1]).is_some());
+        assert_eq!(trusted.untrusted().count(), 0);
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::dates::{self, DateFormat};
+use crate::OutputProfile;
+
+/// How much of a [`FileProvenance`] its footer shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProvenanceMode {
+    /// Path, size, modification time, digest and Git state
+    #[default]
+    Full,
+    /// Only the digest
+    HashOnly,
+}
+
+/// Which version of a file was shown, for proving it later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileProvenance {
+    pub path: PathBuf,
+    /// Bytes read, which the digest covers
+    pub size: u64,
+    /// Modification time as RFC 3339 in UTC, None where the platform has none
+    pub modified: Option<String>,
+    /// The modification time the footer shows in its own format
+    #[serde(skip)]
+    pub modified_time: Option<SystemTime>,
+    /// Lowercase hex SHA-256 of the bytes as read, before any decoding
+    pub sha256: String,
+    /// The repository's state for the file, None outside Git or without
+    /// the `git` feature
+    pub git: Option<GitState>,
+}
+
+/// The Git state of a file at the time it was shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GitState {
+    /// Full id of the commit HEAD points at
+    pub head: String,
+    /// Whether the working copy of the file differs from HEAD or isn't tracked
+    pub dirty: bool,
+}
+
+impl FileProvenance {
+    /// Describes `path`, whose `size` bytes hashed to `sha256` when read.
+    pub fn new(path: &str, size: u64, sha256: [u8; 32]) -> Result<Self> {
+        let absolute = std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to read file '{}'", path))?;
+        let modified_time = std::fs::metadata(&absolute)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        Ok(Self {
+            git: git_state(&absolute),
+            path: absolute,
+            size,
+            modified: modified_time.map(dates::rfc3339),
+            modified_time,
+            sha256: sha256.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        })
+    }
+
+    /// A dim footer showing what `mode` asks for, one `label value` per
+    /// line, with the modification time in `date_format`.
+    pub fn footer(&self, mode: ProvenanceMode, date_format: &DateFormat, profile: &OutputProfile) -> String {
+        let full = mode == ProvenanceMode::Full;
+        let mut fields = Vec::new();
+        if full {
+            fields.push(("path", self.path.display().to_string()));
+            fields.push(("size", format!("{} bytes", self.size)));
+            fields.extend(self.modified_time.map(|modified| ("modified", date_format.format(modified, SystemTime::now()))));
+        }
+        fields.push(("sha256", self.sha256.clone()));
+        if let Some(git) = self.git.as_ref().filter(|_| full) {
+            fields.push(("git", format!("{}, {}", git.head, if git.dirty { "dirty" } else { "clean" })));
+        }
+
+        let (dim, reset) = (profile.sgr("\x1b[2m"), profile.sgr("\x1b[0m"));
+        let label_width = fields.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let rule = profile.symbol("──", "--");
+        let mut footer = format!("{}{} provenance {}{}\n", dim, rule, rule, reset);
+        for (label, value) in fields {
+            footer.push_str(&format!("{}{:width$} {}{}\n", dim, label, value, reset, width = label_width));
+        }
+        footer
+    }
+}
+
+#[cfg(feature = "git")]
+fn git_state(path: &std::path::Path) -> Option<GitState> {
+    let repo = git2::Repository::discover(path.parent()?).ok()?;
+    let workdir = std::fs::canonicalize(repo.workdir()?).ok()?;
+    let relative = path.strip_prefix(workdir).ok()?;
+    let head = repo.head().ok()?.peel_to_commit().ok()?.id().to_string();
+    let status = repo.status_file(relative).ok()?;
+
+    Some(GitState { head, dirty: !status.is_empty() })
+}
+
+#[cfg(not(feature = "git"))]
+fn git_state(_path: &std::path::Path) -> Option<GitState> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn provenance(git: Option<GitState>) -> FileProvenance {
+        let modified_time = UNIX_EPOCH + Duration::from_secs(1_709_211_909);
+        FileProvenance {
+            path: PathBuf::from("/src/a.rs"),
+            size: 42,
+            modified: Some(dates::rfc3339(modified_time)),
+            modified_time: Some(modified_time),
+            sha256: "ab".repeat(32),
+            git,
+        }
+    }
+
+    #[test]
+    fn files_are_described_by_their_absolute_path_and_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        let path = dir.path().join(".").join("a.txt");
+        let mut digest = [0; 32];
+        digest[0] = 0xfe;
+        digest[31] = 0x01;
+
+        let provenance = FileProvenance::new(path.to_str().unwrap(), 2, digest).unwrap();
+        assert_eq!(provenance.path, std::fs::canonicalize(dir.path()).unwrap().join("a.txt"));
+        assert_eq!(provenance.size, 2);
+        assert_eq!(provenance.sha256, format!("fe{}01", "0".repeat(60)));
+        assert!(provenance.modified.unwrap().ends_with('Z'));
+
+        let missing = dir.path().join("missing");
+        let error = FileProvenance::new(missing.to_str().unwrap(), 0, digest).unwrap_err().to_string();
+        assert_eq!(error, format!("Failed to read file '{}'", missing.display()));
+    }
+
+    #[test]
+    fn the_footer_aligns_what_the_mode_asks_for() {
+        let git = GitState { head: "c0ffee".to_string(), dirty: true };
+        let full = provenance(Some(git)).footer(ProvenanceMode::Full, &DateFormat::Iso, &OutputProfile::DUMB);
+        assert_eq!(full, format!(concat!(
+            "-- provenance --\n",
+            "path     /src/a.rs\n",
+            "size     42 bytes\n",
+            "modified 2024-02-29T13:05:09Z\n",
+            "sha256   {}\n",
+            "git      c0ffee, dirty\n",
+        ), "ab".repeat(32)));
+
+        let hash_only = provenance(None).footer(ProvenanceMode::HashOnly, &DateFormat::Iso, &OutputProfile::FULL);
+        assert_eq!(hash_only, format!("\x1b[2m── provenance ──\x1b[0m\n\x1b[2msha256 {}\x1b[0m\n", "ab".repeat(32)));
+    }
+
+    #[test]
+    fn provenance_serializes_without_the_raw_time() {
+        let json = serde_json::to_value(provenance(None)).unwrap();
+        assert_eq!(json["modified"], "2024-02-29T13:05:09Z");
+        assert_eq!(json["git"], serde_json::Value::Null);
+        assert!(json.get("modified_time").is_none());
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn git_state_tells_clean_from_dirty() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("ccat", "ccat@example.com").unwrap();
+        let head = repo.commit(Some("HEAD"), &signature, &signature, "a", &tree, &[]).unwrap();
+
+        let state = |name: &str| git_state(&std::fs::canonicalize(dir.path().join(name)).unwrap());
+        assert_eq!(state("a.txt"), Some(GitState { head: head.to_string(), dirty: false }));
+        std::fs::write(dir.path().join("a.txt"), "b\n").unwrap();
+        assert!(state("a.txt").unwrap().dirty);
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+        assert!(state("new.txt").unwrap().dirty);
+
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("b.txt"), "b\n").unwrap();
+        assert_eq!(git_state(&std::fs::canonicalize(outside.path().join("b.txt")).unwrap()), None);
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// A pair of marker patterns delimiting a region of lines.
+///
+/// The marker lines themselves are not part of the region.
+pub struct Region {
+    pub start: Regex,
+    pub end: Regex,
+}
+
+impl Region {
+    /// Parses a `START_PATTERN:END_PATTERN` spec. The split happens on the
+    /// first `:`, so only the end pattern may contain colons.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (start, end) = spec.split_once(':')
+            .with_context(|| format!("Invalid region '{}', expected START_PATTERN:END_PATTERN", spec))?;
+
+        Ok(Self {
+            start: Regex::new(start)
+                .with_context(|| format!("Invalid start pattern '{}'", start))?,
+            end: Regex::new(end)
+                .with_context(|| format!("Invalid end pattern '{}'", end))?,
+        })
+    }
+}
+
+/// Tracks which lines fall inside a [`Region`] while walking a file top to bottom.
+#[derive(Clone)]
+pub(crate) struct RegionTracker<'a> {
+    region: &'a Region,
+    all_regions: bool,
+    inside: bool,
+    finished: bool,
+}
+
+impl<'a> RegionTracker<'a> {
+    pub(crate) fn new(region: &'a Region, all_regions: bool) -> Self {
+        Self {
+            region,
+            all_regions,
+            inside: false,
+            finished: false,
+        }
+    }
+
+    /// Feeds the next line and returns whether it should be shown.
+    pub(crate) fn accept(&mut self, line: &str) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        if self.inside {
+            if self.region.end.is_match(line) {
+                self.inside = false;
+                self.finished = !self.all_regions;
+                return false;
+            }
+            return true;
+        }
+
+        if self.region.start.is_match(line) {
+            self.inside = true;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+fn before() {}
+// BEGIN example
+fn first() {}
+// END example
+fn between() {}
+// BEGIN example
+fn second() {}
+// END example
+";
+
+    fn shown(spec: &str, all_regions: bool) -> Vec<&'static str> {
+        let region = Region::parse(spec).unwrap();
+        let mut tracker = RegionTracker::new(&region, all_regions);
+        SOURCE.lines().filter(|line| tracker.accept(line)).collect()
+    }
+
+    #[test]
+    fn shows_the_lines_between_the_markers() {
+        assert_eq!(shown("// BEGIN example:// END example", false), ["fn first() {}"]);
+    }
+
+    #[test]
+    fn all_regions_keeps_going_after_the_first() {
+        assert_eq!(shown("BEGIN:END", true), ["fn first() {}", "fn second() {}"]);
+    }
+
+    #[test]
+    fn an_unclosed_region_runs_to_the_end() {
+        let region = Region::parse("^start$:^end$").unwrap();
+        let mut tracker = RegionTracker::new(&region, false);
+        let shown: Vec<_> = ["a", "start", "b", "c\n"].into_iter().filter(|line| tracker.accept(line)).collect();
+        assert_eq!(shown, ["b", "c\n"]);
+    }
+
+    #[test]
+    fn only_the_end_pattern_may_contain_colons() {
+        let region = Region::parse("a:b:c").unwrap();
+        assert_eq!(region.start.as_str(), "a");
+        assert_eq!(region.end.as_str(), "b:c");
+        assert!(Region::parse("no-colon").is_err());
+        assert!(Region::parse("(:x").is_err());
+    }
+}
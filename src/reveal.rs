@@ -0,0 +1,206 @@
+use serde::Serialize;
+use std::ops::Range;
+use syntect::highlighting::Style;
+use syntect::parsing::Scope;
+
+use crate::OutputProfile;
+
+/// Where `--reveal-strings` spells out invisible and confusable characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reveal {
+    /// Only in string literals
+    Strings,
+    /// In string literals and comments
+    Comments,
+}
+
+impl Reveal {
+    /// The scopes the characters are revealed inside of.
+    pub(crate) fn scopes(self) -> Vec<Scope> {
+        let mut scopes = vec![Scope::new("string").expect("valid scope")];
+        if self == Self::Comments {
+            scopes.push(Scope::new("comment").expect("valid scope"));
+        }
+        scopes
+    }
+}
+
+/// A highlighted line whose hidden characters were spelled out.
+pub(crate) struct Revealed {
+    text: String,
+    spans: Vec<(Style, Range<usize>)>,
+}
+
+impl Revealed {
+    pub(crate) fn ranges(&self) -> Vec<(Style, &str)> {
+        self.spans.iter()
+            .map(|(style, range)| (*style, &self.text[range.clone()]))
+            .collect()
+    }
+}
+
+/// How a character is spelled out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shown {
+    /// A space that isn't one: this symbol in its place, then its code point
+    Space,
+    /// A tab: this symbol in its place
+    Tab,
+    /// A character that takes no room: only its code point
+    Invisible,
+    /// A character that looks like another: itself, then its code point
+    Confusable,
+}
+
+/// Letters of other scripts drawn like Latin ones, confused with them when
+/// typed or pasted into a Latin word.
+const CONFUSABLE_LETTERS: &str = "аеорсухіјѕԁԛԝһӏАВЕКМНОРСТХЅІЈοΑΒΕΖΗΙΚΜΝΟΡΤΥΧνϲⅰⅼ";
+
+/// Whether `line` may hold anything [`reveal`] spells out, so most lines
+/// skip it.
+pub(crate) fn may_reveal(line: &str) -> bool {
+    line.contains(|char: char| char == '\t' || !char.is_ascii())
+}
+
+/// Spells out the characters of `ranges`, a highlighted line, that no one
+/// would see or tell apart from others, inside the byte `spans` of the line
+/// alone. A space other than the plain one becomes `⍽` and its code point,
+/// a tab becomes `⇥`, a character that takes no room becomes its code point
+/// alone, and a letter of another script inside a Latin word stays, followed
+/// by its code point. What is added is in the `marker` color over the
+/// background of the text around it, and is measured like any other text.
+pub(crate) fn reveal(ranges: &[(Style, &str)], spans: &[Range<usize>], marker: Style, profile: &OutputProfile) -> Revealed {
+    let line: String = ranges.iter().map(|(_, text)| *text).collect();
+    let shown: Vec<(usize, char, Shown)> = line.char_indices()
+        .filter(|(index, _)| spans.iter().any(|span| span.contains(index)))
+        .filter_map(|(index, char)| Some((index, char, shown(&line, index, char)?)))
+        .collect();
+
+    let mut text = String::with_capacity(line.len() + shown.len() * 10);
+    let mut spans = Vec::with_capacity(ranges.len() + shown.len() * 2);
+    let mut push = |style: Style, piece: &str| {
+        if !piece.is_empty() {
+            let start = text.len();
+            text.push_str(piece);
+            spans.push((style, start..text.len()));
+        }
+    };
+
+    let mut shown = shown.into_iter().peekable();
+    let mut offset = 0;
+    for (style, range) in ranges {
+        let marker = Style { foreground: marker.foreground, ..*style };
+        let end = offset + range.len();
+        let mut plain = offset;
+        while let Some((index, char, how)) = shown.next_if(|(index, _, _)| *index < end) {
+            push(*style, &line[plain..index]);
+            let tag = format!("{}U+{:04X}{}", profile.symbol("⟨", "<"), u32::from(char), profile.symbol("⟩", ">"));
+            match how {
+                Shown::Space => {
+                    push(marker, profile.symbol("⍽", "_"));
+                    push(marker, &tag);
+                }
+                Shown::Tab => push(marker, profile.symbol("⇥", ">")),
+                Shown::Invisible => push(marker, &tag),
+                Shown::Confusable => {
+                    push(*style, &line[index..index + char.len_utf8()]);
+                    push(marker, &tag);
+                }
+            }
+            plain = index + char.len_utf8();
+        }
+        push(*style, &line[plain..end]);
+        offset = end;
+    }
+
+    Revealed { text, spans }
+}
+
+/// How the `char` at byte `index` of `line` is spelled out, None when it
+/// shows as it is.
+fn shown(line: &str, index: usize, char: char) -> Option<Shown> {
+    match char {
+        '\t' => Some(Shown::Tab),
+        '\u{a0}' | '\u{1680}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => Some(Shown::Space),
+        // zero-width characters, the soft hyphen, the byte order mark and the
+        // bidirectional controls that can reorder what follows
+        '\u{ad}' | '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{feff}' => Some(Shown::Invisible),
+        // fullwidth forms of ASCII and the minus sign, never what was meant in code
+        '\u{ff01}'..='\u{ff5e}' | '\u{2212}' => Some(Shown::Confusable),
+        char if CONFUSABLE_LETTERS.contains(char) => {
+            let before = line[..index].chars().next_back();
+            let after = line[index + char.len_utf8()..].chars().next();
+            [before, after].into_iter().flatten().any(|char| char.is_ascii_alphabetic())
+                .then_some(Shown::Confusable)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::Color;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0, a: 255 };
+
+    /// `line` as one range, revealed in the bytes of `span`, as plain text.
+    fn revealed(line: &str, span: Range<usize>, profile: &OutputProfile) -> String {
+        let marker = Style { foreground: RED, ..Style::default() };
+        let revealed = reveal(&[(Style::default(), line)], &[span], marker, profile);
+        revealed.ranges().iter().map(|(_, text)| *text).collect()
+    }
+
+    #[test]
+    fn hidden_characters_are_spelled_out() {
+        let all = |line: &str| revealed(line, 0..line.len(), &OutputProfile::FULL);
+        assert_eq!(all("a\u{a0}b"), "a⍽⟨U+00A0⟩b");
+        assert_eq!(all("a\tb"), "a⇥b");
+        assert_eq!(all("ab\u{200b}c\u{feff}"), "ab⟨U+200B⟩c⟨U+FEFF⟩");
+        assert_eq!(all("\u{202e}evil"), "⟨U+202E⟩evil");
+        assert_eq!(all("１−2"), "１⟨U+FF11⟩−⟨U+2212⟩2");
+        assert_eq!(revealed("a\u{a0}\tb", 0..5, &OutputProfile::ASCII), "a_<U+00A0>>b");
+    }
+
+    #[test]
+    fn letters_of_other_scripts_are_only_revealed_inside_latin_words() {
+        let all = |line: &str| revealed(line, 0..line.len(), &OutputProfile::FULL);
+        // a Cyrillic а in "pаssword"
+        assert_eq!(all("p\u{430}ssword"), "p\u{430}⟨U+0430⟩ssword");
+        assert_eq!(all("\u{430}"), "\u{430}");
+        assert_eq!(all("привет мир"), "привет мир");
+        assert_eq!(all("naïve café"), "naïve café");
+    }
+
+    #[test]
+    fn only_the_spans_are_touched() {
+        let line = "x\u{a0}= \"a\u{a0}b\"";
+        let string = line.find('"').unwrap()..line.len();
+        assert_eq!(revealed(line, string, &OutputProfile::FULL), "x\u{a0}= \"a⍽⟨U+00A0⟩b\"");
+        assert!(!may_reveal("plain ascii"));
+        assert!(may_reveal("tab\there"));
+    }
+
+    #[test]
+    fn markers_take_the_marker_color_over_the_text_around_them() {
+        let background = Color { r: 0, g: 0, b: 40, a: 255 };
+        let text = Style { background, ..Style::default() };
+        let marker = Style { foreground: RED, ..Style::default() };
+        let span = 0..5;
+        let revealed = reveal(&[(text, "a\u{200b}b")], &[span], marker, &OutputProfile::FULL);
+
+        let ranges = revealed.ranges();
+        assert_eq!(ranges.iter().map(|(_, text)| *text).collect::<Vec<_>>(), ["a", "⟨U+200B⟩", "b"]);
+        assert_eq!(ranges[1].0.foreground, RED);
+        assert_eq!(ranges[1].0.background, background);
+        assert_eq!(ranges[0].0, text);
+    }
+
+    #[test]
+    fn comments_are_revealed_on_request() {
+        let names = |reveal: Reveal| reveal.scopes().iter().map(|scope| scope.build_string()).collect::<Vec<_>>();
+        assert_eq!(names(Reveal::Strings), ["string"]);
+        assert_eq!(names(Reveal::Comments), ["string", "comment"]);
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::str::FromStr;
+use syntect::highlighting::{Color, ScopeSelectors, StyleModifier, Theme, ThemeItem};
+
+use crate::Rgb;
+
+/// Recolors the tokens matched by a scope selector, on top of the theme.
+#[derive(Debug, Clone)]
+pub struct ScopeOverride {
+    pub selector: String,
+    pub color: Rgb,
+    selectors: ScopeSelectors,
+}
+
+impl ScopeOverride {
+    /// Parses a `SELECTOR=#RRGGBB` spec such as `comment=#888888` or
+    /// `string.quoted, constant.numeric=#c0ffee`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (selector, color) = spec.rsplit_once('=')
+            .with_context(|| format!("Invalid scope override '{}', expected SELECTOR=#RRGGBB", spec))?;
+
+        Ok(Self {
+            selectors: ScopeSelectors::from_str(selector.trim())
+                .with_context(|| format!("Invalid scope selector '{}'", selector))?,
+            selector: selector.trim().to_string(),
+            color: color.trim().parse().map_err(anyhow::Error::msg)?,
+        })
+    }
+}
+
+impl Serialize for ScopeOverride {
+    /// As the `SELECTOR=#RRGGBB` spec it parses from.
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}={}", self.selector, self.color))
+    }
+}
+
+/// Returns `theme` with the overrides applied, borrowing it when there are none.
+///
+/// syntect keeps the first of several equally specific rules, so the
+/// overrides go in front of the theme's own rules, the last one given first.
+pub(crate) fn apply<'a>(theme: &'a Theme, overrides: &[ScopeOverride]) -> Cow<'a, Theme> {
+    if overrides.is_empty() {
+        return Cow::Borrowed(theme);
+    }
+
+    let mut theme = theme.clone();
+    let items = overrides.iter().rev().map(|scope_override| ThemeItem {
+        scope: scope_override.selectors.clone(),
+        style: StyleModifier {
+            foreground: Some(Color::from(scope_override.color)),
+            ..StyleModifier::default()
+        },
+    });
+    theme.scopes.splice(0..0, items);
+
+    Cow::Owned(theme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::{Highlighter, ThemeSet};
+    use syntect::parsing::Scope;
+
+    fn foreground(theme: &Theme, scope: &str) -> Color {
+        Highlighter::new(theme).style_for_stack(&[Scope::new(scope).unwrap()]).foreground
+    }
+
+    #[test]
+    fn specs_are_split_at_the_last_equals_sign() {
+        let scope_override = ScopeOverride::parse(" string.quoted, constant.numeric = #C0FFEE ").unwrap();
+        assert_eq!(scope_override.selector, "string.quoted, constant.numeric");
+        assert_eq!(serde_json::to_string(&scope_override).unwrap(), "\"string.quoted, constant.numeric=#c0ffee\"");
+
+        assert!(ScopeOverride::parse("comment").is_err());
+        assert!(ScopeOverride::parse("comment=red").is_err());
+    }
+
+    #[test]
+    fn later_overrides_win_and_other_scopes_keep_their_colors() {
+        let theme = &ThemeSet::load_defaults().themes["base16-ocean.dark"];
+        let overrides = [ScopeOverride::parse("comment=#111111").unwrap(), ScopeOverride::parse("comment.line=#222222").unwrap(), ScopeOverride::parse("comment=#333333").unwrap()];
+        let overridden = apply(theme, &overrides);
+
+        assert_eq!(foreground(&overridden, "comment.block.rust"), Color { r: 0x33, g: 0x33, b: 0x33, a: 0xff });
+        // the more specific selector still beats the later one
+        assert_eq!(foreground(&overridden, "comment.line.double-slash.rust"), Color { r: 0x22, g: 0x22, b: 0x22, a: 0xff });
+        assert_eq!(foreground(&overridden, "string.quoted.double.rust"), foreground(theme, "string.quoted.double.rust"));
+        assert!(matches!(apply(theme, &[]), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use syntect::highlighting::{Highlighter, Style};
+use syntect::parsing::{ParseState, ScopeStack};
+use syntect::util::LinesWithEndings;
+
+use crate::width::display_width;
+use crate::{gutter, HighlighterConfig, SyntaxHighlighter};
+
+/// Columns the tokens of [`ScopeLayout::Columns`] are padded to at most;
+/// longer ones push their scopes further right.
+const MAX_TOKEN_WIDTH: usize = 24;
+
+/// How `--show-scopes` lays out the scopes of each token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeLayout {
+    /// A row per token: where it is, its text, then its scopes
+    Columns,
+    /// Each line as highlighted, then a row per token marking it with
+    /// carets and giving its scopes, as a syntax test does
+    Interleaved,
+}
+
+/// A token of a line and the scopes the parser had it in.
+struct Token<'a> {
+    /// Byte offset into the line
+    start: usize,
+    text: &'a str,
+    style: Style,
+    scopes: String,
+}
+
+impl SyntaxHighlighter {
+    /// Lists the tokens of `content` with the scope stack syntect's parser
+    /// gives each, outermost first, for debugging syntax definitions and the
+    /// themes that color them. The tokens are in the theme's colors; the
+    /// whitespace between them is left out.
+    pub fn show_scopes(&self, content: &str, file_path: &str, config: &HighlighterConfig, layout: ScopeLayout) -> Result<String> {
+        let (syntax, _) = self.resolve_syntax(content, file_path, config)?;
+        let theme = self.resolve_theme(config)?;
+        let highlighter = Highlighter::new(&theme);
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(content) {
+            let ops = state.parse_line(line, &self.syntax_set)?;
+            let text = line.trim_end_matches(['\n', '\r']);
+            let mut tokens = Vec::new();
+            let mut push = |start: usize, end: usize, stack: &ScopeStack| {
+                let token = &text[start..end];
+                let start = start + token.len() - token.trim_start().len();
+                let token = token.trim();
+                if !token.is_empty() {
+                    let scopes: Vec<String> = stack.as_slice().iter().map(|scope| scope.build_string()).collect();
+                    tokens.push(Token { start, text: token, style: highlighter.style_for_stack(stack.as_slice()), scopes: scopes.join(" ") });
+                }
+            };
+
+            let mut start = 0;
+            for (at, op) in &ops {
+                // the ops popping at the line ending belong to no token
+                let at = (*at).min(text.len());
+                if at > start {
+                    push(start, at, &stack);
+                    start = at;
+                }
+                stack.apply(op)?;
+            }
+            push(start, text.len(), &stack);
+            lines.push((text, tokens));
+        }
+
+        // tabs are printed as they are, so they stop where the terminal's do
+        let tab_width = gutter::TERMINAL_TAB_WIDTH;
+        let ambiguous = config.profile.ambiguous_width;
+        let column = |text: &str, token: &Token| display_width(&text[..token.start], tab_width, ambiguous);
+        let colored = |token: &Token, text: &str| format!("{}{}", config.profile.escape(&[(token.style, text)], false), config.profile.sgr("\x1b[0m"));
+        let dim = |text: &str| format!("{}{}{}", config.profile.sgr("\x1b[2m"), text, config.profile.sgr("\x1b[0m"));
+
+        let mut result = String::new();
+        match layout {
+            ScopeLayout::Columns => {
+                let positions: Vec<Vec<String>> = lines.iter().enumerate()
+                    .map(|(index, (text, tokens))| tokens.iter()
+                        .map(|token| format!("{}:{}", config.line_offset + index + 1, column(text, token) + 1))
+                        .collect())
+                    .collect();
+                let position_width = positions.iter().flatten().map(String::len).max().unwrap_or(0);
+                let token_width = lines.iter().flat_map(|(_, tokens)| tokens)
+                    .map(|token| display_width(&token.text.replace('\t', " "), tab_width, ambiguous))
+                    .max().unwrap_or(0)
+                    .min(MAX_TOKEN_WIDTH);
+
+                for ((_, tokens), positions) in lines.iter().zip(&positions) {
+                    for (token, position) in tokens.iter().zip(positions) {
+                        let text = token.text.replace('\t', " ");
+                        let padding = token_width.saturating_sub(display_width(&text, tab_width, ambiguous));
+                        result.push_str(&format!(
+                            "{}  {}{}  {}\n",
+                            dim(&format!("{:>width$}", position, width = position_width)), colored(token, &text), " ".repeat(padding), dim(&token.scopes),
+                        ));
+                    }
+                }
+            }
+            ScopeLayout::Interleaved => {
+                for (text, tokens) in &lines {
+                    let mut shown = 0;
+                    for token in tokens {
+                        result.push_str(&text[shown..token.start]);
+                        result.push_str(&colored(token, token.text));
+                        shown = token.start + token.text.len();
+                    }
+                    result.push_str(&text[shown..]);
+                    result.push('\n');
+
+                    for token in tokens {
+                        let carets = display_width(token.text, tab_width, ambiguous).max(1);
+                        result.push_str(&format!("{}{} {}\n", " ".repeat(column(text, token)), "^".repeat(carets), dim(&token.scopes)));
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OutputProfile;
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    fn scopes(content: &str, layout: ScopeLayout) -> String {
+        let config = HighlighterConfig { profile: OutputProfile::DUMB, ..HighlighterConfig::default() };
+        HIGHLIGHTER.show_scopes(content, "main.rs", &config, layout).unwrap()
+    }
+
+    #[test]
+    fn each_token_gets_its_position_and_scope_stack() {
+        let rows = scopes("fn main() {\n    if x { return; }\n}\n", ScopeLayout::Columns);
+        let row = |token: &str| rows.lines().find(|row| row.split_whitespace().nth(1) == Some(token)).unwrap();
+
+        assert!(row("fn").starts_with(" 1:1  fn      source.rust "), "{}", rows);
+        assert!(row("fn").ends_with(" storage.type.function.rust"), "{}", rows);
+        assert!(row("if").starts_with(" 2:5  if      "), "{}", rows);
+        assert!(row("if").ends_with(" keyword.control.rust"), "{}", rows);
+        assert!(row("return").ends_with(" keyword.control.rust"), "{}", rows);
+        assert!(row("main").ends_with(" entity.name.function.rust"), "{}", rows);
+        // whitespace is no token
+        assert_eq!(rows.lines().count(), 12);
+    }
+
+    #[test]
+    fn interleaved_rows_mark_tokens_under_the_line_with_carets() {
+        let rows = scopes("\tfn 日本() {}\n", ScopeLayout::Interleaved);
+        let mut lines = rows.lines();
+
+        assert_eq!(lines.next(), Some("\tfn 日本() {}"));
+        assert!(lines.next().unwrap().starts_with(&format!("{}^^ source.rust ", " ".repeat(8))), "{}", rows);
+        // wide characters take two carets each
+        assert!(lines.next().unwrap().starts_with(&format!("{}^^^^ source.rust ", " ".repeat(11))), "{}", rows);
+    }
+
+    #[test]
+    fn tokens_are_in_the_theme_colors() {
+        let config = HighlighterConfig { profile: OutputProfile { colors: true, ..OutputProfile::DUMB }, ..HighlighterConfig::default() };
+        let rows = HIGHLIGHTER.show_scopes("fn main() {}\n", "main.rs", &config, ScopeLayout::Columns).unwrap();
+        let fn_row = rows.lines().next().unwrap();
+
+        // positions are padded to the widest, 1:11
+        assert!(fn_row.starts_with("\x1b[2m 1:1\x1b[0m  \x1b[38;2;180;142;173mfn\x1b[0m    \x1b[2msource.rust "), "{:?}", fn_row);
+        assert!(fn_row.ends_with("storage.type.function.rust\x1b[0m"), "{:?}", fn_row);
+    }
+}
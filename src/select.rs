@@ -0,0 +1,282 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+/// How `len` is compared against a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    fn holds(self, left: usize, right: usize) -> bool {
+        match self {
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+        }
+    }
+}
+
+/// A per-line filter such as `len > 120 and not matches /^\s*\/\//`.
+///
+/// Lines are tested without their line ending. `len` counts characters.
+/// `and` binds tighter than `or`; `&&`, `||` and `!` work as well.
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Len(Comparison, usize),
+    Matches(Regex),
+    Blank,
+    Not(Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let tokens = tokenize(expression)
+            .with_context(|| format!("Invalid selection '{}'", expression))?;
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+
+        let predicate = parser.or()
+            .with_context(|| format!("Invalid selection '{}'", expression))?;
+        if let Some(token) = parser.peek() {
+            bail!("Invalid selection '{}': unexpected {}", expression, token);
+        }
+
+        Ok(predicate)
+    }
+
+    /// Whether `line` is selected.
+    pub fn matches(&self, line: &str) -> bool {
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        match self {
+            Self::Len(comparison, length) => comparison.holds(line.chars().count(), *length),
+            Self::Matches(regex) => regex.is_match(line),
+            Self::Blank => line.trim().is_empty(),
+            Self::Not(inner) => !inner.matches(line),
+            Self::And(left, right) => left.matches(line) && right.matches(line),
+            Self::Or(left, right) => left.matches(line) || right.matches(line),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(usize),
+    Pattern(String),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "'{}'", word),
+            Self::Number(number) => write!(f, "'{}'", number),
+            Self::Pattern(pattern) => write!(f, "'/{}/'", pattern),
+            Self::Compare(_) => write!(f, "comparison"),
+            Self::And => write!(f, "'and'"),
+            Self::Or => write!(f, "'or'"),
+            Self::Not => write!(f, "'not'"),
+            Self::Open => write!(f, "'('"),
+            Self::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '/' => {
+                let mut pattern = String::new();
+                loop {
+                    match chars.next() {
+                        Some('/') => break,
+                        // `\/` is a literal slash; other escapes are left for the regex
+                        Some('\\') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            pattern.push('/');
+                        }
+                        Some(c) => pattern.push(c),
+                        None => bail!("unterminated pattern '/{}'", pattern),
+                    }
+                }
+                Token::Pattern(pattern)
+            }
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    bail!("expected '{0}{0}'", c);
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '<' | '>' | '=' | '!' => {
+                let equals = chars.next_if_eq(&'=').is_some();
+                match (c, equals) {
+                    ('<', false) => Token::Compare(Comparison::Less),
+                    ('<', true) => Token::Compare(Comparison::LessOrEqual),
+                    ('>', false) => Token::Compare(Comparison::Greater),
+                    ('>', true) => Token::Compare(Comparison::GreaterOrEqual),
+                    ('=', true) => Token::Compare(Comparison::Equal),
+                    ('!', true) => Token::Compare(Comparison::NotEqual),
+                    ('!', false) => Token::Not,
+                    _ => bail!("expected '=='"),
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                Token::Number(digits.parse().with_context(|| format!("number '{}' is too large", digits))?)
+            }
+            c if c.is_alphabetic() => {
+                let mut word = c.to_string();
+                while let Some(letter) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    word.push(letter);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+            other => bail!("unexpected character '{}'", other),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<Predicate> {
+        let mut predicate = self.and()?;
+        while self.eat(&Token::Or) {
+            predicate = Predicate::Or(Box::new(predicate), Box::new(self.and()?));
+        }
+        Ok(predicate)
+    }
+
+    fn and(&mut self) -> Result<Predicate> {
+        let mut predicate = self.unary()?;
+        while self.eat(&Token::And) {
+            predicate = Predicate::And(Box::new(predicate), Box::new(self.unary()?));
+        }
+        Ok(predicate)
+    }
+
+    fn unary(&mut self) -> Result<Predicate> {
+        if self.eat(&Token::Not) {
+            return Ok(Predicate::Not(Box::new(self.unary()?)));
+        }
+
+        if self.eat(&Token::Open) {
+            let predicate = self.or()?;
+            if !self.eat(&Token::Close) {
+                bail!("missing ')'");
+            }
+            return Ok(predicate);
+        }
+
+        match self.next().cloned() {
+            Some(Token::Word(word)) => match word.as_str() {
+                "blank" => Ok(Predicate::Blank),
+                "len" => match (self.next().cloned(), self.next().cloned()) {
+                    (Some(Token::Compare(comparison)), Some(Token::Number(length))) => Ok(Predicate::Len(comparison, length)),
+                    _ => bail!("expected a comparison and a number after 'len'"),
+                },
+                "matches" => match self.next().cloned() {
+                    Some(Token::Pattern(pattern)) => Ok(Predicate::Matches(Regex::new(&pattern)
+                        .with_context(|| format!("invalid pattern '/{}/'", pattern))?)),
+                    _ => bail!("expected a /pattern/ after 'matches'"),
+                },
+                _ => bail!("unknown test '{}', expected len, matches or blank", word),
+            },
+            Some(token) => bail!("unexpected {}", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selects(expression: &str, line: &str) -> bool {
+        Predicate::parse(expression).unwrap().matches(line)
+    }
+
+    #[test]
+    fn len_counts_characters_without_the_line_ending() {
+        assert!(selects("len > 3", "héllo\n"));
+        assert!(selects("len == 5", "héllo\r\n"));
+        assert!(!selects("len >= 6", "héllo\n"));
+        assert!(selects("len != 0", "x"));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // true or (false and false)
+        assert!(selects("blank or len > 100 and matches /x/", "   "));
+        assert!(!selects("(blank or len > 100) and matches /x/", "   "));
+        assert!(selects("!blank && matches /TODO/ || len < 0", "// TODO"));
+        assert!(selects("not not blank", ""));
+    }
+
+    #[test]
+    fn patterns_can_hold_escaped_slashes() {
+        assert!(selects(r"matches /^\s*\/\//", "  // comment"));
+        assert!(!selects(r"matches /^\s*\/\//", "code // comment"));
+    }
+
+    #[test]
+    fn invalid_selections_are_errors() {
+        for expression in ["", "len", "len >", "matches TODO", "matches /(/", "length > 2", "blank blank", "(blank", "blank and"] {
+            assert!(Predicate::parse(expression).is_err(), "{}", expression);
+        }
+    }
+}
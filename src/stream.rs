@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::io::BufRead;
+use syntect::highlighting::{Highlighter, Theme};
+
+use crate::profile::Underline;
+use crate::{diagnostics, newline, HighlighterConfig, LinePipeline};
+
+/// Highlights the lines of a reader one at a time, see
+/// [`SyntaxHighlighter::highlight_reader`](crate::SyntaxHighlighter::highlight_reader).
+pub(crate) struct HighlightReader<'a, R> {
+    reader: R,
+    config: &'a HighlighterConfig,
+    theme: Cow<'a, Theme>,
+    pipeline: LinePipeline<'a>,
+    line_num: usize,
+    /// Rendered lines the pipeline let through and that are not yet yielded
+    ready: Vec<String>,
+    done: bool,
+}
+
+impl<'a, R: BufRead> HighlightReader<'a, R> {
+    pub(crate) fn new(reader: R, config: &'a HighlighterConfig, theme: Cow<'a, Theme>, pipeline: LinePipeline<'a>) -> Self {
+        Self { reader, config, theme, pipeline, line_num: 0, ready: Vec::new(), done: false }
+    }
+
+    /// Feeds the next line of the reader to the pipeline, or ends it at the
+    /// end of the input.
+    fn advance(&mut self) -> Result<()> {
+        let config = self.config;
+        let ready = &mut self.ready;
+        let mut emit = |number: usize, ranges: &[(syntect::highlighting::Style, &str)], _| {
+            ready.push(render_line(config, number, ranges));
+            Ok(())
+        };
+
+        let mut line = String::new();
+        let read = self.reader.read_line(&mut line)
+            .with_context(|| format!("Failed to read line {}", self.line_num + 1))?;
+        if read == 0 {
+            self.done = true;
+            return self.pipeline.finish(&mut emit);
+        }
+
+        // the highlighter borrows the theme, which this reader may own
+        let highlighter = Highlighter::new(&self.theme);
+        self.pipeline.line(&highlighter, self.line_num, &line, None, &mut emit)?;
+        self.line_num += 1;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for HighlightReader<'_, R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            if let Err(error) = self.advance() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+
+        (!self.ready.is_empty()).then(|| Ok(self.ready.remove(0)))
+    }
+}
+
+/// Line `number` as the pipeline let it through, rendered without the
+/// layout options.
+pub(crate) fn render_line(config: &HighlighterConfig, number: usize, ranges: &[(syntect::highlighting::Style, &str)]) -> String {
+    let underline = diagnostics::line_severity(&config.diagnostics, number)
+        .map(|severity| Underline::Curly(severity.color()))
+        .or(config.underline_urls.then_some(Underline::Straight));
+    let rendered = config.profile.escape_underlined(ranges, false, underline);
+    newline::normalize(rendered, config.line_endings)
+}
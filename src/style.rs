@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use syntect::highlighting::{Color, FontStyle};
+
+/// An opaque RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// The style of a highlighted piece of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Style {
+    pub fg: Rgb,
+    pub bg: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl FromStr for Rgb {
+    type Err = String;
+
+    /// Parses `#RRGGBB` or `#RGB`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid color '{}', expected #RRGGBB", value);
+        let hex = value.strip_prefix('#').filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit())).ok_or_else(invalid)?;
+        let digit = |index: usize, len: usize| u8::from_str_radix(&hex[index..index + len], 16).map_err(|_| invalid());
+
+        match hex.len() {
+            6 => Ok(Self(digit(0, 2)?, digit(2, 2)?, digit(4, 2)?)),
+            3 => Ok(Self(digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Serializes colors as the `#rrggbb` strings options take.
+pub(crate) fn serialize_hex<S: Serializer>(colors: &Option<Vec<Rgb>>, serializer: S) -> Result<S::Ok, S::Error> {
+    colors.as_ref()
+        .map(|colors| colors.iter().map(Rgb::to_string).collect::<Vec<_>>())
+        .serialize(serializer)
+}
+
+impl From<Color> for Rgb {
+    fn from(color: Color) -> Self {
+        Self(color.r, color.g, color.b)
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(rgb: Rgb) -> Self {
+        Self { r: rgb.0, g: rgb.1, b: rgb.2, a: 0xff }
+    }
+}
+
+impl From<syntect::highlighting::Style> for Style {
+    fn from(style: syntect::highlighting::Style) -> Self {
+        Self {
+            fg: style.foreground.into(),
+            // syntect marks "no background" with a fully transparent color
+            bg: (style.background.a != 0).then(|| style.background.into()),
+            bold: style.font_style.contains(FontStyle::BOLD),
+            italic: style.font_style.contains(FontStyle::ITALIC),
+            underline: style.font_style.contains(FontStyle::UNDERLINE),
+            strikethrough: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_parse_from_long_and_short_hex() {
+        assert_eq!("#1a2B3c".parse::<Rgb>(), Ok(Rgb(0x1a, 0x2b, 0x3c)));
+        assert_eq!("#f80".parse::<Rgb>(), Ok(Rgb(0xff, 0x88, 0x00)));
+        for invalid in ["", "#", "1a2b3c", "#1a2b3", "#1a2b3c4", "#ggg", "#aé12", "#aéfff", "#+1a2b3"] {
+            assert_eq!(invalid.parse::<Rgb>(), Err(format!("Invalid color '{}', expected #RRGGBB", invalid)));
+        }
+    }
+
+    #[test]
+    fn colors_display_as_they_parse() {
+        assert_eq!(Rgb(0x0a, 0xbc, 0xff).to_string(), "#0abcff");
+        assert_eq!(Rgb(1, 2, 3).to_string().parse::<Rgb>(), Ok(Rgb(1, 2, 3)));
+
+        #[derive(Serialize)]
+        struct Palette {
+            #[serde(serialize_with = "serialize_hex")]
+            colors: Option<Vec<Rgb>>,
+        }
+        let json = |colors| serde_json::to_string(&Palette { colors }).unwrap();
+        assert_eq!(json(Some(vec![Rgb(255, 0, 0), Rgb(0, 0, 1)])), r##"{"colors":["#ff0000","#000001"]}"##);
+        assert_eq!(json(None), r#"{"colors":null}"#);
+    }
+
+    #[test]
+    fn syntect_styles_convert_without_their_transparent_background() {
+        let syntect = syntect::highlighting::Style {
+            foreground: Color { r: 1, g: 2, b: 3, a: 0xff },
+            background: Color { r: 9, g: 9, b: 9, a: 0 },
+            font_style: FontStyle::BOLD | FontStyle::UNDERLINE,
+        };
+        let style = Style::from(syntect);
+        assert_eq!(style, Style { fg: Rgb(1, 2, 3), bg: None, bold: true, italic: false, underline: true, strikethrough: false });
+
+        let opaque = Style::from(syntect::highlighting::Style { background: Color { a: 0x80, ..syntect.background }, ..syntect });
+        assert_eq!(opaque.bg, Some(Rgb(9, 9, 9)));
+        assert_eq!(Color::from(Rgb(1, 2, 3)), Color { r: 1, g: 2, b: 3, a: 0xff });
+    }
+
+    #[test]
+    fn styles_serialize_with_named_fields() {
+        let style = Style { fg: Rgb(1, 2, 3), bg: None, bold: false, italic: true, underline: false, strikethrough: false };
+        let json = serde_json::to_string(&style).unwrap();
+        assert_eq!(json, r#"{"fg":[1,2,3],"bg":null,"bold":false,"italic":true,"underline":false,"strikethrough":false}"#);
+        assert_eq!(serde_json::from_str::<Style>(&json).unwrap(), style);
+    }
+}
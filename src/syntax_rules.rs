@@ -0,0 +1,118 @@
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobMatcher};
+use std::path::Path;
+
+/// A `GLOB=SYNTAX` rule forcing a syntax for matching files.
+pub struct SyntaxRule {
+    pub pattern: String,
+    pub syntax: String,
+    matcher: GlobMatcher,
+}
+
+impl SyntaxRule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (pattern, syntax) = spec.rsplit_once('=')
+            .with_context(|| format!("Invalid syntax rule '{}', expected GLOB=SYNTAX", spec))?;
+
+        let matcher = Glob::new(pattern)
+            .with_context(|| format!("Invalid glob '{}' in syntax rule", pattern))?
+            .compile_matcher();
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            syntax: syntax.to_string(),
+            matcher,
+        })
+    }
+
+    /// Patterns without a `/` are matched against the file name only.
+    pub fn matches(&self, file_path: &str) -> bool {
+        let path = Path::new(file_path);
+
+        if self.pattern.contains('/') {
+            self.matcher.is_match(path)
+        } else {
+            path.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+/// An ordered set of [`SyntaxRule`]s.
+#[derive(Default)]
+pub struct SyntaxRules {
+    rules: Vec<SyntaxRule>,
+}
+
+impl SyntaxRules {
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Self> {
+        let rules = specs.iter()
+            .map(|spec| SyntaxRule::parse(spec.as_ref()))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SyntaxRule> {
+        self.rules.iter()
+    }
+
+    /// Returns the syntax forced for `file_path`, failing when rules naming
+    /// different syntaxes both match it.
+    pub fn resolve(&self, file_path: &str) -> Result<Option<&str>> {
+        let mut matched: Option<&SyntaxRule> = None;
+
+        for rule in self.rules.iter().filter(|rule| rule.matches(file_path)) {
+            match matched {
+                Some(previous) if previous.syntax != rule.syntax => bail!(
+                    "Syntax rules '{}={}' and '{}={}' both match '{}'",
+                    previous.pattern, previous.syntax, rule.pattern, rule.syntax, file_path
+                ),
+                Some(_) => {}
+                None => matched = Some(rule),
+            }
+        }
+
+        Ok(matched.map(|rule| rule.syntax.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_split_at_the_last_equals_sign() {
+        let rule = SyntaxRule::parse("weird=name.*=JSON").unwrap();
+        assert_eq!((rule.pattern.as_str(), rule.syntax.as_str()), ("weird=name.*", "JSON"));
+        assert!(rule.matches("dir/weird=name.txt"));
+
+        assert_eq!(SyntaxRule::parse("*.rs").err().unwrap().to_string(), "Invalid syntax rule '*.rs', expected GLOB=SYNTAX");
+        assert_eq!(SyntaxRule::parse("[x=Rust").err().unwrap().to_string(), "Invalid glob '[x' in syntax rule");
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name() {
+        let name = SyntaxRule::parse("*.conf=INI").unwrap();
+        assert!(name.matches("/etc/app/main.conf"));
+        assert!(name.matches("main.conf"));
+        assert!(!name.matches("main.conf.bak"));
+
+        let path = SyntaxRule::parse("config/*.txt=YAML").unwrap();
+        assert!(path.matches("config/a.txt"));
+        assert!(!path.matches("other/a.txt"));
+        assert!(!path.matches("a.txt"));
+    }
+
+    #[test]
+    fn rules_naming_different_syntaxes_for_one_file_collide() {
+        let rules = SyntaxRules::parse(&["*.h=C++", "include/*=C++", "vendor/*.h=C"]).unwrap();
+        assert_eq!(rules.resolve("include/a.h").unwrap(), Some("C++"));
+        assert_eq!(rules.resolve("src/a.c").unwrap(), None);
+        assert_eq!(
+            rules.resolve("vendor/zlib.h").unwrap_err().to_string(),
+            "Syntax rules '*.h=C++' and 'vendor/*.h=C' both match 'vendor/zlib.h'",
+        );
+        assert_eq!(rules.iter().map(|rule| rule.pattern.as_str()).collect::<Vec<_>>(), ["*.h", "include/*", "vendor/*.h"]);
+        assert!(SyntaxRules::parse(&["ok=Rust", "bad"]).is_err());
+    }
+}
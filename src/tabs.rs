@@ -0,0 +1,115 @@
+use std::ops::Range;
+use syntect::highlighting::{Color, Style};
+
+use crate::width::{grapheme_width, graphemes, AmbiguousWidth};
+
+/// A highlighted line whose tabs were replaced by spaces.
+pub(crate) struct Expanded {
+    text: String,
+    spans: Vec<(Style, Range<usize>)>,
+}
+
+impl Expanded {
+    pub(crate) fn ranges(&self) -> Vec<(Style, &str)> {
+        self.spans.iter()
+            .map(|(style, range)| (*style, &self.text[range.clone()]))
+            .collect()
+    }
+}
+
+/// Replaces each tab with spaces up to the next multiple of `tab_width`.
+/// With an `arrow`, the first column of each tab is the arrow instead, in
+/// its color over the background of the text around it, unless the arrow
+/// is wider than the tab.
+///
+/// Columns are counted from the first column of code, not from the start of
+/// the terminal row, so the gutter never shifts the tab stops.
+pub(crate) fn expand_tabs(ranges: &[(Style, &str)], tab_width: usize, arrow: Option<(Color, &str)>, ambiguous: AmbiguousWidth, escapes: bool) -> Expanded {
+    let tab_width = tab_width.max(1);
+    let arrow_width = arrow.map_or(0, |(_, arrow)| grapheme_width(arrow, ambiguous));
+    let mut text = String::new();
+    let mut spans = Vec::with_capacity(ranges.len());
+    let mut column = 0;
+
+    for &(style, piece) in ranges {
+        let mut start = text.len();
+
+        for (_, grapheme) in graphemes(piece, escapes) {
+            if grapheme == "\t" {
+                let mut spaces = tab_width - column % tab_width;
+                column += spaces;
+                if let Some((color, arrow)) = arrow.filter(|_| arrow_width <= spaces) {
+                    if start < text.len() {
+                        spans.push((style, start..text.len()));
+                    }
+                    text.push_str(arrow);
+                    spans.push((Style { foreground: color, ..style }, text.len() - arrow.len()..text.len()));
+                    start = text.len();
+                    spaces -= arrow_width;
+                }
+                text.extend(std::iter::repeat_n(' ', spaces));
+            } else {
+                text.push_str(grapheme);
+                column += grapheme_width(grapheme, ambiguous);
+            }
+        }
+
+        spans.push((style, start..text.len()));
+    }
+
+    Expanded { text, spans }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(line: &str, tab_width: usize, arrow: Option<(Color, &str)>) -> String {
+        let expanded = expand_tabs(&[(Style::default(), line)], tab_width, arrow, AmbiguousWidth::Narrow, false);
+        expanded.ranges().into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn tabs_stop_at_multiples_of_the_width() {
+        assert_eq!(expand("\tx", 4, None), "    x");
+        assert_eq!(expand("ab\tx", 4, None), "ab  x");
+        assert_eq!(expand("abcd\tx", 4, None), "abcd    x");
+        assert_eq!(expand("漢\tx", 4, None), "漢  x");
+    }
+
+    #[test]
+    fn arrows_take_the_first_column_of_a_tab() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let expanded = expand_tabs(&[(Style::default(), "a\tb")], 4, Some((red, "→")), AmbiguousWidth::Narrow, false);
+        let ranges = expanded.ranges();
+        assert_eq!(ranges.iter().map(|(_, text)| *text).collect::<String>(), "a→  b");
+        assert_eq!(ranges.iter().find(|(_, text)| *text == "→").map(|(style, _)| style.foreground), Some(red));
+        // an arrow wider than the tab is left out
+        assert_eq!(expand("abc\tx", 4, Some((red, "⇥⇥"))), "abc x");
+    }
+
+    #[test]
+    fn a_leading_tab_is_an_arrow_padded_to_the_stop() {
+        let gray = Color { r: 128, g: 128, b: 128, a: 255 };
+        assert_eq!(expand("\tx", 8, Some((gray, "→"))), "→       x");
+        assert_eq!(expand("\t\tx", 4, Some((gray, "→"))), "→   →   x");
+        // a wide arrow takes two of the tab's columns
+        let wide = expand_tabs(&[(Style::default(), "\tx")], 4, Some((gray, "→")), AmbiguousWidth::Wide, false);
+        assert_eq!(wide.ranges().into_iter().map(|(_, text)| text).collect::<String>(), "→  x");
+    }
+
+    #[test]
+    fn the_arrow_keeps_the_background_and_the_text_its_style() {
+        let gray = Color { r: 128, g: 128, b: 128, a: 255 };
+        let token = Style { foreground: Color { r: 1, g: 2, b: 3, a: 255 }, background: Color { r: 9, g: 9, b: 9, a: 255 }, ..Style::default() };
+        let other = Style { foreground: Color { r: 4, g: 5, b: 6, a: 255 }, ..token };
+        let expanded = expand_tabs(&[(token, "a\tb"), (other, "c")], 4, Some((gray, "→")), AmbiguousWidth::Narrow, false);
+
+        assert_eq!(expanded.ranges(), [
+            (token, "a"),
+            (Style { foreground: gray, ..token }, "→"),
+            (token, "  b"),
+            (other, "c"),
+        ]);
+    }
+}
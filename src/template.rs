@@ -0,0 +1,229 @@
+use anyhow::{bail, Context, Result};
+use serde::{Serialize, Serializer};
+
+use crate::width::visible_width;
+use crate::AmbiguousWidth;
+
+/// A `--output-template` such as `{ln:>5} {sep} {code}`, laying out each
+/// row of output in place of the gutter.
+///
+/// Placeholders are `{ln}`, the line number, blank on rows that aren't the
+/// first of a line; `{sep}`, the gutter delimiter; `{code}`, the highlighted
+/// row; `{path}`, the file as given; and `{lang}`, the syntax it is
+/// highlighted as. A placeholder may be padded to a width, left aligned by
+/// default, as in `{ln:>5}`, `{path:<20}` or `{lang:^8}`. `{{` and `}}` are
+/// literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    source: String,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field { field: Field, align: Align, width: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Ln,
+    Sep,
+    Code,
+    Path,
+    Lang,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// What a file's rows are rendered with, besides their own number and code.
+pub(crate) struct TemplateFields<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) lang: &'a str,
+    pub(crate) sep: String,
+    /// Ends the code's colors before the text after it
+    pub(crate) reset: &'a str,
+    pub(crate) ambiguous: AmbiguousWidth,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+
+        while let Some(char) = chars.next() {
+            match char {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let Some((placeholder, rest)) = chars.as_str().split_once('}') else {
+                        bail!("Unclosed '{{' in output template '{}'", template);
+                    };
+                    chars = rest.chars();
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(parse_placeholder(placeholder, template)?);
+                }
+                '}' => bail!("Unmatched '}}' in output template '{}', write '}}}}' for a brace", template),
+                char => text.push(char),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+
+        if !pieces.iter().any(|piece| matches!(piece, Piece::Field { field: Field::Code, .. })) {
+            bail!("Output template '{}' has no {{code}}", template);
+        }
+
+        Ok(Self { source: template.to_string(), pieces })
+    }
+
+    /// One row: `line` is the line number it starts, None for a row that
+    /// continues a line or isn't one, and `code` the row without its line
+    /// ending.
+    pub(crate) fn render(&self, line: Option<&str>, code: &str, fields: &TemplateFields) -> String {
+        let mut row = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => row.push_str(text),
+                Piece::Field { field, align, width } => {
+                    let value = match field {
+                        Field::Ln => line.unwrap_or_default(),
+                        Field::Sep => &fields.sep,
+                        Field::Code => code,
+                        Field::Path => fields.path,
+                        Field::Lang => fields.lang,
+                    };
+                    let padding = width.saturating_sub(visible_width(value, 1, fields.ambiguous));
+                    let (before, after) = match align {
+                        Align::Left => (0, padding),
+                        Align::Right => (padding, 0),
+                        Align::Center => (padding / 2, padding - padding / 2),
+                    };
+                    row.push_str(&" ".repeat(before));
+                    row.push_str(value);
+                    if *field == Field::Code && !code.is_empty() {
+                        row.push_str(fields.reset);
+                    }
+                    row.push_str(&" ".repeat(after));
+                }
+            }
+        }
+        row
+    }
+
+    /// Columns the template takes around the code, with `line` as the
+    /// widest line number.
+    pub(crate) fn width(&self, line: &str, fields: &TemplateFields) -> usize {
+        visible_width(&self.render(Some(line), "", fields), 1, fields.ambiguous)
+    }
+}
+
+fn parse_placeholder(placeholder: &str, template: &str) -> Result<Piece> {
+    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    let field = match name.trim() {
+        "ln" => Field::Ln,
+        "sep" => Field::Sep,
+        "code" => Field::Code,
+        "path" => Field::Path,
+        "lang" => Field::Lang,
+        name => bail!("Unknown placeholder '{{{}}}' in output template '{}', expected ln, sep, code, path or lang", name, template),
+    };
+
+    let (align, width) = match spec.chars().next() {
+        Some('<') => (Align::Left, &spec[1..]),
+        Some('>') => (Align::Right, &spec[1..]),
+        Some('^') => (Align::Center, &spec[1..]),
+        _ => (Align::Left, spec),
+    };
+    let width = match width {
+        "" => 0,
+        width => width.parse()
+            .with_context(|| format!("Invalid width '{}' for {{{}}} in output template '{}'", width, name.trim(), template))?,
+    };
+
+    Ok(Piece::Field { field, align, width })
+}
+
+impl Serialize for OutputTemplate {
+    /// As the template it was parsed from.
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> TemplateFields<'static> {
+        TemplateFields { path: "src/main.rs", lang: "Rust", sep: " | ".to_string(), reset: "", ambiguous: AmbiguousWidth::Narrow }
+    }
+
+    fn render(template: &str, line: Option<&str>, code: &str) -> String {
+        OutputTemplate::parse(template).unwrap().render(line, code, &fields())
+    }
+
+    #[test]
+    fn placeholders_are_filled_per_row() {
+        assert_eq!(render("{path}:{ln}: {code}", Some("7"), "fn main() {}"), "src/main.rs:7: fn main() {}");
+        assert_eq!(render("{lang}{sep}{code}", None, "x"), "Rust | x");
+        assert_eq!(render("{{{ln}}} {code}", Some("3"), "x"), "{3} x");
+    }
+
+    #[test]
+    fn placeholders_are_padded_and_aligned() {
+        assert_eq!(render("{ln:>4}|{code}", Some("12"), "x"), "  12|x");
+        assert_eq!(render("{ln:<4}|{code}", Some("12"), "x"), "12  |x");
+        assert_eq!(render("{ln:4}|{code}", Some("12"), "x"), "12  |x");
+        assert_eq!(render("{lang:^8}|{code}", Some("1"), "x"), "  Rust  |x");
+        // continuation rows leave the number blank but keep its width
+        assert_eq!(render("{ln:>4}|{code}", None, "x"), "    |x");
+    }
+
+    #[test]
+    fn the_code_ends_its_colors_before_the_text_after_it() {
+        let fields = TemplateFields { reset: "\x1b[0m", ..fields() };
+        let template = OutputTemplate::parse("{code}|").unwrap();
+        assert_eq!(template.render(None, "\x1b[1mx", &fields), "\x1b[1mx\x1b[0m|");
+        assert_eq!(template.render(None, "", &fields), "|");
+    }
+
+    #[test]
+    fn the_width_is_the_columns_around_the_code() {
+        let template = OutputTemplate::parse("{ln:>5}{sep}{code}").unwrap();
+        assert_eq!(template.width("100", &fields()), 8);
+        let template = OutputTemplate::parse("{path}:{ln}: {code}").unwrap();
+        assert_eq!(template.width("100", &fields()), "src/main.rs:100: ".len());
+    }
+
+    #[test]
+    fn invalid_templates_are_errors() {
+        let error = |template: &str| OutputTemplate::parse(template).unwrap_err().to_string();
+        assert!(error("{ln} {line} {code}").starts_with("Unknown placeholder '{line}'"));
+        assert!(error("{ln").starts_with("Unclosed '{'"));
+        assert!(error("{code} }").starts_with("Unmatched '}'"));
+        assert!(error("{ln}").ends_with("has no {code}"));
+        assert!(error("{ln:>five}{code}").starts_with("Invalid width 'five' for {ln}"));
+    }
+
+    #[test]
+    fn templates_serialize_as_written() {
+        let template = OutputTemplate::parse("{ln:>5} {code}").unwrap();
+        assert_eq!(serde_json::to_string(&template).unwrap(), "\"{ln:>5} {code}\"");
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use syntect::highlighting::Style;
+
+use crate::width::{display_width, grapheme_width, graphemes};
+use crate::{gutter, palette, scope_override, HighlighterConfig, LinePipeline, SyntaxHighlighter};
+
+/// What sits between the two columns.
+const SEPARATOR: (&str, &str) = (" │ ", " | ");
+
+impl SyntaxHighlighter {
+    /// Renders `content` under `config`'s theme and under `other_theme`
+    /// side by side, each line of the first next to the same line of the
+    /// second, for comparing themes. Each column takes half of `width`
+    /// and shows its theme's background; longer lines are cut off.
+    pub fn theme_diff(&self, content: &str, file_path: &str, config: &HighlighterConfig, other_theme: &str, width: usize) -> Result<String> {
+        let separator = config.profile.symbol(SEPARATOR.0, SEPARATOR.1);
+        let column_width = width.saturating_sub(display_width(separator, gutter::TERMINAL_TAB_WIDTH, config.profile.ambiguous_width)) / 2;
+        let tab_width = config.tab_width.unwrap_or(gutter::TERMINAL_TAB_WIDTH);
+
+        let left = self.resolve_theme(config)?;
+        let right = self.theme_set.themes.get(other_theme)
+            .with_context(|| format!("Theme '{}' not found", other_theme))?;
+        let right = palette::limit(scope_override::apply(right, &config.scope_overrides), config.max_colors);
+
+        let (syntax, _) = self.resolve_syntax(content, file_path, config)?;
+        let mut columns = [Vec::new(), Vec::new()];
+        for (rows, theme) in columns.iter_mut().zip([left.as_ref(), right.as_ref()]) {
+            let background = theme.settings.background.unwrap_or(syntect::highlighting::Color::BLACK);
+            let pipeline = LinePipeline::new(&self.syntax_set, syntax, config, theme);
+            self.highlight_each(content, file_path, pipeline, theme, None, |_, ranges, _| {
+                rows.push(fit_column(ranges, column_width, tab_width, background, config));
+                Ok(())
+            })?;
+        }
+
+        let [left_rows, right_rows] = columns;
+        let mut result = String::new();
+        for (left, right) in left_rows.iter().zip(&right_rows) {
+            result.push_str(&format!(
+                "{}{}{}{}{}\n",
+                left, config.profile.sgr("\x1b[2m"), separator, config.profile.sgr("\x1b[0m"), right,
+            ));
+        }
+
+        Ok(result)
+    }
+}
+
+/// Renders `ranges` exactly `width` columns wide, expanding tabs, cutting
+/// off the grapheme clusters that don't fit and padding the rest with the
+/// theme's `background`.
+fn fit_column(ranges: &[(Style, &str)], width: usize, tab_width: usize, background: syntect::highlighting::Color, config: &HighlighterConfig) -> String {
+    let mut fitted: Vec<(Style, String)> = Vec::new();
+    let mut used = 0;
+
+    'ranges: for (style, text) in ranges {
+        let mut piece = String::new();
+        for (_, grapheme) in graphemes(text, config.profile.zero_width_escapes).into_iter().filter(|(_, grapheme)| !grapheme.starts_with(['\n', '\r'])) {
+            let (expanded, grapheme_columns) = match grapheme {
+                "\t" => {
+                    let stop = (used / tab_width + 1) * tab_width;
+                    (" ".repeat(stop - used), stop - used)
+                }
+                grapheme => (grapheme.to_string(), grapheme_width(grapheme, config.profile.ambiguous_width)),
+            };
+            if used + grapheme_columns > width {
+                fitted.push((*style, piece));
+                break 'ranges;
+            }
+            piece.push_str(&expanded);
+            used += grapheme_columns;
+        }
+        fitted.push((*style, piece));
+    }
+
+    let padding = " ".repeat(width - used);
+    let plain = Style { background, ..Style::default() };
+    fitted.push((plain, padding));
+
+    let ranges: Vec<(Style, &str)> = fitted.iter().map(|(style, text)| (*style, text.as_str())).collect();
+    let mut column = config.profile.escape(&ranges, true);
+    column.push_str(config.profile.sgr("\x1b[0m"));
+    column
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::width::visible_width;
+    use crate::{gutter, HighlighterConfig, SyntaxHighlighter};
+    use std::sync::LazyLock;
+
+    static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+    #[test]
+    fn each_line_has_both_themes_side_by_side() {
+        let config = HighlighterConfig { theme: "base16-ocean.dark".to_string(), ..HighlighterConfig::default() };
+        let content = "// a comment much longer than the column it goes in\nfn main() {}\n\n";
+        let output = HIGHLIGHTER.theme_diff(content, "main.rs", &config, "InspiredGitHub", 41).unwrap();
+
+        let rows: Vec<&str> = output.lines().collect();
+        assert_eq!(rows.len(), 3);
+        for row in rows {
+            let (left, right) = row.split_once(" │ ").unwrap();
+            // the backgrounds of base16-ocean.dark and InspiredGitHub
+            assert!(left.contains("\x1b[48;2;43;48;59m") && !left.contains("\x1b[48;2;255;255;255m"), "{:?}", left);
+            assert!(right.contains("\x1b[48;2;255;255;255m") && !right.contains("\x1b[48;2;43;48;59m"), "{:?}", right);
+            assert_eq!(visible_width(row, gutter::TERMINAL_TAB_WIDTH, config.profile.ambiguous_width), 41, "{:?}", row);
+        }
+    }
+
+    #[test]
+    fn an_unknown_theme_is_an_error() {
+        let error = HIGHLIGHTER.theme_diff("x\n", "a.txt", &HighlighterConfig::default(), "Nope", 40).unwrap_err();
+        assert_eq!(error.to_string(), "Theme 'Nope' not found");
+    }
+}
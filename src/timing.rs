@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::time::Duration;
+
+const SLOWEST_LINES: usize = 3;
+
+/// Where the time went while rendering one file.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub read: Duration,
+    pub detect: Duration,
+    pub highlight: Duration,
+    pub write: Duration,
+    /// The slowest lines to highlight as `(line number, time)`, slowest first
+    pub slowest_lines: Vec<(usize, Duration)>,
+}
+
+impl Timings {
+    pub(crate) fn record_line(&mut self, line_number: usize, elapsed: Duration) {
+        if self.slowest_lines.len() == SLOWEST_LINES
+            && self.slowest_lines.last().is_some_and(|(_, slowest)| *slowest >= elapsed)
+        {
+            return;
+        }
+
+        let position = self.slowest_lines.iter()
+            .position(|(_, time)| *time < elapsed)
+            .unwrap_or(self.slowest_lines.len());
+        self.slowest_lines.insert(position, (line_number, elapsed));
+        self.slowest_lines.truncate(SLOWEST_LINES);
+    }
+
+    pub fn report(&self, path: &str) -> TimingReport {
+        TimingReport {
+            path: path.to_string(),
+            read_ms: millis(self.read),
+            detect_ms: millis(self.detect),
+            highlight_ms: millis(self.highlight),
+            write_ms: millis(self.write),
+            slowest_lines: self.slowest_lines.iter()
+                .map(|(line, time)| LineTiming { line: *line, ms: millis(*time) })
+                .collect(),
+        }
+    }
+}
+
+/// Serializable form of [`Timings`] for one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingReport {
+    pub path: String,
+    pub read_ms: f64,
+    pub detect_ms: f64,
+    pub highlight_ms: f64,
+    pub write_ms: f64,
+    pub slowest_lines: Vec<LineTiming>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineTiming {
+    pub line: usize,
+    pub ms: f64,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_three_slowest_lines_are_kept_slowest_first() {
+        let mut timings = Timings::default();
+        for (line, millis) in [(1, 5), (2, 1), (3, 9), (4, 7), (5, 2), (6, 7)] {
+            timings.record_line(line, Duration::from_millis(millis));
+        }
+
+        let lines: Vec<usize> = timings.slowest_lines.iter().map(|(line, _)| *line).collect();
+        // of two lines as slow, the first stays ahead
+        assert_eq!(lines, [3, 4, 6]);
+    }
+
+    #[test]
+    fn reports_are_in_milliseconds() {
+        let timings = Timings { highlight: Duration::from_micros(2500), slowest_lines: vec![(4, Duration::from_millis(2))], ..Timings::default() };
+        let report = timings.report("a.rs");
+
+        assert_eq!(report.highlight_ms, 2.5);
+        assert_eq!(report.slowest_lines[0].line, 4);
+        assert_eq!(report.slowest_lines[0].ms, 2.0);
+    }
+}
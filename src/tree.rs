@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which entries a [`TreeEntry::walk`] keeps, and how deep it goes.
+#[derive(Debug, Clone, Default)]
+pub struct TreeFilter {
+    include: Vec<GlobMatcher>,
+    exclude: Vec<GlobMatcher>,
+    max_depth: Option<usize>,
+}
+
+impl TreeFilter {
+    /// Keeps files matching any `include` glob, all of them when there are
+    /// none, and drops files and directories matching an `exclude` glob.
+    /// Globs without a `/` match the name only, others the path from the
+    /// root. Entries deeper than `max_depth` below the root aren't listed.
+    pub fn new(include: &[String], exclude: &[String], max_depth: Option<usize>) -> Result<Self> {
+        let compile = |patterns: &[String]| patterns.iter()
+            .map(|pattern| Glob::new(pattern)
+                .map(|glob| glob.compile_matcher())
+                .with_context(|| format!("Invalid glob '{}'", pattern)))
+            .collect::<Result<Vec<_>>>();
+
+        Ok(Self { include: compile(include)?, exclude: compile(exclude)?, max_depth })
+    }
+
+    fn keeps(&self, relative: &Path, is_dir: bool) -> bool {
+        let matches = |matchers: &[GlobMatcher]| matchers.iter().any(|matcher| {
+            matcher.is_match(relative) || relative.file_name().is_some_and(|name| matcher.is_match(name))
+        });
+
+        if matches(&self.exclude) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || matches(&self.include)
+    }
+}
+
+/// A file or directory found by [`TreeEntry::walk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeEntry {
+    File {
+        name: String,
+        path: PathBuf,
+    },
+    Directory {
+        name: String,
+        path: PathBuf,
+        children: Vec<TreeEntry>,
+        /// Whether `max_depth` stopped the walk here, so `children` is empty
+        truncated: bool,
+    },
+}
+
+impl TreeEntry {
+    /// Walks `root`, listing directories first and then files, each sorted
+    /// by name ignoring case. Hidden entries are skipped, and symbolic links
+    /// to directories aren't followed. Directories left with no files, such
+    /// as ones whose files the globs all drop, are left out.
+    pub fn walk(root: &Path, filter: &TreeFilter) -> Result<Self> {
+        let name = root.display().to_string();
+        if !root.is_dir() {
+            return Ok(Self::File { name, path: root.to_path_buf() });
+        }
+
+        Self::walk_directory(root, root, name, 0, filter)
+    }
+
+    fn walk_directory(root: &Path, path: &Path, name: String, depth: usize, filter: &TreeFilter) -> Result<Self> {
+        if filter.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Ok(Self::Directory { name, path: path.to_path_buf(), children: Vec::new(), truncated: true });
+        }
+
+        let mut children = Vec::new();
+        let entries = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory '{}'", path.display()))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("Failed to read directory '{}'", path.display()))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let child_path = entry.path();
+            let is_dir = entry.file_type()
+                .with_context(|| format!("Failed to read '{}'", child_path.display()))?
+                .is_dir();
+            let relative = child_path.strip_prefix(root).unwrap_or(&child_path);
+            if !filter.keeps(relative, is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                let child = Self::walk_directory(root, &child_path, name, depth + 1, filter)?;
+                if child.kept() {
+                    children.push(child);
+                }
+            } else {
+                children.push(Self::File { name, path: child_path });
+            }
+        }
+
+        children.sort_by_cached_key(|child| (matches!(child, Self::File { .. }), child.name().to_lowercase()));
+        Ok(Self::Directory { name, path: path.to_path_buf(), children, truncated: false })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::File { name, .. } | Self::Directory { name, .. } => name,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::File { path, .. } | Self::Directory { path, .. } => path,
+        }
+    }
+
+    /// The number of files listed at or below this entry.
+    pub fn file_count(&self) -> usize {
+        match self {
+            Self::File { .. } => 1,
+            Self::Directory { children, .. } => children.iter().map(Self::file_count).sum(),
+        }
+    }
+
+    /// The paths of the files listed at or below this entry, in the order
+    /// they are listed.
+    pub fn files(&self) -> Vec<&Path> {
+        match self {
+            Self::File { path, .. } => vec![path],
+            Self::Directory { children, .. } => children.iter().flat_map(Self::files).collect(),
+        }
+    }
+
+    /// Whether `max_depth` cut the walk short at or below this entry, so
+    /// there are more files than [`file_count`](Self::file_count).
+    pub fn is_truncated(&self) -> bool {
+        match self {
+            Self::File { .. } => false,
+            Self::Directory { children, truncated, .. } => *truncated || children.iter().any(Self::is_truncated),
+        }
+    }
+
+    fn kept(&self) -> bool {
+        self.file_count() > 0 || self.is_truncated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in [
+            ("src/lib.rs", "fn a() {}\n"), ("src/inner/b.py", "x = 1\n"), ("src/inner/c.min.js", "x\n"),
+            ("docs/README.md", "# T\n"), ("Zeta.txt", "z\n"), ("alpha.txt", "a\n"), (".git/config", "x\n"), ("empty/.keep", ""),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn listed(entry: &TreeEntry, root: &Path) -> Vec<String> {
+        entry.files().iter().map(|path| path.strip_prefix(root).unwrap().display().to_string()).collect()
+    }
+
+    #[test]
+    fn directories_come_first_then_files_ignoring_case() {
+        let dir = project();
+        let tree = TreeEntry::walk(dir.path(), &TreeFilter::default()).unwrap();
+
+        assert_eq!(listed(&tree, dir.path()), ["docs/README.md", "src/inner/b.py", "src/inner/c.min.js", "src/lib.rs", "alpha.txt", "Zeta.txt"]);
+        assert_eq!(tree.file_count(), 6);
+        assert!(!tree.is_truncated());
+
+        // hidden entries are skipped and directories left empty dropped
+        let TreeEntry::Directory { children, .. } = &tree else { panic!("a directory") };
+        let names: Vec<&str> = children.iter().map(TreeEntry::name).collect();
+        assert_eq!(names, ["docs", "src", "alpha.txt", "Zeta.txt"]);
+    }
+
+    #[test]
+    fn globs_match_names_or_paths_from_the_root() {
+        let dir = project();
+        let walk = |include: &[&str], exclude: &[&str]| {
+            let strings = |globs: &[&str]| globs.iter().map(|glob| glob.to_string()).collect::<Vec<_>>();
+            let filter = TreeFilter::new(&strings(include), &strings(exclude), None).unwrap();
+            listed(&TreeEntry::walk(dir.path(), &filter).unwrap(), dir.path())
+        };
+
+        assert_eq!(walk(&["*.txt"], &[]), ["alpha.txt", "Zeta.txt"]);
+        assert_eq!(walk(&[], &["inner", "*.md"]), ["src/lib.rs", "alpha.txt", "Zeta.txt"]);
+        assert_eq!(walk(&["src/**/*.js"], &[]), ["src/inner/c.min.js"]);
+        assert!(TreeFilter::new(&["[".to_string()], &[], None).is_err());
+    }
+
+    #[test]
+    fn max_depth_truncates_without_dropping_directories() {
+        let dir = project();
+        let tree = TreeEntry::walk(dir.path(), &TreeFilter::new(&[], &[], Some(1)).unwrap()).unwrap();
+
+        assert_eq!(listed(&tree, dir.path()), ["alpha.txt", "Zeta.txt"]);
+        assert!(tree.is_truncated());
+        let TreeEntry::Directory { children, .. } = &tree else { panic!("a directory") };
+        assert!(matches!(&children[0], TreeEntry::Directory { name, truncated: true, .. } if name == "docs"));
+    }
+
+    #[test]
+    fn a_file_root_is_a_single_file() {
+        let dir = project();
+        let file = dir.path().join("alpha.txt");
+        let tree = TreeEntry::walk(&file, &TreeFilter::default()).unwrap();
+        assert_eq!((tree.path(), tree.file_count()), (file.as_path(), 1));
+    }
+}
@@ -0,0 +1,210 @@
+const RESET: &str = "\x1b[0m";
+
+enum Token<'a> {
+    /// A CSI escape sequence such as an SGR color
+    Csi(&'a str),
+    /// Any other escape sequence, kept as is
+    Other(&'a str),
+    /// A visible character and whether a background color is active for it
+    Char(&'a str, bool),
+}
+
+/// Removes trailing whitespace and trailing escape sequences from every line of
+/// `rendered`, ending each colored line with a single reset. Whitespace painted
+/// with a background color is kept since it is visible.
+pub(crate) fn trim_output(rendered: &str) -> String {
+    let mut result = String::with_capacity(rendered.len());
+
+    for line in rendered.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[content.len()..];
+
+        result.push_str(&trim_line(content));
+        result.push_str(ending);
+    }
+
+    result
+}
+
+/// Returns the visible text of `line`, without any escape sequences.
+#[cfg(any(feature = "pager", test))]
+pub(crate) fn strip_escapes(line: &str) -> String {
+    tokenize(line).into_iter()
+        .filter_map(|token| match token {
+            Token::Char(text, _) => Some(text),
+            Token::Csi(_) | Token::Other(_) => None,
+        })
+        .collect()
+}
+
+/// `text` without the escape sequences after its last visible character.
+pub(crate) fn strip_trailing_escapes(text: &str) -> &str {
+    let mut end = 0;
+    let mut offset = 0;
+
+    for token in tokenize(text) {
+        match token {
+            Token::Csi(sequence) | Token::Other(sequence) => offset += sequence.len(),
+            Token::Char(char, _) => {
+                offset += char.len();
+                end = offset;
+            }
+        }
+    }
+
+    &text[..end]
+}
+
+fn trim_line(line: &str) -> String {
+    let mut tokens = tokenize(line);
+
+    while let Some(token) = tokens.last() {
+        match token {
+            Token::Csi(_) => {}
+            Token::Char(text, false) if text.chars().all(char::is_whitespace) => {}
+            _ => break,
+        }
+        tokens.pop();
+    }
+
+    let colored = tokens.iter().any(|token| matches!(token, Token::Csi(_)));
+
+    let mut result: String = tokens.iter()
+        .map(|token| match token {
+            Token::Csi(text) | Token::Other(text) | Token::Char(text, _) => *text,
+        })
+        .collect();
+
+    if colored {
+        result.push_str(RESET);
+    }
+
+    result
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut background = false;
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        if let Some(len) = csi_len(rest) {
+            let sequence = &rest[..len];
+            if sequence.ends_with('m') {
+                background = sgr_background(sequence, background);
+            }
+            tokens.push(Token::Csi(sequence));
+            rest = &rest[len..];
+        } else if let Some(len) = osc_len(rest).or_else(|| dcs_len(rest)) {
+            tokens.push(Token::Other(&rest[..len]));
+            rest = &rest[len..];
+        } else {
+            let len = rest.chars().next().map_or(1, char::len_utf8);
+            tokens.push(Token::Char(&rest[..len], background));
+            rest = &rest[len..];
+        }
+    }
+
+    tokens
+}
+
+/// Length of the escape sequence at the start of `text`, if there is one.
+pub(crate) fn escape_len(text: &str) -> Option<usize> {
+    csi_len(text).or_else(|| osc_len(text)).or_else(|| dcs_len(text))
+}
+
+/// Length of the CSI sequence at the start of `text`, if there is one.
+fn csi_len(text: &str) -> Option<usize> {
+    let body = text.strip_prefix("\x1b[")?;
+    let end = body.bytes().position(|byte| (0x40..=0x7e).contains(&byte))?;
+    Some(2 + end + 1)
+}
+
+/// Length of the OSC sequence at the start of `text`, if there is one.
+fn osc_len(text: &str) -> Option<usize> {
+    let body = text.strip_prefix("\x1b]")?;
+
+    if let Some(end) = body.find("\x1b\\") {
+        Some(2 + end + 2)
+    } else {
+        body.find('\x07').map(|end| 2 + end + 1)
+    }
+}
+
+/// Length of the DCS sequence at the start of `text`, such as a multiplexer
+/// passthrough, in which a doubled ESC doesn't end the sequence.
+fn dcs_len(text: &str) -> Option<usize> {
+    let body = text.strip_prefix("\x1bP")?.as_bytes();
+    let mut index = 0;
+
+    while index + 1 < body.len() {
+        match (body[index], body[index + 1]) {
+            (b'\x1b', b'\\') => return Some(2 + index + 2),
+            (b'\x1b', b'\x1b') => index += 2,
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+/// Returns whether a background color is active after applying `sequence`.
+fn sgr_background(sequence: &str, mut active: bool) -> bool {
+    let params = &sequence[2..sequence.len() - 1];
+    let mut params = params.split(';');
+
+    while let Some(param) = params.next() {
+        match param {
+            "" | "0" | "49" => active = false,
+            "48" => {
+                active = true;
+                // skip the color arguments so they aren't read as codes
+                match params.next() {
+                    Some("2") => { params.nth(2); }
+                    Some("5") => { params.next(); }
+                    _ => {}
+                }
+            }
+            "38" => match params.next() {
+                Some("2") => { params.nth(2); }
+                Some("5") => { params.next(); }
+                _ => {}
+            },
+            code => {
+                if let Ok(code) = code.parse::<u8>() {
+                    if (40..=47).contains(&code) || (100..=107).contains(&code) {
+                        active = true;
+                    }
+                }
+            }
+        }
+    }
+
+    active
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_spaces_and_escapes_are_removed() {
+        assert_eq!(trim_output("\x1b[38;2;1;2;3mfn\x1b[0m   \x1b[0m\nplain  \n"), "\x1b[38;2;1;2;3mfn\x1b[0m\nplain\n");
+    }
+
+    #[test]
+    fn whitespace_on_a_background_is_kept() {
+        assert_eq!(trim_output("\x1b[48;2;9;9;9mfn   \x1b[0m\n"), "\x1b[48;2;9;9;9mfn   \x1b[0m\n");
+        // the first argument of a truecolor foreground isn't a background code
+        assert_eq!(trim_output("\x1b[38;2;48;0;0mfn   \n"), "\x1b[38;2;48;0;0mfn\x1b[0m\n");
+    }
+
+    #[test]
+    fn line_endings_are_kept() {
+        assert_eq!(trim_output("a \r\nb\t\n\nc "), "a\r\nb\n\nc");
+    }
+
+    #[test]
+    fn escapes_after_the_last_character_are_stripped() {
+        assert_eq!(strip_trailing_escapes("\x1b[1mbold\x1b[0m\x1b]8;;\x1b\\"), "\x1b[1mbold");
+    }
+}
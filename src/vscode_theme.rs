@@ -0,0 +1,355 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::str::FromStr;
+use syntect::highlighting::{Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings};
+
+/// How deep `include`s, a theme building on another, may nest.
+const MAX_INCLUDES: usize = 8;
+
+/// Loads a VS Code color theme, the `.json` file a VS Code extension ships,
+/// as a syntect theme, with a warning for each part of it that couldn't be
+/// used.
+///
+/// The scopes of `tokenColors` are TextMate's, which syntect's syntaxes use
+/// too, so each entry becomes a theme item as it is. Of `colors`, the editor
+/// background and foreground, selection, cursor, line highlight and gutter
+/// colors are used, and the rest, which style VS Code itself, are ignored.
+/// Comments and trailing commas are allowed, as VS Code allows them, and an
+/// `include` loads the theme it names first, relative to this one.
+pub fn theme_from_vscode_json(path: &Path) -> Result<(Theme, Vec<String>)> {
+    let mut theme = Theme::default();
+    let mut warnings = Vec::new();
+    load(path, &mut theme, &mut warnings, 0)?;
+
+    if theme.scopes.is_empty() {
+        warnings.push(format!("theme '{}' colors no scopes", path.display()));
+    }
+    Ok((theme, warnings))
+}
+
+fn load(path: &Path, theme: &mut Theme, warnings: &mut Vec<String>, depth: usize) -> Result<()> {
+    if depth > MAX_INCLUDES {
+        bail!("Failed to load theme '{}': includes nest more than {} deep", path.display(), MAX_INCLUDES);
+    }
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to load theme '{}'", path.display()))?;
+    let json: Value = serde_json::from_str(&strip_jsonc(&source))
+        .with_context(|| format!("Failed to load theme '{}', which isn't valid JSON", path.display()))?;
+    let Some(json) = json.as_object() else {
+        bail!("Failed to load theme '{}': expected a JSON object", path.display());
+    };
+
+    if let Some(include) = json.get("include").and_then(Value::as_str) {
+        let included = path.parent().unwrap_or(Path::new("")).join(include);
+        load(&included, theme, warnings, depth + 1)?;
+    }
+    let mut warn = |message: String| warnings.push(format!("theme '{}': {}", path.display(), message));
+    if let Some(name) = json.get("name").and_then(Value::as_str) {
+        theme.name = Some(name.to_string());
+    }
+    if let Some(author) = json.get("author").and_then(Value::as_str) {
+        theme.author = Some(author.to_string());
+    }
+    if let Some(colors) = json.get("colors").and_then(Value::as_object) {
+        for (key, value) in colors {
+            let Some(field) = settings_field(&mut theme.settings, key) else {
+                continue;
+            };
+            match value.as_str().and_then(parse_color) {
+                Some(color) => *field = Some(color),
+                None => warn(format!("ignoring '{}', {} isn't a color", key, value)),
+            }
+        }
+    }
+
+    let token_colors = match json.get("tokenColors") {
+        Some(Value::Array(token_colors)) => token_colors.as_slice(),
+        Some(Value::String(file)) => {
+            warn(format!("ignoring tokenColors from '{}', only inline ones are read", file));
+            &[]
+        }
+        Some(_) => bail!("Failed to load theme '{}': tokenColors must be an array", path.display()),
+        None => &[],
+    };
+    // syntect lets the first of equally specific items win, VS Code the
+    // last, so this theme's entries go in backwards, before the included ones
+    let mut items = Vec::new();
+    for entry in token_colors {
+        let Some(entry) = entry.as_object() else {
+            warn(format!("ignoring tokenColors entry {}, expected an object", entry));
+            continue;
+        };
+        let settings = entry.get("settings").and_then(Value::as_object);
+        let style = style_modifier(settings, &mut warn);
+
+        match scope_selectors(entry.get("scope")) {
+            Ok(Some(scope)) => items.push(ThemeItem { scope, style }),
+            // an entry without a scope sets the default colors, as in a .tmTheme
+            Ok(None) => {
+                theme.settings.foreground = style.foreground.or(theme.settings.foreground);
+                theme.settings.background = style.background.or(theme.settings.background);
+            }
+            Err(scope) => warn(format!("ignoring scope '{}', which isn't a scope selector", scope)),
+        }
+    }
+    theme.scopes.splice(0..0, items.into_iter().rev());
+
+    if json.contains_key("semanticTokenColors") {
+        warn("ignoring semanticTokenColors, which need a language server".to_string());
+    }
+    Ok(())
+}
+
+/// The theme setting a `colors` key maps to, None for the keys that style
+/// VS Code's own interface.
+fn settings_field<'a>(settings: &'a mut ThemeSettings, key: &str) -> Option<&'a mut Option<Color>> {
+    Some(match key {
+        "editor.background" => &mut settings.background,
+        "editor.foreground" => &mut settings.foreground,
+        "editor.selectionBackground" => &mut settings.selection,
+        "editor.selectionForeground" => &mut settings.selection_foreground,
+        "editor.lineHighlightBackground" => &mut settings.line_highlight,
+        "editor.findMatchHighlightBackground" => &mut settings.find_highlight,
+        "editorCursor.foreground" => &mut settings.caret,
+        "editorGutter.background" => &mut settings.gutter,
+        "editorLineNumber.foreground" => &mut settings.gutter_foreground,
+        "editorBracketMatch.background" => &mut settings.brackets_background,
+        "editorIndentGuide.background" => &mut settings.guide,
+        _ => return None,
+    })
+}
+
+fn style_modifier(settings: Option<&Map<String, Value>>, warn: &mut impl FnMut(String)) -> StyleModifier {
+    let mut style = StyleModifier::default();
+    let Some(settings) = settings else {
+        return style;
+    };
+
+    for (key, field) in [("foreground", &mut style.foreground), ("background", &mut style.background)] {
+        if let Some(value) = settings.get(key) {
+            match value.as_str().and_then(parse_color) {
+                Some(color) => *field = Some(color),
+                None => warn(format!("ignoring {} {}, which isn't a color", key, value)),
+            }
+        }
+    }
+    if let Some(font_style) = settings.get("fontStyle").and_then(Value::as_str) {
+        let mut parsed = FontStyle::empty();
+        for word in font_style.split_whitespace() {
+            match word {
+                "bold" => parsed |= FontStyle::BOLD,
+                "italic" => parsed |= FontStyle::ITALIC,
+                "underline" => parsed |= FontStyle::UNDERLINE,
+                // the terminal output has no strikethrough
+                "strikethrough" | "normal" | "regular" => {}
+                word => warn(format!("ignoring font style '{}'", word)),
+            }
+        }
+        style.font_style = Some(parsed);
+    }
+    style
+}
+
+/// The selectors of a `scope`, which is a comma separated string or an
+/// array of them; None for an entry without one, and the scope that failed
+/// to parse otherwise.
+fn scope_selectors(scope: Option<&Value>) -> std::result::Result<Option<ScopeSelectors>, String> {
+    let scope = match scope {
+        None => return Ok(None),
+        Some(Value::String(scope)) => scope.clone(),
+        Some(Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "),
+        Some(scope) => return Err(scope.to_string()),
+    };
+    if scope.trim().is_empty() {
+        return Ok(None);
+    }
+
+    ScopeSelectors::from_str(&scope).map(Some).map_err(|_| scope)
+}
+
+/// A `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color.
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    let digits: Vec<u8> = hex.chars().map(|char| char.to_digit(16).map(|digit| digit as u8)).collect::<Option<_>>()?;
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|digit| digit * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+        _ => return None,
+    };
+
+    Some(Color { r: channels[0], g: channels[1], b: channels[2], a: channels.get(3).copied().unwrap_or(0xff) })
+}
+
+/// `source` without the comments and trailing commas JSON with comments
+/// allows, leaving strings alone.
+fn strip_jsonc(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => {
+                result.push(char);
+                while let Some(char) = chars.next() {
+                    result.push(char);
+                    match char {
+                        '\\' => result.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|char| *char != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for char in chars.by_ref() {
+                    if last == '*' && char == '/' {
+                        break;
+                    }
+                    last = char;
+                }
+                result.push(' ');
+            }
+            '}' | ']' => {
+                // a comma before the closing bracket, past any whitespace, is trailing
+                let kept = result.trim_end().len();
+                if result[..kept].ends_with(',') {
+                    result.truncate(kept - 1);
+                }
+                result.push(char);
+            }
+            char => result.push(char),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::{Highlighter, Style};
+    use syntect::parsing::Scope;
+
+    /// The style `theme` gives `scope`.
+    fn style(theme: &Theme, scope: &str) -> Style {
+        Highlighter::new(theme).style_for_stack(&[Scope::new(scope).unwrap()])
+    }
+
+    fn load_theme(source: &str) -> (Theme, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("theme.json");
+        std::fs::write(&path, source).unwrap();
+        theme_from_vscode_json(&path).unwrap()
+    }
+
+    #[test]
+    fn a_minimal_theme_colors_its_scopes() {
+        let (theme, warnings) = load_theme(r##"{
+            // comments and trailing commas are fine
+            "name": "Minimal",
+            "colors": { "editor.background": "#101010", "editor.foreground": "#eee", "activityBar.background": "#000" },
+            "tokenColors": [
+                { "scope": "comment", "settings": { "foreground": "#808080", "fontStyle": "italic" } },
+                { "scope": ["string", "constant.numeric"], "settings": { "foreground": "#00ff0080" } },
+                { "scope": "keyword, storage", "settings": { "foreground": "#f00", "fontStyle": "bold underline" } },
+            ],
+        }"##);
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(theme.name.as_deref(), Some("Minimal"));
+        assert_eq!(theme.settings.background, Some(Color { r: 0x10, g: 0x10, b: 0x10, a: 0xff }));
+        assert_eq!(theme.settings.foreground, Some(Color { r: 0xee, g: 0xee, b: 0xee, a: 0xff }));
+
+        let comment = style(&theme, "comment.line.double-slash.rust");
+        assert_eq!(comment.foreground, Color { r: 0x80, g: 0x80, b: 0x80, a: 0xff });
+        assert_eq!(comment.font_style, FontStyle::ITALIC);
+        assert_eq!(style(&theme, "constant.numeric.integer").foreground, Color { r: 0, g: 0xff, b: 0, a: 0x80 });
+        assert_eq!(style(&theme, "storage.type.rust").font_style, FontStyle::BOLD | FontStyle::UNDERLINE);
+        assert_eq!(style(&theme, "variable").foreground, Color { r: 0xee, g: 0xee, b: 0xee, a: 0xff });
+    }
+
+    #[test]
+    fn the_last_of_equally_specific_entries_wins() {
+        let (theme, _) = load_theme(r##"{ "tokenColors": [
+            { "scope": "comment", "settings": { "foreground": "#111111" } },
+            { "scope": "comment.line", "settings": { "foreground": "#222222" } },
+            { "scope": "comment", "settings": { "foreground": "#333333" } }
+        ] }"##);
+        assert_eq!(style(&theme, "comment.block").foreground, Color { r: 0x33, g: 0x33, b: 0x33, a: 0xff });
+        assert_eq!(style(&theme, "comment.line").foreground, Color { r: 0x22, g: 0x22, b: 0x22, a: 0xff });
+    }
+
+    #[test]
+    fn what_cant_be_used_is_warned_about() {
+        let (theme, warnings) = load_theme(r##"{
+            "colors": { "editor.background": "red" },
+            "tokenColors": [
+                "not an entry",
+                { "scope": "comment", "settings": { "foreground": "#12", "fontStyle": "wavy" } },
+                { "scope": 42, "settings": { "foreground": "#fff" } },
+                { "settings": { "foreground": "#abcdef" } }
+            ],
+            "semanticTokenColors": {}
+        }"##);
+
+        let messages: Vec<&str> = warnings.iter().map(|warning| warning.split_once("': ").unwrap().1).collect();
+        assert_eq!(messages, [
+            "ignoring 'editor.background', \"red\" isn't a color",
+            "ignoring tokenColors entry \"not an entry\", expected an object",
+            "ignoring foreground \"#12\", which isn't a color",
+            "ignoring font style 'wavy'",
+            "ignoring scope '42', which isn't a scope selector",
+            "ignoring semanticTokenColors, which need a language server",
+        ]);
+        // an entry without a scope sets the default colors
+        assert_eq!(theme.settings.foreground, Some(Color { r: 0xab, g: 0xcd, b: 0xef, a: 0xff }));
+    }
+
+    #[test]
+    fn includes_load_their_base_first() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("base")).unwrap();
+        std::fs::write(dir.path().join("base/dark.json"), r##"{
+            "name": "Base",
+            "tokenColors": [{ "scope": "comment", "settings": { "foreground": "#111111" } }, { "scope": "string", "settings": { "foreground": "#222222" } }]
+        }"##).unwrap();
+        std::fs::write(dir.path().join("theme.json"), r##"{
+            "name": "Derived",
+            "include": "base/dark.json",
+            "tokenColors": [{ "scope": "comment", "settings": { "foreground": "#333333" } }]
+        }"##).unwrap();
+
+        let (theme, _) = theme_from_vscode_json(&dir.path().join("theme.json")).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Derived"));
+        assert_eq!(style(&theme, "comment").foreground, Color { r: 0x33, g: 0x33, b: 0x33, a: 0xff });
+        assert_eq!(style(&theme, "string").foreground, Color { r: 0x22, g: 0x22, b: 0x22, a: 0xff });
+
+        std::fs::write(dir.path().join("loop.json"), r#"{ "include": "loop.json" }"#).unwrap();
+        let error = theme_from_vscode_json(&dir.path().join("loop.json")).unwrap_err().to_string();
+        assert!(error.ends_with("includes nest more than 8 deep"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_themes_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("theme.json");
+        let error = |source: &str| {
+            std::fs::write(&path, source).unwrap();
+            theme_from_vscode_json(&path).unwrap_err().to_string()
+        };
+        assert!(error("{ nope").ends_with("which isn't valid JSON"));
+        assert!(error("[]").ends_with("expected a JSON object"));
+        assert!(error(r#"{ "tokenColors": 3 }"#).ends_with("tokenColors must be an array"));
+        assert!(theme_from_vscode_json(&dir.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn comments_and_trailing_commas_are_stripped_outside_strings() {
+        assert_eq!(strip_jsonc("{\"a\": \"//x\", /* c */ \"b\": [1, 2, ], // end\n}"), "{\"a\": \"//x\",   \"b\": [1, 2]}");
+        assert_eq!(strip_jsonc(r#"{"a": "q\"/*"}"#), r#"{"a": "q\"/*"}"#);
+    }
+}
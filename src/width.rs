@@ -0,0 +1,183 @@
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::trim::escape_len;
+use crate::OutputProfile;
+
+/// How many columns East Asian ambiguous characters, such as box drawing,
+/// `±` or circled numbers, take. Terminals disagree: most draw them narrow,
+/// ones set up for CJK text wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+/// Columns one grapheme cluster takes on a terminal. A family joined with
+/// ZWJs, a flag or an emoji with a presentation selector takes two like any
+/// other wide character, combining marks add nothing to their base, and
+/// control characters take nothing.
+pub fn grapheme_width(grapheme: &str, ambiguous: AmbiguousWidth) -> usize {
+    if grapheme.chars().next().is_none_or(char::is_control) {
+        return 0;
+    }
+
+    match ambiguous {
+        AmbiguousWidth::Narrow => grapheme.width(),
+        AmbiguousWidth::Wide => grapheme.width_cjk(),
+    }
+}
+
+/// Columns `text` takes on a terminal, with tabs advancing to the next
+/// multiple of `tab_width`. All of ccat's width math goes through here or
+/// [`grapheme_width`], so wrapped, padded and aligned text agree.
+pub fn display_width(text: &str, tab_width: usize, ambiguous: AmbiguousWidth) -> usize {
+    let tab_width = tab_width.max(1);
+    text.graphemes(true).fold(0, |column, grapheme| match grapheme {
+        "\t" => (column / tab_width + 1) * tab_width,
+        grapheme => column + grapheme_width(grapheme, ambiguous),
+    })
+}
+
+/// Columns `text` takes once the terminal has drawn it: like
+/// [`display_width`], with CSI, SGR, OSC and DCS sequences taking none.
+/// Anything measuring text that may already hold escapes goes through here.
+pub fn visible_width(text: &str, tab_width: usize, ambiguous: AmbiguousWidth) -> usize {
+    let tab_width = tab_width.max(1);
+    graphemes(text, true).into_iter().fold(0, |column, (_, grapheme)| match grapheme {
+        "\t" => (column / tab_width + 1) * tab_width,
+        grapheme => column + grapheme_width(grapheme, ambiguous),
+    })
+}
+
+/// Columns the text of a file takes, with [`visible_width`] when `profile`
+/// says the escapes in it take none and [`display_width`] otherwise.
+pub(crate) fn measure(text: &str, tab_width: usize, profile: &OutputProfile) -> usize {
+    match profile.zero_width_escapes {
+        true => visible_width(text, tab_width, profile.ambiguous_width),
+        false => display_width(text, tab_width, profile.ambiguous_width),
+    }
+}
+
+/// The grapheme clusters of `text` with their byte offsets. With `escapes`,
+/// each escape sequence is one cluster of its own, which starts with a
+/// control character and so takes no columns.
+pub(crate) fn graphemes(text: &str, escapes: bool) -> Vec<(usize, &str)> {
+    if !escapes {
+        return text.grapheme_indices(true).collect();
+    }
+
+    let mut graphemes = Vec::new();
+    let mut offset = 0;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let len = escape_len(rest)
+            .or_else(|| rest.graphemes(true).next().map(str::len))
+            .unwrap_or(rest.len());
+        graphemes.push((offset, &rest[..len]));
+        offset += len;
+    }
+    graphemes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrap::{wrap_ranges, WrapMode};
+    use std::sync::LazyLock;
+    use syntect::highlighting::Style;
+
+    static HIGHLIGHTER: LazyLock<crate::SyntaxHighlighter> = LazyLock::new(crate::SyntaxHighlighter::new);
+
+    const FAMILY: &str = "👨\u{200d}👩\u{200d}👧\u{200d}👦";
+    const FLAG: &str = "🇳🇿";
+    const COMBINED: &str = "e\u{301}";
+
+    fn rows(line: &str, width: usize, mode: WrapMode) -> Vec<String> {
+        wrap_ranges(&[(Style::default(), line)], width, mode, AmbiguousWidth::Narrow, false).into_iter()
+            .map(|row| row.into_iter().map(|(_, text)| text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn clusters_take_the_columns_the_terminal_draws() {
+        assert_eq!(display_width(FAMILY, 4, AmbiguousWidth::Narrow), 2);
+        assert_eq!(display_width(FLAG, 4, AmbiguousWidth::Narrow), 2);
+        assert_eq!(display_width(COMBINED, 4, AmbiguousWidth::Narrow), 1);
+        assert_eq!(display_width("☀\u{fe0f}", 4, AmbiguousWidth::Narrow), 2);
+        assert_eq!(display_width("a\u{200b}b", 4, AmbiguousWidth::Narrow), 2);
+        assert_eq!(display_width("\u{7}x", 4, AmbiguousWidth::Narrow), 1);
+    }
+
+    #[test]
+    fn ambiguous_characters_follow_the_setting() {
+        assert_eq!(display_width("±─①", 4, AmbiguousWidth::Narrow), 3);
+        assert_eq!(display_width("±─①", 4, AmbiguousWidth::Wide), 6);
+        assert_eq!(display_width("abc", 4, AmbiguousWidth::Wide), 3);
+    }
+
+    #[test]
+    fn tabs_stop_at_multiples_of_the_width() {
+        assert_eq!(display_width("a\tb", 4, AmbiguousWidth::Narrow), 5);
+        assert_eq!(display_width(&format!("{}\t", FAMILY), 4, AmbiguousWidth::Narrow), 4);
+        assert_eq!(display_width("\t", 0, AmbiguousWidth::Narrow), 1);
+    }
+
+    #[test]
+    fn escapes_take_no_columns_when_visible() {
+        let text = format!("\x1b[1;31m{}\x1b[0m\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\", FLAG);
+        assert_eq!(visible_width(&text, 4, AmbiguousWidth::Narrow), 6);
+        assert_eq!(graphemes(&text, true)[0], (0, "\x1b[1;31m"));
+        assert!(display_width(&text, 4, AmbiguousWidth::Narrow) > 6);
+    }
+
+    #[test]
+    fn wrapping_counts_rows_by_cluster() {
+        // ten families are twenty columns, four to a row of nine
+        let families = FAMILY.repeat(10);
+        let wrapped = rows(&families, 9, WrapMode::Character);
+        assert_eq!(wrapped.len(), 3);
+        assert_eq!(wrapped.concat(), families);
+        assert!(wrapped.iter().all(|row| row.graphemes(true).all(|grapheme| grapheme == FAMILY)));
+
+        let accented = COMBINED.repeat(12);
+        let wrapped = rows(&accented, 5, WrapMode::Word);
+        assert_eq!(wrapped.iter().map(|row| display_width(row, 4, AmbiguousWidth::Narrow)).collect::<Vec<_>>(), [5, 5, 2]);
+        assert!(wrapped.iter().all(|row| row.starts_with('e')));
+    }
+
+    #[test]
+    fn no_cluster_is_split_at_a_row_end() {
+        let line = format!("ab{}{}c{}d{}", FLAG, FAMILY, COMBINED, FLAG);
+        for width in 1..=10 {
+            for mode in [WrapMode::Character, WrapMode::Word] {
+                let wrapped = rows(&line, width, mode);
+                assert_eq!(wrapped.concat(), line);
+                for row in &wrapped {
+                    // every row is whole clusters of the line
+                    let clusters: Vec<&str> = row.graphemes(true).collect();
+                    assert!(clusters.iter().all(|cluster| line.graphemes(true).any(|whole| whole == *cluster)), "{:?} at {}", row, width);
+                    assert!(display_width(row, 4, AmbiguousWidth::Narrow) <= width.max(2), "{:?} at {}", row, width);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn side_by_side_columns_cut_between_clusters() {
+        let config = crate::HighlighterConfig { profile: OutputProfile::DUMB, ..crate::HighlighterConfig::default() };
+        let content = format!("{}{}{}x\n", FAMILY, COMBINED, FLAG);
+
+        for width in 7..=13 {
+            let output = HIGHLIGHTER.theme_diff(&content, "a.txt", &config, "InspiredGitHub", width).unwrap();
+            let (left, _) = output.trim_end_matches('\n').split_once(" | ").unwrap();
+            let kept = left.trim_end_matches(' ');
+            assert!(content.starts_with(kept), "{:?} at {}", left, width);
+            assert!(kept.graphemes(true).all(|cluster| content.graphemes(true).any(|whole| whole == cluster)), "{:?} at {}", left, width);
+            assert_eq!(display_width(left, 4, AmbiguousWidth::Narrow), (width - 3) / 2);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use serde::Serialize;
+use syntect::highlighting::Style;
+
+use crate::width::{grapheme_width, graphemes, AmbiguousWidth};
+
+/// Where long lines may be broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WrapMode {
+    /// Break at exactly the available width
+    #[default]
+    Character,
+    /// Prefer breaking after whitespace and punctuation, falling back to
+    /// character breaks for runs longer than the available width
+    Word,
+}
+
+/// One grapheme cluster of a line being wrapped.
+struct Cell<'a> {
+    /// Byte offset into the line without its ending
+    offset: usize,
+    text: &'a str,
+    width: usize,
+}
+
+/// Splits a highlighted line into rows at most `width` columns wide, keeping
+/// each piece's style. Rows only break between grapheme clusters, so an emoji
+/// sequence or a letter with its accents is never split. The line ending
+/// stays on the last row. With `escapes`, escape sequences in the text take
+/// no columns and are never split.
+pub(crate) fn wrap_ranges<'a>(ranges: &[(Style, &'a str)], width: usize, mode: WrapMode, ambiguous: AmbiguousWidth, escapes: bool) -> Vec<Vec<(Style, &'a str)>> {
+    let width = width.max(1);
+    let line: String = ranges.iter()
+        .flat_map(|(_, text)| text.chars())
+        .filter(|c| *c != '\n' && *c != '\r')
+        .collect();
+    let cells: Vec<Cell> = graphemes(&line, escapes).into_iter()
+        .map(|(offset, text)| Cell { offset, text, width: grapheme_width(text, ambiguous) })
+        .collect();
+
+    let breaks = match mode {
+        WrapMode::Character => character_breaks(&cells, width),
+        WrapMode::Word => word_breaks(&cells, width),
+    };
+    let offsets: Vec<usize> = breaks.into_iter().map(|i| cells[i].offset).collect();
+
+    split_at(ranges, &offsets)
+}
+
+/// Returns the cell indices at which new rows start.
+fn character_breaks(cells: &[Cell], width: usize) -> Vec<usize> {
+    let mut breaks = Vec::new();
+    let mut row_width = 0;
+
+    for (i, cell) in cells.iter().enumerate() {
+        let w = cell.width;
+        if row_width + w > width && row_width > 0 {
+            breaks.push(i);
+            row_width = 0;
+        }
+        row_width += w;
+    }
+
+    breaks
+}
+
+fn word_breaks(cells: &[Cell], width: usize) -> Vec<usize> {
+    let mut breaks = Vec::new();
+    let mut start = 0;
+
+    loop {
+        // find how many cells fit on the row starting at `start`
+        let mut row_width = 0;
+        let mut end = start;
+        while end < cells.len() {
+            let w = cells[end].width;
+            if row_width + w > width && end > start {
+                break;
+            }
+            row_width += w;
+            end += 1;
+        }
+
+        if end >= cells.len() {
+            return breaks;
+        }
+
+        let candidate = (start + 1..=end)
+            .rev()
+            .find(|i| can_break_before(cells, *i))
+            .unwrap_or(end);
+
+        breaks.push(candidate);
+        start = candidate;
+    }
+}
+
+/// Whether a row may start at `cells[index]`.
+fn can_break_before(cells: &[Cell], index: usize) -> bool {
+    let previous = &cells[index - 1];
+    let next = cells.get(index);
+
+    previous.text.starts_with(char::is_whitespace)
+        || previous.text.starts_with(is_break_punctuation)
+        // ideographic text may be broken between any two characters
+        || previous.width > 1
+        || next.is_some_and(|cell| cell.width > 1)
+}
+
+fn is_break_punctuation(c: char) -> bool {
+    matches!(c, '-' | '/' | '\\' | '.' | ',' | ';' | ':' | '?' | '&' | '=' | ')' | ']' | '}' | '|')
+}
+
+/// Splits `ranges` into rows starting at the given byte offsets into the
+/// line without its ending.
+fn split_at<'a>(ranges: &[(Style, &'a str)], breaks: &[usize]) -> Vec<Vec<(Style, &'a str)>> {
+    let mut rows = vec![Vec::new()];
+    let mut breaks = breaks.iter().peekable();
+    let mut index = 0;
+
+    for &(style, text) in ranges {
+        let mut start = 0;
+
+        for (offset, c) in text.char_indices() {
+            if c == '\n' || c == '\r' {
+                continue;
+            }
+
+            if breaks.peek() == Some(&&index) {
+                breaks.next();
+                if start < offset {
+                    rows.last_mut().expect("rows is never empty").push((style, &text[start..offset]));
+                }
+                rows.push(Vec::new());
+                start = offset;
+            }
+            index += c.len_utf8();
+        }
+
+        if start < text.len() {
+            rows.last_mut().expect("rows is never empty").push((style, &text[start..]));
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(line: &str, width: usize, mode: WrapMode, escapes: bool) -> Vec<String> {
+        wrap_ranges(&[(Style::default(), line)], width, mode, AmbiguousWidth::Narrow, escapes).into_iter()
+            .map(|row| row.into_iter().map(|(_, text)| text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn an_unbreakable_run_falls_back_to_character_breaks() {
+        let line = "a".repeat(500);
+        let wrapped = rows(&line, 40, WrapMode::Word, false);
+        assert_eq!(wrapped.len(), 13);
+        assert!(wrapped[..12].iter().all(|row| row.len() == 40));
+        assert_eq!(wrapped[12].len(), 20);
+    }
+
+    #[test]
+    fn words_are_not_split() {
+        let line = "the quick brown fox jumps over the lazy dog and keeps running far away";
+        let wrapped = rows(line, 38, WrapMode::Word, false);
+        assert_eq!(wrapped, ["the quick brown fox jumps over the ", "lazy dog and keeps running far away"]);
+        assert_eq!(rows(line, 38, WrapMode::Character, false)[0], "the quick brown fox jumps over the laz");
+    }
+
+    #[test]
+    fn long_urls_break_after_punctuation() {
+        let line = "see https://example.com/a/very/long/path/to/some/resource/index.html?query=1";
+        let wrapped = rows(line, 40, WrapMode::Word, false);
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped[0], "see https://example.com/a/very/long/");
+        assert!(wrapped.iter().all(|row| row.chars().count() <= 40));
+        assert_eq!(wrapped.concat(), line);
+    }
+
+    #[test]
+    fn cjk_text_breaks_anywhere_without_splitting_characters() {
+        let line = "漢字".repeat(25);
+        let wrapped = rows(&line, 40, WrapMode::Word, false);
+        // 50 characters two columns wide, 20 to a row
+        assert_eq!(wrapped.iter().map(|row| row.chars().count()).collect::<Vec<_>>(), [20, 20, 10]);
+    }
+
+    #[test]
+    fn escapes_take_no_columns_and_are_never_split() {
+        let line = format!("{}\x1b]8;;https://example.com\x1b\\{}\x1b]8;;\x1b\\", "a".repeat(8), "b".repeat(8));
+        let wrapped = rows(&line, 10, WrapMode::Word, true);
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped.concat(), line);
+        assert_eq!(wrapped[0], format!("{}\x1b]8;;https://example.com\x1b\\bb", "a".repeat(8)), "{:?}", wrapped);
+    }
+
+    #[test]
+    fn the_line_ending_stays_on_the_last_row() {
+        let wrapped = rows("aaaa bbbb\n", 5, WrapMode::Word, false);
+        assert_eq!(wrapped, ["aaaa ", "bbbb\n"]);
+    }
+
+    #[test]
+    fn a_colored_line_breaks_at_its_visible_column() {
+        let line: String = (0..20).map(|index| format!("\x1b[3{}mx\x1b[0m", index % 8)).collect();
+        let wrapped = rows(&line, 10, WrapMode::Character, true);
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped.concat(), line);
+        assert!(wrapped.iter().all(|row| crate::width::visible_width(row, 4, AmbiguousWidth::Narrow) == 10), "{:?}", wrapped);
+
+        // counted as text, the escape bytes fill the rows far sooner
+        assert!(rows(&line, 10, WrapMode::Character, false).len() > 2);
+    }
+}
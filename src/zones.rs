@@ -0,0 +1,90 @@
+use crate::multiplexer::Escape;
+use crate::OutputProfile;
+
+/// OSC 133 marks that make each file a zone of its own, so a terminal's
+/// jump between commands stops at every file and its "select command
+/// output" takes exactly one.
+///
+/// A file's zone starts with `133;A` before its header, which the terminal
+/// takes as the prompt, its content starts with `133;C`, and `133;D` ends
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticZones {
+    start: String,
+    output: String,
+    end: String,
+}
+
+impl SemanticZones {
+    /// The marks as `profile` sends them, through its multiplexer's
+    /// passthrough, or None when it may not send them.
+    pub fn new(profile: &OutputProfile) -> Option<Self> {
+        if !profile.sends(Escape::SemanticZones) {
+            return None;
+        }
+
+        Some(Self {
+            start: profile.passthrough("\x1b]133;A\x07"),
+            output: profile.passthrough("\x1b]133;C\x07"),
+            end: profile.passthrough("\x1b]133;D\x07"),
+        })
+    }
+
+    /// Guesses from `$TERM`, `$TERM_PROGRAM` and `$LC_TERMINAL` whether the
+    /// terminal understands semantic zones: kitty, WezTerm, Ghostty and
+    /// iTerm2 do.
+    pub fn supported(term: Option<&str>, term_program: Option<&str>, lc_terminal: Option<&str>) -> bool {
+        match (term.unwrap_or(""), term_program.unwrap_or(""), lc_terminal.unwrap_or("")) {
+            (term, _, _) if term.contains("kitty") || term.contains("ghostty") => true,
+            (_, "WezTerm" | "ghostty" | "iTerm.app", _) | (_, _, "iTerm2") => true,
+            _ => false,
+        }
+    }
+
+    /// Reads the terminal from the environment, see [`supported`](Self::supported).
+    pub fn supported_by_env() -> bool {
+        let var = |name| std::env::var(name).ok();
+        Self::supported(var("TERM").as_deref(), var("TERM_PROGRAM").as_deref(), var("LC_TERMINAL").as_deref())
+    }
+
+    /// One file's zone: its `header`, then its `output`.
+    pub fn wrap(&self, header: &str, output: &str) -> String {
+        format!("{}{}{}{}{}", self.start, header, self.output, output, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplexer::Multiplexer;
+
+    #[test]
+    fn a_zone_marks_the_header_and_the_output() {
+        let zones = SemanticZones::new(&OutputProfile::FULL).unwrap();
+        assert_eq!(zones.wrap("==> a <==\n", "a\n"), "\x1b]133;A\x07==> a <==\n\x1b]133;C\x07a\n\x1b]133;D\x07");
+        assert_eq!(zones.wrap("", ""), "\x1b]133;A\x07\x1b]133;C\x07\x1b]133;D\x07");
+    }
+
+    #[test]
+    fn marks_go_through_the_multiplexer() {
+        let tmux = OutputProfile { multiplexer: Some(Multiplexer::Tmux), ..OutputProfile::FULL };
+        let zones = SemanticZones::new(&tmux).unwrap();
+        assert!(zones.wrap("", "").starts_with("\x1bPtmux;\x1b\x1b]133;A\x07\x1b\\"));
+    }
+
+    #[test]
+    fn a_profile_without_osc_sends_none() {
+        assert_eq!(SemanticZones::new(&OutputProfile::DUMB), None);
+        assert_eq!(SemanticZones::new(&OutputProfile { osc: false, ..OutputProfile::FULL }), None);
+    }
+
+    #[test]
+    fn supporting_terminals_are_told_from_the_environment() {
+        assert!(SemanticZones::supported(Some("xterm-kitty"), None, None));
+        assert!(SemanticZones::supported(Some("xterm-256color"), Some("WezTerm"), None));
+        assert!(SemanticZones::supported(Some("tmux-256color"), None, Some("iTerm2")));
+        assert!(SemanticZones::supported(Some("xterm-ghostty"), None, None));
+        assert!(!SemanticZones::supported(Some("xterm-256color"), Some("Apple_Terminal"), None));
+        assert!(!SemanticZones::supported(None, None, None));
+    }
+}
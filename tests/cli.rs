@@ -0,0 +1,1640 @@
+mod common;
+
+use common::{plain, stdout};
+
+#[test]
+fn between_shows_only_the_marked_region() {
+    let output = stdout(&["--between", "#region example:#endregion", &common::fixture("regions.rs")]);
+    assert_eq!(plain(&output), "fn example() {\n    println!(\"shown\");\n}\n");
+}
+
+#[test]
+fn a_theme_seed_picks_the_same_theme_every_run() {
+    let theme = |seed: &str| {
+        let output = common::run(&["--random-theme", "--theme-seed", seed, "-v", &common::fixture("regions.rs")]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        stderr.lines().find_map(|line| line.strip_prefix("theme: ")).expect("-v names the theme").to_string()
+    };
+    assert_eq!(theme("42"), theme("42"));
+}
+
+#[test]
+fn term_dumb_output_has_no_escapes() {
+    let sample = common::fixture("sample.rs");
+    let regions = common::fixture("regions.rs");
+    let output = common::ccat()
+        .env("TERM", "dumb")
+        .args(["-l", "--wrap=word", "--terminal-width", "30", "--underline-urls", &sample, &regions])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!output.stdout.contains(&0x1b), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("fn count"));
+}
+
+#[test]
+fn a_long_paragraph_wraps_to_six_rows_at_80_columns() {
+    let paragraph = common::fixture("paragraph.txt");
+    let gutters = |extra: &[&str]| -> Vec<String> {
+        let mut args = vec!["-l", "--wrap=word", "--terminal-width", "80"];
+        args.extend(extra);
+        args.push(&paragraph);
+        plain(&stdout(&args)).lines()
+            .map(|row| row.split('|').next().unwrap().trim().to_string())
+            .collect()
+    };
+
+    assert_eq!(gutters(&[]), ["1", "", "", "", "", ""]);
+    assert_eq!(gutters(&["--number-wrapped"]), ["1", "2", "3", "4", "5", "6"]);
+}
+
+#[test]
+fn trimmed_output_has_no_trailing_whitespace() {
+    let output = stdout(&["--trim-output", "-l", &common::fixture("trailing.rs")]);
+    for line in output.lines() {
+        assert!(!line.ends_with(char::is_whitespace), "{:?}", line);
+        assert!(!line.strip_suffix("\x1b[0m").unwrap_or(line).ends_with(char::is_whitespace), "{:?}", line);
+    }
+    assert_eq!(plain(&output).lines().count(), 3);
+}
+
+#[test]
+fn time_report_has_an_entry_per_file() {
+    let report = tempfile::NamedTempFile::new().unwrap();
+    let sample = common::fixture("sample.rs");
+    let regions = common::fixture("regions.rs");
+    stdout(&["--time-report", report.path().to_str().unwrap(), &sample, &regions]);
+
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(report.path()).unwrap()).unwrap();
+    let files = report.as_array().expect("the report is a list of files");
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["path"], sample.as_str());
+    assert!(files[1]["slowest_lines"].as_array().unwrap().len() <= 3);
+    assert!(files[1]["highlight_ms"].as_f64().is_some());
+}
+
+#[test]
+fn utf16_output_starts_with_a_bom() {
+    let output = tempfile::NamedTempFile::new().unwrap();
+    let status = common::ccat()
+        .args(["--output", output.path().to_str().unwrap(), "--output-encoding", "utf-16le", "--output-bom", &common::fixture("regions.rs")])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let bytes = std::fs::read(output.path()).unwrap();
+    assert_eq!(bytes[..2], [0xff, 0xfe]);
+    let units: Vec<u16> = bytes[2..].chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    assert!(plain(&String::from_utf16(&units).unwrap()).contains("fn example()"));
+}
+
+#[test]
+fn a_pinned_line_number_width_aligns_files_of_any_length() {
+    let mut long = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut long, (1..=150).map(|n| format!("{}\n", n)).collect::<String>().as_bytes()).unwrap();
+    let long = long.path().to_str().unwrap();
+    let short = common::fixture("regions.rs");
+
+    let output = plain(&stdout(&["-l", "--line-number-width", "5", &short, long]));
+    let bars: Vec<usize> = output.lines().filter_map(|line| line.find(" | ")).collect();
+    assert_eq!(bars.len(), 9 + 150);
+    assert!(bars.iter().all(|bar| *bar == 5), "{}", output);
+
+    let too_narrow = common::run(&["-l", "--line-number-width", "2", long]);
+    assert!(!too_narrow.status.success());
+    let overflowing = plain(&stdout(&["-l", "--line-number-width", "2", "--ln-overflow", long]));
+    assert!(overflowing.lines().last().unwrap().starts_with("150 | "));
+}
+
+#[test]
+fn an_unknown_extension_gets_a_plain_text_banner_on_stderr() {
+    let notes = common::fixture("notes.unknownext");
+    let warned = common::run(&["--warn-plain", &notes]);
+    let stderr = plain(&String::from_utf8(warned.stderr).unwrap());
+    assert!(stderr.contains("no syntax matched for .unknownext"), "{}", stderr);
+    // the banner stays out of the content
+    assert_eq!(plain(&String::from_utf8(warned.stdout).unwrap()), "some notes\n");
+
+    let piped = common::run(&[&notes]);
+    assert!(piped.stderr.is_empty(), "{}", String::from_utf8_lossy(&piped.stderr));
+}
+
+#[test]
+fn the_plain_text_banner_is_on_by_default_on_a_terminal() {
+    // util-linux script runs the command on a pseudo-terminal
+    if !std::path::Path::new("/usr/bin/script").exists() {
+        return;
+    }
+    let command = format!("{} {}", env!("CARGO_BIN_EXE_ccat"), common::fixture("notes.unknownext"));
+    let output = common::ccat_command("/usr/bin/script").args(["-qec", &command, "/dev/null"]).output().unwrap();
+    let terminal = plain(&String::from_utf8_lossy(&output.stdout));
+    assert!(terminal.contains("no syntax matched for .unknownext"), "{}", terminal);
+}
+
+#[test]
+fn a_utf16_file_with_a_bom_is_shown_as_text() {
+    let output = stdout(&[&common::fixture("utf16le-bom.txt")]);
+    assert_eq!(plain(&output), "café\nline two\n");
+}
+
+#[test]
+fn a_gzip_file_is_decompressed_and_detected_by_its_inner_name() {
+    let fixture = common::fixture("main.rs.gz");
+    assert_eq!(plain(&stdout(&[&fixture])), "fn main() {\n    println!(\"compressed\");\n}\n");
+
+    assert!(stdout(&["--detect-only", &fixture]).trim_end().ends_with("\tRust"));
+    assert!(!stdout(&["--only-syntax", "rust", &fixture]).is_empty());
+}
+
+#[test]
+fn select_shows_matching_lines_with_their_numbers() {
+    let output = plain(&stdout(&["-l", "--select", "len > 10", &common::fixture("select.txt")]));
+    assert_eq!(output, "   2 | a line longer than ten\n   4 | // TODO: fix this\n");
+}
+
+#[test]
+fn selected_lines_are_highlighted_in_the_context_of_the_lines_before() {
+    let mut file = tempfile::Builder::new().suffix(".rs").tempfile().unwrap();
+    std::io::Write::write_all(&mut file, b"/*\nstill inside the comment\n*/\n").unwrap();
+    let path = file.path().to_str().unwrap();
+
+    let whole = stdout(&[path]);
+    let selected = stdout(&["--select", "len > 10", path]);
+    assert_eq!(selected, whole.lines().nth(1).unwrap().to_string() + "\n");
+}
+
+/// Declining has to stay fast enough for preview hooks, well under what
+/// loading the syntaxes takes.
+const DECLINE_TIME: std::time::Duration = std::time::Duration::from_millis(500);
+
+fn declined(args: &[&str]) -> (Option<i32>, std::time::Duration) {
+    let started = std::time::Instant::now();
+    let output = common::run(args);
+    let elapsed = started.elapsed();
+    assert!(output.stdout.is_empty() && output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    (output.status.code(), elapsed)
+}
+
+#[test]
+fn if_text_declines_a_binary_file_quickly_and_silently() {
+    let mut binary = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut binary, &[0x7f, b'E', b'L', b'F', 0, 1, 2, 0]).unwrap();
+
+    let (code, elapsed) = declined(&["--if-text", binary.path().to_str().unwrap()]);
+    assert_eq!(code, Some(3));
+    assert!(elapsed < DECLINE_TIME, "took {:?}", elapsed);
+    assert!(common::run(&["--if-text", &common::fixture("regions.rs")]).status.success());
+}
+
+#[test]
+fn if_smaller_than_declines_a_large_file_quickly_and_silently() {
+    let mut large = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut large, &b"a\n".repeat(2048)).unwrap();
+    let large = large.path().to_str().unwrap();
+
+    let (code, elapsed) = declined(&["--if-smaller-than", "4K", large]);
+    assert_eq!(code, Some(4));
+    assert!(elapsed < DECLINE_TIME, "took {:?}", elapsed);
+    assert!(common::run(&["--if-smaller-than", "5K", large]).status.success());
+}
+
+fn write_theme(path: &std::path::Path, background: &str) {
+    std::fs::write(path, format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\"><dict>\
+         <key>name</key><string>Cached</string><key>settings</key><array><dict><key>settings</key><dict>\
+         <key>background</key><string>{}</string><key>foreground</key><string>#ffffff</string>\
+         </dict></dict></array></dict></plist>\n",
+        background,
+    )).unwrap();
+}
+
+#[test]
+fn a_second_run_loads_the_theme_from_the_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let theme = dir.path().join("Cached.tmTheme");
+    let cache = dir.path().join("cache");
+    write_theme(&theme, "#102030");
+
+    let from_cache = || {
+        let output = common::run(&[
+            "-v", "--theme-file", theme.to_str().unwrap(), "--theme-cache-dir", cache.to_str().unwrap(), &common::fixture("regions.rs"),
+        ]);
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap().contains("from cache")
+    };
+    assert!(!from_cache());
+    assert!(from_cache());
+
+    // a changed theme file is parsed again
+    write_theme(&theme, "#405060");
+    assert!(!from_cache());
+    assert!(from_cache());
+}
+
+#[test]
+fn a_tab_delimited_gutter_splits_back_into_the_original_content() {
+    let fixture = common::fixture("sample.rs");
+    let output = common::ccat()
+        .env("TERM", "dumb")
+        .args(["-l", "--gutter-delimiter", "\t", "--no-gutter-padding", &fixture])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut content = String::new();
+    for (index, row) in String::from_utf8(output.stdout).unwrap().split_inclusive('\n').enumerate() {
+        let (number, text) = row.split_once('\t').expect("every row has a delimiter");
+        assert_eq!(number.parse::<usize>().unwrap(), index + 1);
+        content.push_str(text);
+    }
+    assert_eq!(content, std::fs::read_to_string(&fixture).unwrap());
+}
+
+#[test]
+fn the_tee_file_is_plain_text_by_default() {
+    let tee = tempfile::NamedTempFile::new().unwrap();
+    let output = stdout(&["--tee", tee.path().to_str().unwrap(), "--max-line-length", "20", &common::fixture("sample.rs")]);
+    let saved = std::fs::read_to_string(tee.path()).unwrap();
+
+    assert!(output.contains("\x1b["));
+    assert!(output.contains("⟨not highlighted⟩"));
+    assert!(!saved.contains('\x1b'), "{:?}", saved);
+    // the file gets the plain profile's symbols, not the terminal's
+    assert!(saved.contains(" <not highlighted>"));
+    assert!(!saved.contains('⟨'));
+    assert_eq!(plain(&output).replace("⟨not highlighted⟩", "<not highlighted>"), saved);
+}
+
+#[test]
+fn an_ansi_tee_gets_the_displayed_escapes() {
+    let tee = tempfile::NamedTempFile::new().unwrap();
+    let output = stdout(&["--tee", tee.path().to_str().unwrap(), "--tee-format", "ansi", &common::fixture("sample.rs")]);
+    assert_eq!(std::fs::read_to_string(tee.path()).unwrap(), output);
+}
+
+#[test]
+fn the_tee_gets_the_same_fences_as_the_display() {
+    let tee = tempfile::NamedTempFile::new().unwrap();
+    let fixture = common::fixture("fences.md");
+    let output = stdout(&["--extract-fences", "rust", "--tee", tee.path().to_str().unwrap(), &fixture]);
+
+    let shown = plain(&output);
+    assert_eq!(shown, format!("{0}:4 (rust)\nfn main() {{\n    println!(\"first\");\n}}\n{0}:16 (rust)\nlet x = 1;\n", fixture));
+    assert_eq!(std::fs::read_to_string(tee.path()).unwrap(), shown);
+}
+
+#[test]
+fn tab_stops_line_up_the_same_with_and_without_line_numbers() {
+    let fixture = common::fixture("tabs.txt");
+    let bare = stdout(&["--tabs", "4", &fixture]);
+    let numbered = stdout(&["--tabs", "4", "-l", &fixture]);
+
+    let code: Vec<String> = plain(&numbered).lines().map(|row| row.split_once(" | ").unwrap().1.to_string()).collect();
+    assert_eq!(code, plain(&bare).lines().collect::<Vec<_>>());
+    assert_eq!(code, ["a   b", "    indented", "abcdefghi   j"]);
+}
+
+#[test]
+fn a_file_over_max_memory_is_an_error() {
+    let output = common::run(&["--max-memory", "16", &common::fixture("sample.rs")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("over the memory budget of 16 bytes"));
+    assert!(common::run(&["--max-memory", "1M", &common::fixture("sample.rs")]).status.success());
+}
+
+#[test]
+fn only_syntax_keeps_just_the_files_of_that_syntax() {
+    let tree = tempfile::tempdir().unwrap();
+    std::fs::create_dir(tree.path().join("src")).unwrap();
+    let files: Vec<String> = [("src/lib.rs", "fn a() {}\n"), ("main.rs", "fn b() {}\n"), ("build.py", "print(1)\n"), ("README.md", "# hi\n")]
+        .iter()
+        .map(|(name, content)| {
+            let path = tree.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    let only = plain(&stdout(&[&["--only-syntax", "rust"], files.as_slice()].concat()));
+    assert_eq!(only, format!("==> {} <==\nfn a() {{}}\n\n==> {} <==\nfn b() {{}}\n", files[0], files[1]));
+
+    let output = common::run(&[&["-v", "--skip-syntax", "Rust", "--skip-syntax", "py"], files.as_slice()].concat());
+    assert!(output.status.success());
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), format!("==> {} <==\n# hi\n", files[3]));
+    assert_eq!(String::from_utf8(output.stderr).unwrap().matches("skipped 1 file").count(), 3);
+}
+
+#[test]
+fn files0_from_stdin_reads_paths_with_spaces_and_newlines() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let spaced = dir.path().join("with space.rs");
+    let broken = dir.path().join("line\nbreak.txt");
+    std::fs::write(&spaced, "fn main() {}\n").unwrap();
+    std::fs::write(&broken, "text\n").unwrap();
+
+    let mut child = common::ccat()
+        .args(["--files0-from", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let list = format!("{}\0{}\0", spaced.display(), broken.display());
+    child.stdin.take().unwrap().write_all(list.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(
+        plain(&String::from_utf8(output.stdout).unwrap()),
+        format!("==> {} <==\nfn main() {{}}\n\n==> {} <==\ntext\n", spaced.display(), broken.display()),
+    );
+}
+
+#[test]
+fn org_and_rst_documents_have_their_blocks_extracted() {
+    let org = common::fixture("blocks.org");
+    assert_eq!(plain(&stdout(&["--extract-fences", "python", &org])), format!("{}:13 (python)\ndef greet():\n    return \"hi\"\n", org));
+    let rst = common::fixture("blocks.rst");
+    assert_eq!(plain(&stdout(&["--extract-fences", "sh", &rst])), format!("{}:25 (sh)\necho done\n", rst));
+}
+
+#[test]
+fn the_summary_counts_files_by_language() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut files = Vec::new();
+    for (name, content) in [("a.rs", &b"fn a() {}\n"[..]), ("b.rs", b"fn b() {}\n"), ("c.py", b"print(1)\n"), ("README.md", b"# hi\n"), ("blob.bin", b"\0\x01\x02")] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        files.push(path.to_str().unwrap().to_string());
+    }
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    let output = common::run(&[&["--summary"], files.as_slice()].concat());
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "4 highlighted, 1 skipped, 0 errors\n  Rust: 2\n  Markdown: 1\n  Python: 1\n");
+
+    let shown = stdout(&[&["--summary-stdout"], files.as_slice()].concat());
+    assert!(shown.ends_with("4 highlighted, 1 skipped, 0 errors\n  Rust: 2\n  Markdown: 1\n  Python: 1\n"), "{}", shown);
+
+    let quiet = common::run(&[&["--summary", "--quiet"], files.as_slice()].concat());
+    assert!(quiet.stderr.is_empty());
+}
+
+#[cfg(feature = "pager")]
+#[test]
+fn full_screen_prints_inline_when_stdout_is_not_a_terminal() {
+    let fixture = common::fixture("sample.rs");
+    assert_eq!(stdout(&["--full-screen", &fixture]), stdout(&[&fixture]));
+}
+
+#[test]
+fn detect_only_labels_each_file_with_its_language() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut files = Vec::new();
+    for (name, content) in [("lib.rs", &b"fn a() {}\n"[..]), ("run", b"#!/usr/bin/env python3\nprint(1)\n"), ("notes.txt", b"hi\n"), ("blob.bin", b"\0\x01\x02")] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        files.push(path.to_str().unwrap().to_string());
+    }
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    let text = stdout(&[&["--detect-only"], files.as_slice()].concat());
+    assert_eq!(text, format!("{}\tRust\n{}\tPython\n{}\tPlain Text\n{}\tbinary\n", files[0], files[1], files[2], files[3]));
+
+    let json: serde_json::Value = serde_json::from_str(&stdout(&[&["--detect-only", "--format", "json"], files.as_slice()].concat())).unwrap();
+    let records: Vec<(&str, &str)> = json.as_array().unwrap().iter()
+        .map(|record| (record["language"].as_str().unwrap(), record["method"].as_str().unwrap()))
+        .collect();
+    assert_eq!(records, [("Rust", "mapping"), ("Python", "first-line"), ("Plain Text", "extension"), ("binary", "binary")]);
+}
+
+#[test]
+fn configured_policies_yield_to_the_command_line() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), "[policies]\n\"*.rs\" = \"skip\"\n").unwrap();
+    let fixture = common::fixture("sample.rs");
+
+    let skipped = common::ccat().env("XDG_CONFIG_HOME", config.path()).arg(&fixture).output().unwrap();
+    assert_eq!(plain(&String::from_utf8(skipped.stdout).unwrap()), format!("skipped '{}' (policy '*.rs')\n", fixture));
+
+    let shown = common::ccat().env("XDG_CONFIG_HOME", config.path()).args(["--policy", "sample.*=full", &fixture]).output().unwrap();
+    assert_eq!(String::from_utf8(shown.stdout).unwrap(), stdout(&[&fixture]));
+}
+
+#[test]
+fn reverse_fits_in_a_memory_budget_that_holds_its_output_once() {
+    use std::io::Write;
+
+    let mut file = tempfile::Builder::new().suffix(".rs").tempfile().unwrap();
+    for line in 0..700 {
+        writeln!(file, "let value_{} = {};", line, line).unwrap();
+    }
+    let path = file.path().to_str().unwrap();
+
+    let unlimited = stdout(&["--reverse", path]);
+    assert!(unlimited.len() > 100_000);
+    assert_eq!(stdout(&["--reverse", "--max-memory", "200K", path]), unlimited);
+}
+
+#[test]
+fn max_memory_in_the_config_file_yields_to_the_flag() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), "max-memory = \"16\"\n").unwrap();
+    let fixture = common::fixture("sample.rs");
+
+    let limited = common::ccat().env("XDG_CONFIG_HOME", config.path()).arg(&fixture).output().unwrap();
+    assert!(!limited.status.success());
+    assert!(String::from_utf8(limited.stderr).unwrap().contains("over the memory budget of 16 bytes"));
+
+    let lifted = common::ccat().env("XDG_CONFIG_HOME", config.path()).args(["--max-memory", "1M", &fixture]).output().unwrap();
+    assert!(lifted.status.success());
+
+    let printed = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).arg("--config-print").args(args).output().unwrap();
+        assert!(output.status.success());
+        toml::from_str::<toml::Table>(&String::from_utf8(output.stdout).unwrap()).unwrap()["max-memory"].as_integer()
+    };
+    assert_eq!(printed(&[]), Some(16));
+    assert_eq!(printed(&["--max-memory", "1M"]), Some(1 << 20));
+
+    std::fs::write(config.path().join("ccat/config.toml"), "max-memory = \"lots\"\n").unwrap();
+    let invalid = common::ccat().env("XDG_CONFIG_HOME", config.path()).arg(&fixture).output().unwrap();
+    assert!(String::from_utf8(invalid.stderr).unwrap().contains("Invalid 'max-memory' in the config file"));
+    assert!(common::ccat().env("XDG_CONFIG_HOME", config.path()).args(["--max-memory", "1M", &fixture]).output().unwrap().status.success());
+}
+
+#[test]
+fn the_contact_sheet_has_a_block_per_listed_theme() {
+    let sheet = tempfile::NamedTempFile::new().unwrap();
+    let output = common::run(&["--contact-sheet", "--preview-file", &common::fixture("sample.rs"), "--output", sheet.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let html = std::fs::read_to_string(sheet.path()).unwrap();
+    for theme in stdout(&["--list-themes"]).lines() {
+        assert_eq!(html.matches(&format!("<figcaption>{}</figcaption>", theme)).count(), 1, "{}", theme);
+    }
+    assert!(!common::run(&["--contact-sheet", "--format", "png"]).status.success());
+}
+
+#[test]
+fn editorconfig_sets_the_tab_width_unless_given_or_disabled() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".editorconfig"), "root = true\n[*.txt]\nindent_size = 4\n").unwrap();
+    let file = dir.path().join("tabbed.txt");
+    std::fs::write(&file, "a\tb\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&[file])), "a   b\n");
+    assert_eq!(plain(&stdout(&["--tabs", "2", file])), "a b\n");
+    assert_eq!(plain(&stdout(&["--no-editorconfig", file])), "a\tb\n");
+}
+
+#[test]
+fn no_firstline_syntax_leaves_a_data_file_plain() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("export");
+    std::fs::write(&file, "<?xml version=\"1.0\"?>\n<rows/>\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    assert_eq!(stdout(&["--detect-only", file]), format!("{}\tXML\n", file));
+    assert_eq!(stdout(&["--detect-only", "--no-firstline-syntax", "xml,bash", file]), format!("{}\tPlain Text\n", file));
+}
+
+#[test]
+fn dedup_collapses_runs_and_the_summary_counts_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("app.log");
+    std::fs::write(&log, "start\n10:00:01 retry\n10:00:02 retry\n10:00:03 retry\nend\n").unwrap();
+    let log = log.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&["-l", "--dedup-consecutive", log])).lines().count(), 5);
+
+    let output = common::ccat().env("TERM", "dumb").args(["-l", "--dedup-consecutive", "--dedup-ignore-timestamps", "--summary", log]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "   1 | start\n 2-4 | 10:00:01 retry\n     | x 3\n   5 | end\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("2 duplicate lines collapsed\n"));
+}
+
+#[test]
+fn right_side_gutters_line_up_at_the_terminal_width() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {\n    println!(\"a longer line\");\n}\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    let output = stdout(&["-l", "--gutter-side", "right", "--terminal-width", "40", file]);
+    let rows: Vec<String> = plain(&output).lines().map(str::to_string).collect();
+    assert_eq!(rows.len(), 3);
+    for (index, row) in rows.iter().enumerate() {
+        assert_eq!(row.chars().count(), 40, "{:?}", row);
+        assert!(row.ends_with(&format!(" | {:>4}", index + 1)), "{:?}", row);
+    }
+}
+
+#[test]
+fn diagnostics_overlay_composes_with_numbers_ranges_and_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    let file = dir.path().join("src/lib.rs");
+    std::fs::write(&file, "fn main() {\n    let é = 1;\n    let y = 2;\n}\n").unwrap();
+    let diagnostics = dir.path().join("diag.json");
+    std::fs::write(&diagnostics, concat!(
+        "{\"reason\":\"compiler-message\",\"message\":{\"message\":\"unused variable: `é`\",\"level\":\"warning\",\"spans\":",
+        "[{\"file_name\":\"src/lib.rs\",\"line_start\":2,\"line_end\":2,\"column_start\":9,\"column_end\":10,\"is_primary\":true}]}}\n",
+        "{\"message\":\"elsewhere\",\"level\":\"error\",\"spans\":",
+        "[{\"file_name\":\"src/main.rs\",\"line_start\":3,\"line_end\":3,\"column_start\":1,\"column_end\":2,\"is_primary\":true}]}\n",
+    )).unwrap();
+    let annotations = dir.path().join("notes.toml");
+    std::fs::write(&annotations, "[\"lib.rs\"]\n\"2\" = \"here\"\n").unwrap();
+    let (file, diagnostics, annotations) = (file.to_str().unwrap(), diagnostics.to_str().unwrap(), annotations.to_str().unwrap());
+
+    let output = common::ccat().env("TERM", "dumb")
+        .args(["-l", "--line-range", "2:3", "--annotations", annotations, "--diagnostics", diagnostics, "--terminal-width", "50", file])
+        .output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+        "W    2 |     let é = 1;                       here\n",
+        "       | `- warning: unused variable: `é`\n",
+        "     3 |     let y = 2;\n",
+    ));
+
+    // only the span is curly underlined, in the warning's color
+    let colored = stdout(&["--diagnostics", diagnostics, file]);
+    assert_eq!(colored.matches("\x1b[4:3m\x1b[58;2;229;192;123m").count(), 1);
+    assert!(colored.contains("\x1b[4:3m\x1b[58;2;229;192;123m\x1b[38;2;192;197;206mé\x1b[59m\x1b[24m"), "{:?}", colored);
+}
+
+#[test]
+fn edit_only_launches_the_configured_editor_at_the_first_selected_line() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), "editor-command = \"echo open {file} at {line}\"\n").unwrap();
+    let fixture = common::fixture("sample.rs");
+    let edit = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).env_remove("VISUAL").env_remove("EDITOR")
+            .args(args).arg(&fixture).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(edit(&["--edit-only"]), format!("open {} at 1\n", fixture));
+    assert_eq!(edit(&["--edit-only", "--edit=9"]), format!("open {} at 9\n", fixture));
+    assert_eq!(edit(&["--edit-only", "--between", "^fn count:^}"]), format!("open {} at 6\n", fixture));
+    assert_eq!(edit(&["--edit-only", "--outline", "--pattern", "split_white"]), format!("open {} at 7\n", fixture));
+    assert_eq!(edit(&["--edit-only", "--outline", "--pattern", "nowhere", "--between", "^fn count:^}"]), format!("open {} at 6\n", fixture));
+    assert_eq!(edit(&["--edit-only", "--outline", "--pattern", "split_white", "--edit=2"]), format!("open {} at 2\n", fixture));
+
+    let printed = edit(&["--edit=2"]);
+    assert!(printed.ends_with(&format!("open {} at 2\n", fixture)));
+    assert!(printed.len() > format!("open {} at 2\n", fixture).len());
+
+    // the outline is shown first, then the editor opens at the match
+    let dir = tempfile::tempdir().unwrap();
+    let json = dir.path().join("config.json");
+    std::fs::write(&json, "{\n  \"a\": {\n    \"b\": 1\n  },\n  \"c\": {\n    \"d\": 2\n  }\n}\n").unwrap();
+    let json = json.to_str().unwrap();
+    let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).env_remove("VISUAL").env_remove("EDITOR")
+        .args(["--edit", "--outline", "--pattern", "\"d\"", json]).output().unwrap();
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert!(printed.ends_with(&format!("open {} at 6\n", json)), "{}", printed);
+}
+
+#[test]
+fn newline_flags_decide_how_the_output_ends() {
+    let dir = tempfile::tempdir().unwrap();
+    let with = dir.path().join("with.txt");
+    let without = dir.path().join("without.txt");
+    std::fs::write(&with, "a\nb\n").unwrap();
+    std::fs::write(&without, "a\nb").unwrap();
+    let (with, without) = (with.to_str().unwrap(), without.to_str().unwrap());
+
+    // piped output is raw by default
+    assert_eq!(plain(&stdout(&["-l", without])), "   1 | a\n   2 | b");
+    assert_eq!(plain(&stdout(&["-l", with])), "   1 | a\n   2 | b\n");
+    for file in [with, without] {
+        assert_eq!(plain(&stdout(&["-l", "--ensure-newline", file])), "   1 | a\n   2 | b\n");
+    }
+    assert_eq!(plain(&stdout(&["-l", "--no-newline-fix", without])), "   1 | a\n   2 | b");
+    assert_eq!(plain(&stdout(&["--no-newline-fix", with])), "a\nb\n");
+
+    assert!(!common::run(&["--ensure-newline", "--no-newline-fix", with]).status.success());
+}
+
+#[test]
+fn group_by_language_puts_a_counted_heading_above_each_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<String> = [("notes.md", "# Notes\n"), ("b.rs", "fn b() {}\n"), ("run.py", "print(1)\n"), ("a.rs", "fn a() {}\n")].iter()
+        .map(|(name, content)| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let outline = |order: &str| {
+        let mut args = vec![order];
+        args.extend(files.iter().map(String::as_str));
+        plain(&stdout(&args)).lines()
+            .filter(|line| line.starts_with("═══ ") || line.starts_with("==> "))
+            .map(|line| line.replace(&format!("{}/", dir.path().display()), ""))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(outline("--group-by-language"), [
+        "═══ Rust (2 files) ═══", "==> b.rs <==", "==> a.rs <==",
+        "═══ Markdown (1 file) ═══", "==> notes.md <==",
+        "═══ Python (1 file) ═══", "==> run.py <==",
+    ]);
+    assert_eq!(outline("--group-by-language=name"), [
+        "═══ Markdown (1 file) ═══", "==> notes.md <==",
+        "═══ Python (1 file) ═══", "==> run.py <==",
+        "═══ Rust (2 files) ═══", "==> b.rs <==", "==> a.rs <==",
+    ]);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn piped_images_get_the_metadata_summary_not_graphics_escapes() {
+    let fixture = common::fixture("pixels.png");
+
+    for protocol in ["kitty", "iterm2"] {
+        let output = common::ccat().env("TERM", "xterm-kitty").args(["--image-protocol", protocol, &fixture]).output().unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        assert!(!output.contains("\x1b_G") && !output.contains("\x1b]1337"), "{:?}", output);
+        assert_eq!(plain(&output), format!("{}: PNG image, 4x2, RGB, 8-bit\n", fixture));
+    }
+}
+
+#[test]
+fn hex_dumps_binary_files_and_their_byte_ranges() {
+    let mut blob = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut blob, b"ELF\x00\x01\x02hello, world!\xff\x7fABCDEFGH").unwrap();
+    let blob = blob.path().to_str().unwrap();
+
+    assert!(plain(&stdout(&[blob])).contains("binary"));
+    assert_eq!(plain(&stdout(&["--hex", "--range", "4:20", blob])), concat!(
+        "00000004  01 02 68 65 6c 6c 6f 2c  20 77 6f 72 6c 64 21 ff  |..hello, world!.|\n",
+        "00000014\n",
+    ));
+    assert_eq!(plain(&stdout(&["--hex", blob])).lines().last(), Some("0000001d"));
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn since_marks_the_changes_against_an_older_revision() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    let signature = git2::Signature::now("ccat", "ccat@example.com").unwrap();
+    let mut parents = Vec::new();
+    for content in ["a\nb\nc\nd\n", "a\nB\nc\nd\ne\n"] {
+        std::fs::write(dir.path().join("f.txt"), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("f.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+        let id = repo.commit(Some("HEAD"), &signature, &signature, "change", &tree, &parent_refs).unwrap();
+        parents = vec![repo.find_commit(id).unwrap()];
+    }
+    std::fs::write(dir.path().join("f.txt"), "a\nB\nd\ne\nf\n").unwrap();
+    std::fs::write(dir.path().join("new.txt"), "x\n").unwrap();
+    let (changed, new) = (dir.path().join("f.txt"), dir.path().join("new.txt"));
+
+    let output = common::ccat().env("TERM", "dumb").args(["--no-headers", "--since", "HEAD~1"]).arg(&changed).arg(&new).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "  a\n- b\n- c\n~ B\n  d\n+ e\n+ f\n+ x\n");
+}
+
+#[test]
+fn tree_labels_files_and_previews_their_first_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, content) in [("src/lib.rs", "fn a() {}\nfn b() {}\n"), ("src/inner/b.py", "x = 1\n"), ("docs/README.md", "# T\n"), ("Zeta.txt", "z\n"), ("alpha.txt", "a\nb\nc\n")] {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let root = format!("{}/", dir.path().display());
+    let tree = |args: &[&str]| {
+        let output = common::ccat().env("TERM", "dumb").arg("--tree").args(args).arg(&root).output().unwrap();
+        String::from_utf8(output.stdout).unwrap().replacen(&root, "root/", 1)
+    };
+
+    assert_eq!(tree(&[]), concat!(
+        "root/ (5 files)\n",
+        "|-- docs/ (1 file)\n",
+        "|   `-- README.md  Markdown, 1 line\n",
+        "|-- src/ (2 files)\n",
+        "|   |-- inner/ (1 file)\n",
+        "|   |   `-- b.py  Python, 1 line\n",
+        "|   `-- lib.rs  Rust, 2 lines\n",
+        "|-- alpha.txt  Plain Text, 3 lines\n",
+        "`-- Zeta.txt  Plain Text, 1 line\n",
+    ));
+    assert_eq!(tree(&["--preview", "2", "--max-depth", "1", "--glob", "*.txt"]), concat!(
+        "root/ (2+ files)\n",
+        "|-- docs/ (...)\n",
+        "|-- src/ (...)\n",
+        "|-- alpha.txt  Plain Text, 3 lines\n",
+        "|     a\n",
+        "|     b\n",
+        "`-- Zeta.txt  Plain Text, 1 line\n",
+        "      z\n",
+    ));
+}
+
+#[test]
+fn detect_order_puts_the_shebang_before_the_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("build.sh");
+    std::fs::write(&file, "#!/usr/bin/env python3\nprint('hi')\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    assert_eq!(stdout(&["--detect-only", file]), format!("{}\tBourne Again Shell (bash)\n", file));
+    assert_eq!(stdout(&["--detect-only", "--detect-order", "shebang,extension", file]), format!("{}\tPython\n", file));
+
+    let repeated = common::run(&["--detect-order", "shebang,shebang", file]);
+    assert!(!repeated.status.success());
+    let unknown = common::run(&["--detect-order", "shebang,magic", file]);
+    assert!(String::from_utf8(unknown.stderr).unwrap().contains("magic"));
+}
+
+#[test]
+fn ambiguous_width_changes_where_rows_wrap() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("box.txt");
+    std::fs::write(&file, format!("{}\n", "─".repeat(12))).unwrap();
+    let file = file.to_str().unwrap();
+    let rows = |ambiguous: &str| plain(&stdout(&["--wrap", "--terminal-width", "8", "--ambiguous-width", ambiguous, file])).lines().count();
+
+    assert_eq!(rows("narrow"), 2);
+    assert_eq!(rows("wide"), 3);
+}
+
+#[test]
+fn strip_shebang_hides_the_first_line_of_a_bash_script() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("deploy");
+    std::fs::write(&script, "#!/bin/bash\necho hi\nexit 0\n").unwrap();
+    let script = script.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&["--strip-shebang", script])), "echo hi\nexit 0\n");
+    assert_eq!(plain(&stdout(&["-l", "--strip-shebang=keep-numbers", script])), "   2 | echo hi\n   3 | exit 0\n");
+    assert_eq!(stdout(&["--detect-only", "--strip-shebang", script]), format!("{}\tBourne Again Shell (bash)\n", script));
+}
+
+#[test]
+fn line_range_reads_through_a_cached_index_and_notes_the_lookback() {
+    let file = tempfile::Builder::new().suffix(".rs").tempfile().unwrap();
+    std::fs::write(file.path(), (1..=20_000).map(|line| format!("let x{} = {};\n", line, line)).collect::<String>()).unwrap();
+    let file = file.path().to_str().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let range = |args: &[&str]| {
+        let output = common::ccat().env("TERM", "dumb").env("XDG_CACHE_HOME", cache.path())
+            .args(["-l", "--line-range", "10000:10002"]).args(args).arg(file).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let lines = "10000 | let x10000 = 10000;\n10001 | let x10001 = 10001;\n10002 | let x10002 = 10002;\n";
+
+    assert_eq!(range(&[]), format!("... highlighted from line 9800 rather than the top of the file, so colors may be inexact; --exact-highlighting starts at the top\n{}", lines));
+    assert_eq!(std::fs::read_dir(cache.path().join("ccat/index")).unwrap().count(), 1);
+    assert!(range(&["--lookback", "10"]).starts_with("... highlighted from line 9990 "));
+    assert_eq!(range(&["--exact-highlighting"]), lines);
+}
+
+#[test]
+fn max_colors_limits_the_distinct_foregrounds() {
+    let fixture = common::fixture("sample.rs");
+    let foregrounds = |output: &str| {
+        let mut colors: Vec<String> = output.split("\x1b[38;2;").skip(1)
+            .map(|rest| rest.split_once('m').unwrap().0.to_string())
+            .collect();
+        colors.sort();
+        colors.dedup();
+        colors.len()
+    };
+
+    assert!(foregrounds(&stdout(&[&fixture])) > 4);
+    assert!(foregrounds(&stdout(&["--max-colors", "4", &fixture])) <= 4);
+    assert_eq!(plain(&stdout(&["--max-colors", "4", &fixture])), plain(&stdout(&[&fixture])));
+}
+
+#[test]
+fn normalize_eol_rewrites_every_line_ending() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, content: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let crlf = write("crlf.txt", "a\r\nb\r\n");
+    let lf = write("lf.txt", "a\nb\n");
+    let mixed = write("mixed.txt", "a\r\nb\nc");
+    let run = |args: &[&str], file: &str| {
+        let output = common::ccat().env("TERM", "dumb").args(args).arg(file).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(run(&["--normalize-eol", "lf"], &crlf), "a\nb\n");
+    assert_eq!(run(&["-l", "--normalize-eol", "lf"], &crlf), "   1 | a\n   2 | b\n");
+    assert_eq!(run(&["--normalize-eol", "lf"], &mixed), "a\nb\nc");
+    assert_eq!(run(&["--normalize-eol", "crlf"], &lf), "a\r\nb\r\n");
+    assert_eq!(run(&["-l", "--normalize-eol", "crlf"], &lf), "   1 | a\r\n   2 | b\r\n");
+    assert_eq!(run(&["-l", "--normalize-eol", "crlf"], &mixed), "   1 | a\r\n   2 | b\r\n   3 | c");
+    assert_eq!(run(&["--no-highlight", "--normalize-eol", "lf"], &mixed), "a\nb\nc");
+    assert_eq!(run(&["--normalize-eol", "keep"], &mixed), "a\r\nb\nc");
+
+    // the added ending is one of the normalized kind
+    assert_eq!(run(&["--normalize-eol", "crlf", "--ensure-final-newline"], &mixed), "a\r\nb\r\nc\r\n");
+    assert_eq!(run(&["-l", "--ensure-final-newline"], &lf), "   1 | a\n   2 | b\n");
+}
+
+#[test]
+fn set_bg_sends_nothing_into_a_pipe() {
+    let fixture = common::fixture("sample.rs");
+    let output = stdout(&["--set-bg", &fixture]);
+
+    assert!(!output.contains("\x1b]11;") && !output.contains("\x1b]111"));
+    assert_eq!(output, stdout(&[&fixture]));
+}
+
+#[cfg(unix)]
+#[test]
+fn configured_preprocessors_replace_the_file_with_their_output() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), r#"
+        [preprocessors]
+        "*.model" = { command = ["sh", "-c", "echo \"// from $1\"; echo 'fn main() {}'", "sh", "{file}"], syntax = "Rust" }
+    "#).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let model = dir.path().join("ship.model");
+    std::fs::write(&model, "raw bytes\n").unwrap();
+    let model = model.to_str().unwrap();
+    let run = |args: &[&str]| common::ccat().env("XDG_CONFIG_HOME", config.path()).args(args).arg(model).output().unwrap();
+
+    let output = run(&["--allow-preprocessors"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(plain(&stdout), format!("// from {}\nfn main() {{}}\n", model));
+    assert!(stdout.contains("\x1b[38;2;"));
+
+    let output = run(&["--paranoid"]);
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), "raw bytes\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not running preprocessor '*.model'"));
+}
+
+#[test]
+fn collapse_blank_keeps_n_blank_lines_and_counts_the_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gaps.txt");
+    std::fs::write(&path, format!("a\n{}b\n", "\n".repeat(10))).unwrap();
+    let path = path.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&["--collapse-blank", "2", path])), "a\n\n\n⋯ (8 more blank lines) ⋯\nb\n");
+    assert_eq!(plain(&stdout(&["--squeeze-blank", path])), "a\n\n⋯ (9 more blank lines) ⋯\nb\n");
+    assert!(!common::run(&["--squeeze-blank", "--collapse-blank", "2", path]).status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn compare_reads_a_pipe_under_the_name_given_to_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("old.txt");
+    std::fs::write(&old, "a\nb\nc\n").unwrap();
+    let old = old.to_str().unwrap();
+    let piped = |args: &[&str], input: &str| {
+        let mut command = common::ccat();
+        command.env("TERM", "dumb").args(args);
+        let output = common::with_stdin(command, input);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(piped(&["--compare", old, "/dev/stdin"], "a\nB\nc\nd\n"), "  a\n- b\n~ B\n  c\n+ d\n");
+    // the pipe is named for detection and headers, the file given a syntax of its own
+    assert_eq!(
+        piped(&["--detect-only", "--file-name", "2:new.json", "--syntax", "1:Rust", old, "/dev/stdin"], "{}\n"),
+        format!("{}\tRust\nnew.json\tJSON\n", old),
+    );
+
+    let output = common::run(&["--file-name", "3:x.json", old]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid --file-name '3:x.json', there is no file 3"));
+    let output = common::run(&["--compare", old]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--compare needs two files, the old one and the new one, got 1"));
+}
+
+#[test]
+fn config_print_dumps_the_configuration_in_effect() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), r#"
+        editor-command = "vi +{line} {file}"
+        [policies]
+        "*.lock" = "skip"
+    "#).unwrap();
+    let print = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).arg("--config-print").args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        toml::from_str::<toml::Table>(&String::from_utf8(output.stdout).unwrap()).unwrap()
+    };
+
+    let printed = print(&["--theme", "Nord", "-l", "--policy", "*.min.js=plain"]);
+    assert_eq!(printed["highlight"]["theme"].as_str(), Some("Nord"));
+    assert_eq!(printed["highlight"]["show-line-numbers"].as_bool(), Some(true));
+    assert_eq!(printed["command-line"]["theme"].as_str(), Some("Nord"));
+    assert_eq!(printed["editor-command"].as_str(), Some("vi +{line} {file}"));
+    assert_eq!(printed["policies"]["*.lock"].as_str(), Some("skip"));
+    assert_eq!(printed["policies"]["*.min.js"].as_str(), Some("plain"));
+
+    // the files are not read
+    assert_eq!(print(&["does-not-exist.rs"])["highlight"]["show-line-numbers"].as_bool(), Some(false));
+}
+
+#[test]
+fn outline_keeps_the_line_numbers_of_the_keys_it_shows() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("deploy.yaml");
+    std::fs::write(&path, "a:\n  x: 1\n  y: 2\nb:\n  needle: 3\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&["--outline", "--outline-depth", "1", "-l", path])), "   1 | a:\n     | … 2 lines\n   4 | b:\n     | … 1 line\n");
+    assert_eq!(plain(&stdout(&["--outline", "--outline-depth", "1", "--pattern", "needle", path])), "a:\n… 2 lines\nb:\n  needle: 3\n");
+    assert!(!common::run(&["--outline", &common::fixture("sample.rs")]).status.success());
+}
+
+#[test]
+fn fold_markers_collapse_a_csharp_region() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("A.cs");
+    std::fs::write(&path, "class A {\n    #region Fields\n    int a;\n    int b;\n    #endregion\n    void M() {}\n}\n").unwrap();
+    let path = path.to_str().unwrap();
+    let run = |args: &[&str]| plain(&stdout(&[args, &[path]].concat()));
+
+    assert_eq!(run(&["--fold-markers", "-l"]), "   1 | class A {\n 2-5 |     #region Fields ⋯ 3 lines\n   6 |     void M() {}\n   7 | }\n");
+    assert_eq!(run(&["--fold-markers", "--unfold", "Fields"]), run(&[]));
+}
+
+#[cfg(unix)]
+#[test]
+fn project_configs_run_programs_only_once_trusted() {
+    let project = tempfile::tempdir().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let config = "theme = \"InspiredGitHub\"\n[preprocessors]\n\"*.model\" = { command = [\"sh\", \"-c\", \"echo converted\"] }\n";
+    std::fs::write(project.path().join(".ccat.toml"), config).unwrap();
+    let model = project.path().join("ship.model");
+    std::fs::write(&model, "raw\n").unwrap();
+    let source = project.path().join("main.rs");
+    std::fs::write(&source, "fn main() {}\n").unwrap();
+    let run = |args: &[&str]| common::ccat().env("XDG_CACHE_HOME", cache.path()).args(args).output().unwrap();
+    let model = model.to_str().unwrap();
+    let dir = project.path().to_str().unwrap();
+
+    // settings that run nothing apply right away, the rest is denied
+    let output = run(&["--allow-preprocessors", model]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), "raw\n");
+    assert!(stderr.contains("wants to run programs, which it may not until it is trusted:"), "{}", stderr);
+    assert!(stderr.contains("  preprocessor for '*.model': sh -c echo converted {file}"), "{}", stderr);
+    assert_eq!(run(&[source.to_str().unwrap()]).stdout, stdout(&["--theme", "InspiredGitHub", source.to_str().unwrap()]).into_bytes());
+
+    let trusted = run(&["--trust", dir]);
+    assert!(String::from_utf8(trusted.stdout).unwrap().starts_with("trusted '"));
+    let output = run(&["--allow-preprocessors", model]);
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), "converted\n");
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // an edit takes the trust away again
+    std::fs::write(project.path().join(".ccat.toml"), format!("{}# edited\n", config)).unwrap();
+    let output = run(&["--allow-preprocessors", model]);
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), "raw\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("changed since it was trusted and wants to run programs"));
+    assert!(!run(&["--trust", cache.path().to_str().unwrap()]).status.success());
+}
+
+#[test]
+fn tabs_as_arrows_pads_each_arrow_to_the_tab_stop() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("indent.txt");
+    std::fs::write(&path, "\tx\nab\ty\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    assert_eq!(plain(&stdout(&["--tabs-as-arrows", path])), "→       x\nab→     y\n");
+    assert_eq!(plain(&stdout(&["--tabs-as-arrows", "--tabs", "4", path])), "→   x\nab→ y\n");
+    let ascii = common::ccat().env("TERM", "dumb").args(["--tabs-as-arrows", path]).output().unwrap();
+    assert_eq!(String::from_utf8(ascii.stdout).unwrap(), ">       x\nab>     y\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn ccat_language_picks_the_syntax_of_piped_input() {
+    let python = "def f():\n    return 1\n";
+    let piped = |language: Option<&str>, args: &[&str]| {
+        let mut command = common::ccat();
+        command.args(args).arg("/dev/stdin");
+        if let Some(language) = language {
+            command.env("CCAT_LANGUAGE", language);
+        }
+        common::with_stdin(command, python)
+    };
+    let stdout = |output: std::process::Output| {
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let as_python = stdout(piped(None, &["--syntax", "Python"]));
+    assert_eq!(stdout(piped(Some("python"), &[])), as_python);
+    assert_eq!(stdout(piped(None, &["--language", "py"])), as_python);
+    assert_ne!(stdout(piped(None, &[])), as_python);
+
+    // the flags and rules come first
+    assert_eq!(stdout(piped(Some("python"), &["--detect-only", "--language", "rs"])), "/dev/stdin\tRust\n");
+    assert_eq!(stdout(piped(Some("python"), &["--detect-only", "--syntax-for", "*=JSON"])), "/dev/stdin\tJSON\n");
+    let unknown = piped(Some("klingon"), &[]);
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Unknown language 'klingon' in $CCAT_LANGUAGE; see --list-syntaxes"));
+}
+
+#[test]
+fn an_invalid_utf8_byte_is_shown_in_its_line_before_the_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.txt");
+    std::fs::write(&path, b"ok\nb\xffd\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = common::ccat().env("RUST_BACKTRACE", "0").arg(path).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let snippet = format!(" --> {}:2:2\n  |\n1 | ok\n2 | b\u{fffd}d\n  |  ^\nError: Failed to read file '{}'", path, path);
+    assert!(stderr.starts_with(&snippet), "{}", stderr);
+}
+
+#[test]
+fn underline_urls_leaves_piped_output_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("links.rs");
+    std::fs::write(&path, "// see https://example.com/docs\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = stdout(&["--underline-urls", path]);
+    assert!(!output.contains("\x1b[4m"));
+    assert_eq!(output, stdout(&[path]));
+}
+
+#[test]
+fn patch_files_render_as_mail_unless_told_not_to() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("0001-greet.patch");
+    let patch = "From 3f2a9c1 Mon Sep 17 00:00:00 2001\nFrom: Ada <ada@example.com>\nSubject: [PATCH] Add a greeting\n\n\
+        ---\ndiff --git a/main.rs b/main.rs\n--- a/main.rs\n+++ b/main.rs\n@@ -0,0 +1 @@\n+fn hello() {}\n";
+    std::fs::write(&path, patch).unwrap();
+    let path = path.to_str().unwrap();
+
+    let mail = stdout(&[path]);
+    assert!(mail.contains("\x1b[1m[PATCH] Add a greeting"), "{:?}", mail);
+    assert_eq!(plain(&mail), patch);
+
+    let file = stdout(&["--no-mail-rendering", path]);
+    assert!(!file.contains("\x1b[1m[PATCH] Add a greeting"));
+    assert_eq!(plain(&file), patch);
+}
+
+#[test]
+fn keep_ansi_width_wraps_colored_lines_at_their_visible_width() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("colored.log");
+    let line: String = (0..20).map(|index| format!("\x1b[3{}mx\x1b[0m", index % 8)).collect();
+    std::fs::write(&path, format!("{}\n", line)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let wrapped = |extra: &[&str]| {
+        let mut args = vec!["--wrap=char", "--terminal-width", "10"];
+        args.extend(extra);
+        args.push(path);
+        let output = common::ccat().env("TERM", "dumb").args(&args).output().unwrap();
+        assert!(output.status.success());
+        plain(&String::from_utf8(output.stdout).unwrap()).lines().map(str::to_string).collect::<Vec<_>>()
+    };
+
+    assert_eq!(wrapped(&["--keep-ansi-width"]), ["x".repeat(10), "x".repeat(10)]);
+    assert!(wrapped(&[]).len() > 2);
+}
+
+#[test]
+fn output_template_prefixes_rows_with_path_and_line_number() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("two.rs");
+    std::fs::write(&path, "fn a() {}\nfn b() {}\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = stdout(&["--output-template", "{path}:{ln}: {code}", path]);
+    assert_eq!(plain(&output), format!("{}:1: fn a() {{}}\n{}:2: fn b() {{}}\n", path, path));
+
+    let output = common::ccat().args(["--output-template", "{ln} {line}", path]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown placeholder '{line}'"));
+}
+
+#[test]
+fn number_base_applies_to_the_gutter_and_templates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lines.txt");
+    std::fs::write(&path, "x\n".repeat(74)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = plain(&stdout(&["-l", "--number-base", "hex", "--number-pad-zero", path]));
+    assert_eq!(output.lines().next(), Some("0x0001 | x"));
+    assert_eq!(output.lines().last(), Some("0x004a | x"));
+
+    let output = plain(&stdout(&["-l", "--number-base", "oct", path]));
+    assert_eq!(output.lines().last(), Some(" 0o112 | x"));
+
+    let output = plain(&stdout(&["-l", "--number-base", "hex", "--output-template", "{ln}:{code}", path]));
+    assert_eq!(output.lines().last(), Some("0x4a:x"));
+}
+
+#[test]
+fn cache_reuses_the_output_of_an_unchanged_file() {
+    let cache = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "fn main() {}\n").unwrap();
+    let path = path.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CACHE_HOME", cache.path()).args(args).output().unwrap();
+        assert!(output.status.success());
+        (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+
+    let (first, log) = run(&["--cache", "-v", path]);
+    assert!(log.contains(&format!("cached the output of '{}'", path)), "{}", log);
+    let (second, log) = run(&["--cache", "-v", path]);
+    assert!(log.contains(&format!("cache hit for '{}'", path)), "{}", log);
+    assert_eq!(first, second);
+
+    // another theme, or a changed file, is rendered again
+    let (_, log) = run(&["--cache", "-v", "--theme", "InspiredGitHub", path]);
+    assert!(!log.contains("cache hit"), "{}", log);
+    std::fs::write(path, "fn main() { println!(); }\n").unwrap();
+    let (changed, log) = run(&["--cache", "-v", path]);
+    assert!(!log.contains("cache hit"), "{}", log);
+    assert!(plain(&changed).contains("println!"));
+}
+
+#[test]
+fn cache_misses_after_an_edit_to_the_files_editorconfig_or_project() {
+    let cache = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "\tfn main() {}\n").unwrap();
+    std::fs::write(dir.path().join(".editorconfig"), "root = true\n[*]\ntab_width = 2\n").unwrap();
+    let path = path.to_str().unwrap();
+    let run = || {
+        let output = common::ccat().env("TERM", "dumb").env("XDG_CACHE_HOME", cache.path())
+            .args(["--cache", "-v", path]).output().unwrap();
+        assert!(output.status.success());
+        (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap().contains("cache hit"))
+    };
+
+    assert_eq!(run(), ("  fn main() {}\n".to_string(), false));
+    assert!(run().1);
+    std::fs::write(dir.path().join(".editorconfig"), "root = true\n[*]\ntab_width = 4\n").unwrap();
+    assert_eq!(run(), ("    fn main() {}\n".to_string(), false));
+    std::fs::write(dir.path().join(".ccat.toml"), "tabs = 3\n").unwrap();
+    assert_eq!(run(), ("   fn main() {}\n".to_string(), false));
+    assert!(run().1);
+}
+
+#[test]
+fn semantic_zones_mark_each_file_of_a_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    std::fs::write(&a, "a\n").unwrap();
+    std::fs::write(&b, "b\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let (start, output, end) = ("\x1b]133;A\x07", "\x1b]133;C\x07", "\x1b]133;D\x07");
+
+    let marked = stdout(&["--semantic-zones", "always", "--theme", "InspiredGitHub", a, b]);
+    let unmarked = stdout(&["--theme", "InspiredGitHub", a, b]);
+    assert_eq!(marked.replace(start, "").replace(output, "").replace(end, ""), unmarked);
+
+    // A opens each file before its header, C starts its content, D ends it
+    let zones: Vec<&str> = marked.split_inclusive(end).collect();
+    assert_eq!(zones.len(), 2);
+    for (zone, path) in zones.iter().zip([a, b]) {
+        assert!(zone.starts_with(start), "{:?}", zone);
+        let (header, content) = zone[start.len()..].split_once(output).unwrap();
+        assert!(plain(header).contains(&format!("==> {} <==", path)), "{:?}", header);
+        assert!(!content.contains("==>"));
+        assert!(content.ends_with(&format!("\n{}", end)));
+    }
+
+    // auto leaves piped output alone, and the dumb profile never marks
+    let auto = common::ccat().env("TERM_PROGRAM", "WezTerm").args([a, b]).output().unwrap();
+    assert!(!String::from_utf8(auto.stdout).unwrap().contains("\x1b]133"));
+    let dumb = common::ccat().env("TERM", "dumb").args(["--semantic-zones", "always", a, b]).output().unwrap();
+    assert!(!String::from_utf8(dumb.stdout).unwrap().contains("\x1b]133"));
+}
+
+#[test]
+fn semantic_zones_stay_out_of_output_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let (file, out) = (dir.path().join("a.txt"), dir.path().join("out.txt"));
+    std::fs::write(&file, "a\n").unwrap();
+
+    stdout(&["--semantic-zones", "always", "-o", out.to_str().unwrap(), file.to_str().unwrap()]);
+    assert!(!std::fs::read_to_string(&out).unwrap().contains("\x1b]133"));
+}
+
+#[test]
+fn no_highlight_numbers_a_huge_file_quickly_and_plainly() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("huge.rs");
+    let content: String = (0..200_000).map(|index| format!("fn f{}() {{ let x = \"{}\"; }}\n", index, index)).collect();
+    std::fs::write(&path, &content).unwrap();
+
+    // highlighting this takes a debug build about a minute
+    let started = std::time::Instant::now();
+    let output = stdout(&["--no-highlight", "-l", path.to_str().unwrap()]);
+    assert!(started.elapsed() < std::time::Duration::from_secs(30), "took {:?}", started.elapsed());
+
+    assert!(!output.contains('\x1b'));
+    assert_eq!(output.lines().count(), 200_000);
+    assert_eq!(output.lines().next(), Some("     1 | fn f0() { let x = \"0\"; }"));
+    assert_eq!(output.lines().last(), Some("200000 | fn f199999() { let x = \"199999\"; }"));
+}
+
+#[test]
+fn date_format_sets_how_the_provenance_footer_shows_times() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    std::fs::write(&path, "a\n").unwrap();
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_709_211_909);
+    std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    let path = path.to_str().unwrap();
+
+    let modified_line = |args: &[&str]| {
+        let output = plain(&stdout(&[&["--provenance"], args, &[path]].concat()));
+        output.lines().find(|line| line.starts_with("modified")).unwrap().to_string()
+    };
+    assert_eq!(modified_line(&[]), "modified 2024-02-29T13:05:09Z");
+    assert_eq!(modified_line(&["--date-format", "custom:%d %B %Y"]), "modified 29 February 2024");
+    assert!(modified_line(&["--date-format", "relative"]).ends_with(" years ago"));
+
+    let output = common::run(&["--provenance", "--date-format", "custom:%Q", path]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown conversion '%Q'"));
+}
+
+#[test]
+fn split_on_shows_multi_document_yaml_as_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("docs.yaml");
+    std::fs::write(&path, "a: 1\n---\nb: 2\nc: 3\n---\n#!/bin/sh\necho hi\n").unwrap();
+    let path = path.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let output = common::ccat().env("TERM", "dumb").args(args).arg(path).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // the numbers are those of the whole file
+    assert_eq!(
+        run(&["-l", "--split-on", "^---$"]),
+        format!(
+            "{path}:1 document 1/3 (YAML)\n   1 | a: 1\n\
+             {path}:2 document 2/3 (YAML)\n   2 | ---\n   3 | b: 2\n   4 | c: 3\n\
+             {path}:5 document 3/3 (YAML)\n   5 | ---\n   6 | #!/bin/sh\n   7 | echo hi\n",
+            path = path,
+        ),
+    );
+
+    let detected = run(&["--split-on", "^---$", "--detect-per-segment"]);
+    assert!(detected.contains(&format!("{}:2 document 2/3 (YAML)\n", path)));
+    assert!(detected.contains(&format!("{}:5 document 3/3 (Bourne Again Shell (bash))\n", path)));
+
+    // in color, the documents together are still the whole file
+    let whole = plain(&stdout(&[path]));
+    let split = plain(&stdout(&["--split-on", "^---$", path]));
+    let bodies: String = split.lines().filter(|line| !line.contains(" document ")).map(|line| format!("{}\n", line)).collect();
+    assert_eq!(bodies, whole);
+}
+
+#[test]
+fn join_concatenates_files_each_in_its_own_syntax() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<String> = [("a.rs", "fn main() {}\n"), ("b.py", "import os\nx = 1"), ("c.md", "# Title\n")].iter()
+        .map(|(name, content)| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let run = |extra: &[&str]| stdout(&[extra, &files.iter().map(String::as_str).collect::<Vec<_>>()].concat());
+
+    let headed = format!(
+        "==> {} <==\nfn main() {{}}\n\n==> {} <==\nimport os\nx = 1\n\n==> {} <==\n# Title\n",
+        files[0], files[1], files[2],
+    );
+    assert_eq!(plain(&run(&[])), headed);
+
+    // no separators at all, and the python's last line still ends before the markdown
+    let joined = run(&["--join"]);
+    assert_eq!(plain(&joined), "fn main() {}\nimport os\nx = 1\n# Title\n");
+    // each file as it is shown alone, its own syntax and all
+    let each: Vec<String> = files.iter().map(|file| stdout(&[file])).collect();
+    assert!(!each[1].ends_with('\n'));
+    assert_eq!(joined, format!("{}{}\n{}", each[0], each[1], each[2]));
+
+    assert_eq!(plain(&run(&["--join", "-l", "--number-continuous"])), "   1 | fn main() {}\n   2 | import os\n   3 | x = 1\n   4 | # Title\n");
+    assert_eq!(
+        plain(&run(&["-l", "--number-continuous"])),
+        format!(
+            "==> {} <==\n   1 | fn main() {{}}\n\n==> {} <==\n   2 | import os\n   3 | x = 1\n\n==> {} <==\n   4 | # Title\n",
+            files[0], files[1], files[2],
+        ),
+    );
+    assert_eq!(plain(&run(&["--no-headers"])), plain(&joined));
+}
+
+#[test]
+fn bytes_highlights_only_its_window() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lines.txt");
+    let content: String = (0..20).map(|index| format!("line {:03} abcdefghijklmnop\n", index)).collect();
+    std::fs::write(&path, &content).unwrap();
+    let path = path.to_str().unwrap();
+
+    let window = plain(&stdout(&["--bytes", "100:200", path]));
+    let (note, text) = window.split_once('\n').unwrap();
+    assert_eq!(note, "⋯ bytes 100-200 only, highlighted without the 100 before them, so colors may be inexact");
+    assert_eq!(text, &content[100..200]);
+
+    // a window at the start of the file has seen all there is before it
+    assert_eq!(plain(&stdout(&["--bytes", ":10", path])), "line 000 a");
+}
+
+#[test]
+fn bytes_with_hex_dumps_the_window_of_a_binary_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    std::fs::write(&path, (0..64u8).collect::<Vec<_>>()).unwrap();
+
+    let output = common::ccat().env("TERM", "dumb").args(["--hex", "--bytes", "16:32", path.to_str().unwrap()]).output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00000010  10 11 12 13 14 15 16 17  18 19 1a 1b 1c 1d 1e 1f  |................|\n00000020\n",
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn sudo_reads_an_unreadable_file_through_ccat_sudo() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let (path, readable) = (dir.path().join("secret.rs"), dir.path().join("open.rs"));
+    std::fs::write(&path, "fn secret() {}\n").unwrap();
+    std::fs::write(&readable, "fn secret() {}\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+    if std::fs::File::open(&path).is_ok() {
+        // run as root, nothing is unreadable
+        return;
+    }
+    // stands in for root by giving the owner back the right to read
+    let sudo = dir.path().join("fake-sudo");
+    std::fs::write(&sudo, "#!/bin/sh\n[ \"$1\" = -v ] && exit 0\nshift 4\nchmod u+r \"$1\" && exec cat -- \"$1\"\n").unwrap();
+    std::fs::set_permissions(&sudo, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = common::ccat().env("CCAT_SUDO", &sudo).args(["--sudo", path]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("'{}' can only be read as root", path)));
+    // detected by its own path, as the readable copy is
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), stdout(&[readable.to_str().unwrap()]));
+}
+
+#[test]
+fn merge_adjacent_colors_shrinks_output_without_changing_it() {
+    let sample = common::fixture("sample.rs");
+    let merged = stdout(&["--merge-adjacent-colors", &sample]);
+    let unmerged = stdout(&[&sample]);
+
+    assert_eq!(plain(&merged), plain(&unmerged));
+    assert!(merged.len() * 3 < unmerged.len() * 2, "{} of {} bytes", merged.len(), unmerged.len());
+}
+
+#[test]
+fn the_config_file_can_turn_the_cache_on() {
+    let config = tempfile::tempdir().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), "cache = true\n").unwrap();
+    let sample = common::fixture("sample.rs");
+    let run = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).env("XDG_CACHE_HOME", cache.path())
+            .args(args).arg(&sample).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    run(&["-v"]);
+    assert!(run(&["-v"]).contains("cache hit for"));
+    // --tee can't be cached, so it turns the config's cache off
+    let tee = cache.path().join("tee.txt");
+    assert!(!run(&["-v", "--tee", tee.to_str().unwrap()]).contains("cache hit for"));
+}
+
+#[test]
+fn a_malformed_config_file_is_reported_once() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    let path = config.path().join("ccat/config.toml");
+    std::fs::write(&path, "cache = true\nsudo-prompt = = 1\n").unwrap();
+
+    let output = common::ccat().env("XDG_CONFIG_HOME", config.path()).env("RUST_BACKTRACE", "0")
+        .arg(common::fixture("sample.rs")).output().unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let error = format!("Error: Failed to parse config file '{}'", path.display());
+    assert_eq!(stderr.matches(&error).count(), 1, "{}", stderr);
+    assert_eq!(stderr.matches(" --> ").count(), 1, "{}", stderr);
+    assert!(stderr.starts_with(&format!(" --> {}:2:15\n", path.display())), "{}", stderr);
+}
+
+#[test]
+fn a_vscode_theme_highlights_through_theme() {
+    let dir = tempfile::tempdir().unwrap();
+    let theme = dir.path().join("minimal.json");
+    std::fs::write(&theme, r##"{
+        "name": "Minimal",
+        "colors": { "editor.foreground": "#dddddd" },
+        "tokenColors": [
+            { "scope": "storage.type", "settings": { "foreground": "#ff0000" } },
+            { "scope": "comment", "settings": { "foreground": "#00ff00", "fontStyle": "sparkly" } }
+        ],
+    }"##).unwrap();
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "fn main() {} // hi\n").unwrap();
+
+    let output = common::run(&["--merge-adjacent-colors", "--theme", theme.to_str().unwrap(), source.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("\x1b[38;2;255;0;0mfn\x1b[38;2;221;221;221m main() {} "), "{:?}", stdout);
+    assert!(stdout.contains("\x1b[38;2;0;255;0m// hi"), "{:?}", stdout);
+    assert!(String::from_utf8(output.stderr).unwrap().contains("ignoring font style 'sparkly'"));
+}
+
+#[test]
+fn reveal_strings_spells_out_hidden_characters_where_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "let key = \"p\u{430}ss\u{200b}\"; // a\u{a0}b\n").unwrap();
+    let reveal = |arg: &str| {
+        let output = common::ccat().env("TERM", "dumb").args([arg, source.to_str().unwrap()]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(reveal("--reveal-strings"), "let key = \"p\u{430}<U+0430>ss<U+200B>\"; // a\u{a0}b\n");
+    assert_eq!(reveal("--reveal-strings=comments"), "let key = \"p\u{430}<U+0430>ss<U+200B>\"; // a_<U+00A0>b\n");
+}
+
+#[test]
+fn line_diff_shows_a_changed_line_as_one_removal_and_one_addition() {
+    let dir = tempfile::tempdir().unwrap();
+    let (old, new) = (dir.path().join("old.rs"), dir.path().join("new.txt"));
+    std::fs::write(&old, "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+    // a missing last newline doesn't change the last line
+    std::fs::write(&new, "fn a() {}\nfn B() {}\nfn c() {}").unwrap();
+    let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+    let output = common::ccat().env("TERM", "dumb").args(["--line-diff", old, new]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "  fn a() {}\n- fn b() {}\n+ fn B() {}\n  fn c() {}");
+
+    // the new file is highlighted as the old one, the removed line struck through
+    let colored = stdout(&["--line-diff", old, new]);
+    assert_eq!(colored.lines().filter(|line| plain(line).starts_with("- ")).count(), 1);
+    assert_eq!(colored.lines().filter(|line| plain(line).starts_with("+ ")).count(), 1);
+    assert!(colored.contains("\x1b[2;9mfn b() {}\x1b[0m"), "{:?}", colored);
+    assert!(colored.contains("\x1b[38;2;180;142;173mfn"), "{:?}", colored);
+}
+
+#[test]
+fn show_scopes_reports_the_scopes_of_rust_keywords() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "fn main() {\n    if true { return; }\n}\n").unwrap();
+    let scopes = |arg: &str| {
+        let output = common::ccat().env("TERM", "dumb").args([arg, source.to_str().unwrap()]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let columns = scopes("--show-scopes");
+    let first = columns.lines().next().unwrap();
+    // syntect's Rust has `fn` as the storage type themes color as keywords
+    assert!(first.starts_with(" 1:1  fn ") && first.ends_with(" storage.type.function.rust"), "{}", columns);
+    assert!(columns.lines().any(|row| row.contains(" if ") && row.ends_with(" keyword.control.rust")), "{}", columns);
+
+    let interleaved = scopes("--syntax-test=interleaved");
+    assert!(interleaved.starts_with("fn main() {\n^^ source.rust "), "{}", interleaved);
+    assert!(interleaved.contains("\n    ^^ source.rust meta.function.rust meta.block.rust keyword.control.rust\n"), "{}", interleaved);
+}
+
+#[test]
+fn rainbow_brackets_color_code_brackets_by_depth_in_the_palette_given() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "fn f() { g(\")\"); } // (\n").unwrap();
+
+    let output = stdout(&["--rainbow-brackets", "--rainbow-palette", "#010101,#020202", source.to_str().unwrap()]);
+    let depth = |red: u8| format!("\x1b[38;2;{0};{0};{0}m", red);
+    assert_eq!(output.matches(&depth(1)).count(), 4, "{:?}", output);
+    assert_eq!(output.matches(&depth(2)).count(), 2, "{:?}", output);
+    assert!(output.contains("\x1b[38;2;163;190;140m)"), "the bracket in the string keeps its color: {:?}", output);
+    assert_eq!(plain(&output), "fn f() { g(\")\"); } // (\n");
+}
+
+#[test]
+fn the_rainbow_palette_in_the_config_file_yields_to_the_flag() {
+    let config = tempfile::tempdir().unwrap();
+    std::fs::create_dir(config.path().join("ccat")).unwrap();
+    std::fs::write(config.path().join("ccat/config.toml"), "rainbow-palette = [\"#010101\"]\n").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("main.rs");
+    std::fs::write(&source, "f(x)\n").unwrap();
+    let run = |args: &[&str]| {
+        let output = common::ccat().env("XDG_CONFIG_HOME", config.path())
+            .arg("--rainbow-brackets").args(args).arg(&source).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(run(&[]).matches("\x1b[38;2;1;1;1m").count(), 2);
+    let flagged = run(&["--rainbow-palette", "#020202"]);
+    assert_eq!(flagged.matches("\x1b[38;2;2;2;2m").count(), 2, "{:?}", flagged);
+    assert!(!flagged.contains("\x1b[38;2;1;1;1m"));
+}
+
+#[test]
+fn annotations_past_the_end_of_a_file_are_warned_about() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("lib.rs");
+    std::fs::write(&source, "fn a() {}\nfn b() {}\n").unwrap();
+    let notes = dir.path().join("notes.json");
+    std::fs::write(&notes, r#"{ "lib.rs": { "2": "the second", "2:9": "too far" } }"#).unwrap();
+
+    let output = common::ccat().env("TERM", "dumb")
+        .args(["--annotations", notes.to_str().unwrap(), source.to_str().unwrap()])
+        .output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "fn a() {}\nfn b() {}  the second; too far\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, format!("warning: annotation for lines 2-9 is past the end of '{}'\n", source.display()));
+}
+
+#[test]
+fn syntax_for_forces_a_syntax_on_the_files_it_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.rs"));
+    std::fs::write(&a, "{}\n").unwrap();
+    std::fs::write(&b, "fn main() {}\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    assert_eq!(stdout(&["--detect-only", "--syntax-for", "*.txt=JSON", a, b]), format!("{}\tJSON\n{}\tRust\n", a, b));
+
+    let unknown = common::run(&["--syntax-for", "*.txt=Klingon", a]);
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Syntax 'Klingon' not found (from rule '*.txt=Klingon')"));
+
+    let collision = common::run(&["--syntax-for", "*.txt=JSON", "--syntax-for", "a.*=Python", a]);
+    assert!(!collision.status.success());
+    let stderr = String::from_utf8_lossy(&collision.stderr);
+    assert!(stderr.contains(&format!("Syntax rules '*.txt=JSON' and 'a.*=Python' both match '{}'", a)), "{}", stderr);
+}
+
+#[test]
+fn binary_files_are_summarized_unless_told_not_to() {
+    let fixture = common::fixture("pixels.png");
+    assert_eq!(plain(&stdout(&[&fixture])), format!("{}: PNG image, 4x2, RGB, 8-bit\n", fixture));
+    assert_eq!(plain(&stdout(&["--binary-metadata=never", &fixture])), format!("{}: binary file, 76 bytes, not shown\n", fixture));
+
+    // a corrupt file is just binary
+    let mut corrupt = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut corrupt, b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDX").unwrap();
+    let corrupt = corrupt.path().to_str().unwrap();
+    let output = common::run(&[corrupt]);
+    assert!(output.status.success());
+    assert_eq!(plain(&String::from_utf8(output.stdout).unwrap()), format!("{}: binary file, 16 bytes, not shown\n", corrupt));
+}
+
+#[test]
+fn provenance_hashes_the_bytes_before_they_are_decoded() {
+    let fixture = common::fixture("utf16le-bom.txt");
+    let output = common::ccat().env("TERM", "dumb").args(["--provenance=hash-only", &fixture]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // the SHA-256 of the UTF-16 file, not of the text shown
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "café\nline two\n-- provenance --\nsha256 e04634a6bdb9854fca14210c850cbac1d1aca14c6c9561b02aa596e1a7b6d8a4\n",
+    );
+}
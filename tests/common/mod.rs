@@ -0,0 +1,84 @@
+//! Runs the ccat binary for the integration tests, away from the user's
+//! config, cache and terminal.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// A ccat command that reads no config file and caches into the test's
+/// own directory, with the environment variables ccat reads cleared.
+pub fn ccat() -> Command {
+    ccat_command(env!("CARGO_BIN_EXE_ccat"))
+}
+
+/// Runs `program` in the environment of [`ccat`], for a wrapper that
+/// starts ccat itself.
+pub fn ccat_command(program: &str) -> Command {
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let mut command = Command::new(program);
+    command
+        .env("XDG_CONFIG_HOME", scratch.join("no-config"))
+        .env("XDG_CACHE_HOME", scratch.join("cache"))
+        .env_remove("CCAT_LANGUAGE")
+        .env_remove("CCAT_SUDO")
+        .env_remove("NO_COLOR")
+        .env_remove("TMUX")
+        .env_remove("STY")
+        .env("TERM", "xterm-256color");
+    command
+}
+
+/// The path of a file under `tests/fixtures`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Runs ccat with `args`, expecting it to succeed, and returns its stdout.
+pub fn stdout(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(output.status.success(), "ccat {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("ccat writes UTF-8")
+}
+
+/// Runs ccat with `args`.
+pub fn run(args: &[&str]) -> Output {
+    ccat().args(args).output().expect("ccat runs")
+}
+
+/// Runs `command`, writing `input` to its stdin.
+pub fn with_stdin(mut command: Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("ccat runs");
+    child.stdin.take().expect("stdin is piped").write_all(input.as_bytes()).expect("ccat reads its stdin");
+    child.wait_with_output().expect("ccat runs")
+}
+
+/// `text` without its escape sequences.
+pub fn plain(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            result.push(char);
+            continue;
+        }
+        match chars.next() {
+            // CSI, ended by a byte in @..~
+            Some('[') => while chars.next().is_some_and(|char| !('@'..='~').contains(&char)) {},
+            // OSC and DCS, ended by BEL or ST
+            Some(']') | Some('P') => while let Some(char) = chars.next() {
+                if char == '\x07' || (char == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                    break;
+                }
+            },
+            _ => {}
+        }
+    }
+    result
+}
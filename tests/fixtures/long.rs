@@ -0,0 +1,5 @@
+// A comment long enough to run well past the width the snippet is exported at.
+fn main() {
+    let greeting = format!("{}, {}!", "Hello", "a world of rather long lines");
+    println!("{greeting}");
+}
@@ -0,0 +1,9 @@
+fn setup() {}
+
+// #region example
+fn example() {
+    println!("shown");
+}
+// #endregion
+
+fn teardown() {}
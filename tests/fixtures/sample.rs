@@ -0,0 +1,11 @@
+// A small file touching most of the decorations.
+use std::collections::HashMap;
+
+/// Counts the words of `text`, see https://example.com/words.
+fn count(text: &str) -> HashMap<&str, usize> {
+	let mut counts = HashMap::new();
+	for word in text.split_whitespace() {
+		*counts.entry(word).or_insert(0) += 1;
+	}
+	counts
+}
@@ -0,0 +1,68 @@
+//! Pins the exact output of `ccat --deterministic` for the fixtures under
+//! `tests/fixtures` against the files under `tests/golden`. After a change
+//! to the output that is meant, run with `CCAT_BLESS=1` to write the new
+//! expected files, and review their diff.
+
+mod common;
+
+use std::path::PathBuf;
+
+/// Runs ccat with `--deterministic` and `args` from the crate root, so the
+/// paths in the output are the relative ones given, and compares its stdout
+/// to `tests/golden/<name>`.
+fn golden(name: &str, args: &[&str]) {
+    let root = env!("CARGO_MANIFEST_DIR");
+    let output = common::ccat()
+        .current_dir(root)
+        .arg("--deterministic")
+        .args(args)
+        .output()
+        .expect("ccat runs");
+    assert!(output.status.success(), "ccat {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    let actual = String::from_utf8(output.stdout).expect("ccat writes UTF-8");
+
+    let path = PathBuf::from(root).join("tests/golden").join(name);
+    if std::env::var_os("CCAT_BLESS").is_some_and(|bless| bless != "0") {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("{} is missing; run with CCAT_BLESS=1 to write it", path.display()));
+    if actual != expected {
+        let line = actual.lines().zip(expected.lines()).position(|(a, b)| a != b)
+            .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+        panic!(
+            "ccat {:?} differs from {} from line {}:\n  got:      {:?}\n  expected: {:?}\nrun with CCAT_BLESS=1 if the change is meant",
+            args, path.display(), line + 1, actual.lines().nth(line), expected.lines().nth(line),
+        );
+    }
+}
+
+#[test]
+fn terminal() {
+    golden("sample.ansi", &["tests/fixtures/sample.rs"]);
+    golden("numbered.ansi", &["-l", "tests/fixtures/regions.rs", "tests/fixtures/tabs.txt"]);
+    golden("wrapped.ansi", &["-l", "--wrap=word", "--terminal-width", "40", "tests/fixtures/paragraph.txt"]);
+    golden("fences.ansi", &["--extract-fences", "*", "tests/fixtures/fences.md"]);
+    golden("dumb.txt", &["--profile", "dumb", "-l", "tests/fixtures/sample.rs"]);
+}
+
+#[test]
+fn html() {
+    golden("contact-sheet.html", &["--contact-sheet", "--preview-file", "tests/fixtures/sample.rs"]);
+    for mode in ["wrap", "chop", "scroll"] {
+        golden(
+            &format!("contact-sheet-{}.html", mode),
+            &["--contact-sheet", "--preview-file", "tests/fixtures/long.rs", "--export-width", "40", "--export-wrap", mode],
+        );
+    }
+}
+
+#[test]
+fn json() {
+    golden("detect.json", &[
+        "--detect-only", "--format", "json",
+        "tests/fixtures/sample.rs", "tests/fixtures/fences.md", "tests/fixtures/blocks.org", "tests/fixtures/notes.unknownext",
+    ]);
+}
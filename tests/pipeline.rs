@@ -0,0 +1,49 @@
+//! Arbitrary input through the input pipeline, in the spirit of the
+//! `pipeline` fuzz target but small enough to run with the other tests.
+
+use ccat::{ExtractOptions, HighlighterConfig, MemoryBudget, OutputProfile, SyntaxHighlighter};
+use proptest::prelude::*;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static HIGHLIGHTER: LazyLock<SyntaxHighlighter> = LazyLock::new(SyntaxHighlighter::new);
+
+/// The limits of `--paranoid`.
+fn paranoid(syntax: Option<String>) -> HighlighterConfig {
+    HighlighterConfig {
+        force_syntax: syntax,
+        profile: OutputProfile::DUMB,
+        max_line_length: Some(1024),
+        line_timeout: Some(Duration::from_millis(100)),
+        memory_budget: MemoryBudget::new(256 << 20),
+        ..HighlighterConfig::default()
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn arbitrary_bytes_are_read_without_panicking(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &bytes).unwrap();
+        let _ = HIGHLIGHTER.extract_text(&file.path().to_string_lossy(), &ExtractOptions::default());
+    }
+
+    #[test]
+    fn highlighting_keeps_every_line(bytes in proptest::collection::vec(any::<u8>(), 0..512), choice in any::<usize>()) {
+        let content = String::from_utf8_lossy(&bytes);
+        let syntaxes = HIGHLIGHTER.available_syntaxes();
+        let config = paranoid(Some(syntaxes[choice % syntaxes.len()].to_string()));
+
+        let output = HIGHLIGHTER.highlight_content(&content, "input", &config).unwrap();
+        prop_assert_eq!(output.lines().count(), content.lines().count());
+    }
+
+    #[test]
+    fn detection_takes_any_text(text in "\\PC*(\n\\PC*){0,8}") {
+        let _ = HIGHLIGHTER.detect(&text, "input", &paranoid(None));
+        let output = HIGHLIGHTER.highlight_content(&text, "input", &paranoid(None)).unwrap();
+        prop_assert_eq!(output.lines().count(), text.lines().count());
+    }
+}